@@ -34,7 +34,7 @@ use matrix_sdk_crypto::{
     olm::{IdentityKeys, InboundGroupSession, Session},
     store::{Changes, CryptoStore, PendingChanges, RoomSettings as RustRoomSettings},
     types::{EventEncryptionAlgorithm as RustEventEncryptionAlgorithm, SigningKey},
-    EncryptionSettings as RustEncryptionSettings,
+    CollectStrategy as RustCollectStrategy, EncryptionSettings as RustEncryptionSettings,
 };
 use matrix_sdk_sqlite::SqliteCryptoStore;
 pub use responses::{
@@ -620,6 +620,39 @@ impl From<HistoryVisibility> for RustHistoryVisibility {
     }
 }
 
+/// Strategy to collect the devices that should receive a room key.
+#[derive(Debug, Deserialize, Serialize, PartialEq, uniffi::Enum)]
+pub enum CollectStrategy {
+    /// Share the room key with all the devices of the members of the room.
+    AllDevices,
+    /// Share the room key only with the devices that were cross-signed by
+    /// their owner.
+    CrossSignedOnly,
+    /// Share the room key only with the devices that were cross-signed by
+    /// their owner, if we verified the identity of that owner.
+    VerifiedIdentitiesOnly,
+}
+
+impl From<CollectStrategy> for RustCollectStrategy {
+    fn from(s: CollectStrategy) -> Self {
+        match s {
+            CollectStrategy::AllDevices => Self::AllDevices,
+            CollectStrategy::CrossSignedOnly => Self::CrossSignedOnly,
+            CollectStrategy::VerifiedIdentitiesOnly => Self::VerifiedIdentitiesOnly,
+        }
+    }
+}
+
+impl From<RustCollectStrategy> for CollectStrategy {
+    fn from(s: RustCollectStrategy) -> Self {
+        match s {
+            RustCollectStrategy::AllDevices => Self::AllDevices,
+            RustCollectStrategy::CrossSignedOnly => Self::CrossSignedOnly,
+            RustCollectStrategy::VerifiedIdentitiesOnly => Self::VerifiedIdentitiesOnly,
+        }
+    }
+}
+
 /// Settings that should be used when a room key is shared.
 ///
 /// These settings control which algorithm the room key should use, how long a
@@ -641,6 +674,15 @@ pub struct EncryptionSettings {
     /// Should untrusted devices receive the room key, or should they be
     /// excluded from the conversation.
     pub only_allow_trusted_devices: bool,
+    /// Should sharing the room key fail if the identity of a member of the
+    /// room changed without the change being acknowledged.
+    #[uniffi(default = false)]
+    pub error_on_identity_violation: bool,
+    /// The strategy to collect the devices that should receive the room key,
+    /// on top of `only_allow_trusted_devices`. All the devices receive it if
+    /// it's not set.
+    #[uniffi(default = None)]
+    pub sharing_strategy: Option<CollectStrategy>,
 }
 
 impl From<EncryptionSettings> for RustEncryptionSettings {
@@ -651,6 +693,8 @@ impl From<EncryptionSettings> for RustEncryptionSettings {
             rotation_period_msgs: v.rotation_period_msgs,
             history_visibility: v.history_visibility.into(),
             only_allow_trusted_devices: v.only_allow_trusted_devices,
+            error_on_identity_violation: v.error_on_identity_violation,
+            sharing_strategy: v.sharing_strategy.map(Into::into).unwrap_or_default(),
         }
     }
 }
@@ -828,6 +872,10 @@ pub struct RoomSettings {
     /// Should untrusted devices receive the room key, or should they be
     /// excluded from the conversation.
     pub only_allow_trusted_devices: bool,
+    /// The strategy to collect the devices that should receive the room keys
+    /// of this room, overriding the global one.
+    #[serde(default)]
+    pub sharing_strategy: Option<CollectStrategy>,
 }
 
 impl TryFrom<RustRoomSettings> for RoomSettings {
@@ -835,7 +883,11 @@ impl TryFrom<RustRoomSettings> for RoomSettings {
 
     fn try_from(value: RustRoomSettings) -> Result<Self, Self::Error> {
         let algorithm = value.algorithm.try_into()?;
-        Ok(Self { algorithm, only_allow_trusted_devices: value.only_allow_trusted_devices })
+        Ok(Self {
+            algorithm,
+            only_allow_trusted_devices: value.only_allow_trusted_devices,
+            sharing_strategy: value.sharing_strategy.map(Into::into),
+        })
     }
 }
 
@@ -844,6 +896,7 @@ impl From<RoomSettings> for RustRoomSettings {
         Self {
             algorithm: value.algorithm.into(),
             only_allow_trusted_devices: value.only_allow_trusted_devices,
+            sharing_strategy: value.sharing_strategy.map(Into::into),
             ..RustRoomSettings::default()
         }
     }
@@ -1031,7 +1084,8 @@ mod tests {
         assert_eq!(
             Some(RoomSettings {
                 algorithm: EventEncryptionAlgorithm::OlmV1Curve25519AesSha2,
-                only_allow_trusted_devices: true
+                only_allow_trusted_devices: true,
+                sharing_strategy: None,
             }),
             settings1
         );
@@ -1040,7 +1094,8 @@ mod tests {
         assert_eq!(
             Some(RoomSettings {
                 algorithm: EventEncryptionAlgorithm::MegolmV1AesSha2,
-                only_allow_trusted_devices: false
+                only_allow_trusted_devices: false,
+                sharing_strategy: None,
             }),
             settings2
         );
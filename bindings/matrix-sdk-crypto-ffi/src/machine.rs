@@ -53,11 +53,12 @@ use crate::{
     error::{CryptoStoreError, DecryptionError, SecretImportError, SignatureError},
     parse_user_id,
     responses::{response_from_string, OwnedResponse},
-    BackupKeys, BackupRecoveryKey, BootstrapCrossSigningResult, CrossSigningKeyExport,
-    CrossSigningStatus, DecodeError, DecryptedEvent, Device, DeviceLists, EncryptionSettings,
-    EventEncryptionAlgorithm, KeyImportError, KeysImportResult, MegolmV1BackupKey,
-    ProgressListener, Request, RequestType, RequestVerificationResult, RoomKeyCounts, RoomSettings,
-    Sas, SignatureUploadRequest, StartSasResult, UserIdentity, Verification, VerificationRequest,
+    BackupKeys, BackupRecoveryKey, BootstrapCrossSigningResult, CollectStrategy,
+    CrossSigningKeyExport, CrossSigningStatus, DecodeError, DecryptedEvent, Device, DeviceLists,
+    EncryptionSettings, EventEncryptionAlgorithm, KeyImportError, KeysImportResult,
+    MegolmV1BackupKey, ProgressListener, Request, RequestType, RequestVerificationResult,
+    RoomKeyCounts, RoomSettings, Sas, SignatureUploadRequest, StartSasResult, UserIdentity,
+    Verification, VerificationRequest,
 };
 
 /// The return value for the [`OlmMachine::receive_sync_changes()`] method.
@@ -713,6 +714,53 @@ impl OlmMachine {
         Ok(())
     }
 
+    /// Set the strategy to collect the devices that should receive the room
+    /// keys of this room, overriding the global one.
+    ///
+    /// Passing `None` makes the room use the global strategy set with
+    /// [set_room_key_sharing_strategy()](Self::set_room_key_sharing_strategy).
+    pub fn set_room_sharing_strategy(
+        &self,
+        room_id: String,
+        sharing_strategy: Option<CollectStrategy>,
+    ) -> Result<(), CryptoStoreError> {
+        let room_id = RoomId::parse(room_id)?;
+        self.runtime.block_on(async move {
+            let mut settings =
+                self.inner.store().get_room_settings(&room_id).await?.unwrap_or_default();
+            settings.sharing_strategy = sharing_strategy.map(Into::into);
+            self.inner
+                .store()
+                .save_changes(Changes {
+                    room_settings: HashMap::from([(room_id, settings)]),
+                    ..Default::default()
+                })
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Get the global strategy to collect the devices that should receive
+    /// room keys.
+    pub fn get_room_key_sharing_strategy(&self) -> Result<CollectStrategy, CryptoStoreError> {
+        let strategy = self.runtime.block_on(self.inner.store().get_room_key_sharing_strategy())?;
+        Ok(strategy.into())
+    }
+
+    /// Set the global strategy to collect the devices that should receive
+    /// room keys.
+    ///
+    /// Note that it can be overridden by the per-room strategy set with
+    /// [set_room_sharing_strategy()](Self::set_room_sharing_strategy).
+    pub fn set_room_key_sharing_strategy(
+        &self,
+        sharing_strategy: CollectStrategy,
+    ) -> Result<(), CryptoStoreError> {
+        self.runtime
+            .block_on(self.inner.store().set_room_key_sharing_strategy(sharing_strategy.into()))?;
+        Ok(())
+    }
+
     /// Share a room key with the given list of users for the given room.
     ///
     /// After the request was sent out and a successful response was received
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use futures_util::StreamExt;
 use matrix_sdk::{
+    deserialized_responses::UtdCause as SdkUtdCause,
     encryption,
     encryption::{backups, recovery},
 };
@@ -211,6 +212,32 @@ impl From<encryption::VerificationState> for VerificationState {
     }
 }
 
+/// The reason why an encrypted event couldn't be decrypted.
+#[derive(Clone, uniffi::Enum)]
+pub enum UtdCause {
+    MissingRoomKey,
+    Withheld { code: String },
+    UnverifiedDevice,
+    SentBeforeDeviceExisted,
+    UnknownMessageIndex,
+    MismatchedSenderKeys,
+    Unknown,
+}
+
+impl From<SdkUtdCause> for UtdCause {
+    fn from(value: SdkUtdCause) -> Self {
+        match value {
+            SdkUtdCause::MissingRoomKey => Self::MissingRoomKey,
+            SdkUtdCause::Withheld { code } => Self::Withheld { code },
+            SdkUtdCause::UnverifiedDevice => Self::UnverifiedDevice,
+            SdkUtdCause::SentBeforeDeviceExisted => Self::SentBeforeDeviceExisted,
+            SdkUtdCause::UnknownMessageIndex => Self::UnknownMessageIndex,
+            SdkUtdCause::MismatchedSenderKeys => Self::MismatchedSenderKeys,
+            SdkUtdCause::Unknown => Self::Unknown,
+        }
+    }
+}
+
 #[uniffi::export(async_runtime = "tokio")]
 impl Encryption {
     pub fn backup_state_listener(&self, listener: Box<dyn BackupStateListener>) -> Arc<TaskHandle> {
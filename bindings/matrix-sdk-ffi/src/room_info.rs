@@ -44,6 +44,24 @@ pub struct RoomInfo {
     /// Events causing mentions/highlights for the user, according to their
     /// notification settings.
     num_unread_mentions: u64,
+    /// The unread counts of the threads of the room, by ID of their root
+    /// event.
+    thread_unread_counts: HashMap<String, ThreadUnreadCounts>,
+}
+
+/// The unread counts of a thread, computed client-side from the threaded read
+/// receipts.
+#[derive(uniffi::Record)]
+pub struct ThreadUnreadCounts {
+    /// "Interesting" messages received in that thread, independently of the
+    /// notification settings.
+    num_unread_messages: u64,
+    /// Events that will notify the user, according to their
+    /// notification settings.
+    num_unread_notifications: u64,
+    /// Events causing mentions/highlights for the user, according to their
+    /// notification settings.
+    num_unread_mentions: u64,
 }
 
 impl RoomInfo {
@@ -98,6 +116,18 @@ impl RoomInfo {
             num_unread_messages: room.num_unread_messages(),
             num_unread_notifications: room.num_unread_notifications(),
             num_unread_mentions: room.num_unread_mentions(),
+            thread_unread_counts: room
+                .thread_read_receipts()
+                .into_iter()
+                .map(|(thread_root, receipts)| {
+                    let counts = ThreadUnreadCounts {
+                        num_unread_messages: receipts.num_unread,
+                        num_unread_notifications: receipts.num_notifications,
+                        num_unread_mentions: receipts.num_mentions,
+                    };
+                    (thread_root.to_string(), counts)
+                })
+                .collect(),
         })
     }
 }
@@ -20,7 +20,11 @@ use matrix_sdk_ui::{
             new_filter_none, new_filter_normalized_match_room_name, new_filter_unread,
             RoomCategory,
         },
-        BoxedFilterFn,
+        sorters::{
+            new_sorter_favourite, new_sorter_lexicographic, new_sorter_name, new_sorter_recency,
+            new_sorter_unread,
+        },
+        BoxedFilterFn, BoxedSorterFn,
     },
     timeline::default_event_filter,
     unable_to_decrypt_hook::UtdHookManager,
@@ -409,6 +413,11 @@ impl RoomListDynamicEntriesController {
         self.inner.set_filter(filter)
     }
 
+    fn set_sorter(&self, kind: RoomListEntriesDynamicSorterKind) -> bool {
+        let SorterWrapper(sorter) = SorterWrapper::from(&self.client, kind);
+        self.inner.set_sorter(sorter)
+    }
+
     fn add_one_page(&self) {
         self.inner.add_one_page();
     }
@@ -478,6 +487,35 @@ impl FilterWrapper {
     }
 }
 
+#[derive(uniffi::Enum)]
+pub enum RoomListEntriesDynamicSorterKind {
+    Lexicographic { sorters: Vec<RoomListEntriesDynamicSorterKind> },
+    Recency,
+    Unread,
+    Name,
+    Favourite,
+}
+
+/// Custom internal type to transform a `RoomListEntriesDynamicSorterKind` into
+/// a `BoxedSorterFn`.
+struct SorterWrapper(BoxedSorterFn);
+
+impl SorterWrapper {
+    fn from(client: &matrix_sdk::Client, value: RoomListEntriesDynamicSorterKind) -> Self {
+        use RoomListEntriesDynamicSorterKind as Kind;
+
+        match value {
+            Kind::Lexicographic { sorters } => Self(Box::new(new_sorter_lexicographic(
+                sorters.into_iter().map(|sorter| SorterWrapper::from(client, sorter).0).collect(),
+            ))),
+            Kind::Recency => Self(Box::new(new_sorter_recency(client))),
+            Kind::Unread => Self(Box::new(new_sorter_unread(client))),
+            Kind::Name => Self(Box::new(new_sorter_name(client))),
+            Kind::Favourite => Self(Box::new(new_sorter_favourite(client))),
+        }
+    }
+}
+
 #[derive(uniffi::Object)]
 pub struct RoomListItem {
     inner: Arc<matrix_sdk_ui::room_list_service::Room>,
@@ -18,8 +18,8 @@ use futures_util::pin_mut;
 use matrix_sdk::Client;
 use matrix_sdk_ui::{
     sync_service::{
-        State as MatrixSyncServiceState, SyncService as MatrixSyncService,
-        SyncServiceBuilder as MatrixSyncServiceBuilder,
+        OfflineReason as MatrixOfflineReason, State as MatrixSyncServiceState,
+        SyncService as MatrixSyncService, SyncServiceBuilder as MatrixSyncServiceBuilder,
     },
     unable_to_decrypt_hook::{
         UnableToDecryptHook, UnableToDecryptInfo as SdkUnableToDecryptInfo, UtdHookManager,
@@ -27,8 +27,8 @@ use matrix_sdk_ui::{
 };
 
 use crate::{
-    error::ClientError, helpers::unwrap_or_clone_arc, room_list::RoomListService, TaskHandle,
-    RUNTIME,
+    encryption::UtdCause, error::ClientError, helpers::unwrap_or_clone_arc,
+    room_list::RoomListService, TaskHandle, RUNTIME,
 };
 
 #[derive(uniffi::Enum)]
@@ -37,6 +37,7 @@ pub enum SyncServiceState {
     Running,
     Terminated,
     Error,
+    Offline,
 }
 
 impl From<MatrixSyncServiceState> for SyncServiceState {
@@ -46,6 +47,7 @@ impl From<MatrixSyncServiceState> for SyncServiceState {
             MatrixSyncServiceState::Running => Self::Running,
             MatrixSyncServiceState::Terminated => Self::Terminated,
             MatrixSyncServiceState::Error => Self::Error,
+            MatrixSyncServiceState::Offline => Self::Offline,
         }
     }
 }
@@ -55,6 +57,38 @@ pub trait SyncServiceStateObserver: Send + Sync + Debug {
     fn on_update(&self, state: SyncServiceState);
 }
 
+/// The reason why the sync service is in the [`SyncServiceState::Offline`]
+/// state.
+#[derive(uniffi::Enum)]
+pub enum OfflineReason {
+    /// Any of the underlying syncs ran into a network error. The syncs will be
+    /// restarted after `retry_in`, or as soon as the network is reachable
+    /// again.
+    NetworkError { message: String, retry_in: Duration },
+    /// The network is unreachable, as signalled with
+    /// `SyncService::set_network_reachable`. The syncs will be restarted as
+    /// soon as the network is reachable again.
+    NetworkUnreachable,
+}
+
+impl From<MatrixOfflineReason> for OfflineReason {
+    fn from(value: MatrixOfflineReason) -> Self {
+        match value {
+            MatrixOfflineReason::NetworkError { message, retry_in } => {
+                Self::NetworkError { message, retry_in }
+            }
+            MatrixOfflineReason::NetworkUnreachable => Self::NetworkUnreachable,
+        }
+    }
+}
+
+#[uniffi::export(callback_interface)]
+pub trait SyncServiceOfflineReasonObserver: Send + Sync + Debug {
+    /// Called with the reason why the sync service is offline, or `None` when
+    /// it's not offline anymore.
+    fn on_update(&self, reason: Option<OfflineReason>);
+}
+
 #[derive(uniffi::Object)]
 pub struct SyncService {
     pub(crate) inner: Arc<MatrixSyncService>,
@@ -78,6 +112,10 @@ impl SyncService {
         Ok(self.inner.stop().await?)
     }
 
+    pub async fn set_network_reachable(&self, reachable: bool) {
+        self.inner.set_network_reachable(reachable).await;
+    }
+
     pub fn state(&self, listener: Box<dyn SyncServiceStateObserver>) -> Arc<TaskHandle> {
         let state_stream = self.inner.state();
 
@@ -89,6 +127,21 @@ impl SyncService {
             }
         })))
     }
+
+    pub fn offline_reason(
+        &self,
+        listener: Box<dyn SyncServiceOfflineReasonObserver>,
+    ) -> Arc<TaskHandle> {
+        let offline_reason_stream = self.inner.offline_reason();
+
+        Arc::new(TaskHandle::new(RUNTIME.spawn(async move {
+            pin_mut!(offline_reason_stream);
+
+            while let Some(reason) = offline_reason_stream.next().await {
+                listener.on_update(reason.map(Into::into));
+            }
+        })))
+    }
 }
 
 #[derive(Clone, uniffi::Object)]
@@ -121,6 +174,12 @@ impl SyncServiceBuilder {
         Arc::new(Self { builder, utd_hook: this.utd_hook })
     }
 
+    pub fn with_offline_mode(self: Arc<Self>) -> Arc<Self> {
+        let this = unwrap_or_clone_arc(self);
+        let builder = this.builder.with_offline_mode();
+        Arc::new(Self { builder, utd_hook: this.utd_hook })
+    }
+
     pub fn with_utd_hook(self: Arc<Self>, delegate: Box<dyn UnableToDecryptDelegate>) -> Arc<Self> {
         // UTDs detected before this duration may be reclassified as "late decryption"
         // events (or discarded, if they get decrypted fast enough).
@@ -187,6 +246,9 @@ pub struct UnableToDecryptInfo {
     ///
     /// If set, this is in milliseconds.
     pub time_to_decrypt_ms: Option<u64>,
+
+    /// Why the event couldn't be decrypted.
+    pub cause: UtdCause,
 }
 
 impl From<SdkUnableToDecryptInfo> for UnableToDecryptInfo {
@@ -194,6 +256,7 @@ impl From<SdkUnableToDecryptInfo> for UnableToDecryptInfo {
         Self {
             event_id: value.event_id.to_string(),
             time_to_decrypt_ms: value.time_to_decrypt.map(|ttd| ttd.as_millis() as u64),
+            cause: value.cause.into(),
         }
     }
 }
@@ -19,7 +19,10 @@ use matrix_sdk_ui::timeline::{PollResult, TimelineDetails};
 use tracing::warn;
 
 use super::ProfileDetails;
-use crate::ruma::{ImageInfo, MessageType, PollKind};
+use crate::{
+    encryption::UtdCause,
+    ruma::{ImageInfo, MessageType, PollKind},
+};
 
 #[derive(Clone, uniffi::Object)]
 pub struct TimelineItemContent(pub(crate) matrix_sdk_ui::timeline::TimelineItemContent);
@@ -42,6 +45,19 @@ impl TimelineItemContent {
             }
             Content::Poll(poll_state) => TimelineItemContentKind::from(poll_state.results()),
             Content::CallInvite => TimelineItemContentKind::CallInvite,
+            Content::Location(location) => TimelineItemContentKind::Location {
+                body: location.body().map(ToOwned::to_owned),
+                geo_uri: location.geo_uri().to_owned(),
+                description: location.description().map(ToOwned::to_owned),
+            },
+            Content::LiveLocation(state) => TimelineItemContentKind::LiveLocation {
+                description: state.description().map(ToOwned::to_owned),
+                is_live: state.is_live(),
+                start_ts: state.start_ts().0.into(),
+                timeout_ms: state.timeout().as_millis() as u64,
+                latest_geo_uri: state.latest_location().map(|l| l.geo_uri().to_owned()),
+                latest_ts: state.latest_location().map(|l| l.ts().0.into()),
+            },
             Content::UnableToDecrypt(msg) => {
                 TimelineItemContentKind::UnableToDecrypt { msg: EncryptedMessage::new(msg) }
             }
@@ -115,6 +131,19 @@ pub enum TimelineItemContentKind {
         has_been_edited: bool,
     },
     CallInvite,
+    Location {
+        body: Option<String>,
+        geo_uri: String,
+        description: Option<String>,
+    },
+    LiveLocation {
+        description: Option<String>,
+        is_live: bool,
+        start_ts: u64,
+        timeout_ms: u64,
+        latest_geo_uri: Option<String>,
+        latest_ts: Option<u64>,
+    },
     UnableToDecrypt {
         msg: EncryptedMessage,
     },
@@ -214,6 +243,8 @@ pub enum EncryptedMessage {
     MegolmV1AesSha2 {
         /// The ID of the session used to encrypt the message.
         session_id: String,
+        /// Why the message couldn't be decrypted.
+        cause: UtdCause,
     },
     Unknown,
 }
@@ -227,9 +258,9 @@ impl EncryptedMessage {
                 let sender_key = sender_key.clone();
                 Self::OlmV1Curve25519AesSha2 { sender_key }
             }
-            Message::MegolmV1AesSha2 { session_id, .. } => {
+            Message::MegolmV1AesSha2 { session_id, cause, .. } => {
                 let session_id = session_id.clone();
-                Self::MegolmV1AesSha2 { session_id }
+                Self::MegolmV1AesSha2 { session_id, cause: cause.clone().into() }
             }
             Message::Unknown => Self::Unknown,
         }
@@ -743,6 +743,17 @@ impl TimelineItem {
         match self.0.as_virtual()? {
             VItem::DayDivider(ts) => Some(VirtualTimelineItem::DayDivider { ts: ts.0.into() }),
             VItem::ReadMarker => Some(VirtualTimelineItem::ReadMarker),
+            VItem::CollapsedStateEvents(summary) => {
+                Some(VirtualTimelineItem::CollapsedStateEvents {
+                    num_events: summary.num_events() as u64,
+                    joined: summary.joined() as u64,
+                    left: summary.left() as u64,
+                    invited: summary.invited() as u64,
+                    removed: summary.removed() as u64,
+                    profile_changes: summary.profile_changes() as u64,
+                    other_changes: summary.other_changes() as u64,
+                })
+            }
         }
     }
 
@@ -1014,6 +1025,24 @@ pub enum VirtualTimelineItem {
 
     /// The user's own read marker.
     ReadMarker,
+
+    /// A summary of the consecutive state events following this item.
+    CollapsedStateEvents {
+        /// The number of summarised events, directly following this item.
+        num_events: u64,
+        /// The number of users who joined the room.
+        joined: u64,
+        /// The number of users who left the room.
+        left: u64,
+        /// The number of users who were invited to the room.
+        invited: u64,
+        /// The number of users who were kicked or banned from the room.
+        removed: u64,
+        /// The number of changes of display names or avatars.
+        profile_changes: u64,
+        /// The number of other membership changes and state events.
+        other_changes: u64,
+    },
 }
 
 /// A [`TimelineItem`](super::TimelineItem) that doesn't correspond to an event.
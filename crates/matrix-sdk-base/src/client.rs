@@ -13,12 +13,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "e2e-encryption")]
+use std::ops::Deref;
 use std::{
     collections::{BTreeMap, BTreeSet},
     fmt, iter,
+    sync::Arc,
 };
-#[cfg(feature = "e2e-encryption")]
-use std::{ops::Deref, sync::Arc};
 
 use eyeball::{SharedObservable, Subscriber};
 use matrix_sdk_common::instant::Instant;
@@ -58,12 +59,16 @@ use tokio::sync::{RwLock, RwLockReadGuard};
 use tracing::{debug, info, instrument, trace, warn};
 
 #[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
-use crate::latest_event::{is_suitable_for_latest_event, LatestEvent, PossibleLatestEvent};
+use crate::latest_event::{
+    as_latest_event_edit, is_suitable_for_latest_event, LatestEvent, LatestEventEdits,
+    PossibleLatestEvent,
+};
 #[cfg(feature = "e2e-encryption")]
 use crate::RoomMemberships;
 use crate::{
     deserialized_responses::{RawAnySyncOrStrippedTimelineEvent, SyncTimelineEvent},
     error::{Error, Result},
+    event_cache_store::DynEventCacheStore,
     rooms::{normal::RoomInfoUpdate, Room, RoomInfo, RoomState},
     store::{
         ambiguity_map::AmbiguityCache, DynStateStore, MemoryStore, Result as StoreResult,
@@ -81,6 +86,8 @@ use crate::{
 pub struct BaseClient {
     /// Database
     pub(crate) store: Store,
+    /// The store used by the event cache.
+    event_cache_store: Arc<DynEventCacheStore>,
     /// The store used for encryption.
     ///
     /// This field is only meant to be used for `OlmMachine` initialization.
@@ -92,6 +99,9 @@ pub struct BaseClient {
     /// [`BaseClient::set_session_meta`]
     #[cfg(feature = "e2e-encryption")]
     olm_machine: Arc<RwLock<Option<OlmMachine>>>,
+    /// Observable of when the olm-machine is (re)created.
+    #[cfg(feature = "e2e-encryption")]
+    olm_machine_changes: SharedObservable<()>,
     /// Observable of when a user is ignored/unignored.
     pub(crate) ignore_user_list_changes: SharedObservable<Vec<String>>,
 
@@ -129,10 +139,13 @@ impl BaseClient {
 
         BaseClient {
             store: Store::new(config.state_store),
+            event_cache_store: config.event_cache_store,
             #[cfg(feature = "e2e-encryption")]
             crypto_store: config.crypto_store,
             #[cfg(feature = "e2e-encryption")]
             olm_machine: Default::default(),
+            #[cfg(feature = "e2e-encryption")]
+            olm_machine_changes: Default::default(),
             ignore_user_list_changes: Default::default(),
             roominfo_update_sender,
         }
@@ -186,6 +199,11 @@ impl BaseClient {
         &*self.store
     }
 
+    /// Get a reference to the event cache store.
+    pub fn event_cache_store(&self) -> &DynEventCacheStore {
+        &*self.event_cache_store
+    }
+
     /// Is the client logged in.
     pub fn logged_in(&self) -> bool {
         self.store.session_meta().is_some()
@@ -230,9 +248,21 @@ impl BaseClient {
         .map_err(OlmError::from)?;
 
         *self.olm_machine.write().await = Some(olm_machine);
+        self.olm_machine_changes.set(());
+
         Ok(())
     }
 
+    /// Returns a subscriber that publishes an update every time the
+    /// `OlmMachine` is (re)created, e.g. with [`BaseClient::regenerate_olm`].
+    ///
+    /// This is useful to resubscribe to the streams of the `OlmMachine`, which
+    /// stop yielding updates when it is replaced.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn subscribe_to_olm_machine_changes(&self) -> Subscriber<()> {
+        self.olm_machine_changes.subscribe()
+    }
+
     /// Get the current, if any, sync token of the client.
     /// This will be None if the client didn't sync at least once.
     pub async fn sync_token(&self) -> Option<String> {
@@ -362,12 +392,21 @@ impl BaseClient {
                             AnySyncMessageLikeEvent::RoomEncrypted(
                                 SyncMessageLikeEvent::Original(_),
                             ) => {
-                                if let Ok(Some(e)) = Box::pin(
+                                match Box::pin(
                                     self.decrypt_sync_room_event(&event.event, room.room_id()),
                                 )
                                 .await
                                 {
-                                    event = e;
+                                    Ok(Some(e)) => event = e,
+                                    Err(Error::MegolmError(e)) => {
+                                        // Remember why the event couldn't be decrypted, so it
+                                        // can be shown to the user.
+                                        if let Some(olm) = self.olm_machine().await.as_ref() {
+                                            event.utd_cause =
+                                                Some(olm.utd_cause(event.event.cast_ref(), &e));
+                                        }
+                                    }
+                                    _ => {}
                                 }
                             }
                             AnySyncMessageLikeEvent::RoomMessage(
@@ -666,17 +705,64 @@ impl BaseClient {
         }
     }
 
+    /// Decrypt the latest encrypted events of the given rooms, after new room
+    /// keys have been received for them outside of a sync, e.g. from the key
+    /// backup.
+    ///
+    /// If a decrypted event is suitable, it becomes the latest event of its
+    /// room, see [`Room::latest_event`].
+    #[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
+    pub async fn decrypt_latest_events_for_rooms(
+        &self,
+        room_ids: impl IntoIterator<Item = &RoomId>,
+    ) -> Result<()> {
+        let _sync_lock = self.sync_lock().lock().await;
+        let mut changes = StateChanges::default();
+
+        for room_id in room_ids {
+            if let Some(room) = self.get_room(room_id) {
+                self.decrypt_latest_events(&room, &mut changes).await;
+            }
+        }
+
+        if !changes.room_infos.is_empty() {
+            self.store.save_changes(&changes).await?;
+            self.apply_changes(&changes, true);
+        }
+
+        Ok(())
+    }
+
     /// Decrypt any of this room's latest_encrypted_events
     /// that we can and if we can, change latest_event to reflect what we
     /// found, and remove any older encrypted events from
     /// latest_encrypted_events.
+    ///
+    /// The decrypted edits of the latest event are applied to it.
     #[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
     async fn decrypt_latest_events(&self, room: &Room, changes: &mut StateChanges) {
         // Try to find a message we can decrypt and is suitable for using as the latest
         // event. If we found one, set it as the latest and delete any older
         // encrypted events
-        if let Some((found, found_index)) = self.decrypt_latest_suitable_event(room).await {
+        let (found, edits) = self.decrypt_latest_suitable_event(room).await;
+
+        if let Some((found, found_index)) = found {
             room.on_latest_event_decrypted(found, found_index, changes);
+            return;
+        }
+
+        if edits.is_empty() {
+            return;
+        }
+
+        // The current latest event may have been edited.
+        let mut room_info =
+            changes.room_infos.get(room.room_id()).cloned().unwrap_or_else(|| room.clone_info());
+
+        if let Some(latest_event) = &mut room_info.latest_event {
+            if latest_event.apply_edit_from(&edits) {
+                changes.add_room(room_info);
+            }
         }
     }
 
@@ -686,12 +772,17 @@ impl BaseClient {
     /// (i.e. we can usefully display it as a message preview). Returns the
     /// decrypted event if we found one, along with its index in the
     /// latest_encrypted_events list, or None if we didn't find one.
+    ///
+    /// The edits decrypted along the way are applied to the found event, and
+    /// returned too, by edited event ID and sender, so they can be applied to
+    /// the current latest event if no newer one was found.
     #[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
     async fn decrypt_latest_suitable_event(
         &self,
         room: &Room,
-    ) -> Option<(Box<LatestEvent>, usize)> {
+    ) -> (Option<(Box<LatestEvent>, usize)>, LatestEventEdits) {
         let enc_events = room.latest_encrypted_events();
+        let mut edits = LatestEventEdits::new();
 
         // Walk backwards through the encrypted events, looking for one we can decrypt
         for (i, event) in enc_events.iter().enumerate().rev() {
@@ -704,20 +795,35 @@ impl BaseClient {
             if let Ok(Some(decrypted)) = decrypt_sync_room_event.await {
                 // We found an event we can decrypt
                 if let Ok(any_sync_event) = decrypted.event.deserialize() {
+                    if let Some((edited_event_id, new_content)) =
+                        as_latest_event_edit(&any_sync_event)
+                    {
+                        // Only keep the most recent edit of an event by each sender.
+                        edits
+                            .entry((edited_event_id.to_owned(), any_sync_event.sender().to_owned()))
+                            .or_insert_with(|| new_content.clone());
+                        continue;
+                    }
+
                     // We can deserialize it to find its type
                     match is_suitable_for_latest_event(&any_sync_event) {
                         PossibleLatestEvent::YesRoomMessage(_)
                         | PossibleLatestEvent::YesPoll(_)
                         | PossibleLatestEvent::YesCallInvite(_) => {
                             // The event is the right type for us to use as latest_event
-                            return Some((Box::new(LatestEvent::new(decrypted)), i));
+                            let mut latest_event = Box::new(LatestEvent::new(decrypted));
+
+                            latest_event.apply_edit_from(&edits);
+
+                            return (Some((latest_event, i)), edits);
                         }
                         _ => (),
                     }
                 }
             }
         }
-        None
+
+        (None, edits)
     }
 
     /// User has joined a room.
@@ -730,6 +836,11 @@ impl BaseClient {
             self.roominfo_update_sender.clone(),
         );
         if room.state() != RoomState::Joined {
+            // If we were invited, the inviter might have forwarded us the room
+            // keys with a shared history of the room.
+            #[cfg(feature = "e2e-encryption")]
+            let inviter = self.inviter(&room).await?;
+
             let _sync_lock = self.sync_lock().lock().await;
 
             let mut room_info = room.clone_info();
@@ -741,11 +852,95 @@ impl BaseClient {
             self.store.save_changes(&changes).await?; // Update the store
             room.set_room_info(room_info, false); // Update the cached room
                                                   // handle
+
+            #[cfg(feature = "e2e-encryption")]
+            if let Some(inviter) = inviter {
+                self.handle_shared_history_room_keys(vec![(room_id.to_owned(), inviter)]).await?;
+            }
         }
 
         Ok(room)
     }
 
+    /// Get the user who invited us to the given room, if we're invited to it.
+    #[cfg(feature = "e2e-encryption")]
+    async fn inviter(&self, room: &Room) -> Result<Option<OwnedUserId>> {
+        if room.state() != RoomState::Invited {
+            return Ok(None);
+        }
+
+        let member = room.get_member(room.own_user_id()).await?;
+        Ok(member.map(|member| member.event().sender().to_owned()))
+    }
+
+    /// Find the rooms that we joined after being invited in the given changes,
+    /// with the user who invited us.
+    ///
+    /// This must be called before the changes are applied.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) async fn accepted_invites(
+        &self,
+        changes: &StateChanges,
+    ) -> Result<Vec<(OwnedRoomId, OwnedUserId)>> {
+        let mut accepted_invites = Vec::new();
+
+        for (room_id, room_info) in &changes.room_infos {
+            if room_info.state() != RoomState::Joined {
+                continue;
+            }
+
+            let Some(room) = self.store.get_room(room_id) else {
+                continue;
+            };
+
+            if let Some(inviter) = self.inviter(&room).await? {
+                accepted_invites.push((room_id.clone(), inviter));
+            }
+        }
+
+        Ok(accepted_invites)
+    }
+
+    /// Import the room keys with a shared history that were forwarded to us by
+    /// the users who invited us to the rooms we joined, as defined in
+    /// [MSC3061].
+    ///
+    /// The room keys forwarded by users we don't have an invite from are
+    /// discarded, unless we don't know about their room yet.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) async fn handle_shared_history_room_keys(
+        &self,
+        accepted_invites: Vec<(OwnedRoomId, OwnedUserId)>,
+    ) -> Result<()> {
+        let olm = self.olm_machine().await;
+        let Some(olm) = olm.as_ref() else {
+            return Ok(());
+        };
+
+        for (room_id, inviter) in accepted_invites {
+            olm.accept_shared_history_room_keys(&room_id, &inviter).await?;
+        }
+
+        for (room_id, senders) in olm.shared_history_room_key_senders().await? {
+            // The room keys might be received before the invite.
+            let Some(room) = self.store.get_room(&room_id) else {
+                continue;
+            };
+
+            let inviter = self.inviter(&room).await?;
+
+            for sender in senders {
+                if inviter.as_ref() != Some(&sender) {
+                    olm.discard_shared_history_room_keys(&room_id, &sender).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// User has left a room.
     ///
     /// Update the internal and cached state accordingly.
@@ -1037,6 +1232,9 @@ impl BaseClient {
 
         changes.ambiguity_maps = ambiguity_cache.cache;
 
+        #[cfg(feature = "e2e-encryption")]
+        let accepted_invites = self.accepted_invites(&changes).await?;
+
         {
             let _sync_lock = self.sync_lock().lock().await;
             self.store.save_changes(&changes).await?;
@@ -1044,6 +1242,9 @@ impl BaseClient {
             self.apply_changes(&changes, false);
         }
 
+        #[cfg(feature = "e2e-encryption")]
+        self.handle_shared_history_room_keys(accepted_invites).await?;
+
         info!("Processed a sync response in {:?}", now.elapsed());
 
         let response = SyncResponse {
@@ -1261,7 +1462,22 @@ impl BaseClient {
                 let members = self.store.get_user_ids(room_id, filter).await?;
 
                 let settings = settings.ok_or(Error::EncryptionNotEnabled)?;
-                let settings = EncryptionSettings::new(settings, history_visibility, false);
+                let error_on_identity_violation =
+                    o.store().get_error_on_identity_violation().await?;
+                // The strategy of the room overrides the global one.
+                let sharing_strategy = match o
+                    .room_settings(room_id)
+                    .await?
+                    .and_then(|settings| settings.sharing_strategy)
+                {
+                    Some(strategy) => strategy,
+                    None => o.store().get_room_key_sharing_strategy().await?,
+                };
+                let settings = EncryptionSettings {
+                    error_on_identity_violation,
+                    sharing_strategy,
+                    ..EncryptionSettings::new(settings, history_visibility, false)
+                };
 
                 Ok(o.share_room_key(room_id, members.iter().map(Deref::deref), settings).await?)
             }
@@ -0,0 +1,323 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Trait and macro of integration tests for `EventCacheStore`
+//! implementations.
+
+use assert_matches2::assert_let;
+use async_trait::async_trait;
+use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+use matrix_sdk_test::sync_timeline_event;
+use ruma::{event_id, room_id, user_id, MilliSecondsSinceUnixEpoch, UInt};
+
+use super::{ChunkUpdate, DynEventCacheStore, EventCacheChunk, IndexedBody, StoredChunk};
+
+fn make_chunk(identifier: u64, previous: Option<u64>, content: EventCacheChunk) -> ChunkUpdate {
+    ChunkUpdate::Upsert(StoredChunk { identifier, previous, content })
+}
+
+fn make_body(body: &str, origin_server_ts: u32, is_edit: bool) -> IndexedBody {
+    IndexedBody {
+        body: body.to_owned(),
+        sender: user_id!("@alice:example.org").to_owned(),
+        origin_server_ts: MilliSecondsSinceUnixEpoch(UInt::from(origin_server_ts)),
+        edit_id: is_edit.then(|| format!("$edit{origin_server_ts}").try_into().unwrap()),
+    }
+}
+
+fn make_event(event_id: &str, body: &str) -> SyncTimelineEvent {
+    SyncTimelineEvent::new(sync_timeline_event!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": 152037280,
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+    }))
+}
+
+/// `EventCacheStore` integration tests.
+///
+/// This trait is not meant to be used directly, but will be used with the
+/// [`event_cache_store_integration_tests!`] macro.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait EventCacheStoreIntegrationTests {
+    /// Test saving and loading the chunks of a room.
+    async fn test_save_and_load_chunks(&self);
+    /// Test replacing and removing single chunks.
+    async fn test_update_and_remove_single_chunks(&self);
+    /// Test removing the chunks of a room.
+    async fn test_remove_chunks(&self);
+    /// Test indexing and searching messages.
+    async fn test_search_messages(&self);
+    /// Test updating and removing indexed messages.
+    async fn test_update_and_remove_indexed_messages(&self);
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl EventCacheStoreIntegrationTests for DynEventCacheStore {
+    async fn test_save_and_load_chunks(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+        let other_room_id = room_id!("!r1:matrix.org");
+
+        assert!(self.load_chunks(room_id).await.unwrap().is_empty());
+
+        let updates = vec![
+            make_chunk(0, None, EventCacheChunk::Gap { prev_token: "prev_token".to_owned() }),
+            make_chunk(
+                1,
+                Some(0),
+                EventCacheChunk::Events(vec![
+                    make_event("$ev0", "hello"),
+                    make_event("$ev1", "world"),
+                ]),
+            ),
+            make_chunk(2, Some(1), EventCacheChunk::Events(vec![make_event("$ev2", "!")])),
+        ];
+        self.update_chunks(room_id, updates).await.unwrap();
+
+        let mut chunks = self.load_chunks(room_id).await.unwrap();
+        chunks.sort_by_key(|chunk| chunk.identifier);
+        assert_eq!(chunks.len(), 3);
+
+        assert_eq!(chunks[0].previous, None);
+        assert_let!(EventCacheChunk::Gap { prev_token } = &chunks[0].content);
+        assert_eq!(prev_token, "prev_token");
+
+        assert_eq!(chunks[1].previous, Some(0));
+        assert_let!(EventCacheChunk::Events(events) = &chunks[1].content);
+        let event_ids = events.iter().map(|ev| ev.event_id().unwrap()).collect::<Vec<_>>();
+        assert_eq!(event_ids, ["$ev0", "$ev1"]);
+
+        assert_eq!(chunks[2].previous, Some(1));
+        assert_let!(EventCacheChunk::Events(events) = &chunks[2].content);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().unwrap(), "$ev2");
+
+        // Chunks are stored per room.
+        assert!(self.load_chunks(other_room_id).await.unwrap().is_empty());
+    }
+
+    async fn test_update_and_remove_single_chunks(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+
+        let updates = vec![
+            make_chunk(0, None, EventCacheChunk::Events(vec![make_event("$ev0", "hello")])),
+            make_chunk(1, Some(0), EventCacheChunk::Gap { prev_token: "prev_token".to_owned() }),
+            make_chunk(2, Some(1), EventCacheChunk::Events(vec![make_event("$ev1", "world")])),
+        ];
+        self.update_chunks(room_id, updates).await.unwrap();
+
+        // Replace the gap by events, and add events to the last chunk.
+        let updates = vec![
+            ChunkUpdate::Remove { identifier: 1 },
+            make_chunk(3, Some(0), EventCacheChunk::Events(vec![make_event("$ev2", "old")])),
+            make_chunk(
+                2,
+                Some(3),
+                EventCacheChunk::Events(vec![make_event("$ev1", "world"), make_event("$ev3", "!")]),
+            ),
+        ];
+        self.update_chunks(room_id, updates).await.unwrap();
+
+        let mut chunks = self.load_chunks(room_id).await.unwrap();
+        chunks.sort_by_key(|chunk| chunk.identifier);
+        let identifiers = chunks.iter().map(|chunk| chunk.identifier).collect::<Vec<_>>();
+        assert_eq!(identifiers, [0, 2, 3]);
+
+        // The untouched chunk is still there.
+        assert_let!(EventCacheChunk::Events(events) = &chunks[0].content);
+        assert_eq!(events[0].event_id().unwrap(), "$ev0");
+
+        assert_eq!(chunks[1].previous, Some(3));
+        assert_let!(EventCacheChunk::Events(events) = &chunks[1].content);
+        let event_ids = events.iter().map(|ev| ev.event_id().unwrap()).collect::<Vec<_>>();
+        assert_eq!(event_ids, ["$ev1", "$ev3"]);
+
+        assert_eq!(chunks[2].previous, Some(0));
+
+        // Removing a chunk that isn't stored is fine.
+        self.update_chunks(room_id, vec![ChunkUpdate::Remove { identifier: 42 }]).await.unwrap();
+        assert_eq!(self.load_chunks(room_id).await.unwrap().len(), 3);
+    }
+
+    async fn test_remove_chunks(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+        let other_room_id = room_id!("!r1:matrix.org");
+
+        let updates =
+            vec![make_chunk(0, None, EventCacheChunk::Events(vec![make_event("$ev0", "hello")]))];
+        self.update_chunks(room_id, updates.clone()).await.unwrap();
+        self.update_chunks(other_room_id, updates).await.unwrap();
+
+        self.remove_chunks(room_id).await.unwrap();
+
+        assert!(self.load_chunks(room_id).await.unwrap().is_empty());
+        assert_eq!(self.load_chunks(other_room_id).await.unwrap().len(), 1);
+    }
+
+    async fn test_search_messages(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+        let other_room_id = room_id!("!r1:matrix.org");
+
+        self.index_message(room_id, event_id!("$ev0"), make_body("Who wants pancakes?", 0, false))
+            .await
+            .unwrap();
+        self.index_message(
+            room_id,
+            event_id!("$ev1"),
+            make_body("I'd rather have waffles", 0, false),
+        )
+        .await
+        .unwrap();
+        self.index_message(
+            other_room_id,
+            event_id!("$ev2"),
+            make_body("Pancakes! Pancakes!", 0, false),
+        )
+        .await
+        .unwrap();
+
+        // Search in a single room, with a prefix of a word and any case.
+        let hits = self.search_messages(Some(room_id), "PANCAKE", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].room_id, room_id);
+        assert_eq!(hits[0].event_id, event_id!("$ev0"));
+        assert!(hits[0].snippet.contains("pancakes"));
+
+        // Search in all the rooms, the best match comes first.
+        let hits = self.search_messages(None, "pancakes", 10).await.unwrap();
+        let event_ids = hits.iter().map(|hit| hit.event_id.as_str()).collect::<Vec<_>>();
+        assert_eq!(event_ids, ["$ev2", "$ev0"]);
+
+        // The results are limited.
+        assert_eq!(self.search_messages(None, "pancakes", 1).await.unwrap().len(), 1);
+
+        // All the words of the query must match.
+        assert!(self.search_messages(None, "pancakes waffles", 10).await.unwrap().is_empty());
+        assert!(self.search_messages(None, "syrup", 10).await.unwrap().is_empty());
+
+        // Removing the chunks of a room doesn't clear its index.
+        self.remove_chunks(room_id).await.unwrap();
+        assert_eq!(self.search_messages(Some(room_id), "waffles", 10).await.unwrap().len(), 1);
+    }
+
+    async fn test_update_and_remove_indexed_messages(&self) {
+        let room_id = room_id!("!r0:matrix.org");
+
+        self.index_message(room_id, event_id!("$ev0"), make_body("Who wants pancakes?", 0, false))
+            .await
+            .unwrap();
+
+        // Indexing an edit of the message replaces its body.
+        self.index_message(room_id, event_id!("$ev0"), make_body("Who wants waffles?", 2, true))
+            .await
+            .unwrap();
+        assert!(self.search_messages(None, "pancakes", 10).await.unwrap().is_empty());
+        assert_eq!(self.search_messages(None, "waffles", 10).await.unwrap().len(), 1);
+
+        // An older edit doesn't replace the newest one.
+        self.index_message(room_id, event_id!("$ev0"), make_body("Who wants crepes?", 1, true))
+            .await
+            .unwrap();
+        assert!(self.search_messages(None, "crepes", 10).await.unwrap().is_empty());
+        assert_eq!(self.search_messages(None, "waffles", 10).await.unwrap().len(), 1);
+
+        // Redacting the indexed edit indexes the original body again.
+        self.redact_indexed_message(room_id, event_id!("$edit2")).await.unwrap();
+        assert!(self.search_messages(None, "waffles", 10).await.unwrap().is_empty());
+        assert_eq!(self.search_messages(None, "pancakes", 10).await.unwrap().len(), 1);
+
+        // Redacting the message removes it from the results.
+        self.redact_indexed_message(room_id, event_id!("$ev0")).await.unwrap();
+        assert!(self.search_messages(None, "pancakes", 10).await.unwrap().is_empty());
+
+        // Redacting an event that isn't indexed is fine.
+        self.redact_indexed_message(room_id, event_id!("$ev1")).await.unwrap();
+    }
+}
+
+/// Macro building to allow your `EventCacheStore` implementation to run the
+/// entire tests suite locally.
+///
+/// You need to provide a `async fn get_event_cache_store() ->
+/// EventCacheStoreResult<impl EventCacheStore>` providing a fresh event cache
+/// store on the same level you invoke the macro.
+///
+/// ## Usage Example:
+/// ```no_run
+/// # use matrix_sdk_base::event_cache_store::{
+/// #    EventCacheStore,
+/// #    MemoryStore as MyStore,
+/// #    Result as EventCacheStoreResult,
+/// # };
+///
+/// #[cfg(test)]
+/// mod tests {
+///     use super::{EventCacheStore, EventCacheStoreResult, MyStore};
+///
+///     async fn get_event_cache_store(
+///     ) -> EventCacheStoreResult<impl EventCacheStore> {
+///         Ok(MyStore::new())
+///     }
+///
+///     event_cache_store_integration_tests!();
+/// }
+/// ```
+#[allow(unused_macros, unused_extern_crates)]
+#[macro_export]
+macro_rules! event_cache_store_integration_tests {
+    () => {
+        mod event_cache_store_integration_tests {
+            use matrix_sdk_test::async_test;
+            use $crate::event_cache_store::{EventCacheStoreIntegrationTests, IntoEventCacheStore};
+
+            use super::get_event_cache_store;
+
+            #[async_test]
+            async fn test_save_and_load_chunks() {
+                let store = get_event_cache_store().await.unwrap().into_event_cache_store();
+                store.test_save_and_load_chunks().await;
+            }
+
+            #[async_test]
+            async fn test_update_and_remove_single_chunks() {
+                let store = get_event_cache_store().await.unwrap().into_event_cache_store();
+                store.test_update_and_remove_single_chunks().await;
+            }
+
+            #[async_test]
+            async fn test_remove_chunks() {
+                let store = get_event_cache_store().await.unwrap().into_event_cache_store();
+                store.test_remove_chunks().await;
+            }
+
+            #[async_test]
+            async fn test_search_messages() {
+                let store = get_event_cache_store().await.unwrap().into_event_cache_store();
+                store.test_search_messages().await;
+            }
+
+            #[async_test]
+            async fn test_update_and_remove_indexed_messages() {
+                let store = get_event_cache_store().await.unwrap().into_event_cache_store();
+                store.test_update_and_remove_indexed_messages().await;
+            }
+        }
+    };
+}
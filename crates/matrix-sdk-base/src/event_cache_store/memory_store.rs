@@ -0,0 +1,161 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock as StdRwLock,
+};
+
+use async_trait::async_trait;
+use ruma::{EventId, OwnedEventId, OwnedRoomId, RoomId};
+
+use super::{
+    ChunkUpdate, EventCacheStore, EventCacheStoreError, IndexedBody, IndexedMessage, Result,
+    SearchHit, StoredChunk,
+};
+
+/// In-memory, non-persistent implementation of the `EventCacheStore`.
+///
+/// Default if no other is configured at startup.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    chunks: StdRwLock<HashMap<OwnedRoomId, BTreeMap<u64, StoredChunk>>>,
+    search_index: StdRwLock<BTreeMap<(OwnedRoomId, OwnedEventId), IndexedMessage>>,
+}
+
+impl MemoryStore {
+    /// Create a new empty `MemoryStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl EventCacheStore for MemoryStore {
+    type Error = EventCacheStoreError;
+
+    async fn update_chunks(&self, room_id: &RoomId, updates: Vec<ChunkUpdate>) -> Result<()> {
+        let mut chunks = self.chunks.write().unwrap();
+        let room_chunks = chunks.entry(room_id.to_owned()).or_default();
+
+        for update in updates {
+            match update {
+                ChunkUpdate::Upsert(chunk) => {
+                    room_chunks.insert(chunk.identifier, chunk);
+                }
+                ChunkUpdate::Remove { identifier } => {
+                    room_chunks.remove(&identifier);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn load_chunks(&self, room_id: &RoomId) -> Result<Vec<StoredChunk>> {
+        Ok(self
+            .chunks
+            .read()
+            .unwrap()
+            .get(room_id)
+            .map(|chunks| chunks.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn remove_chunks(&self, room_id: &RoomId) -> Result<()> {
+        self.chunks.write().unwrap().remove(room_id);
+        Ok(())
+    }
+
+    async fn index_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        body: IndexedBody,
+    ) -> Result<()> {
+        let mut search_index = self.search_index.write().unwrap();
+        let key = (room_id.to_owned(), event_id.to_owned());
+
+        if let Some(message) = IndexedMessage::with_body(
+            search_index.get(&key).cloned(),
+            room_id.to_owned(),
+            event_id.to_owned(),
+            body,
+        ) {
+            search_index.insert(key, message);
+        }
+
+        Ok(())
+    }
+
+    async fn redact_indexed_message(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+        let mut search_index = self.search_index.write().unwrap();
+
+        // The redacted event is either an indexed message, or the indexed edit of one.
+        let Some((key, message)) = search_index
+            .iter()
+            .find(|((indexed_room_id, indexed_event_id), message)| {
+                indexed_room_id == room_id
+                    && (indexed_event_id == event_id
+                        || message.body.edit_id.as_deref() == Some(event_id))
+            })
+            .map(|(key, message)| (key.clone(), message.clone()))
+        else {
+            return Ok(());
+        };
+
+        match message.without_redacted(event_id) {
+            Some(message) => search_index.insert(key, message),
+            None => search_index.remove(&key),
+        };
+
+        Ok(())
+    }
+
+    async fn search_messages(
+        &self,
+        room_id: Option<&RoomId>,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let mut hits = self
+            .search_index
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|((indexed_room_id, _), _)| room_id.map_or(true, |r| r == indexed_room_id))
+            .filter_map(|((room_id, event_id), message)| {
+                SearchHit::from_match(room_id.clone(), event_id.clone(), &message.body.body, query)
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank));
+        hits.truncate(limit);
+
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryStore, Result};
+    use crate::event_cache_store_integration_tests;
+
+    async fn get_event_cache_store() -> Result<MemoryStore> {
+        Ok(MemoryStore::new())
+    }
+
+    event_cache_store_integration_tests!();
+}
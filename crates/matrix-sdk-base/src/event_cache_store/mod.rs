@@ -0,0 +1,146 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The event cache stores holds the events of the rooms, as organized by the
+//! event cache of the SDK, so they survive restarts of the client.
+//!
+//! The events of a room are stored as a list of chunks: either a list of
+//! events, or a gap, i.e. a hole in the timeline that can be filled by
+//! back-paginating with the token of the gap. Each chunk is linked to the
+//! previous one, so that only the chunks that changed need to be saved.
+//!
+//! The event cache stores also hold an optional full-text search index of the
+//! messages, so that messages can be searched locally, including in encrypted
+//! rooms. See [`SearchHit`].
+//!
+//! Implementing the [`EventCacheStore`] trait, you can plug any storage backend
+//! into the event cache for the actual storage. By default this brings an
+//! in-memory store.
+
+use std::{result::Result as StdResult, str::Utf8Error};
+
+use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+use serde::{Deserialize, Serialize};
+
+pub use crate::store::StoreEncryptionError;
+
+#[cfg(any(test, feature = "testing"))]
+#[macro_use]
+pub mod integration_tests;
+mod memory_store;
+mod search;
+mod traits;
+
+#[cfg(any(test, feature = "testing"))]
+pub use self::integration_tests::EventCacheStoreIntegrationTests;
+pub use self::{
+    memory_store::MemoryStore,
+    search::{IndexedBody, IndexedMessage, SearchHit},
+    traits::{DynEventCacheStore, EventCacheStore, IntoEventCacheStore},
+};
+
+/// The content of a chunk of the events of a room, as persisted in an
+/// [`EventCacheStore`].
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum EventCacheChunk {
+    /// A list of consecutive events, the oldest one first.
+    Events(Vec<SyncTimelineEvent>),
+
+    /// A hole in the timeline, which can be filled by back-paginating.
+    Gap {
+        /// The token to use to back-paginate from this gap.
+        prev_token: String,
+    },
+}
+
+/// A chunk stored in an [`EventCacheStore`], linked to the previous chunk of
+/// its room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct StoredChunk {
+    /// The identifier of the chunk, unique in its room.
+    pub identifier: u64,
+
+    /// The identifier of the previous chunk, or `None` if this is the first
+    /// chunk of the room.
+    pub previous: Option<u64>,
+
+    /// The content of the chunk.
+    pub content: EventCacheChunk,
+}
+
+/// A change to the chunks stored for a room.
+#[derive(Clone, Debug)]
+pub enum ChunkUpdate {
+    /// Store the chunk, replacing the stored chunk with the same identifier, if
+    /// any.
+    Upsert(StoredChunk),
+
+    /// Remove the chunk with the given identifier.
+    Remove {
+        /// The identifier of the chunk to remove.
+        identifier: u64,
+    },
+}
+
+/// Event cache store specific error type.
+#[derive(Debug, thiserror::Error)]
+pub enum EventCacheStoreError {
+    /// An error happened in the underlying database backend.
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+
+    /// An error happened while serializing or deserializing some data.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// The store is locked with a passphrase and an incorrect passphrase was
+    /// given.
+    #[error("The event cache store failed to be unlocked")]
+    Locked,
+
+    /// An unencrypted store was tried to be unlocked with a passphrase.
+    #[error("The event cache store is not encrypted but tried to be opened with a passphrase")]
+    Unencrypted,
+
+    /// The store failed to encrypt or decrypt some data.
+    #[error("Error encrypting or decrypting data from the event cache store: {0}")]
+    Encryption(#[from] StoreEncryptionError),
+
+    /// The store failed to encode or decode some data.
+    #[error("Error encoding or decoding data from the event cache store: {0}")]
+    Codec(#[from] Utf8Error),
+
+    /// The database format has changed in a backwards incompatible way.
+    #[error(
+        "The database format of the event cache store changed in an incompatible way, \
+         current version: {0}, latest version: {1}"
+    )]
+    UnsupportedDatabaseVersion(usize, usize),
+}
+
+impl EventCacheStoreError {
+    /// Create a new [`Backend`][Self::Backend] error.
+    ///
+    /// Shorthand for `EventCacheStoreError::Backend(Box::new(error))`.
+    #[inline]
+    pub fn backend<E>(error: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self::Backend(Box::new(error))
+    }
+}
+
+/// An `EventCacheStore` specific result type.
+pub type Result<T, E = EventCacheStoreError> = StdResult<T, E>;
@@ -0,0 +1,325 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ruma::{EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedRoomId, OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+
+/// The number of words in the snippet of a [`SearchHit`].
+const SNIPPET_WORDS: usize = 10;
+
+/// A message matching a search query in the local search index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchHit {
+    /// The room the message belongs to.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the message.
+    pub event_id: OwnedEventId,
+
+    /// How well the message matches the query; the higher, the better.
+    ///
+    /// Ranks are only meaningful when compared to the ranks of the other hits
+    /// of the same search.
+    pub rank: f64,
+
+    /// An excerpt of the body of the message, around the first match.
+    pub snippet: String,
+}
+
+impl SearchHit {
+    /// Match the body of a message against a search query, for stores that
+    /// can't search natively.
+    ///
+    /// A message matches if each word of the query is a prefix of a word of
+    /// its body, ignoring case. Returns `None` if the message doesn't match.
+    pub fn from_match(
+        room_id: OwnedRoomId,
+        event_id: OwnedEventId,
+        body: &str,
+        query: &str,
+    ) -> Option<Self> {
+        let terms = tokenize(query).collect::<Vec<_>>();
+        if terms.is_empty() {
+            return None;
+        }
+
+        let words = body.split_whitespace().collect::<Vec<_>>();
+        let word_matches =
+            |word: &str| tokenize(word).any(|token| terms.iter().any(|t| token.starts_with(t)));
+
+        // Every term must match at least once.
+        let all_terms_match =
+            terms.iter().all(|term| tokenize(body).any(|token| token.starts_with(term.as_str())));
+        if !all_terms_match {
+            return None;
+        }
+
+        let num_matches = words.iter().filter(|word| word_matches(word)).count();
+        let first_match = words.iter().position(|word| word_matches(word)).unwrap_or(0);
+
+        Some(Self {
+            room_id,
+            event_id,
+            rank: num_matches as f64 / (words.len() as f64).sqrt(),
+            snippet: snippet(&words, first_match),
+        })
+    }
+}
+
+/// A version of the body of a message, to be added to the search index.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexedBody {
+    /// The text to index.
+    pub body: String,
+
+    /// The sender of the event this body comes from.
+    pub sender: OwnedUserId,
+
+    /// The timestamp of the event this body comes from.
+    pub origin_server_ts: MilliSecondsSinceUnixEpoch,
+
+    /// The ID of the edit this body comes from, or `None` if it comes from
+    /// the message itself.
+    pub edit_id: Option<OwnedEventId>,
+}
+
+impl IndexedBody {
+    /// Whether this body comes from an edit of the message, rather than from
+    /// the message itself.
+    pub fn is_edit(&self) -> bool {
+        self.edit_id.is_some()
+    }
+}
+
+/// A message of the search index, as persisted by an
+/// [`EventCacheStore`](super::EventCacheStore).
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IndexedMessage {
+    /// The room the message belongs to.
+    pub room_id: OwnedRoomId,
+
+    /// The ID of the message.
+    pub event_id: OwnedEventId,
+
+    /// The body of the message itself, once it's been indexed, so that it can
+    /// be indexed again if the indexed edit is redacted.
+    pub original_body: Option<IndexedBody>,
+
+    /// The body that's currently indexed for the message.
+    pub body: IndexedBody,
+}
+
+impl IndexedMessage {
+    /// Compute the message to index after receiving a new version of its body,
+    /// given the message that's currently indexed, if any.
+    ///
+    /// Edits are only accepted if they have the same sender as the message,
+    /// and the newest edit wins, whatever the order they're received in.
+    /// Returns `None` if the indexed message doesn't change.
+    pub fn with_body(
+        indexed: Option<Self>,
+        room_id: OwnedRoomId,
+        event_id: OwnedEventId,
+        body: IndexedBody,
+    ) -> Option<Self> {
+        let Some(mut indexed) = indexed else {
+            let original_body = (!body.is_edit()).then(|| body.clone());
+            return Some(Self { room_id, event_id, original_body, body });
+        };
+
+        if body.is_edit() {
+            if indexed.sender().is_some_and(|sender| *sender != body.sender) {
+                // Only the sender of a message can edit it.
+                return None;
+            }
+
+            if indexed.body.is_edit() && indexed.body.origin_server_ts > body.origin_server_ts {
+                // A newer edit has been indexed already.
+                return None;
+            }
+
+            indexed.body = body;
+        } else {
+            // An edit is always newer than the message itself, so keep it, unless it
+            // turns out to come from someone else.
+            if !indexed.body.is_edit() || indexed.body.sender != body.sender {
+                indexed.body = body.clone();
+            }
+
+            indexed.original_body = Some(body);
+        }
+
+        Some(indexed)
+    }
+
+    /// Compute the message to index after the given event has been redacted,
+    /// given that it's either this message or its indexed edit.
+    ///
+    /// If the indexed edit is redacted, the body of the message itself is
+    /// indexed again. Returns `None` if the message must be removed from the
+    /// index, because it's been redacted, or because only its redacted edit
+    /// was known.
+    pub fn without_redacted(mut self, redacted_event_id: &EventId) -> Option<Self> {
+        if self.body.edit_id.as_deref() != Some(redacted_event_id) {
+            return None;
+        }
+
+        self.body = self.original_body.clone()?;
+        Some(self)
+    }
+
+    /// The sender of the message, once the message itself has been indexed.
+    pub fn sender(&self) -> Option<&UserId> {
+        self.original_body.as_ref().map(|body| &*body.sender)
+    }
+}
+
+/// Split some text in lowercase alphanumeric tokens.
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Build an excerpt of at most [`SNIPPET_WORDS`] words, around the word at the
+/// given index.
+fn snippet(words: &[&str], around: usize) -> String {
+    let start = around.saturating_sub(SNIPPET_WORDS / 3);
+    let end = (start + SNIPPET_WORDS).min(words.len());
+    let start = end.saturating_sub(SNIPPET_WORDS);
+
+    let mut snippet = words[start..end].join(" ");
+
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < words.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{
+        event_id, owned_event_id, owned_room_id, user_id, MilliSecondsSinceUnixEpoch, UInt,
+    };
+
+    use super::{IndexedBody, IndexedMessage, SearchHit};
+
+    fn hit(body: &str, query: &str) -> Option<SearchHit> {
+        SearchHit::from_match(owned_room_id!("!r:b.c"), owned_event_id!("$e"), body, query)
+    }
+
+    #[test]
+    fn test_matching() {
+        assert!(hit("Hello, World!", "hello").is_some());
+        assert!(hit("Hello, World!", "WOR").is_some());
+        assert!(hit("Hello, World!", "hello world").is_some());
+        assert!(hit("Hello, World!", "hello there").is_none());
+        assert!(hit("Hello, World!", "").is_none());
+        assert!(hit("Hello, World!", "orld").is_none());
+    }
+
+    #[test]
+    fn test_ranking() {
+        let short = hit("pancakes", "pancakes").unwrap();
+        let long = hit("I'd like to eat some pancakes tonight", "pancakes").unwrap();
+        let repeated = hit("pancakes, pancakes and more pancakes", "pancakes").unwrap();
+
+        assert!(short.rank > long.rank);
+        assert!(repeated.rank > long.rank);
+    }
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(hit("Hello, World!", "world").unwrap().snippet, "Hello, World!");
+
+        let body = "one two three four five six seven eight nine ten eleven twelve thirteen";
+        assert_eq!(
+            hit(body, "eight").unwrap().snippet,
+            "…four five six seven eight nine ten eleven twelve thirteen"
+        );
+        assert_eq!(
+            hit(body, "one").unwrap().snippet,
+            "one two three four five six seven eight nine ten…"
+        );
+        assert_eq!(
+            hit(body, "six").unwrap().snippet,
+            "…three four five six seven eight nine ten eleven twelve…"
+        );
+    }
+
+    fn body(text: &str, sender: &str, ts: u32, is_edit: bool) -> IndexedBody {
+        IndexedBody {
+            body: text.to_owned(),
+            sender: sender.try_into().unwrap(),
+            origin_server_ts: MilliSecondsSinceUnixEpoch(UInt::from(ts)),
+            edit_id: is_edit.then(|| format!("$edit{ts}").try_into().unwrap()),
+        }
+    }
+
+    fn index(indexed: Option<IndexedMessage>, body: IndexedBody) -> Option<IndexedMessage> {
+        IndexedMessage::with_body(indexed, owned_room_id!("!r:b.c"), owned_event_id!("$e"), body)
+    }
+
+    #[test]
+    fn test_edits_of_other_senders_are_ignored() {
+        let message = index(None, body("Hello", "@alice:b.c", 1, false)).unwrap();
+        assert_eq!(message.sender(), Some(user_id!("@alice:b.c")));
+
+        assert!(index(Some(message.clone()), body("Hacked", "@mallory:b.c", 2, true)).is_none());
+
+        let edited = index(Some(message), body("Hello!", "@alice:b.c", 2, true)).unwrap();
+        assert_eq!(edited.body.body, "Hello!");
+
+        // An edit received before the message is dropped once the message shows it
+        // comes from someone else.
+        let edit = index(None, body("Hacked", "@mallory:b.c", 2, true)).unwrap();
+        assert_eq!(edit.sender(), None);
+        let message = index(Some(edit), body("Hello", "@alice:b.c", 1, false)).unwrap();
+        assert_eq!(message.body.body, "Hello");
+        assert_eq!(message.sender(), Some(user_id!("@alice:b.c")));
+    }
+
+    #[test]
+    fn test_newest_edit_wins() {
+        // The edits are received before the message, in the wrong order.
+        let message = index(None, body("Third", "@alice:b.c", 3, true)).unwrap();
+        assert!(index(Some(message.clone()), body("Second", "@alice:b.c", 2, true)).is_none());
+
+        // The message itself doesn't replace the edit.
+        let message = index(Some(message), body("First", "@alice:b.c", 1, false)).unwrap();
+        assert_eq!(message.body.body, "Third");
+        assert_eq!(message.sender(), Some(user_id!("@alice:b.c")));
+    }
+
+    #[test]
+    fn test_redacted_edit_restores_original_body() {
+        let message = index(None, body("Hello", "@alice:b.c", 1, false)).unwrap();
+        let edited = index(Some(message), body("Hello!", "@alice:b.c", 2, true)).unwrap();
+
+        let restored = edited.clone().without_redacted(event_id!("$edit2")).unwrap();
+        assert_eq!(restored.body.body, "Hello");
+        assert_eq!(restored.body.edit_id, None);
+
+        // Redacting the message itself removes it.
+        assert!(edited.without_redacted(event_id!("$e")).is_none());
+
+        // There's nothing to index if only the edit was known.
+        let edit = index(None, body("Hello!", "@alice:b.c", 2, true)).unwrap();
+        assert!(edit.without_redacted(event_id!("$edit2")).is_none());
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{fmt, sync::Arc};
+
+use async_trait::async_trait;
+use matrix_sdk_common::AsyncTraitDeps;
+use ruma::{EventId, RoomId};
+
+use super::{
+    ChunkUpdate, EventCacheStoreError, IndexedBody, IndexedMessage, SearchHit, StoredChunk,
+};
+
+/// An abstract trait that can be used to implement different stores for the
+/// event cache of the SDK.
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+pub trait EventCacheStore: AsyncTraitDeps {
+    /// The error type used by this event cache store.
+    type Error: fmt::Debug + Into<EventCacheStoreError>;
+
+    /// Apply changes to the chunks stored for a room.
+    ///
+    /// The changes should be applied atomically, if the backend allows it.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the chunks belong to.
+    ///
+    /// * `updates` - The changes to apply, in order.
+    async fn update_chunks(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<(), Self::Error>;
+
+    /// Get all the chunks stored for a room, in no particular order.
+    ///
+    /// The order of the chunks is given by their [`StoredChunk::previous`]
+    /// links. Returns an empty list if nothing is stored for this room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to get the chunks of.
+    async fn load_chunks(&self, room_id: &RoomId) -> Result<Vec<StoredChunk>, Self::Error>;
+
+    /// Remove all the chunks stored for a room.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room to remove the chunks of.
+    async fn remove_chunks(&self, room_id: &RoomId) -> Result<(), Self::Error>;
+
+    /// Add a version of the body of a message to the search index.
+    ///
+    /// If the message was indexed already, the new body is merged with the
+    /// indexed one with [`IndexedMessage::with_body`], so that only the newest
+    /// valid edit is indexed.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the message belongs to.
+    ///
+    /// * `event_id` - The `EventId` of the message.
+    ///
+    /// * `body` - The body to index for this message.
+    async fn index_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        body: IndexedBody,
+    ) -> Result<(), Self::Error>;
+
+    /// Update the search index after an event has been redacted.
+    ///
+    /// If the event is an indexed message, it's removed from the index. If
+    /// it's the indexed edit of a message, the message is indexed again with
+    /// its original body, see [`IndexedMessage::without_redacted`]. Does
+    /// nothing otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the event belongs to.
+    ///
+    /// * `event_id` - The `EventId` of the redacted event.
+    async fn redact_indexed_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<(), Self::Error>;
+
+    /// Search the indexed messages matching the given query, the best matches
+    /// first.
+    ///
+    /// Each word of the query must match the beginning of a word of a message,
+    /// ignoring case, for the message to be returned.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The room to search in, or `None` to search in all the
+    ///   rooms.
+    ///
+    /// * `query` - The words to search for.
+    ///
+    /// * `limit` - The maximum number of results to return.
+    async fn search_messages(
+        &self,
+        room_id: Option<&RoomId>,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, Self::Error>;
+}
+
+#[repr(transparent)]
+struct EraseEventCacheStoreError<T>(T);
+
+#[cfg(not(tarpaulin_include))]
+impl<T: fmt::Debug> fmt::Debug for EraseEventCacheStoreError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+impl<T: EventCacheStore> EventCacheStore for EraseEventCacheStoreError<T> {
+    type Error = EventCacheStoreError;
+
+    async fn update_chunks(
+        &self,
+        room_id: &RoomId,
+        updates: Vec<ChunkUpdate>,
+    ) -> Result<(), Self::Error> {
+        self.0.update_chunks(room_id, updates).await.map_err(Into::into)
+    }
+
+    async fn load_chunks(&self, room_id: &RoomId) -> Result<Vec<StoredChunk>, Self::Error> {
+        self.0.load_chunks(room_id).await.map_err(Into::into)
+    }
+
+    async fn remove_chunks(&self, room_id: &RoomId) -> Result<(), Self::Error> {
+        self.0.remove_chunks(room_id).await.map_err(Into::into)
+    }
+
+    async fn index_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        body: IndexedBody,
+    ) -> Result<(), Self::Error> {
+        self.0.index_message(room_id, event_id, body).await.map_err(Into::into)
+    }
+
+    async fn redact_indexed_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+    ) -> Result<(), Self::Error> {
+        self.0.redact_indexed_message(room_id, event_id).await.map_err(Into::into)
+    }
+
+    async fn search_messages(
+        &self,
+        room_id: Option<&RoomId>,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>, Self::Error> {
+        self.0.search_messages(room_id, query, limit).await.map_err(Into::into)
+    }
+}
+
+/// A type-erased [`EventCacheStore`].
+pub type DynEventCacheStore = dyn EventCacheStore<Error = EventCacheStoreError>;
+
+/// A type that can be type-erased into `Arc<dyn EventCacheStore>`.
+///
+/// This trait is not meant to be implemented directly outside
+/// `matrix-sdk-base`, but it is automatically implemented for everything that
+/// implements `EventCacheStore`.
+pub trait IntoEventCacheStore {
+    #[doc(hidden)]
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore>;
+}
+
+impl<T> IntoEventCacheStore for T
+where
+    T: EventCacheStore + Sized + 'static,
+{
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore> {
+        Arc::new(EraseEventCacheStoreError(self))
+    }
+}
+
+// Turns a given `Arc<T>` into `Arc<DynEventCacheStore>` by attaching the
+// EventCacheStore impl vtable of `EraseEventCacheStoreError<T>`.
+impl<T> IntoEventCacheStore for Arc<T>
+where
+    T: EventCacheStore + 'static,
+{
+    fn into_event_cache_store(self) -> Arc<DynEventCacheStore> {
+        let ptr: *const T = Arc::into_raw(self);
+        let ptr_erased = ptr as *const EraseEventCacheStoreError<T>;
+        // SAFETY: EraseEventCacheStoreError is repr(transparent) so T and
+        //         EraseEventCacheStoreError<T> have the same layout and ABI
+        unsafe { Arc::from_raw(ptr_erased) }
+    }
+}
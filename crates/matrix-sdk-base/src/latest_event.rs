@@ -3,17 +3,29 @@
 
 #![cfg(feature = "experimental-sliding-sync")]
 
-use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
 #[cfg(feature = "e2e-encryption")]
-use ruma::events::{
-    poll::unstable_start::SyncUnstablePollStartEvent, room::message::SyncRoomMessageEvent,
-    AnySyncMessageLikeEvent, AnySyncTimelineEvent,
-};
+use std::collections::BTreeMap;
+
+use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
 use ruma::{
     events::{call::invite::SyncCallInviteEvent, relation::RelationType},
     MxcUri, OwnedEventId,
 };
+#[cfg(feature = "e2e-encryption")]
+use ruma::{
+    events::{
+        poll::unstable_start::SyncUnstablePollStartEvent,
+        room::message::{Relation, RoomMessageEventContentWithoutRelation, SyncRoomMessageEvent},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    serde::Raw,
+    EventId, OwnedUserId,
+};
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "e2e-encryption")]
+use serde_json::{Map, Value as JsonValue};
+#[cfg(feature = "e2e-encryption")]
+use tracing::warn;
 
 use crate::MinimalRoomMemberEvent;
 
@@ -95,6 +107,37 @@ pub fn is_suitable_for_latest_event(event: &AnySyncTimelineEvent) -> PossibleLat
     }
 }
 
+/// If the given event is an edit of a message, get the ID of the edited event
+/// and the new content of the message.
+///
+/// Edits aren't suitable as latest events themselves, but they must be applied
+/// to the latest event if it's the edited event and if they were sent by the
+/// sender of the edited event, with [`LatestEvent::apply_edit`].
+#[cfg(feature = "e2e-encryption")]
+pub fn as_latest_event_edit(
+    event: &AnySyncTimelineEvent,
+) -> Option<(&EventId, &RoomMessageEventContentWithoutRelation)> {
+    let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+        SyncRoomMessageEvent::Original(message),
+    )) = event
+    else {
+        return None;
+    };
+
+    match &message.content.relates_to {
+        Some(Relation::Replacement(replacement)) => {
+            Some((&replacement.event_id, &replacement.new_content))
+        }
+        _ => None,
+    }
+}
+
+/// The edits found while looking for the latest event of a room, by ID of the
+/// edited event and sender of the edit.
+#[cfg(feature = "e2e-encryption")]
+pub(crate) type LatestEventEdits =
+    BTreeMap<(OwnedEventId, OwnedUserId), RoomMessageEventContentWithoutRelation>;
+
 /// Represent all information required to represent a latest event in an
 /// efficient way.
 ///
@@ -216,6 +259,84 @@ impl LatestEvent {
         self.event.event_id()
     }
 
+    /// Replace the content of the event with the new content of an edit of
+    /// it, so the latest event shows the edited message.
+    ///
+    /// The relation of the original event, e.g. to the event it replies to, is
+    /// kept. If the event has been redacted, the edit is ignored.
+    #[cfg(feature = "e2e-encryption")]
+    pub fn apply_edit(&mut self, new_content: &RoomMessageEventContentWithoutRelation) {
+        if self.is_redacted() {
+            return;
+        }
+
+        let mut event = match self.event.event.deserialize_as::<Map<String, JsonValue>>() {
+            Ok(event) => event,
+            Err(error) => {
+                warn!("Failed to deserialize the latest event to apply an edit: {error}");
+                return;
+            }
+        };
+
+        let mut content = match serde_json::to_value(new_content) {
+            Ok(JsonValue::Object(content)) => content,
+            _ => {
+                warn!("Failed to serialize the new content of the latest event");
+                return;
+            }
+        };
+
+        if let Some(relates_to) =
+            event.get("content").and_then(|content| content.get("m.relates_to")).cloned()
+        {
+            content.insert("m.relates_to".to_owned(), relates_to);
+        }
+
+        event.insert("content".to_owned(), content.into());
+
+        match Raw::new(&event) {
+            Ok(raw) => self.event.event = raw.cast(),
+            Err(error) => warn!("Failed to serialize the edited latest event: {error}"),
+        }
+    }
+
+    /// Apply the edit of the event found in `edits`, if any, with
+    /// [`Self::apply_edit`].
+    ///
+    /// Only an edit sent by the sender of the event is applied. Returns whether
+    /// an edit was found.
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) fn apply_edit_from(&mut self, edits: &LatestEventEdits) -> bool {
+        let (Some(event_id), Some(sender)) = (self.event_id(), self.sender()) else {
+            return false;
+        };
+
+        match edits.get(&(event_id, sender)) {
+            Some(new_content) => {
+                self.apply_edit(new_content);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the sender of the event.
+    #[cfg(feature = "e2e-encryption")]
+    fn sender(&self) -> Option<OwnedUserId> {
+        self.event.event.get_field("sender").ok().flatten()
+    }
+
+    /// Whether the event has been redacted.
+    #[cfg(feature = "e2e-encryption")]
+    fn is_redacted(&self) -> bool {
+        self.event
+            .event
+            .get_field::<Map<String, JsonValue>>("unsigned")
+            .ok()
+            .flatten()
+            .is_some_and(|unsigned| unsigned.contains_key("redacted_because"))
+    }
+
     /// Check whether [`Self`] has a sender profile.
     pub fn has_sender_profile(&self) -> bool {
         self.sender_profile.is_some()
@@ -471,6 +592,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_apply_edit_keeps_the_relation_of_the_original_event() {
+        let mut latest_event = LatestEvent::new(SyncTimelineEvent::new(
+            Raw::from_json_string(
+                json!({
+                    "type": "m.room.message",
+                    "event_id": "$1",
+                    "content": {
+                        "msgtype": "m.text",
+                        "body": "Hello, world!",
+                        "m.relates_to": {
+                            "m.in_reply_to": { "event_id": "$0" },
+                        },
+                    },
+                    "sender": "@a:b.c",
+                    "origin_server_ts": 2123,
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        ));
+
+        latest_event.apply_edit(&RoomMessageEventContent::text_plain("Bye bye, world!").into());
+
+        let event = latest_event.event().event.deserialize().unwrap();
+        assert_let!(
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+                SyncRoomMessageEvent::Original(message)
+            )) = event
+        );
+        assert_eq!(message.event_id, "$1");
+        assert_eq!(message.content.body(), "Bye bye, world!");
+        assert_matches!(message.content.relates_to, Some(Relation::Reply { in_reply_to }) => {
+            assert_eq!(in_reply_to.event_id, "$0");
+        });
+    }
+
     #[test]
     fn test_deserialize_latest_event() {
         #[derive(Debug, serde::Serialize, serde::Deserialize)]
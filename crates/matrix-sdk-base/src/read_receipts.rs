@@ -101,6 +101,19 @@
 //! case, we can just consider that all the events are new, and count them as
 //! such.
 //!
+//! ### Threads
+//!
+//! The same computation is done for each thread of the room, with the threaded
+//! receipts of that thread, and the unthreaded receipts, which apply to all the
+//! threads. Only the events of the thread are counted, and the results are
+//! stored by thread root in `RoomReadReceipts::threads`. The room-wide counts
+//! still include the events of all the threads.
+//!
+//! Since these results are persisted, only the threads with unread events or
+//! pending receipts are kept, and at most [`MAX_THREADS`] of them: those whose
+//! latest event is the most recent. A thread that has been dropped is counted
+//! from scratch when a new event is received in it.
+//!
 //! ### Edge cases
 //!
 //! - `compute_unread_counts` is called after receiving a sliding sync response,
@@ -118,6 +131,7 @@
 #![allow(dead_code)] // too many different build configurations, I give up
 
 use std::{
+    borrow::Cow,
     collections::{BTreeMap, BTreeSet},
     num::NonZeroUsize,
 };
@@ -128,16 +142,21 @@ use ruma::{
     events::{
         poll::{start::PollStartEventContent, unstable_start::UnstablePollStartEventContent},
         receipt::{ReceiptEventContent, ReceiptThread, ReceiptType},
+        relation::RelationType,
         room::message::Relation,
         AnySyncMessageLikeEvent, AnySyncTimelineEvent, OriginalSyncMessageLikeEvent,
         SyncMessageLikeEvent,
     },
     serde::Raw,
-    EventId, OwnedEventId, OwnedUserId, RoomId, UserId,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, RoomId, UserId,
 };
 use serde::{Deserialize, Serialize};
 use tracing::{debug, instrument, trace, warn};
 
+/// The maximum number of threads for which read receipts data is kept in
+/// [`RoomReadReceipts::threads`].
+pub const MAX_THREADS: usize = 32;
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 struct LatestReadReceipt {
     /// The id of the event the read receipt is referring to. (Not the read
@@ -176,6 +195,14 @@ pub struct RoomReadReceipts {
     /// not the event ids of the receipt events themselves.
     #[serde(default = "new_nonempty_ring_buffer")]
     pending: RingBuffer<OwnedEventId>,
+
+    /// The read receipts data of the threads of the room, by ID of their root
+    /// event.
+    ///
+    /// Only contains the threads with unread events or pending receipts, up to
+    /// [`MAX_THREADS`].
+    #[serde(default)]
+    pub threads: BTreeMap<OwnedEventId, ThreadReadReceipts>,
 }
 
 impl Default for RoomReadReceipts {
@@ -186,6 +213,72 @@ impl Default for RoomReadReceipts {
             num_mentions: Default::default(),
             latest_active: Default::default(),
             pending: new_nonempty_ring_buffer(),
+            threads: Default::default(),
+        }
+    }
+}
+
+/// The read state of a room for the current user.
+///
+/// It merges the public and private read receipts, and the fully-read marker,
+/// of all the devices of the user into a single "latest read" position, so it
+/// changes when the room is read on another device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomReadState {
+    /// The ID of the latest event the user has read, if known.
+    pub latest_read_event_id: Option<OwnedEventId>,
+
+    /// The number of unread messages after the latest read event.
+    pub num_unread: u64,
+
+    /// The number of unread events that should notify after the latest read
+    /// event.
+    pub num_notifications: u64,
+
+    /// The number of unread mentions after the latest read event.
+    pub num_mentions: u64,
+}
+
+/// Public data about the read receipts of a thread, collected during processing
+/// of the room it belongs to.
+///
+/// Only the events of the thread are counted, and only the threaded receipts of
+/// this thread and the unthreaded receipts are taken into account.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ThreadReadReceipts {
+    /// Does the thread have unread messages?
+    pub num_unread: u64,
+
+    /// Does the thread have unread events that should notify?
+    pub num_notifications: u64,
+
+    /// Does the thread have messages causing highlights for the users? (aka
+    /// mentions)
+    pub num_mentions: u64,
+
+    /// The latest read receipt (threaded or unthreaded) known for the thread.
+    #[serde(default)]
+    latest_active: Option<LatestReadReceipt>,
+
+    /// Threaded read receipts that haven't been matched to their event.
+    #[serde(default = "new_nonempty_ring_buffer")]
+    pending: RingBuffer<OwnedEventId>,
+
+    /// The timestamp of the latest event of the thread, used to choose which
+    /// threads to keep.
+    #[serde(default)]
+    latest_event_ts: Option<MilliSecondsSinceUnixEpoch>,
+}
+
+impl Default for ThreadReadReceipts {
+    fn default() -> Self {
+        Self {
+            num_unread: Default::default(),
+            num_notifications: Default::default(),
+            num_mentions: Default::default(),
+            latest_active: Default::default(),
+            pending: new_nonempty_ring_buffer(),
+            latest_event_ts: Default::default(),
         }
     }
 }
@@ -197,29 +290,33 @@ fn new_nonempty_ring_buffer() -> RingBuffer<OwnedEventId> {
 }
 
 impl RoomReadReceipts {
+    /// Get the read state of the room, merged from the read receipts and
+    /// the fully-read marker sent by all the devices of the user.
+    pub fn read_state(&self) -> RoomReadState {
+        RoomReadState {
+            latest_read_event_id: self
+                .latest_active
+                .as_ref()
+                .map(|receipt| receipt.event_id.clone()),
+            num_unread: self.num_unread,
+            num_notifications: self.num_notifications,
+            num_mentions: self.num_mentions,
+        }
+    }
+
     /// Update the [`RoomReadReceipts`] unread counts according to the new
     /// event.
     ///
     /// Returns whether a new event triggered a new unread/notification/mention.
     #[inline(always)]
     fn process_event(&mut self, event: &SyncTimelineEvent, user_id: &UserId) {
-        if marks_as_unread(&event.event, user_id) {
-            self.num_unread += 1;
-        }
-
-        let mut has_notify = false;
-        let mut has_mention = false;
-
-        for action in &event.push_actions {
-            if !has_notify && action.should_notify() {
-                self.num_notifications += 1;
-                has_notify = true;
-            }
-            if !has_mention && action.is_highlight() {
-                self.num_mentions += 1;
-                has_mention = true;
-            }
-        }
+        count_event(
+            event,
+            user_id,
+            &mut self.num_unread,
+            &mut self.num_notifications,
+            &mut self.num_mentions,
+        );
     }
 
     #[inline(always)]
@@ -265,6 +362,89 @@ impl RoomReadReceipts {
     }
 }
 
+impl ThreadReadReceipts {
+    /// Update the [`ThreadReadReceipts`] unread counts according to the new
+    /// event of the thread.
+    #[inline(always)]
+    fn process_event(&mut self, event: &SyncTimelineEvent, user_id: &UserId) {
+        count_event(
+            event,
+            user_id,
+            &mut self.num_unread,
+            &mut self.num_notifications,
+            &mut self.num_mentions,
+        );
+    }
+
+    #[inline(always)]
+    fn reset(&mut self) {
+        self.num_unread = 0;
+        self.num_notifications = 0;
+        self.num_mentions = 0;
+    }
+
+    /// Whether this has any information that a new [`ThreadReadReceipts`]
+    /// wouldn't have, i.e. unread events or pending receipts.
+    fn has_unread_or_pending(&self) -> bool {
+        self.num_unread > 0
+            || self.num_notifications > 0
+            || self.num_mentions > 0
+            || !self.pending.is_empty()
+    }
+
+    /// Reset the notification count of the thread, and update it with the
+    /// events of the thread following the event the receipt attaches to, at the
+    /// given position.
+    ///
+    /// Like [`RoomReadReceipts::find_and_process_events`], but the receipt
+    /// can attach to an event outside of the thread, like for unthreaded
+    /// receipts, so it uses positions in all the events of the room.
+    fn process_events_after<'a>(
+        &mut self,
+        receipt_pos: usize,
+        user_id: &UserId,
+        thread_events: impl IntoIterator<Item = (usize, &'a SyncTimelineEvent)>,
+    ) {
+        // NOTE: SS proxy workaround, see `RoomReadReceipts::find_and_process_events`.
+        // The position is the one of the last occurrence of the event.
+        self.reset();
+
+        for (pos, event) in thread_events {
+            if pos > receipt_pos {
+                self.process_event(event, user_id);
+            }
+        }
+    }
+}
+
+/// Increment the given unread counts according to the new event.
+#[inline(always)]
+fn count_event(
+    event: &SyncTimelineEvent,
+    user_id: &UserId,
+    num_unread: &mut u64,
+    num_notifications: &mut u64,
+    num_mentions: &mut u64,
+) {
+    if marks_as_unread(&event.event, user_id) {
+        *num_unread += 1;
+    }
+
+    let mut has_notify = false;
+    let mut has_mention = false;
+
+    for action in &event.push_actions {
+        if !has_notify && action.should_notify() {
+            *num_notifications += 1;
+            has_notify = true;
+        }
+        if !has_mention && action.is_highlight() {
+            *num_mentions += 1;
+            has_mention = true;
+        }
+    }
+}
+
 /// Provider for timeline events prior to the current sync.
 pub trait PreviousEventsProvider: Send + Sync {
     /// Returns the list of known timeline events, in sync order, for the given
@@ -280,9 +460,9 @@ impl PreviousEventsProvider for () {
 
 /// Small helper to select the "best" receipt (that with the biggest sync
 /// order).
-struct ReceiptSelector {
+struct ReceiptSelector<'a> {
     /// Mapping of known event IDs to their sync order.
-    event_id_to_pos: BTreeMap<OwnedEventId, usize>,
+    event_id_to_pos: Cow<'a, BTreeMap<OwnedEventId, usize>>,
     /// The event with the greatest sync order, for which we had a read-receipt,
     /// so far.
     latest_event_with_receipt: Option<OwnedEventId>,
@@ -290,13 +470,21 @@ struct ReceiptSelector {
     latest_event_pos: Option<usize>,
 }
 
-impl ReceiptSelector {
+impl<'a> ReceiptSelector<'a> {
     fn new(
         all_events: &Vector<SyncTimelineEvent>,
         latest_active_receipt_event: Option<&EventId>,
     ) -> Self {
         let event_id_to_pos = Self::create_sync_index(all_events.iter());
+        Self::with_sync_index(Cow::Owned(event_id_to_pos), latest_active_receipt_event)
+    }
 
+    /// Like [`Self::new`], but reusing a mapping created with
+    /// [`Self::create_sync_index`].
+    fn with_sync_index(
+        event_id_to_pos: Cow<'a, BTreeMap<OwnedEventId, usize>>,
+        latest_active_receipt_event: Option<&EventId>,
+    ) -> Self {
         let best_pos =
             latest_active_receipt_event.and_then(|event_id| event_id_to_pos.get(event_id)).copied();
 
@@ -309,8 +497,8 @@ impl ReceiptSelector {
 
     /// Create a mapping of `event_id` -> sync order for all events that have an
     /// `event_id`.
-    fn create_sync_index<'a>(
-        events: impl Iterator<Item = &'a SyncTimelineEvent> + 'a,
+    fn create_sync_index<'e>(
+        events: impl Iterator<Item = &'e SyncTimelineEvent> + 'e,
     ) -> BTreeMap<OwnedEventId, usize> {
         // TODO: this should be cached and incrementally updated.
         BTreeMap::from_iter(
@@ -379,6 +567,33 @@ impl ReceiptSelector {
         &mut self,
         user_id: &UserId,
         receipt_event: &ReceiptEventContent,
+    ) -> Vec<OwnedEventId> {
+        self.handle_new_receipt_matching(user_id, receipt_event, |thread| {
+            matches!(thread, ReceiptThread::Main | ReceiptThread::Unthreaded)
+        })
+    }
+
+    /// Like [`Self::handle_new_receipt`], but for the receipts of the thread
+    /// with the given root.
+    #[instrument(skip_all)]
+    fn handle_new_thread_receipt(
+        &mut self,
+        user_id: &UserId,
+        receipt_event: &ReceiptEventContent,
+        thread_root: &EventId,
+    ) -> Vec<OwnedEventId> {
+        self.handle_new_receipt_matching(user_id, receipt_event, |thread| match thread {
+            ReceiptThread::Thread(root) => root == thread_root,
+            ReceiptThread::Unthreaded => true,
+            _ => false,
+        })
+    }
+
+    fn handle_new_receipt_matching(
+        &mut self,
+        user_id: &UserId,
+        receipt_event: &ReceiptEventContent,
+        matches_thread: impl Fn(&ReceiptThread) -> bool,
     ) -> Vec<OwnedEventId> {
         let mut pending = Vec::new();
         // Now consider new receipts.
@@ -386,7 +601,7 @@ impl ReceiptSelector {
             for ty in [ReceiptType::Read, ReceiptType::ReadPrivate] {
                 if let Some(receipt) = receipts.get(&ty).and_then(|receipts| receipts.get(user_id))
                 {
-                    if matches!(receipt.thread, ReceiptThread::Main | ReceiptThread::Unthreaded) {
+                    if matches_thread(&receipt.thread) {
                         trace!(%event_id, "found new candidate");
                         if let Some(event_pos) = self.event_id_to_pos.get(event_id) {
                             self.try_select_later(event_id, *event_pos);
@@ -402,10 +617,30 @@ impl ReceiptSelector {
         pending
     }
 
+    /// Try to match the fully-read marker of the room against the events we
+    /// know about, as if it was a read receipt.
+    ///
+    /// Returns the event ID of the marker if it couldn't be matched against
+    /// any event, in which case it's "pending".
+    #[instrument(skip(self))]
+    fn handle_fully_read(&mut self, event_id: &EventId) -> Option<OwnedEventId> {
+        if let Some(event_pos) = self.event_id_to_pos.get(event_id) {
+            self.try_select_later(event_id, *event_pos);
+            None
+        } else {
+            trace!(%event_id, "fully-read marker stashed as pending");
+            Some(event_id.to_owned())
+        }
+    }
+
     /// Try to match an implicit receipt, that is, the one we get for events we
     /// sent ourselves.
     #[instrument(skip_all)]
-    fn try_match_implicit(&mut self, user_id: &UserId, new_events: &[SyncTimelineEvent]) {
+    fn try_match_implicit<'e>(
+        &mut self,
+        user_id: &UserId,
+        new_events: impl IntoIterator<Item = &'e SyncTimelineEvent>,
+    ) {
         for ev in new_events {
             // Get the `sender` field, if any, or skip this event.
             let Ok(Some(sender)) = ev.event.get_field::<OwnedUserId>("sender") else { continue };
@@ -448,6 +683,10 @@ fn events_intersects<'a>(
 /// A provider of previous events may be required to reconcile a read receipt
 /// that has been just received for an event that came in a previous sync.
 ///
+/// The public and private read receipts, and the fully-read marker, are merged
+/// into a single latest active receipt, so that reading a room on any of the
+/// user's devices updates the counts.
+///
 /// See this module's documentation for more information.
 ///
 /// Returns a boolean indicating if a field changed value in the read receipts.
@@ -456,6 +695,7 @@ pub(crate) fn compute_unread_counts(
     user_id: &UserId,
     room_id: &RoomId,
     receipt_event: Option<&ReceiptEventContent>,
+    fully_read_event: Option<&EventId>,
     previous_events: Vector<SyncTimelineEvent>,
     new_events: &[SyncTimelineEvent],
     read_receipts: &mut RoomReadReceipts,
@@ -475,6 +715,8 @@ pub(crate) fn compute_unread_counts(
         all_events
     };
 
+    compute_thread_unread_counts(user_id, receipt_event, &all_events, new_events, read_receipts);
+
     let new_receipt = {
         let mut selector = ReceiptSelector::new(
             &all_events,
@@ -488,6 +730,11 @@ pub(crate) fn compute_unread_counts(
                 read_receipts.pending.extend(new_pending);
             }
         }
+        if let Some(fully_read_event) = fully_read_event {
+            if let Some(new_pending) = selector.handle_fully_read(fully_read_event) {
+                read_receipts.pending.push(new_pending);
+            }
+        }
         selector.select()
     };
 
@@ -525,6 +772,160 @@ pub(crate) fn compute_unread_counts(
     debug!(?read_receipts, "no better receipt, {} new events", new_events.len());
 }
 
+/// Update the [`ThreadReadReceipts`] of the threads that have new events or
+/// new receipts, the same way [`compute_unread_counts`] does for the whole
+/// room.
+#[instrument(skip_all)]
+fn compute_thread_unread_counts(
+    user_id: &UserId,
+    receipt_event: Option<&ReceiptEventContent>,
+    all_events: &Vector<SyncTimelineEvent>,
+    new_events: &[SyncTimelineEvent],
+    read_receipts: &mut RoomReadReceipts,
+) {
+    // Index the events by thread once, rather than looking for the events of each
+    // thread in all the events. The new events are always the last ones.
+    let first_new_pos = all_events.len() - new_events.len();
+    let mut events_by_thread = BTreeMap::<OwnedEventId, Vec<(usize, &SyncTimelineEvent)>>::new();
+    for (pos, event) in all_events.iter().enumerate() {
+        if let Some(thread_root) = thread_root_of(event) {
+            events_by_thread.entry(thread_root).or_default().push((pos, event));
+        }
+    }
+
+    let mut thread_roots = BTreeSet::from_iter(
+        events_by_thread
+            .iter()
+            .filter(|(_, events)| events.last().is_some_and(|(pos, _)| *pos >= first_new_pos))
+            .map(|(thread_root, _)| thread_root.clone()),
+    );
+
+    if let Some(receipt_event) = receipt_event {
+        for receipts in receipt_event.0.values() {
+            for ty in [ReceiptType::Read, ReceiptType::ReadPrivate] {
+                let Some(receipt) = receipts.get(&ty).and_then(|receipts| receipts.get(user_id))
+                else {
+                    continue;
+                };
+
+                match &receipt.thread {
+                    ReceiptThread::Thread(thread_root) => {
+                        thread_roots.insert(thread_root.clone());
+                    }
+                    // An unthreaded receipt applies to all the threads.
+                    ReceiptThread::Unthreaded => {
+                        thread_roots.extend(read_receipts.threads.keys().cloned());
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    let event_id_to_pos = ReceiptSelector::create_sync_index(all_events.iter());
+
+    for thread_root in thread_roots {
+        let thread_receipts = read_receipts.threads.entry(thread_root.clone()).or_default();
+
+        let thread_events =
+            events_by_thread.get(&thread_root).map(Vec::as_slice).unwrap_or_default();
+        let new_thread_events = thread_events
+            .iter()
+            .filter(|(pos, _)| *pos >= first_new_pos)
+            .map(|(_, event)| *event)
+            .collect::<Vec<_>>();
+
+        let new_receipt = {
+            let mut selector = ReceiptSelector::with_sync_index(
+                Cow::Borrowed(&event_id_to_pos),
+                thread_receipts.latest_active.as_ref().map(|receipt| &*receipt.event_id),
+            );
+            selector.try_match_implicit(user_id, new_thread_events.iter().copied());
+            selector.handle_pending_receipts(&mut thread_receipts.pending);
+            if let Some(receipt_event) = receipt_event {
+                let new_pending =
+                    selector.handle_new_thread_receipt(user_id, receipt_event, &thread_root);
+                if !new_pending.is_empty() {
+                    thread_receipts.pending.extend(new_pending);
+                }
+            }
+            selector.select()
+        };
+
+        if let Some(new_receipt) = new_receipt {
+            let event_id = new_receipt.event_id.clone();
+
+            trace!(%thread_root, %event_id, "Saving a new active threaded read receipt");
+            thread_receipts.latest_active = Some(new_receipt);
+
+            // The selected receipt is always for a known event.
+            if let Some(receipt_pos) = event_id_to_pos.get(&event_id) {
+                thread_receipts.process_events_after(
+                    *receipt_pos,
+                    user_id,
+                    thread_events.iter().copied(),
+                );
+            }
+        } else {
+            for event in &new_thread_events {
+                thread_receipts.process_event(event, user_id);
+            }
+        }
+
+        if let Some(ts) = new_thread_events
+            .last()
+            .and_then(|event| event.event.get_field("origin_server_ts").ok().flatten())
+        {
+            thread_receipts.latest_event_ts = Some(ts);
+        }
+    }
+
+    prune_threads(&mut read_receipts.threads);
+}
+
+/// Remove the threads that don't need to be remembered, and the ones with the
+/// oldest events if there are more than [`MAX_THREADS`].
+fn prune_threads(threads: &mut BTreeMap<OwnedEventId, ThreadReadReceipts>) {
+    threads.retain(|_, thread| thread.has_unread_or_pending());
+
+    if threads.len() <= MAX_THREADS {
+        return;
+    }
+
+    let mut by_latest_event = threads
+        .iter()
+        .map(|(root, thread)| (thread.latest_event_ts, root.clone()))
+        .collect::<Vec<_>>();
+    by_latest_event.sort();
+
+    let num_removed = threads.len() - MAX_THREADS;
+    for (_, thread_root) in by_latest_event.into_iter().take(num_removed) {
+        trace!(%thread_root, "Forgetting read receipts data of thread");
+        threads.remove(&thread_root);
+    }
+}
+
+/// Returns the ID of the root of the thread the event belongs to, if any.
+fn thread_root_of(event: &SyncTimelineEvent) -> Option<OwnedEventId> {
+    #[derive(Deserialize)]
+    struct RelatesTo {
+        rel_type: Option<RelationType>,
+        event_id: Option<OwnedEventId>,
+    }
+
+    #[derive(Deserialize)]
+    struct ContentWithRelation {
+        #[serde(rename = "m.relates_to")]
+        relates_to: Option<RelatesTo>,
+    }
+
+    let relates_to = event.event.get_field::<ContentWithRelation>("content").ok()??.relates_to?;
+    match relates_to.rel_type {
+        Some(RelationType::Thread) => relates_to.event_id,
+        _ => None,
+    }
+}
+
 /// Is the event worth marking a room as unread?
 fn marks_as_unread(event: &Raw<AnySyncTimelineEvent>, user_id: &UserId) -> bool {
     let event = match event.deserialize() {
@@ -632,7 +1033,7 @@ mod tests {
     };
 
     use super::compute_unread_counts;
-    use crate::read_receipts::{marks_as_unread, ReceiptSelector, RoomReadReceipts};
+    use crate::read_receipts::{marks_as_unread, ReceiptSelector, RoomReadReceipts, MAX_THREADS};
 
     #[test]
     fn test_room_message_marks_as_unread() {
@@ -771,6 +1172,7 @@ mod tests {
                 }),
                 encryption_info: None,
                 push_actions,
+                utd_cause: None,
             }
         }
 
@@ -856,6 +1258,7 @@ mod tests {
                 }),
                 encryption_info: None,
                 push_actions: Vec::new(),
+                utd_cause: None,
             }
         }
 
@@ -993,6 +1396,7 @@ mod tests {
             user_id,
             room_id,
             Some(&receipt_event),
+            None,
             previous_events.clone(),
             &[ev1.clone(), ev2.clone()],
             &mut read_receipts,
@@ -1010,6 +1414,7 @@ mod tests {
             user_id,
             room_id,
             Some(&receipt_event),
+            None,
             previous_events,
             &[new_event],
             &mut read_receipts,
@@ -1019,6 +1424,182 @@ mod tests {
         assert_eq!(read_receipts.num_unread, 2);
     }
 
+    fn sync_timeline_thread_message(
+        sender: &UserId,
+        event_id: impl serde::Serialize,
+        thread_root: impl serde::Serialize,
+    ) -> SyncTimelineEvent {
+        SyncTimelineEvent::new(sync_timeline_event!({
+            "sender": sender,
+            "type": "m.room.message",
+            "event_id": event_id,
+            "origin_server_ts": 42,
+            "content": {
+                "body": "In a thread",
+                "msgtype": "m.text",
+                "m.relates_to": {
+                    "rel_type": "m.thread",
+                    "event_id": thread_root,
+                },
+            },
+        }))
+    }
+
+    #[test]
+    fn test_compute_thread_unread_counts() {
+        let user_id = user_id!("@alice:example.org");
+        let other_user_id = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+        let thread_root = owned_event_id!("$root");
+
+        let events = vec![
+            sync_timeline_message(other_user_id, "$root", "A"),
+            sync_timeline_thread_message(other_user_id, "$t1", "$root"),
+            sync_timeline_thread_message(other_user_id, "$t2", "$root"),
+            sync_timeline_message(other_user_id, "$m", "B"),
+        ];
+
+        // Without any receipt, all the events of the thread are unread.
+        let mut read_receipts = RoomReadReceipts::default();
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            None,
+            Vector::new(),
+            &events,
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 4);
+        assert_eq!(read_receipts.threads.len(), 1);
+        assert_eq!(read_receipts.threads[&thread_root].num_unread, 2);
+
+        // A threaded receipt only updates the counts of its thread.
+        let receipt_event = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$t1"),
+            ReceiptType::Read,
+            user_id.to_owned(),
+            ReceiptThread::Thread(thread_root.clone()),
+        )]);
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&receipt_event),
+            None,
+            events.iter().cloned().collect(),
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 4);
+        assert_eq!(read_receipts.threads[&thread_root].num_unread, 1);
+
+        // A receipt for another thread doesn't change anything.
+        let receipt_event = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$t2"),
+            ReceiptType::Read,
+            user_id.to_owned(),
+            ReceiptThread::Thread(owned_event_id!("$other_root")),
+        )]);
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&receipt_event),
+            None,
+            events.iter().cloned().collect(),
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.threads[&thread_root].num_unread, 1);
+
+        // A new event in the thread is counted.
+        let new_event = sync_timeline_thread_message(other_user_id, "$t3", "$root");
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            None,
+            events.iter().cloned().collect(),
+            &[new_event.clone()],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.threads[&thread_root].num_unread, 2);
+
+        // An unthreaded receipt applies to the thread too.
+        let mut all_events: Vector<_> = events.into_iter().collect();
+        all_events.push_back(new_event);
+
+        let receipt_event = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$t3"),
+            ReceiptType::Read,
+            user_id.to_owned(),
+            ReceiptThread::Unthreaded,
+        )]);
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&receipt_event),
+            None,
+            all_events,
+            &[],
+            &mut read_receipts,
+        );
+
+        // The thread is completely read, so it's forgotten.
+        assert_eq!(read_receipts.num_unread, 0);
+        assert!(read_receipts.threads.is_empty());
+    }
+
+    #[test]
+    fn test_compute_thread_unread_counts_keeps_most_recent_threads() {
+        let user_id = user_id!("@alice:example.org");
+        let other_user_id = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+
+        let mut read_receipts = RoomReadReceipts::default();
+        let mut all_events = Vector::new();
+
+        // Each event is in its own thread, and is more recent than the previous ones.
+        for i in 0..MAX_THREADS + 2 {
+            let event = SyncTimelineEvent::new(sync_timeline_event!({
+                "sender": other_user_id,
+                "type": "m.room.message",
+                "event_id": format!("$t{i}"),
+                "origin_server_ts": i,
+                "content": {
+                    "body": "In a thread",
+                    "msgtype": "m.text",
+                    "m.relates_to": {
+                        "rel_type": "m.thread",
+                        "event_id": format!("$root{i}"),
+                    },
+                },
+            }));
+
+            compute_unread_counts(
+                user_id,
+                room_id,
+                None,
+                None,
+                all_events.clone(),
+                &[event.clone()],
+                &mut read_receipts,
+            );
+            all_events.push_back(event);
+        }
+
+        // The room-wide counts include all the threads, but only the most recent
+        // threads are kept.
+        assert_eq!(read_receipts.num_unread, MAX_THREADS as u64 + 2);
+        assert_eq!(read_receipts.threads.len(), MAX_THREADS);
+        assert!(!read_receipts.threads.contains_key(event_id!("$root0")));
+        assert!(!read_receipts.threads.contains_key(event_id!("$root1")));
+        assert_eq!(read_receipts.threads[event_id!("$root2")].num_unread, 1);
+    }
+
     fn make_test_events(user_id: &UserId) -> Vector<SyncTimelineEvent> {
         let ev1 = sync_timeline_message(user_id, "$1", "With the lights out, it's less dangerous");
         let ev2 = sync_timeline_message(user_id, "$2", "Here we are now, entertain us");
@@ -1073,6 +1654,7 @@ mod tests {
                             user_id,
                             room_id,
                             Some(&receipt_event),
+                            None,
                             all_events.clone(),
                             &[],
                             &mut read_receipts,
@@ -1094,6 +1676,7 @@ mod tests {
                             user_id,
                             room_id,
                             Some(&receipt_event),
+                            None,
                             head_events.clone(),
                             &tail_events,
                             &mut read_receipts,
@@ -1140,6 +1723,7 @@ mod tests {
             &user_id,
             room_id,
             Some(&receipt_event),
+            None,
             events,
             &[], // no new events
             &mut read_receipts,
@@ -1179,6 +1763,7 @@ mod tests {
             &user_id,
             room_id,
             Some(&receipt_event),
+            None,
             events,
             &[ev0], // duplicate event!
             &mut read_receipts,
@@ -1611,6 +2196,7 @@ mod tests {
             &user_id,
             room_id,
             Some(&receipt_event),
+            None,
             Vector::new(),
             &events,
             &mut read_receipts,
@@ -1625,4 +2211,188 @@ mod tests {
         // And the active receipt is the implicit one on my event.
         assert_eq!(read_receipts.latest_active.unwrap().event_id, event_id!("$6"));
     }
+
+    /// A private receipt sent by one device, then a public receipt sent by
+    /// another device on a later event, are merged into the latest one.
+    #[test]
+    fn test_compute_unread_counts_multi_device_receipts() {
+        let user_id = user_id!("@alice:example.org");
+        let bob = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+
+        let events = make_test_events(bob);
+
+        // The first device sends a private receipt on $2.
+        let private_receipt = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$2"),
+            ReceiptType::ReadPrivate,
+            user_id.to_owned(),
+            ReceiptThread::Unthreaded,
+        )]);
+
+        let mut read_receipts = RoomReadReceipts::default();
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&private_receipt),
+            None,
+            Vector::new(),
+            &events.iter().cloned().collect::<Vec<_>>(),
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 3);
+        assert_eq!(
+            read_receipts.read_state().latest_read_event_id.as_deref(),
+            Some(event_id!("$2"))
+        );
+
+        // The second device sends a public receipt on $4, without new events.
+        let public_receipt = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$4"),
+            ReceiptType::Read,
+            user_id.to_owned(),
+            ReceiptThread::Unthreaded,
+        )]);
+
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&public_receipt),
+            None,
+            events.clone(),
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 1);
+        assert_eq!(
+            read_receipts.read_state().latest_read_event_id.as_deref(),
+            Some(event_id!("$4"))
+        );
+
+        // A late private receipt of the first device, on an older event, is ignored.
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&private_receipt),
+            None,
+            events,
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 1);
+        assert_eq!(
+            read_receipts.read_state().latest_read_event_id.as_deref(),
+            Some(event_id!("$4"))
+        );
+    }
+
+    /// The fully-read marker set by another device is considered like a read
+    /// receipt, but only if it's more recent than the latest receipt.
+    #[test]
+    fn test_compute_unread_counts_with_fully_read_marker() {
+        let user_id = user_id!("@alice:example.org");
+        let bob = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+
+        let events = make_test_events(bob);
+
+        let mut read_receipts = RoomReadReceipts::default();
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            Some(event_id!("$3")),
+            Vector::new(),
+            &events.iter().cloned().collect::<Vec<_>>(),
+            &mut read_receipts,
+        );
+
+        // Only the events after the fully-read marker are unread.
+        assert_eq!(read_receipts.num_unread, 2);
+        assert_eq!(
+            read_receipts.read_state().latest_read_event_id.as_deref(),
+            Some(event_id!("$3"))
+        );
+
+        // A receipt on a later event takes over.
+        let receipt_event = EventBuilder::new().make_receipt_event_content([(
+            owned_event_id!("$5"),
+            ReceiptType::Read,
+            user_id.to_owned(),
+            ReceiptThread::Unthreaded,
+        )]);
+
+        compute_unread_counts(
+            user_id,
+            room_id,
+            Some(&receipt_event),
+            None,
+            events.clone(),
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 0);
+
+        // A fully-read marker on an older event doesn't make the room unread again.
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            Some(event_id!("$1")),
+            events,
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.num_unread, 0);
+        assert_eq!(
+            read_receipts.read_state().latest_read_event_id.as_deref(),
+            Some(event_id!("$5"))
+        );
+    }
+
+    /// A fully-read marker on an event that isn't known yet is matched when the
+    /// event is received.
+    #[test]
+    fn test_compute_unread_counts_with_pending_fully_read_marker() {
+        let user_id = user_id!("@alice:example.org");
+        let bob = user_id!("@bob:example.org");
+        let room_id = room_id!("!room:example.org");
+
+        let mut read_receipts = RoomReadReceipts::default();
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            Some(event_id!("$4")),
+            Vector::new(),
+            &[],
+            &mut read_receipts,
+        );
+
+        assert_eq!(read_receipts.pending.len(), 1);
+        assert!(read_receipts.read_state().latest_read_event_id.is_none());
+
+        let events: Vec<_> = make_test_events(bob).into_iter().collect();
+        compute_unread_counts(
+            user_id,
+            room_id,
+            None,
+            None,
+            Vector::new(),
+            &events,
+            &mut read_receipts,
+        );
+
+        assert!(read_receipts.pending.is_empty());
+        assert_eq!(read_receipts.num_unread, 1);
+        assert_eq!(
+            read_receipts.read_state().latest_read_event_id.as_deref(),
+            Some(event_id!("$4"))
+        );
+    }
 }
@@ -27,6 +27,7 @@ use ruma::{
             join_rules::RoomJoinRulesEventContent,
             member::MembershipState,
             name::RoomNameEventContent,
+            pinned_events::RoomPinnedEventsEventContent,
             tombstone::RoomTombstoneEventContent,
             topic::RoomTopicEventContent,
         },
@@ -35,7 +36,7 @@ use ruma::{
         RedactedStateEventContent, StaticStateEventContent, SyncStateEvent,
     },
     room::RoomType,
-    EventId, OwnedUserId, RoomVersionId,
+    EventId, OwnedEventId, OwnedUserId, RoomVersionId,
 };
 use serde::{Deserialize, Serialize};
 
@@ -114,6 +115,13 @@ pub struct BaseRoomInfo {
     /// others, and this field collects them.
     #[serde(skip_serializing_if = "RoomNotableTags::is_empty", default)]
     pub(crate) notable_tags: RoomNotableTags,
+    /// The `order` of the `m.favourite` tag, used to manually sort the
+    /// favourite rooms.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub(crate) favourite_order: Option<f64>,
+    /// The `m.room.pinned_events` event content of this room.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub(crate) pinned_events: Option<RoomPinnedEventsEventContent>,
 }
 
 impl BaseRoomInfo {
@@ -122,6 +130,12 @@ impl BaseRoomInfo {
         Self::default()
     }
 
+    /// The IDs of the events pinned in this room, in the order of the
+    /// `m.room.pinned_events` event.
+    pub(crate) fn pinned_event_ids(&self) -> Vec<OwnedEventId> {
+        self.pinned_events.as_ref().map(|content| content.pinned.clone()).unwrap_or_default()
+    }
+
     pub(crate) fn calculate_room_name(
         &self,
         joined_member_count: u64,
@@ -185,6 +199,9 @@ impl BaseRoomInfo {
             AnySyncStateEvent::RoomPowerLevels(p) => {
                 self.max_power_level = p.power_levels().max().into();
             }
+            AnySyncStateEvent::RoomPinnedEvents(p) => {
+                self.pinned_events = p.as_original().map(|p| p.content.clone());
+            }
             AnySyncStateEvent::CallMember(m) => {
                 let Some(o_ev) = m.as_original() else {
                     return false;
@@ -308,6 +325,7 @@ impl BaseRoomInfo {
         }
 
         self.notable_tags = notable_tags;
+        self.favourite_order = tags.get(&TagName::Favorite).and_then(|info| info.order);
     }
 }
 
@@ -359,6 +377,8 @@ impl Default for BaseRoomInfo {
             rtc_member: BTreeMap::new(),
             is_marked_unread: false,
             notable_tags: RoomNotableTags::empty(),
+            favourite_order: None,
+            pinned_events: None,
         }
     }
 }
@@ -564,7 +584,15 @@ impl RoomMemberships {
 mod tests {
     use std::ops::Not;
 
-    use ruma::events::tag::{TagInfo, TagName, Tags};
+    use ruma::{
+        event_id,
+        events::{
+            tag::{TagInfo, TagName, Tags},
+            AnySyncStateEvent,
+        },
+        serde::Raw,
+    };
+    use serde_json::json;
 
     use super::{calculate_room_name, BaseRoomInfo, DisplayName, RoomNotableTags};
 
@@ -630,4 +658,53 @@ mod tests {
         base_room_info.handle_notable_tags(&tags);
         assert!(base_room_info.notable_tags.contains(RoomNotableTags::LOW_PRIORITY).not());
     }
+
+    #[test]
+    fn test_handle_notable_tags_favourite_order() {
+        let mut base_room_info = BaseRoomInfo::default();
+
+        let mut tags = Tags::new();
+        tags.insert(TagName::Favorite, TagInfo::default());
+
+        base_room_info.handle_notable_tags(&tags);
+        assert_eq!(base_room_info.favourite_order, None);
+
+        let mut tag_info = TagInfo::default();
+        tag_info.order = Some(0.25);
+        tags.insert(TagName::Favorite, tag_info);
+
+        base_room_info.handle_notable_tags(&tags);
+        assert_eq!(base_room_info.favourite_order, Some(0.25));
+
+        tags.clear();
+        base_room_info.handle_notable_tags(&tags);
+        assert_eq!(base_room_info.favourite_order, None);
+    }
+
+    #[test]
+    fn test_handle_pinned_events() {
+        let mut base_room_info = BaseRoomInfo::default();
+        assert!(base_room_info.pinned_event_ids().is_empty());
+
+        let event = Raw::new(&json!({
+            "content": {
+                "pinned": ["$a:example.org", "$b:example.org"],
+            },
+            "event_id": "$pinned",
+            "origin_server_ts": 151800140,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.pinned_events",
+        }))
+        .unwrap()
+        .cast::<AnySyncStateEvent>()
+        .deserialize()
+        .unwrap();
+
+        assert!(base_room_info.handle_state_event(&event));
+        assert_eq!(
+            base_room_info.pinned_event_ids(),
+            vec![event_id!("$a:example.org").to_owned(), event_id!("$b:example.org").to_owned()]
+        );
+    }
 }
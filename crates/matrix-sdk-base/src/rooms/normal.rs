@@ -22,7 +22,11 @@ use std::{
 
 use bitflags::bitflags;
 use eyeball::{SharedObservable, Subscriber};
-use futures_util::stream::{self, StreamExt};
+use futures_util::{
+    future,
+    stream::{self, StreamExt},
+    Stream,
+};
 #[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
 use matrix_sdk_common::ring_buffer::RingBuffer;
 #[cfg(feature = "experimental-sliding-sync")]
@@ -65,7 +69,7 @@ use super::{
 use crate::latest_event::LatestEvent;
 use crate::{
     deserialized_responses::MemberEvent,
-    read_receipts::RoomReadReceipts,
+    read_receipts::{RoomReadReceipts, RoomReadState, ThreadReadReceipts},
     store::{DynStateStore, Result as StoreResult, StateStoreExt},
     sync::UnreadNotificationsCount,
     MinimalStateEvent, OriginalMinimalStateEvent, RoomMemberships,
@@ -240,6 +244,42 @@ impl Room {
         self.inner.read().read_receipts.num_mentions
     }
 
+    /// Get the detailed information about read receipts for each thread of the
+    /// room, by ID of its root event.
+    ///
+    /// The counts of the threads are computed client-side, from the threaded
+    /// read receipts.
+    pub fn thread_read_receipts(&self) -> BTreeMap<OwnedEventId, ThreadReadReceipts> {
+        self.inner.read().read_receipts.threads.clone()
+    }
+
+    /// Get the read state of the room, that is, the latest event the user has
+    /// read on any of their devices, and the unread counts after it.
+    pub fn read_state(&self) -> RoomReadState {
+        self.inner.read().read_receipts.read_state()
+    }
+
+    /// Subscribe to the read state of the room.
+    ///
+    /// The stream yields the current read state, then a new one every time it
+    /// changes, e.g. when the room is read on another device of the user.
+    pub fn subscribe_to_read_state(&self) -> impl Stream<Item = RoomReadState> {
+        let subscriber = self.inner.subscribe();
+        let initial_state = subscriber.get().read_receipts.read_state();
+        let mut previous_state = initial_state.clone();
+
+        let updates = subscriber.filter_map(move |room_info| {
+            let state = room_info.read_receipts.read_state();
+            let has_changed = state != previous_state;
+            if has_changed {
+                previous_state = state.clone();
+            }
+            future::ready(has_changed.then_some(state))
+        });
+
+        stream::once(future::ready(initial_state)).chain(updates)
+    }
+
     /// Check if the room has its members fully synced.
     ///
     /// Members might be missing if lazy member loading was enabled for the
@@ -663,8 +703,12 @@ impl Room {
     /// Update the summary with given RoomInfo.
     ///
     /// This also triggers an update for room info observers if
-    /// `trigger_room_list_update` is true.
+    /// `trigger_room_list_update` is true, or if the new `RoomInfo` changes
+    /// data that is used to sort the room list.
     pub fn set_room_info(&self, room_info: RoomInfo, trigger_room_list_update: bool) {
+        let trigger_room_list_update = trigger_room_list_update
+            || self.inner.read().has_room_list_sort_keys_changed(&room_info);
+
         self.inner.set(room_info);
 
         // Ignore error if no receiver exists.
@@ -673,6 +717,18 @@ impl Room {
             .send(RoomInfoUpdate { room_id: self.room_id.clone(), trigger_room_list_update });
     }
 
+    /// Trigger an update of the room list for this room, without changing its
+    /// [`RoomInfo`].
+    ///
+    /// This is useful when data that is not part of the `RoomInfo`, but that is
+    /// used to filter or sort the room list, has changed.
+    pub fn trigger_room_list_update(&self) {
+        // Ignore error if no receiver exists.
+        let _ = self
+            .roominfo_update_sender
+            .send(RoomInfoUpdate { room_id: self.room_id.clone(), trigger_room_list_update: true });
+    }
+
     /// Get the `RoomMember` with the given `user_id`.
     ///
     /// Returns `None` if the member was never part of this room, otherwise
@@ -752,6 +808,14 @@ impl Room {
         self.inner.read().base_info.notable_tags.contains(RoomNotableTags::FAVOURITE)
     }
 
+    /// Get the order of the room among the favourite rooms, i.e. the `order`
+    /// of its `m.favourite` tag, if any.
+    ///
+    /// Rooms with a lower order should be displayed first.
+    pub fn favourite_order(&self) -> Option<f64> {
+        self.inner.read().base_info.favourite_order
+    }
+
     /// Check whether the room is marked as low priority.
     ///
     /// A room is considered low priority if it has received the `m.lowpriority`
@@ -790,6 +854,15 @@ impl Room {
     pub fn is_marked_unread(&self) -> bool {
         self.inner.read().base_info.is_marked_unread
     }
+
+    /// Get the IDs of the events pinned in this room, from the
+    /// `m.room.pinned_events` state event.
+    ///
+    /// The events are in the order of the state event, which is usually the
+    /// order in which they have been pinned.
+    pub fn pinned_event_ids(&self) -> Vec<OwnedEventId> {
+        self.inner.read().base_info.pinned_event_ids()
+    }
 }
 
 /// The underlying pure data structure for joined and left rooms.
@@ -1204,6 +1277,25 @@ impl RoomInfo {
     pub fn latest_event(&self) -> Option<&LatestEvent> {
         self.latest_event.as_deref()
     }
+
+    /// Whether `other` differs from this `RoomInfo` in data that is used to
+    /// sort the room list, i.e. the name, the latest event, the unread state
+    /// or the favourite tag.
+    fn has_room_list_sort_keys_changed(&self, other: &RoomInfo) -> bool {
+        #[cfg(feature = "experimental-sliding-sync")]
+        if self.latest_event().and_then(LatestEvent::event_id)
+            != other.latest_event().and_then(LatestEvent::event_id)
+        {
+            return true;
+        }
+
+        self.name() != other.name()
+            || self.canonical_alias() != other.canonical_alias()
+            || self.read_receipts.num_unread != other.read_receipts.num_unread
+            || self.base_info.is_marked_unread != other.base_info.is_marked_unread
+            || self.base_info.notable_tags.bits() != other.base_info.notable_tags.bits()
+            || self.base_info.favourite_order != other.base_info.favourite_order
+    }
 }
 
 #[cfg(feature = "experimental-sliding-sync")]
@@ -1414,7 +1506,8 @@ mod tests {
                 "num_mentions": 0,
                 "num_notifications": 0,
                 "latest_active": null,
-                "pending": []
+                "pending": [],
+                "threads": {}
             }
         });
 
@@ -1893,6 +1986,39 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_setting_room_info_triggers_room_list_update_on_sort_key_change() {
+        let store = Arc::new(MemoryStore::new());
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(4);
+        let room = Room::new(
+            user_id!("@me:example.org"),
+            store,
+            room_id!("!test:localhost"),
+            RoomState::Joined,
+            sender,
+        );
+
+        // Nothing used to sort the room list has changed.
+        room.set_room_info(room.clone_info(), false);
+        assert!(!receiver.try_recv().unwrap().trigger_room_list_update);
+
+        // The name has changed.
+        let mut room_info = room.clone_info();
+        room_info.update_name("new name".to_owned());
+        room.set_room_info(room_info, false);
+        assert!(receiver.try_recv().unwrap().trigger_room_list_update);
+
+        // The room has been marked as unread.
+        let mut room_info = room.clone_info();
+        room_info.base_info.is_marked_unread = true;
+        room.set_room_info(room_info, false);
+        assert!(receiver.try_recv().unwrap().trigger_room_list_update);
+
+        // An explicit trigger is still honored.
+        room.set_room_info(room.clone_info(), true);
+        assert!(receiver.try_recv().unwrap().trigger_room_list_update);
+    }
+
     #[async_test]
     #[cfg(feature = "experimental-sliding-sync")]
     async fn test_setting_the_latest_event_doesnt_cause_a_room_info_update() {
@@ -14,11 +14,9 @@
 
 use std::collections::BTreeMap;
 #[cfg(feature = "e2e-encryption")]
-use std::ops::Deref;
+use std::{collections::BTreeSet, ops::Deref};
 
 use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
-#[cfg(feature = "e2e-encryption")]
-use ruma::events::AnyToDeviceEvent;
 use ruma::{
     api::client::sync::sync_events::{
         v3::{self, InvitedRoom, RoomSummary},
@@ -26,13 +24,21 @@ use ruma::{
     },
     events::{AnyRoomAccountDataEvent, AnySyncStateEvent, AnySyncTimelineEvent},
     serde::Raw,
-    JsOption, OwnedRoomId, RoomId,
+    JsOption, OwnedEventId, OwnedRoomId, RoomId,
+};
+#[cfg(feature = "e2e-encryption")]
+use ruma::{
+    events::{AnySyncMessageLikeEvent, AnyToDeviceEvent},
+    RoomVersionId,
 };
 use tracing::{instrument, trace, warn};
 
 use super::BaseClient;
 #[cfg(feature = "e2e-encryption")]
-use crate::latest_event::{is_suitable_for_latest_event, LatestEvent, PossibleLatestEvent};
+use crate::latest_event::{
+    as_latest_event_edit, is_suitable_for_latest_event, LatestEvent, LatestEventEdits,
+    PossibleLatestEvent,
+};
 #[cfg(feature = "e2e-encryption")]
 use crate::RoomMemberships;
 use crate::{
@@ -254,11 +260,14 @@ impl BaseClient {
                 .or_else(|| self.get_room(room_id).map(|r| r.clone_info()))
             {
                 let prev_read_receipts = room_info.read_receipts.clone();
+                let fully_read_event =
+                    rooms_account_data.get(room_id).and_then(|raw| fully_read_event_id(raw));
 
                 compute_unread_counts(
                     user_id,
                     room_id,
                     changes.receipts.get(room_id),
+                    fully_read_event.as_deref(),
                     previous_events_provider.for_room(room_id),
                     &joined_room_update.timeline.events,
                     &mut room_info.read_receipts,
@@ -290,11 +299,17 @@ impl BaseClient {
 
         changes.ambiguity_maps = ambiguity_cache.cache;
 
+        #[cfg(feature = "e2e-encryption")]
+        let accepted_invites = self.accepted_invites(&changes).await?;
+
         trace!("ready to submit changes to store");
         store.save_changes(&changes).await?;
         self.apply_changes(&changes, false);
         trace!("applied changes");
 
+        #[cfg(feature = "e2e-encryption")]
+        self.handle_shared_history_room_keys(accepted_invites).await?;
+
         Ok(SyncResponse {
             rooms: new_rooms,
             notifications,
@@ -569,6 +584,9 @@ impl BaseClient {
 /// If any encrypted events are found after that one, store them in the RoomInfo
 /// too so we can use them when we get the relevant keys.
 ///
+/// The edits of the latest event are applied to it, and the events redacted in
+/// the same batch of events are ignored.
+///
 /// It is the responsibility of the caller to update the `RoomInfo` instance
 /// stored in the `Room`.
 #[cfg(feature = "e2e-encryption")]
@@ -582,8 +600,41 @@ async fn cache_latest_events(
     let mut encrypted_events =
         Vec::with_capacity(room.latest_encrypted_events.read().unwrap().capacity());
 
+    // The edits and redactions found in the events, going backwards, so they are
+    // found before the events they apply to.
+    let mut edits = LatestEventEdits::new();
+    let mut redacted_event_ids = BTreeSet::new();
+    let mut found_latest_event = false;
+
     for event in events.iter().rev() {
         if let Ok(timeline_event) = event.event.deserialize() {
+            if let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomRedaction(
+                redaction,
+            )) = &timeline_event
+            {
+                let room_version = room_info.room_version().unwrap_or(&RoomVersionId::V1);
+
+                if let Some(redacts) = redaction.redacts(room_version) {
+                    redacted_event_ids.insert(redacts.to_owned());
+                }
+
+                continue;
+            }
+
+            if redacted_event_ids.contains(timeline_event.event_id()) {
+                // The event has been redacted in the same batch, it can't be the latest event.
+                continue;
+            }
+
+            if let Some((edited_event_id, new_content)) = as_latest_event_edit(&timeline_event) {
+                // Only keep the most recent edit of an event by each sender, only the one of
+                // the sender of the event will be applied.
+                edits
+                    .entry((edited_event_id.to_owned(), timeline_event.sender().to_owned()))
+                    .or_insert_with(|| new_content.clone());
+                continue;
+            }
+
             match is_suitable_for_latest_event(&timeline_event) {
                 PossibleLatestEvent::YesRoomMessage(_)
                 | PossibleLatestEvent::YesPoll(_)
@@ -639,17 +690,20 @@ async fn cache_latest_events(
                         }
                     }
 
-                    let latest_event = Box::new(LatestEvent::new_with_sender_details(
+                    let mut latest_event = Box::new(LatestEvent::new_with_sender_details(
                         event.clone(),
                         sender_profile,
                         sender_name_is_ambiguous,
                     ));
 
+                    latest_event.apply_edit_from(&edits);
+
                     // Store it in the return RoomInfo (it will be saved for us in the room later).
                     room_info.latest_event = Some(latest_event.clone());
                     // We don't need any of the older encrypted events because we have a new
                     // decrypted one.
                     room.latest_encrypted_events.write().unwrap().clear();
+                    found_latest_event = true;
                     // We can stop looking through the timeline now because everything else is
                     // older.
                     break;
@@ -676,9 +730,36 @@ async fn cache_latest_events(
         }
     }
 
+    if !found_latest_event {
+        // The latest event we already had may have been edited, unless it has been
+        // redacted in the same batch.
+        if let Some(latest_event) = &mut room_info.latest_event {
+            let is_redacted = latest_event
+                .event_id()
+                .is_some_and(|event_id| redacted_event_ids.contains(&event_id));
+
+            if !is_redacted {
+                latest_event.apply_edit_from(&edits);
+            }
+        }
+    }
+
+    let mut latest_encrypted_events = room.latest_encrypted_events.write().unwrap();
+
+    // The encrypted events we already had may have been redacted.
+    if !redacted_event_ids.is_empty() {
+        latest_encrypted_events.retain(|event| {
+            event
+                .get_field::<OwnedEventId>("event_id")
+                .ok()
+                .flatten()
+                .map_or(true, |event_id| !redacted_event_ids.contains(&event_id))
+        });
+    }
+
     // Push the encrypted events we found into the Room, in reverse order, so
     // the latest is last
-    room.latest_encrypted_events.write().unwrap().extend(encrypted_events.into_iter().rev());
+    latest_encrypted_events.extend(encrypted_events.into_iter().rev());
 }
 
 fn process_room_properties(room_data: &v4::SlidingSyncRoom, room_info: &mut RoomInfo) {
@@ -718,6 +799,15 @@ fn process_room_properties(room_data: &v4::SlidingSyncRoom, room_info: &mut Room
     }
 }
 
+/// Get the event ID of the latest fully-read marker among the given room
+/// account data events, if any.
+fn fully_read_event_id(account_data: &[Raw<AnyRoomAccountDataEvent>]) -> Option<OwnedEventId> {
+    account_data.iter().rev().find_map(|raw| match raw.deserialize() {
+        Ok(AnyRoomAccountDataEvent::FullyRead(event)) => Some(event.content.event_id),
+        _ => None,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -750,7 +840,8 @@ mod tests {
 
     use super::cache_latest_events;
     use crate::{
-        store::MemoryStore, test_utils::logged_in_base_client, BaseClient, Room, RoomState,
+        latest_event::LatestEvent, store::MemoryStore, test_utils::logged_in_base_client,
+        BaseClient, Room, RoomState,
     };
 
     #[async_test]
@@ -1578,6 +1669,170 @@ mod tests {
         assert_eq!(rawev_id(room.latest_event().unwrap().event().clone()), "$a");
     }
 
+    #[async_test]
+    async fn the_latest_edit_of_the_latest_event_is_applied_to_it() {
+        // Given a message followed by two edits of it
+        let event1 = make_event("m.room.message", "$1");
+        let event2 = make_edit_event("$2", "$1", "first edit");
+        let event3 = make_edit_event("$3", "$1", "second edit");
+
+        // When I ask to cache events
+        let room = make_room();
+        let mut room_info = room.clone_info();
+        cache_latest_events(&room, &mut room_info, &[event1, event2, event3], None, None).await;
+        room.set_room_info(room_info, false);
+
+        // The message is stored as the latest event, with the content of the latest
+        // edit
+        let latest_event = room.latest_event().unwrap();
+        assert_eq!(rawev_id(latest_event.event().clone()), "$1");
+        assert_eq!(latest_event_body(&latest_event), "second edit");
+    }
+
+    #[async_test]
+    async fn edits_of_the_existing_latest_event_are_applied_to_it() {
+        // Given a RoomInfo with a latest event already
+        let room = make_room();
+        let mut room_info = room.clone_info();
+        cache_latest_events(
+            &room,
+            &mut room_info,
+            &[make_event("m.room.message", "$1")],
+            None,
+            None,
+        )
+        .await;
+
+        // When I ask to cache an edit of it
+        let event2 = make_edit_event("$2", "$1", "edited");
+        cache_latest_events(&room, &mut room_info, &[event2], None, None).await;
+        room.set_room_info(room_info, false);
+
+        // The latest event has the content of the edit
+        let latest_event = room.latest_event().unwrap();
+        assert_eq!(rawev_id(latest_event.event().clone()), "$1");
+        assert_eq!(latest_event_body(&latest_event), "edited");
+    }
+
+    #[async_test]
+    async fn edits_from_another_sender_are_not_applied() {
+        // Given a message, edited by its sender then by someone else
+        let event1 = make_event("m.room.message", "$1");
+        let event2 = make_edit_event("$2", "$1", "edited");
+        let event3 = make_edit_event_by("@mallory:h.uk", "$3", "$1", "hijacked");
+
+        // When I ask to cache events
+        let room = make_room();
+        let mut room_info = room.clone_info();
+        cache_latest_events(&room, &mut room_info, &[event1, event2, event3], None, None).await;
+
+        // When I ask to cache another edit by someone else afterwards
+        let event4 = make_edit_event_by("@mallory:h.uk", "$4", "$1", "hijacked again");
+        cache_latest_events(&room, &mut room_info, &[event4], None, None).await;
+        room.set_room_info(room_info, false);
+
+        // Only the edit of the sender of the message is applied
+        let latest_event = room.latest_event().unwrap();
+        assert_eq!(rawev_id(latest_event.event().clone()), "$1");
+        assert_eq!(latest_event_body(&latest_event), "edited");
+    }
+
+    #[async_test]
+    async fn edits_of_a_redacted_cached_latest_event_are_ignored() {
+        // Given a logged-in client with a latest event in a room
+        let client = logged_in_base_client(None).await;
+        let room_id = room_id!("!r:e.uk");
+        let event_a = json!({
+            "sender": "@alice:example.com",
+            "type": "m.room.message",
+            "event_id": "$ida",
+            "origin_server_ts": 12344446,
+            "content": { "body": "A", "msgtype": "m.text" },
+        });
+
+        let room = room_with_timeline(&[event_a]);
+        let response = response_with_room(room_id, room).await;
+        client.process_sliding_sync(&response, &()).await.expect("Failed to process sync");
+
+        // When a redaction of the latest event is received in a later batch
+        let redaction = json!({
+            "sender": "@alice:example.com",
+            "type": "m.room.redaction",
+            "event_id": "$idb",
+            "redacts": "$ida",
+            "origin_server_ts": 12344448,
+            "content": {},
+        });
+
+        let room = room_with_timeline(&[redaction]);
+        let response = response_with_room(room_id, room).await;
+        client.process_sliding_sync(&response, &()).await.expect("Failed to process sync");
+
+        // And an edit of the redacted event is received afterwards
+        let edit = json!({
+            "sender": "@alice:example.com",
+            "type": "m.room.message",
+            "event_id": "$idc",
+            "origin_server_ts": 12344449,
+            "content": {
+                "body": "* edited",
+                "msgtype": "m.text",
+                "m.new_content": { "body": "edited", "msgtype": "m.text" },
+                "m.relates_to": { "rel_type": "m.replace", "event_id": "$ida" },
+            },
+        });
+
+        let room = room_with_timeline(&[edit]);
+        let response = response_with_room(room_id, room).await;
+        client.process_sliding_sync(&response, &()).await.expect("Failed to process sync");
+
+        // Then the latest event is still redacted
+        let client_room = client.get_room(room_id).expect("No room found");
+        let latest_event = client_room.latest_event().unwrap();
+        assert_eq!(latest_event.event_id().unwrap(), "$ida");
+        assert_matches!(
+            latest_event.event().event.deserialize().unwrap(),
+            AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomMessage(
+                SyncRoomMessageEvent::Redacted(_)
+            ))
+        );
+    }
+
+    #[async_test]
+    async fn dont_cache_events_redacted_in_the_same_batch() {
+        // Given two messages, the last one being redacted
+        let event1 = make_event("m.room.message", "$1");
+        let event2 = make_event("m.room.message", "$2");
+        let event3 = make_redaction_event("$3", "$2");
+        let events = &[event1.clone(), event2, event3];
+
+        // When I ask to cache events, the first message is chosen
+        let chosen = choose_event_to_cache(events).await;
+        assert_eq!(ev_id(chosen), rawev_id(event1));
+    }
+
+    #[async_test]
+    async fn redacted_encrypted_events_are_deleted() {
+        // Given a RoomInfo with some encrypted events already inside it
+        let room = make_room();
+        let mut room_info = room.clone_info();
+        cache_latest_events(
+            &room,
+            &mut room_info,
+            &[make_encrypted_event("$0"), make_encrypted_event("$1")],
+            None,
+            None,
+        )
+        .await;
+
+        // When I ask to cache a redaction of one of them
+        let event2 = make_redaction_event("$2", "$0");
+        cache_latest_events(&room, &mut room_info, &[event2], None, None).await;
+
+        // The redacted encrypted event is not stored anymore
+        assert_eq!(rawevs_ids(&room.latest_encrypted_events), &["$1"]);
+    }
+
     async fn choose_event_to_cache(events: &[SyncTimelineEvent]) -> Option<SyncTimelineEvent> {
         let room = make_room();
         let mut room_info = room.clone_info();
@@ -1630,6 +1885,59 @@ mod tests {
         )
     }
 
+    fn make_edit_event(id: &str, edited_id: &str, body: &str) -> SyncTimelineEvent {
+        make_edit_event_by("@u:h.uk", id, edited_id, body)
+    }
+
+    fn make_edit_event_by(
+        sender: &str,
+        id: &str,
+        edited_id: &str,
+        body: &str,
+    ) -> SyncTimelineEvent {
+        SyncTimelineEvent::new(
+            Raw::from_json_string(
+                json!({
+                    "type": "m.room.message",
+                    "event_id": id,
+                    "content": {
+                        "msgtype": "m.text",
+                        "body": format!("* {body}"),
+                        "m.new_content": { "msgtype": "m.text", "body": body },
+                        "m.relates_to": { "rel_type": "m.replace", "event_id": edited_id },
+                    },
+                    "sender": sender,
+                    "origin_server_ts": 12344445,
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn make_redaction_event(id: &str, redacted_id: &str) -> SyncTimelineEvent {
+        SyncTimelineEvent::new(
+            Raw::from_json_string(
+                json!({
+                    "type": "m.room.redaction",
+                    "event_id": id,
+                    "redacts": redacted_id,
+                    "content": {},
+                    "sender": "@u:h.uk",
+                    "origin_server_ts": 12344445,
+                })
+                .to_string(),
+            )
+            .unwrap(),
+        )
+    }
+
+    fn latest_event_body(latest_event: &LatestEvent) -> String {
+        let content =
+            latest_event.event().event.get_field::<serde_json::Value>("content").unwrap().unwrap();
+        content["body"].as_str().unwrap().to_owned()
+    }
+
     fn make_encrypted_event(id: &str) -> SyncTimelineEvent {
         SyncTimelineEvent::new(
             Raw::from_json_string(
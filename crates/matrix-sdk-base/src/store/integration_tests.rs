@@ -25,7 +25,7 @@ use ruma::{
         AnyStrippedStateEvent, AnySyncEphemeralRoomEvent, AnySyncStateEvent,
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
-    mxc_uri, room_id,
+    mxc_uri, owned_event_id, room_id,
     serde::Raw,
     uint, user_id, EventId, OwnedEventId, OwnedUserId, RoomId, UserId,
 };
@@ -35,7 +35,7 @@ use super::DynStateStore;
 use crate::{
     deserialized_responses::MemberEvent,
     media::{MediaFormat, MediaRequest, MediaThumbnailSize},
-    store::{Result, StateStoreExt},
+    store::{QueuedRequest, QueuedRequestKind, Result, StateStoreExt},
     RoomInfo, RoomMemberships, RoomState, StateChanges, StateStoreDataKey, StateStoreDataValue,
 };
 
@@ -80,6 +80,8 @@ pub trait StateStoreIntegrationTests {
     async fn test_presence_saving(&self);
     /// Test display names saving.
     async fn test_display_names_saving(&self);
+    /// Test send queue requests saving.
+    async fn test_send_queue_saving(&self);
 }
 
 #[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
@@ -1236,6 +1238,79 @@ impl StateStoreIntegrationTests for DynStateStore {
         let names = self.get_users_with_display_names(room_id, &[]).await;
         assert!(names.unwrap().is_empty());
     }
+
+    async fn test_send_queue_saving(&self) {
+        let room_id = room_id!("!test_send_queue_saving:localhost");
+        let other_room_id = room_id!("!test_send_queue_saving_other:localhost");
+
+        // No request in store.
+        assert!(self.load_send_queue_requests(room_id).await.unwrap().is_empty());
+        assert!(self.load_rooms_with_unsent_requests().await.unwrap().is_empty());
+
+        // Requests are loaded back in the order they were saved.
+        let first = QueuedRequest::new(QueuedRequestKind::Event {
+            event_type: "m.room.message".to_owned(),
+            content: Raw::new(&json!({ "body": "first", "msgtype": "m.text" })).unwrap().cast(),
+        });
+        let second = QueuedRequest::new(QueuedRequestKind::Redaction {
+            redacts: owned_event_id!("$redacted"),
+            reason: Some("spam".to_owned()),
+        });
+        let third = QueuedRequest::new(QueuedRequestKind::Event {
+            event_type: "m.reaction".to_owned(),
+            content: Raw::new(&json!({
+                "m.relates_to": { "rel_type": "m.annotation", "event_id": "$ev", "key": "👍" },
+            }))
+            .unwrap()
+            .cast(),
+        });
+
+        self.save_send_queue_request(room_id, first.clone()).await.unwrap();
+        self.save_send_queue_request(room_id, second.clone()).await.unwrap();
+        self.save_send_queue_request(other_room_id, third.clone()).await.unwrap();
+
+        let requests = self.load_send_queue_requests(room_id).await.unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].transaction_id, first.transaction_id);
+        assert_let!(QueuedRequestKind::Event { event_type, .. } = &requests[0].kind);
+        assert_eq!(event_type, "m.room.message");
+        assert_eq!(requests[1].transaction_id, second.transaction_id);
+        assert_let!(QueuedRequestKind::Redaction { redacts, reason } = &requests[1].kind);
+        assert_eq!(redacts, "$redacted");
+        assert_eq!(reason.as_deref(), Some("spam"));
+
+        let mut rooms = self.load_rooms_with_unsent_requests().await.unwrap();
+        rooms.sort();
+        assert_eq!(rooms, [room_id.to_owned(), other_room_id.to_owned()]);
+
+        // Requests can be loaded one by one.
+        let next = self.load_next_send_queue_request(room_id).await.unwrap().unwrap();
+        assert_eq!(next.transaction_id, first.transaction_id);
+        let request =
+            self.load_send_queue_request(room_id, &second.transaction_id).await.unwrap().unwrap();
+        assert_eq!(request.transaction_id, second.transaction_id);
+        assert!(self
+            .load_send_queue_request(room_id, &third.transaction_id)
+            .await
+            .unwrap()
+            .is_none());
+
+        // Removing a request only removes that one.
+        assert!(self.remove_send_queue_request(room_id, &first.transaction_id).await.unwrap());
+        assert!(!self.remove_send_queue_request(room_id, &first.transaction_id).await.unwrap());
+        assert!(!self.remove_send_queue_request(room_id, &third.transaction_id).await.unwrap());
+
+        let requests = self.load_send_queue_requests(room_id).await.unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].transaction_id, second.transaction_id);
+        let next = self.load_next_send_queue_request(room_id).await.unwrap().unwrap();
+        assert_eq!(next.transaction_id, second.transaction_id);
+
+        // A room without requests isn't listed anymore.
+        assert!(self.remove_send_queue_request(room_id, &second.transaction_id).await.unwrap());
+        assert_eq!(self.load_rooms_with_unsent_requests().await.unwrap(), [other_room_id]);
+        assert!(self.load_next_send_queue_request(room_id).await.unwrap().is_none());
+    }
 }
 
 /// Macro building to allow your StateStore implementation to run the entire
@@ -1378,6 +1453,12 @@ macro_rules! statestore_integration_tests {
             let store = get_store().await.expect("creating store failed").into_state_store();
             store.test_display_names_saving().await;
         }
+
+        #[async_test]
+        async fn test_send_queue_saving() {
+            let store = get_store().await.expect("creating store failed").into_state_store();
+            store.test_send_queue_saving().await;
+        }
     };
 }
 
@@ -31,11 +31,11 @@ use ruma::{
     },
     serde::Raw,
     CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedMxcUri, OwnedRoomId, OwnedUserId,
-    RoomId, RoomVersionId, UserId,
+    RoomId, RoomVersionId, TransactionId, UserId,
 };
 use tracing::{debug, warn};
 
-use super::{Result, RoomInfo, StateChanges, StateStore, StoreError};
+use super::{QueuedRequest, Result, RoomInfo, StateChanges, StateStore, StoreError};
 use crate::{
     deserialized_responses::RawAnySyncOrStrippedState,
     media::{MediaRequest, UniqueKey as _},
@@ -81,6 +81,7 @@ pub struct MemoryStore {
     >,
     media: StdRwLock<RingBuffer<(OwnedMxcUri, String /* unique key */, Vec<u8>)>>,
     custom: StdRwLock<HashMap<Vec<u8>, Vec<u8>>>,
+    send_queue_requests: StdRwLock<BTreeMap<OwnedRoomId, Vec<QueuedRequest>>>,
 }
 
 // SAFETY: `new_unchecked` is safe because 20 is not zero.
@@ -106,6 +107,7 @@ impl Default for MemoryStore {
             room_event_receipts: Default::default(),
             media: StdRwLock::new(RingBuffer::new(NUMBER_OF_MEDIAS)),
             custom: Default::default(),
+            send_queue_requests: Default::default(),
         }
     }
 }
@@ -804,9 +806,76 @@ impl StateStore for MemoryStore {
         self.stripped_members.write().unwrap().remove(room_id);
         self.room_user_receipts.write().unwrap().remove(room_id);
         self.room_event_receipts.write().unwrap().remove(room_id);
+        self.send_queue_requests.write().unwrap().remove(room_id);
 
         Ok(())
     }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        self.send_queue_requests
+            .write()
+            .unwrap()
+            .entry(room_id.to_owned())
+            .or_default()
+            .push(request);
+        Ok(())
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let mut send_queue_requests = self.send_queue_requests.write().unwrap();
+
+        let Some(requests) = send_queue_requests.get_mut(room_id) else {
+            return Ok(false);
+        };
+        let Some(index) = requests.iter().position(|r| r.transaction_id == transaction_id) else {
+            return Ok(false);
+        };
+
+        requests.remove(index);
+        if requests.is_empty() {
+            send_queue_requests.remove(room_id);
+        }
+
+        Ok(true)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        Ok(self.send_queue_requests.read().unwrap().get(room_id).cloned().unwrap_or_default())
+    }
+
+    async fn load_next_send_queue_request(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<QueuedRequest>> {
+        Ok(self
+            .send_queue_requests
+            .read()
+            .unwrap()
+            .get(room_id)
+            .and_then(|requests| requests.first().cloned()))
+    }
+
+    async fn load_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<Option<QueuedRequest>> {
+        Ok(self.send_queue_requests.read().unwrap().get(room_id).and_then(|requests| {
+            requests.iter().find(|r| r.transaction_id == transaction_id).cloned()
+        }))
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        Ok(self.send_queue_requests.read().unwrap().keys().cloned().collect())
+    }
 }
 
 #[cfg(test)]
@@ -206,6 +206,8 @@ impl BaseRoomInfoV1 {
             rtc_member: BTreeMap::new(),
             is_marked_unread: false,
             notable_tags: RoomNotableTags::empty(),
+            favourite_order: None,
+            pinned_events: None,
         })
     }
 }
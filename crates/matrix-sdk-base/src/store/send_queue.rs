@@ -0,0 +1,91 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The types of the requests persisted by the send queue of a room.
+
+use ruma::{
+    events::{room::MediaSource, AnyMessageLikeEventContent},
+    serde::Raw,
+    OwnedEventId, OwnedMxcUri, OwnedTransactionId, TransactionId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::media::{MediaFormat, MediaRequest};
+
+/// A request waiting to be sent by the send queue of a room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct QueuedRequest {
+    /// The transaction ID of the request, which is also the transaction ID of
+    /// its local echo.
+    pub transaction_id: OwnedTransactionId,
+
+    /// What should be sent.
+    pub kind: QueuedRequestKind,
+}
+
+impl QueuedRequest {
+    /// Create a new `QueuedRequest` with a random transaction ID.
+    pub fn new(kind: QueuedRequestKind) -> Self {
+        Self { transaction_id: TransactionId::new(), kind }
+    }
+
+    /// The media request under which the content of the file of this request
+    /// is kept in the media store, if it's an attachment, until it's been
+    /// sent.
+    ///
+    /// The file is keyed by the transaction ID of the request, with a local
+    /// MXC URI that can't clash with a real one.
+    pub fn attachment_media_request(&self) -> MediaRequest {
+        let uri = OwnedMxcUri::from(format!("mxc://send-queue.localhost/{}", self.transaction_id));
+        MediaRequest { source: MediaSource::Plain(uri), format: MediaFormat::File }
+    }
+}
+
+/// The different kinds of requests that can be queued in the send queue of a
+/// room.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum QueuedRequestKind {
+    /// A message-like event, including reactions.
+    Event {
+        /// The type of the event.
+        event_type: String,
+
+        /// The content of the event.
+        content: Raw<AnyMessageLikeEventContent>,
+    },
+
+    /// A file that must be uploaded before sending the event referencing it.
+    ///
+    /// The content of the file isn't part of the request, it's kept in the
+    /// media store, see [`QueuedRequest::attachment_media_request`].
+    Attachment {
+        /// The name of the file.
+        filename: String,
+
+        /// The media type of the file.
+        content_type: String,
+
+        /// An optional caption to send along the file.
+        caption: Option<String>,
+    },
+
+    /// A redaction of an event.
+    Redaction {
+        /// The ID of the event to redact.
+        redacts: OwnedEventId,
+
+        /// The reason of the redaction, if any.
+        reason: Option<String>,
+    },
+}
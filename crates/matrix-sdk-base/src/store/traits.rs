@@ -32,10 +32,10 @@ use ruma::{
         RoomAccountDataEventType, StateEventType, StaticEventContent, StaticStateEventContent,
     },
     serde::Raw,
-    EventId, MxcUri, OwnedEventId, OwnedUserId, RoomId, UserId,
+    EventId, MxcUri, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, TransactionId, UserId,
 };
 
-use super::{StateChanges, StoreError};
+use super::{QueuedRequest, StateChanges, StoreError};
 use crate::{
     deserialized_responses::{RawAnySyncOrStrippedState, RawMemberEvent, RawSyncOrStrippedState},
     media::MediaRequest,
@@ -388,6 +388,77 @@ pub trait StateStore: AsyncTraitDeps {
     ///
     /// * `room_id` - The `RoomId` of the room to delete.
     async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error>;
+
+    /// Save a request to be sent by the send queue of a room.
+    ///
+    /// Requests are loaded back in the order in which they were saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the request must be sent in.
+    ///
+    /// * `request` - The request to save.
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<(), Self::Error>;
+
+    /// Remove a request that was saved for the send queue of a room, usually
+    /// because it has been sent or was cancelled.
+    ///
+    /// Returns whether a request with the given transaction ID was found.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the request was queued in.
+    ///
+    /// * `transaction_id` - The transaction ID of the request to remove.
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error>;
+
+    /// Load the requests waiting in the send queue of a room, in the order in
+    /// which they were saved.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room.
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error>;
+
+    /// Load the request at the head of the send queue of a room, i.e. the
+    /// oldest one that was saved, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room.
+    async fn load_next_send_queue_request(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<QueuedRequest>, Self::Error>;
+
+    /// Load the request with the given transaction ID from the send queue of a
+    /// room, if it's there.
+    ///
+    /// # Arguments
+    ///
+    /// * `room_id` - The `RoomId` of the room the request was queued in.
+    ///
+    /// * `transaction_id` - The transaction ID of the request.
+    async fn load_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<Option<QueuedRequest>, Self::Error>;
+
+    /// Load the IDs of the rooms that have requests waiting in their send
+    /// queue.
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error>;
 }
 
 #[repr(transparent)]
@@ -611,6 +682,48 @@ impl<T: StateStore> StateStore for EraseStateStoreError<T> {
     async fn remove_room(&self, room_id: &RoomId) -> Result<(), Self::Error> {
         self.0.remove_room(room_id).await.map_err(Into::into)
     }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<(), Self::Error> {
+        self.0.save_send_queue_request(room_id, request).await.map_err(Into::into)
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool, Self::Error> {
+        self.0.remove_send_queue_request(room_id, transaction_id).await.map_err(Into::into)
+    }
+
+    async fn load_send_queue_requests(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<QueuedRequest>, Self::Error> {
+        self.0.load_send_queue_requests(room_id).await.map_err(Into::into)
+    }
+
+    async fn load_next_send_queue_request(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<QueuedRequest>, Self::Error> {
+        self.0.load_next_send_queue_request(room_id).await.map_err(Into::into)
+    }
+
+    async fn load_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<Option<QueuedRequest>, Self::Error> {
+        self.0.load_send_queue_request(room_id, transaction_id).await.map_err(Into::into)
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>, Self::Error> {
+        self.0.load_rooms_with_unsent_requests().await.map_err(Into::into)
+    }
 }
 
 /// Convenience functionality for state stores.
@@ -223,6 +223,44 @@ pub struct EncryptionInfo {
     pub verification_state: VerificationState,
 }
 
+/// The reason why an encrypted event couldn't be decrypted, to tell the user
+/// whether the event might still be decrypted later on.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq, Hash)]
+pub enum UtdCause {
+    /// We don't have the room key that was used to encrypt the event yet, it
+    /// might still arrive later.
+    MissingRoomKey,
+
+    /// The sender refused to share the room key with us, with the given
+    /// `m.room_key.withheld` code, e.g. `m.blacklisted`.
+    Withheld {
+        /// The code sent by the sender, e.g. `m.unavailable`.
+        code: String,
+    },
+
+    /// The sender doesn't share room keys with unverified devices, and our
+    /// device isn't verified.
+    ///
+    /// This is reported with the `m.unverified` withheld code.
+    UnverifiedDevice,
+
+    /// The event was sent before our device existed, so the sender couldn't
+    /// share the room key with us.
+    SentBeforeDeviceExisted,
+
+    /// We have the room key that was used to encrypt the event, but only from
+    /// a later point of the session, so the event can't be decrypted with it.
+    UnknownMessageIndex,
+
+    /// The identity keys of the device that sent us the room key don't match
+    /// the ones recorded in the room key, so it can't be trusted.
+    MismatchedSenderKeys,
+
+    /// The event couldn't be decrypted for another reason.
+    #[default]
+    Unknown,
+}
+
 /// A customized version of a room event coming from a sync that holds optional
 /// encryption info.
 #[derive(Clone, Deserialize, Serialize)]
@@ -235,6 +273,10 @@ pub struct SyncTimelineEvent {
     /// The push actions associated with this event.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub push_actions: Vec<Action>,
+    /// Why the event couldn't be decrypted, if it's an encrypted event that we
+    /// failed to decrypt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub utd_cause: Option<UtdCause>,
 }
 
 impl SyncTimelineEvent {
@@ -243,7 +285,7 @@ impl SyncTimelineEvent {
     /// This is a convenience constructor for when you don't need to set
     /// `encryption_info` or `push_action`, for example inside a test.
     pub fn new(event: Raw<AnySyncTimelineEvent>) -> Self {
-        Self { event, encryption_info: None, push_actions: vec![] }
+        Self { event, encryption_info: None, push_actions: vec![], utd_cause: None }
     }
 
     /// Get the event id of this `SyncTimelineEvent` if the event has any valid
@@ -256,20 +298,21 @@ impl SyncTimelineEvent {
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for SyncTimelineEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let SyncTimelineEvent { event, encryption_info, push_actions } = self;
+        let SyncTimelineEvent { event, encryption_info, push_actions, utd_cause } = self;
         let mut s = f.debug_struct("SyncTimelineEvent");
         s.field("event", &DebugRawEvent(event));
         s.maybe_field("encryption_info", encryption_info);
         if !push_actions.is_empty() {
             s.field("push_actions", push_actions);
         }
+        s.maybe_field("utd_cause", utd_cause);
         s.finish()
     }
 }
 
 impl From<Raw<AnySyncTimelineEvent>> for SyncTimelineEvent {
     fn from(inner: Raw<AnySyncTimelineEvent>) -> Self {
-        Self { encryption_info: None, event: inner, push_actions: Vec::default() }
+        Self { encryption_info: None, event: inner, push_actions: Vec::default(), utd_cause: None }
     }
 }
 
@@ -283,6 +326,7 @@ impl From<TimelineEvent> for SyncTimelineEvent {
             event: o.event.cast(),
             encryption_info: o.encryption_info,
             push_actions: o.push_actions.unwrap_or_default(),
+            utd_cause: o.utd_cause,
         }
     }
 }
@@ -297,6 +341,9 @@ pub struct TimelineEvent {
     /// The push actions associated with this event, if we had sufficient
     /// context to compute them.
     pub push_actions: Option<Vec<Action>>,
+    /// Why the event couldn't be decrypted, if it's an encrypted event that we
+    /// failed to decrypt.
+    pub utd_cause: Option<UtdCause>,
 }
 
 impl TimelineEvent {
@@ -305,14 +352,14 @@ impl TimelineEvent {
     /// This is a convenience constructor for when you don't need to set
     /// `encryption_info` or `push_action`, for example inside a test.
     pub fn new(event: Raw<AnyTimelineEvent>) -> Self {
-        Self { event, encryption_info: None, push_actions: None }
+        Self { event, encryption_info: None, push_actions: None, utd_cause: None }
     }
 }
 
 #[cfg(not(tarpaulin_include))]
 impl fmt::Debug for TimelineEvent {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let TimelineEvent { event, encryption_info, push_actions } = self;
+        let TimelineEvent { event, encryption_info, push_actions, utd_cause } = self;
         let mut s = f.debug_struct("TimelineEvent");
         s.field("event", &DebugRawEvent(event));
         s.maybe_field("encryption_info", encryption_info);
@@ -321,6 +368,7 @@ impl fmt::Debug for TimelineEvent {
                 s.field("push_actions", push_actions);
             }
         }
+        s.maybe_field("utd_cause", utd_cause);
         s.finish()
     }
 }
@@ -69,6 +69,15 @@ pub enum OlmError {
             have a valid Olm session with us"
     )]
     MissingSession,
+
+    /// The room key couldn't be shared because the identity of some members of
+    /// the room changed without the change being acknowledged.
+    ///
+    /// See [`EncryptionSettings::error_on_identity_violation`].
+    ///
+    /// [`EncryptionSettings::error_on_identity_violation`]: crate::EncryptionSettings::error_on_identity_violation
+    #[error("the identity of some users changed and needs to be acknowledged: {0:?}")]
+    IdentityViolation(Vec<OwnedUserId>),
 }
 
 /// Error representing a failure during a group encryption operation.
@@ -34,9 +34,11 @@ use ruma::{
     events::secret::request::{
         RequestAction, SecretName, ToDeviceSecretRequestEvent as SecretRequestEvent,
     },
-    DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedTransactionId, OwnedUserId, RoomId,
-    TransactionId, UserId,
+    DeviceId, DeviceKeyAlgorithm, OwnedDeviceId, OwnedRoomId, OwnedTransactionId, OwnedUserId,
+    RoomId, TransactionId, UserId,
 };
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
 use tracing::{debug, field::debug, info, instrument, trace, warn, Span};
 use vodozemac::{megolm::SessionOrdering, Curve25519PublicKey};
 
@@ -44,7 +46,7 @@ use super::{GossipRequest, GossippedSecret, RequestEvent, RequestInfo, SecretInf
 use crate::{
     error::{EventError, OlmError, OlmResult},
     identities::IdentityManager,
-    olm::{InboundGroupSession, Session},
+    olm::{InboundGroupSession, PickledInboundGroupSession, Session},
     requests::{OutgoingRequest, ToDeviceRequest},
     session_manager::GroupSessionCache,
     store::{Changes, CryptoStoreError, SecretImportError, Store, StoreCache},
@@ -59,6 +61,32 @@ use crate::{
     Device, MegolmError,
 };
 
+/// The key of the custom value of the store holding the room keys with a
+/// shared history that are waiting for us to accept an invite.
+const PENDING_SHARED_HISTORY_KEYS: &str = "pending_shared_history_keys";
+
+/// The maximum number of room keys with a shared history that are kept per
+/// room and sender while waiting for us to accept an invite.
+const MAX_SHARED_HISTORY_KEYS_PER_ROOM: usize = 100;
+
+/// The maximum number of rooms for which room keys with a shared history sent
+/// by the same user are kept while waiting for us to accept an invite.
+const MAX_SHARED_HISTORY_ROOMS_PER_SENDER: usize = 10;
+
+/// A room key with a shared history that was forwarded to us without us
+/// requesting it, as defined in [MSC3061].
+///
+/// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+#[derive(Serialize, Deserialize)]
+struct PendingSharedHistoryKey {
+    /// The user that forwarded the room key.
+    sender: OwnedUserId,
+    /// The ID of the session of the room key.
+    session_id: String,
+    /// The room key.
+    session: PickledInboundGroupSession,
+}
+
 #[derive(Clone, Debug)]
 pub(crate) struct GossipMachine {
     inner: Arc<GossipMachineInner>,
@@ -80,6 +108,13 @@ pub(crate) struct GossipMachineInner {
     /// Whether we should send out `m.room_key_request` messages.
     room_key_requests_enabled: AtomicBool,
 
+    /// Lock held while updating the room keys with a shared history that were
+    /// forwarded to us without us requesting them, and that are kept in the
+    /// store until we accept the invite of the user that forwarded them.
+    ///
+    /// See [`GossipMachine::accept_shared_history_room_keys`].
+    pending_shared_history_keys_lock: Mutex<()>,
+
     identity_manager: IdentityManager,
 }
 
@@ -107,6 +142,7 @@ impl GossipMachine {
                 users_for_key_claim,
                 room_key_forwarding_enabled,
                 room_key_requests_enabled,
+                pending_shared_history_keys_lock: Default::default(),
                 identity_manager,
             }),
         }
@@ -1035,6 +1071,10 @@ impl GossipMachine {
         let Some(request) =
             self.inner.store.get_secret_request_by_info(&info.clone().into()).await?
         else {
+            if event.content.shared_history() {
+                return self.receive_shared_history_room_key(sender_key, event).await;
+            }
+
             warn!(
                 sender_key = ?sender_key,
                 room_id = ?info.room_id(),
@@ -1061,6 +1101,196 @@ impl GossipMachine {
         }
     }
 
+    /// Receive a forwarded room key that we didn't request, but that can be
+    /// shared with users invited to the room, as defined in [MSC3061].
+    ///
+    /// The room key is kept aside in the store until we accept the invite of
+    /// the user who forwarded it to us, so it's never imported on its own. At
+    /// most [`MAX_SHARED_HISTORY_KEYS_PER_ROOM`] room keys are kept per room
+    /// and sender, and for at most [`MAX_SHARED_HISTORY_ROOMS_PER_SENDER`]
+    /// rooms per sender.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    async fn receive_shared_history_room_key(
+        &self,
+        sender_key: Curve25519PublicKey,
+        event: &DecryptedForwardedRoomKeyEvent,
+    ) -> Result<Option<InboundGroupSession>, CryptoStoreError> {
+        if self.inner.store.get_device_from_curve_key(&event.sender, sender_key).await?.is_none() {
+            warn!(
+                sender = ?event.sender,
+                ?sender_key,
+                "Received a room key with a shared history from an unknown device",
+            );
+            return Ok(None);
+        }
+
+        let session = match InboundGroupSession::try_from(event) {
+            Ok(session) => session,
+            Err(e) => {
+                warn!(?sender_key, "Couldn't create a group session from a received room key");
+                return Err(e.into());
+            }
+        };
+
+        let _guard = self.inner.pending_shared_history_keys_lock.lock().await;
+        let mut pending = self.load_pending_shared_history_keys().await?;
+
+        let sender_room_count =
+            pending.values().filter(|keys| keys.iter().any(|k| k.sender == event.sender)).count();
+        let room_keys = pending.entry(session.room_id().to_owned()).or_default();
+        let sender_keys = room_keys.iter().filter(|k| k.sender == event.sender);
+
+        if sender_keys.clone().next().is_none()
+            && sender_room_count >= MAX_SHARED_HISTORY_ROOMS_PER_SENDER
+        {
+            warn!(
+                sender = ?event.sender,
+                room_id = ?session.room_id(),
+                "Discarding a room key with a shared history, too many rooms have pending \
+                 room keys from the sender",
+            );
+            return Ok(None);
+        }
+
+        if sender_keys.clone().count() >= MAX_SHARED_HISTORY_KEYS_PER_ROOM {
+            warn!(
+                sender = ?event.sender,
+                room_id = ?session.room_id(),
+                "Discarding a room key with a shared history, too many room keys of the room \
+                 are pending from the sender",
+            );
+            return Ok(None);
+        }
+
+        if sender_keys.clone().any(|k| k.session_id == session.session_id()) {
+            debug!(
+                sender = ?event.sender,
+                session_id = session.session_id(),
+                "Received a room key with a shared history that is already pending",
+            );
+            return Ok(None);
+        }
+
+        info!(
+            sender = ?event.sender,
+            ?sender_key,
+            room_id = ?session.room_id(),
+            session_id = session.session_id(),
+            "Received a room key with a shared history, keeping it until we accept the invite \
+             of the sender",
+        );
+
+        room_keys.push(PendingSharedHistoryKey {
+            sender: event.sender.to_owned(),
+            session_id: session.session_id().to_owned(),
+            session: session.pickle().await,
+        });
+        self.save_pending_shared_history_keys(&pending).await?;
+
+        Ok(None)
+    }
+
+    async fn load_pending_shared_history_keys(
+        &self,
+    ) -> Result<BTreeMap<OwnedRoomId, Vec<PendingSharedHistoryKey>>, CryptoStoreError> {
+        Ok(self.inner.store.get_value(PENDING_SHARED_HISTORY_KEYS).await?.unwrap_or_default())
+    }
+
+    async fn save_pending_shared_history_keys(
+        &self,
+        pending: &BTreeMap<OwnedRoomId, Vec<PendingSharedHistoryKey>>,
+    ) -> Result<(), CryptoStoreError> {
+        if pending.is_empty() {
+            self.inner.store.remove_custom_value(PENDING_SHARED_HISTORY_KEYS).await
+        } else {
+            self.inner.store.set_value(PENDING_SHARED_HISTORY_KEYS, pending).await
+        }
+    }
+
+    /// Accept the room keys with a shared history of the given room that were
+    /// forwarded to us by the given user, usually once we accept their invite
+    /// to the room.
+    ///
+    /// Returns the sessions that are better than the ones we already have, and
+    /// that need to be saved. The room keys forwarded by other users are
+    /// discarded.
+    pub async fn accept_shared_history_room_keys(
+        &self,
+        room_id: &RoomId,
+        inviter: &UserId,
+    ) -> Result<Vec<InboundGroupSession>, CryptoStoreError> {
+        let _guard = self.inner.pending_shared_history_keys_lock.lock().await;
+        let mut pending_keys = self.load_pending_shared_history_keys().await?;
+
+        let Some(pending) = pending_keys.remove(room_id) else {
+            return Ok(Vec::new());
+        };
+
+        self.save_pending_shared_history_keys(&pending_keys).await?;
+
+        let mut sessions = Vec::new();
+
+        for PendingSharedHistoryKey { sender, session_id, session } in pending {
+            if sender != inviter {
+                debug!(
+                    ?sender,
+                    session_id,
+                    "Discarding a room key with a shared history that wasn't forwarded by the \
+                     inviter",
+                );
+                continue;
+            }
+
+            let session = InboundGroupSession::from_pickle(session)?;
+
+            if self.inner.store.compare_group_session(&session).await? == SessionOrdering::Better {
+                sessions.push(session);
+            }
+        }
+
+        Ok(sessions)
+    }
+
+    /// Get the senders of the room keys with a shared history that are
+    /// waiting for us to accept their invite, grouped by room.
+    pub async fn shared_history_room_key_senders(
+        &self,
+    ) -> Result<BTreeMap<OwnedRoomId, BTreeSet<OwnedUserId>>, CryptoStoreError> {
+        let _guard = self.inner.pending_shared_history_keys_lock.lock().await;
+
+        Ok(self
+            .load_pending_shared_history_keys()
+            .await?
+            .into_iter()
+            .map(|(room_id, keys)| (room_id, keys.into_iter().map(|k| k.sender).collect()))
+            .collect())
+    }
+
+    /// Discard the room keys with a shared history of the given room that were
+    /// forwarded to us by the given user, because we don't have an invite from
+    /// them.
+    pub async fn discard_shared_history_room_keys(
+        &self,
+        room_id: &RoomId,
+        sender: &UserId,
+    ) -> Result<(), CryptoStoreError> {
+        let _guard = self.inner.pending_shared_history_keys_lock.lock().await;
+        let mut pending = self.load_pending_shared_history_keys().await?;
+
+        let Entry::Occupied(mut room_keys) = pending.entry(room_id.to_owned()) else {
+            return Ok(());
+        };
+
+        room_keys.get_mut().retain(|k| k.sender != sender);
+
+        if room_keys.get().is_empty() {
+            room_keys.remove();
+        }
+
+        self.save_pending_shared_history_keys(&pending).await
+    }
+
     /// Receive a forwarded room key event.
     pub async fn receive_forwarded_room_key(
         &self,
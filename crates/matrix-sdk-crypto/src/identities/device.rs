@@ -433,7 +433,8 @@ impl Device {
             } else {
                 session.export().await
             };
-            let content: ForwardedRoomKeyContent = export.try_into()?;
+            let mut content: ForwardedRoomKeyContent = export.try_into()?;
+            content.set_shared_history(session.shared_history());
 
             (content.event_type(), content)
         };
@@ -649,7 +649,10 @@ impl IdentityManager {
     ///   the `/keys/query` response.
     /// * `self_signing` - The public self-signing key from the `/keys/query`
     ///   response.
+    /// * `own_identity` - Our own stored identity, if any, used to check
+    ///   whether the identity of another user has been verified by us.
     #[instrument(skip_all, fields(user_id))]
+    #[allow(clippy::too_many_arguments)]
     async fn update_or_create_identity(
         &self,
         response: &KeysQueryResponse,
@@ -658,10 +661,15 @@ impl IdentityManager {
         user_id: &UserId,
         master_key: MasterPubkey,
         self_signing: SelfSigningPubkey,
+        own_identity: Option<&ReadOnlyOwnUserIdentity>,
     ) -> StoreResult<()> {
         if master_key.user_id() != user_id || self_signing.user_id() != user_id {
             warn!(?user_id, "User ID mismatch in one of the cross signing keys");
         } else if let Some(i) = self.store.get_user_identity(user_id).await? {
+            // Remember if we verified the identity before it gets updated, so a
+            // change of its master key is detected as a verification violation.
+            Self::check_previously_verified(&i, own_identity);
+
             // an identity we knew about before, which is being updated
             match self
                 .handle_changed_identity(
@@ -675,6 +683,7 @@ impl IdentityManager {
             {
                 Ok(IdentityUpdateResult::Updated(identity)) => {
                     trace!(?identity, "Updated a user identity");
+                    Self::check_previously_verified(&identity, own_identity);
                     changes.changed.push(identity);
                 }
                 Ok(IdentityUpdateResult::Unchanged(identity)) => {
@@ -693,6 +702,7 @@ impl IdentityManager {
             {
                 Ok(identity) => {
                     trace!(?identity, "Created new user identity");
+                    Self::check_previously_verified(&identity, own_identity);
                     changes.new.push(identity);
                 }
                 Err(e) => {
@@ -704,6 +714,26 @@ impl IdentityManager {
         Ok(())
     }
 
+    /// Mark the identity of another user as previously verified if it's
+    /// signed by our own identity.
+    ///
+    /// This is used to detect a [verification violation] if the identity
+    /// changes later on.
+    ///
+    /// [verification violation]: crate::UserIdentity::has_verification_violation
+    fn check_previously_verified(
+        identity: &ReadOnlyUserIdentities,
+        own_identity: Option<&ReadOnlyOwnUserIdentity>,
+    ) {
+        if let (ReadOnlyUserIdentities::Other(identity), Some(own_identity)) =
+            (identity, own_identity)
+        {
+            if own_identity.is_identity_signed(identity).is_ok() {
+                identity.mark_as_previously_verified();
+            }
+        }
+    }
+
     /// Handle the cross signing keys part of a key query response.
     ///
     /// # Arguments
@@ -726,6 +756,9 @@ impl IdentityManager {
         let mut changes = IdentityChanges::default();
         let mut changed_identity = None;
 
+        let own_identity =
+            self.store.get_user_identity(self.user_id()).await?.and_then(|i| i.into_own());
+
         for (user_id, master_key) in &response.master_keys {
             // Get the master and self-signing key for each identity; those are required for
             // every user identity type. If we don't have those we skip over.
@@ -742,6 +775,7 @@ impl IdentityManager {
                 user_id,
                 master_key,
                 self_signing,
+                own_identity.as_ref(),
             )
             .await?;
         }
@@ -1340,7 +1374,10 @@ pub(crate) mod tests {
         device_id, key_query, manager_test_helper, other_key_query, other_user_id, user_id,
     };
     use crate::{
-        identities::manager::testing::{other_key_query_cross_signed, own_key_query},
+        identities::{
+            manager::testing::{other_key_query_cross_signed, own_key_query},
+            ReadOnlyUserIdentity,
+        },
         olm::PrivateCrossSigningIdentity,
     };
 
@@ -1789,4 +1826,91 @@ pub(crate) mod tests {
 
         manager.take();
     }
+
+    /// A `/keys/query` response containing only the given cross-signing keys
+    /// of the other user.
+    fn other_identity_key_query(
+        master_key: impl serde::Serialize,
+        self_signing_key: impl serde::Serialize,
+    ) -> KeysQueryResponse {
+        let response = json!({
+            "device_keys": {},
+            "failures": {},
+            "master_keys": {
+                other_user_id().as_str(): master_key,
+            },
+            "self_signing_keys": {
+                other_user_id().as_str(): self_signing_key,
+            },
+        });
+
+        KeysQueryResponse::try_from_http_response(response_from_file(&response)).unwrap()
+    }
+
+    #[async_test]
+    async fn test_identity_signed_by_us_is_previously_verified() {
+        let manager = manager_test_helper(user_id(), device_id()).await;
+        let private_identity = manager.store.private_identity();
+
+        // Our own identity is known.
+        let identity_request = private_identity.lock().await.as_upload_request().await;
+        let device_keys =
+            manager.store.cache().await.unwrap().account().await.unwrap().device_keys();
+        manager
+            .receive_keys_query_response(
+                &TransactionId::new(),
+                &key_query(identity_request, device_keys),
+            )
+            .await
+            .unwrap();
+
+        let own_identity = manager.store.get_user_identity(user_id()).await.unwrap().unwrap();
+        let own_identity = own_identity.own().unwrap();
+
+        // The identity of the other user is signed by our user-signing key.
+        let other_private_identity = PrivateCrossSigningIdentity::new(other_user_id().to_owned());
+        let other_identity = ReadOnlyUserIdentity::from_private(&other_private_identity).await;
+        let signatures = private_identity.lock().await.sign_user(&other_identity).await.unwrap();
+        let signed_master_key = serde_json::to_value(&signatures.signed_keys).unwrap()
+            [other_user_id().as_str()]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap()
+        .clone();
+        let other_request = other_private_identity.as_upload_request().await;
+
+        manager
+            .receive_keys_query_response(
+                &TransactionId::new(),
+                &other_identity_key_query(signed_master_key, other_request.self_signing_key),
+            )
+            .await
+            .unwrap();
+
+        let identity = manager.store.get_user_identity(other_user_id()).await.unwrap().unwrap();
+        let identity = identity.other().unwrap();
+        assert!(identity.was_previously_verified());
+        assert!(!identity.has_pin_violation());
+        assert!(!identity.needs_user_approval(Some(own_identity)));
+
+        // The identity of the other user changes, and isn't signed by us anymore.
+        let new_private_identity = PrivateCrossSigningIdentity::new(other_user_id().to_owned());
+        let new_request = new_private_identity.as_upload_request().await;
+
+        manager
+            .receive_keys_query_response(
+                &TransactionId::new(),
+                &other_identity_key_query(new_request.master_key, new_request.self_signing_key),
+            )
+            .await
+            .unwrap();
+
+        let identity = manager.store.get_user_identity(other_user_id()).await.unwrap().unwrap();
+        let identity = identity.other().unwrap();
+        assert!(identity.was_previously_verified());
+        assert!(identity.has_pin_violation());
+        assert!(identity.needs_user_approval(Some(own_identity)));
+    }
 }
@@ -17,7 +17,7 @@ use std::{
     ops::Deref,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        Arc, RwLock,
     },
 };
 
@@ -254,6 +254,61 @@ impl UserIdentity {
         }
     }
 
+    /// Has the identity of this user changed since we first saw it, or since
+    /// we last acknowledged it, without us having verified the new identity?
+    ///
+    /// This is the case if the identity isn't verified, and:
+    ///
+    /// * The master key of the user changed since it was pinned, see
+    ///   [`ReadOnlyUserIdentity::has_pin_violation()`]. The change can be
+    ///   acknowledged with [`UserIdentity::pin_current_identity()`].
+    /// * The user was verified in the past but isn't verified anymore, see
+    ///   [`UserIdentity::has_verification_violation()`]. The change can be
+    ///   acknowledged with [`UserIdentity::withdraw_verification()`].
+    pub fn identity_needs_user_approval(&self) -> bool {
+        self.inner.needs_user_approval(self.own_identity.as_ref())
+    }
+
+    /// Was this user verified in the past, but isn't verified anymore, most
+    /// likely because their identity changed?
+    pub fn has_verification_violation(&self) -> bool {
+        self.inner.was_previously_verified() && !self.is_verified()
+    }
+
+    /// Acknowledge a change of the identity of this user, by pinning its
+    /// current master key.
+    ///
+    /// This doesn't clear a [verification violation], which needs to be
+    /// acknowledged with [`UserIdentity::withdraw_verification()`].
+    ///
+    /// [verification violation]: UserIdentity::has_verification_violation
+    pub async fn pin_current_identity(&self) -> Result<(), CryptoStoreError> {
+        self.inner.pin();
+        self.save().await
+    }
+
+    /// Acknowledge that this user, who was verified in the past, isn't
+    /// verified anymore, and pin their current master key.
+    ///
+    /// The user needs to be verified again to be considered as verified.
+    pub async fn withdraw_verification(&self) -> Result<(), CryptoStoreError> {
+        self.inner.withdraw_verification();
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<(), CryptoStoreError> {
+        let changes = Changes {
+            identities: IdentityChanges {
+                changed: vec![self.inner.clone().into()],
+                new: vec![],
+                unchanged: vec![],
+            },
+            ..Default::default()
+        };
+
+        self.verification_machine.store.save_changes(changes).await
+    }
+
     /// Create a `VerificationRequest` object after the verification request
     /// content has been sent out.
     pub async fn request_verification(
@@ -366,11 +421,27 @@ impl ReadOnlyUserIdentities {
 /// This is the user identity of a user that isn't our own. Other users will
 /// only contain a master key and a self signing key, meaning that only device
 /// signatures can be checked with this identity.
+///
+/// The first master key we see for a user is pinned, and a change of the master
+/// key is considered as a violation until the new one is pinned. This protects
+/// against a malicious homeserver replacing the identity of a user.
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ReadOnlyUserIdentity {
     user_id: OwnedUserId,
     pub(crate) master_key: MasterPubkey,
     self_signing_key: SelfSigningPubkey,
+    /// The pinned master key, if it differs from the current one.
+    ///
+    /// `None` means that the current master key is the pinned one.
+    #[serde(default)]
+    pinned_master_key: Arc<RwLock<Option<MasterPubkey>>>,
+    /// Whether this identity was verified by us at some point.
+    #[serde(
+        default,
+        serialize_with = "atomic_bool_serializer",
+        deserialize_with = "atomic_bool_deserializer"
+    )]
+    previously_verified: Arc<AtomicBool>,
 }
 
 impl PartialEq for ReadOnlyUserIdentity {
@@ -411,7 +482,13 @@ impl ReadOnlyUserIdentity {
     ) -> Result<Self, SignatureError> {
         master_key.verify_subkey(&self_signing_key)?;
 
-        Ok(Self { user_id: master_key.user_id().into(), master_key, self_signing_key })
+        Ok(Self {
+            user_id: master_key.user_id().into(),
+            master_key,
+            self_signing_key,
+            pinned_master_key: Default::default(),
+            previously_verified: Default::default(),
+        })
     }
 
     #[cfg(test)]
@@ -420,7 +497,13 @@ impl ReadOnlyUserIdentity {
         let self_signing_key =
             identity.self_signing_key.lock().await.as_ref().unwrap().public_key.clone();
 
-        Self { user_id: identity.user_id().into(), master_key, self_signing_key }
+        Self {
+            user_id: identity.user_id().into(),
+            master_key,
+            self_signing_key,
+            pinned_master_key: Default::default(),
+            previously_verified: Default::default(),
+        }
     }
 
     /// Get the user id of this identity.
@@ -438,8 +521,61 @@ impl ReadOnlyUserIdentity {
         &self.self_signing_key
     }
 
+    /// Has the master key of this identity changed since it was pinned?
+    ///
+    /// The first master key we see for a user is pinned, a new master key can
+    /// be pinned with [`UserIdentity::pin_current_identity()`].
+    pub fn has_pin_violation(&self) -> bool {
+        self.pinned_master_key
+            .read()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|pinned| *pinned != self.master_key)
+    }
+
+    /// Was this identity verified by us at some point?
+    ///
+    /// This is reset by [`UserIdentity::withdraw_verification()`].
+    pub fn was_previously_verified(&self) -> bool {
+        self.previously_verified.load(Ordering::SeqCst)
+    }
+
+    /// Remember that this identity was verified by us, which also pins its
+    /// current master key.
+    pub(crate) fn mark_as_previously_verified(&self) {
+        self.previously_verified.store(true, Ordering::SeqCst);
+        self.pin();
+    }
+
+    /// Pin the current master key of this identity.
+    pub(crate) fn pin(&self) {
+        *self.pinned_master_key.write().unwrap() = None;
+    }
+
+    /// Forget that this identity was verified by us, and pin its current
+    /// master key.
+    pub(crate) fn withdraw_verification(&self) {
+        self.previously_verified.store(false, Ordering::SeqCst);
+        self.pin();
+    }
+
+    /// Does a change of this identity need to be acknowledged by the user?
+    ///
+    /// See [`UserIdentity::identity_needs_user_approval()`].
+    pub(crate) fn needs_user_approval(
+        &self,
+        own_identity: Option<&ReadOnlyOwnUserIdentity>,
+    ) -> bool {
+        let is_verified = own_identity.is_some_and(|o| o.is_identity_signed(self).is_ok());
+
+        !is_verified && (self.has_pin_violation() || self.was_previously_verified())
+    }
+
     /// Update the identity with a new master key and self signing key.
     ///
+    /// If the master key changes, the previous one stays pinned, unless the
+    /// new master key is the pinned one.
+    ///
     /// # Arguments
     ///
     /// * `master_key` - The new master key of the user identity.
@@ -459,6 +595,20 @@ impl ReadOnlyUserIdentity {
         let new = Self::new(master_key, self_signing_key)?;
         let changed = new != *self;
 
+        let pinned_master_key = {
+            let pinned = self.pinned_master_key.read().unwrap();
+
+            match pinned.as_ref() {
+                Some(pinned) if *pinned == new.master_key => None,
+                Some(pinned) => Some(pinned.clone()),
+                None if self.master_key != new.master_key => Some(self.master_key.clone()),
+                None => None,
+            }
+        };
+
+        *new.pinned_master_key.write().unwrap() = pinned_master_key;
+        new.previously_verified.store(self.was_previously_verified(), Ordering::SeqCst);
+
         *self = new;
         Ok(changed)
     }
@@ -784,7 +934,7 @@ pub(crate) mod tests {
 
     use super::{
         testing::{device, get_other_identity, get_own_identity},
-        ReadOnlyOwnUserIdentity, ReadOnlyUserIdentities,
+        ReadOnlyOwnUserIdentity, ReadOnlyUserIdentities, ReadOnlyUserIdentity,
     };
     use crate::{
         identities::{manager::testing::own_key_query, Device},
@@ -852,6 +1002,82 @@ pub(crate) mod tests {
         get_other_identity();
     }
 
+    #[async_test]
+    async fn other_identity_pin_violation() {
+        let user_id = user_id!("@bob:localhost");
+        let first = PrivateCrossSigningIdentity::new(user_id.to_owned());
+        let second = PrivateCrossSigningIdentity::new(user_id.to_owned());
+
+        let mut identity = ReadOnlyUserIdentity::from_private(&first).await;
+        let first_identity = identity.clone();
+        let second_identity = ReadOnlyUserIdentity::from_private(&second).await;
+
+        // The first master key we see is pinned.
+        assert!(!identity.has_pin_violation());
+
+        // A new master key is a violation.
+        let changed = identity
+            .update(
+                second_identity.master_key().clone(),
+                second_identity.self_signing_key().clone(),
+            )
+            .unwrap();
+        assert!(changed);
+        assert!(identity.has_pin_violation());
+
+        // The violation survives a round-trip through the store.
+        let serialized = serde_json::to_value(&identity).unwrap();
+        let deserialized: ReadOnlyUserIdentity = serde_json::from_value(serialized).unwrap();
+        assert!(deserialized.has_pin_violation());
+
+        // Going back to the pinned master key isn't a violation anymore.
+        identity
+            .update(first_identity.master_key().clone(), first_identity.self_signing_key().clone())
+            .unwrap();
+        assert!(!identity.has_pin_violation());
+
+        // Pinning the new master key acknowledges the change.
+        identity
+            .update(
+                second_identity.master_key().clone(),
+                second_identity.self_signing_key().clone(),
+            )
+            .unwrap();
+        assert!(identity.has_pin_violation());
+        identity.pin();
+        assert!(!identity.has_pin_violation());
+    }
+
+    #[async_test]
+    async fn other_identity_verification_violation() {
+        let user_id = user_id!("@bob:localhost");
+        let first = PrivateCrossSigningIdentity::new(user_id.to_owned());
+        let second = PrivateCrossSigningIdentity::new(user_id.to_owned());
+
+        let mut identity = ReadOnlyUserIdentity::from_private(&first).await;
+        let second_identity = ReadOnlyUserIdentity::from_private(&second).await;
+
+        identity.mark_as_previously_verified();
+        assert!(identity.was_previously_verified());
+        // We don't have an identity, so the user isn't verified anymore.
+        assert!(identity.needs_user_approval(None));
+
+        identity
+            .update(
+                second_identity.master_key().clone(),
+                second_identity.self_signing_key().clone(),
+            )
+            .unwrap();
+        assert!(identity.was_previously_verified());
+        assert!(identity.has_pin_violation());
+
+        // Withdrawing the verification acknowledges both violations.
+        identity.withdraw_verification();
+        assert!(!identity.was_previously_verified());
+        assert!(!identity.has_pin_violation());
+        assert!(!identity.needs_user_approval(None));
+    }
+
     #[test]
     fn own_identity_check_signatures() {
         let response = own_key_query();
@@ -13,14 +13,14 @@
 // limitations under the License.
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     sync::{Arc, RwLock as StdRwLock},
     time::Duration,
 };
 
 use itertools::Itertools;
 use matrix_sdk_common::deserialized_responses::{
-    AlgorithmInfo, DeviceLinkProblem, EncryptionInfo, TimelineEvent, VerificationLevel,
+    AlgorithmInfo, DeviceLinkProblem, EncryptionInfo, TimelineEvent, UtdCause, VerificationLevel,
     VerificationState,
 };
 use ruma::{
@@ -36,12 +36,12 @@ use ruma::{
     },
     assign,
     events::{
-        secret::request::SecretName, AnyMessageLikeEvent, AnyMessageLikeEventContent,
-        AnyToDeviceEvent, MessageLikeEventContent,
+        room::history_visibility::HistoryVisibility, secret::request::SecretName,
+        AnyMessageLikeEvent, AnyMessageLikeEventContent, AnyToDeviceEvent, MessageLikeEventContent,
     },
     serde::Raw,
     DeviceId, DeviceKeyAlgorithm, MilliSecondsSinceUnixEpoch, OwnedDeviceId, OwnedDeviceKeyId,
-    OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
+    OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, TransactionId, UInt, UserId,
 };
 use serde_json::value::to_raw_value;
 use tokio::sync::Mutex;
@@ -83,6 +83,7 @@ use crate::{
             room_key::{MegolmV1AesSha2Content, RoomKeyContent},
             room_key_withheld::{
                 MegolmV1AesSha2WithheldContent, RoomKeyWithheldContent, RoomKeyWithheldEvent,
+                WithheldCode,
             },
             ToDeviceEvents,
         },
@@ -947,6 +948,98 @@ impl OlmMachine {
         self.inner.group_session_manager.share_room_key(room_id, users, encryption_settings).await
     }
 
+    /// Get to-device requests to forward the room keys of a room with a
+    /// shared history to a user we invited to the room, as defined in
+    /// [MSC3061].
+    ///
+    /// Nothing is shared unless it was enabled with
+    /// [`Store::set_share_history_on_invite`], and only the room keys that were
+    /// created while the history of the room was visible to new members are
+    /// shared.
+    ///
+    /// The Olm sessions with the devices of the user must be established
+    /// beforehand, using [`OlmMachine::get_missing_sessions`].
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The room id of the room the user was invited to.
+    ///
+    /// `user_id` - The user that was invited to the room.
+    ///
+    /// `history_visibility` - The current history visibility of the room.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub async fn share_room_history_keys(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        history_visibility: HistoryVisibility,
+    ) -> OlmResult<Vec<Arc<ToDeviceRequest>>> {
+        self.inner
+            .group_session_manager
+            .share_history_room_keys(room_id, user_id, history_visibility)
+            .await
+    }
+
+    /// Import the room keys with a shared history that the given user
+    /// forwarded to us when they invited us to the given room, as defined in
+    /// [MSC3061].
+    ///
+    /// This should be called once we accept the invite. The room keys of the
+    /// room that were forwarded by other users are discarded.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub async fn accept_shared_history_room_keys(
+        &self,
+        room_id: &RoomId,
+        inviter: &UserId,
+    ) -> StoreResult<()> {
+        let sessions = self
+            .inner
+            .key_request_machine
+            .accept_shared_history_room_keys(room_id, inviter)
+            .await?;
+
+        if !sessions.is_empty() {
+            info!(
+                ?room_id,
+                ?inviter,
+                session_count = sessions.len(),
+                "Importing the room keys with a shared history of the room"
+            );
+
+            self.store().save_inbound_group_sessions(&sessions).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the users that forwarded us room keys with a shared history, as
+    /// defined in [MSC3061], that are kept until we accept their invite,
+    /// grouped by room.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub async fn shared_history_room_key_senders(
+        &self,
+    ) -> StoreResult<BTreeMap<OwnedRoomId, BTreeSet<OwnedUserId>>> {
+        self.inner.key_request_machine.shared_history_room_key_senders().await
+    }
+
+    /// Discard the room keys with a shared history of the given room that the
+    /// given user forwarded to us, as defined in [MSC3061].
+    ///
+    /// This should be called if we don't have an invite to the room from that
+    /// user.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub async fn discard_shared_history_room_keys(
+        &self,
+        room_id: &RoomId,
+        sender: &UserId,
+    ) -> StoreResult<()> {
+        self.inner.key_request_machine.discard_shared_history_room_keys(room_id, sender).await
+    }
+
     /// Receive an unencrypted verification event.
     ///
     /// This method can be used to pass verification events that are happening
@@ -1491,6 +1584,7 @@ impl OlmMachine {
                     encryption_info: Some(encryption_info),
                     event: decrypted_event,
                     push_actions: None,
+                    utd_cause: None,
                 })
             }
             Err(error) => Err(
@@ -1600,6 +1694,43 @@ impl OlmMachine {
         result
     }
 
+    /// Find out why the given event couldn't be decrypted, to tell the user
+    /// whether it may still be decrypted later on.
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event that couldn't be decrypted.
+    ///
+    /// * `error` - The error returned by [`OlmMachine::decrypt_room_event`] for
+    ///   this event.
+    pub fn utd_cause(&self, event: &Raw<EncryptedEvent>, error: &MegolmError) -> UtdCause {
+        match error {
+            MegolmError::MissingRoomKey(Some(WithheldCode::Unverified)) => {
+                UtdCause::UnverifiedDevice
+            }
+            MegolmError::MissingRoomKey(Some(code)) => {
+                UtdCause::Withheld { code: code.as_str().to_owned() }
+            }
+            MegolmError::MissingRoomKey(None) => {
+                let origin_server_ts = event
+                    .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                    .ok()
+                    .flatten();
+
+                if origin_server_ts.is_some_and(|ts| ts < self.device_creation_time()) {
+                    UtdCause::SentBeforeDeviceExisted
+                } else {
+                    UtdCause::MissingRoomKey
+                }
+            }
+            MegolmError::Decryption(DecryptionError::UnknownMessageIndex(_, _)) => {
+                UtdCause::UnknownMessageIndex
+            }
+            MegolmError::MismatchedIdentityKeys { .. } => UtdCause::MismatchedSenderKeys,
+            _ => UtdCause::Unknown,
+        }
+    }
+
     /// Do we have the room key for the given room and with the given session id
     /// in the store?
     pub async fn is_room_key_available(
@@ -2191,7 +2322,7 @@ pub(crate) mod testing {
 #[cfg(test)]
 pub(crate) mod tests {
     use std::{
-        collections::BTreeMap,
+        collections::{BTreeMap, BTreeSet},
         iter,
         sync::Arc,
         time::{Duration, SystemTime},
@@ -2202,7 +2333,7 @@ pub(crate) mod tests {
     use futures_util::{FutureExt, StreamExt};
     use itertools::Itertools;
     use matrix_sdk_common::deserialized_responses::{
-        DeviceLinkProblem, ShieldState, VerificationLevel, VerificationState,
+        DeviceLinkProblem, ShieldState, UtdCause, VerificationLevel, VerificationState,
     };
     use matrix_sdk_test::{async_test, message_like_event_content, test_json};
     use ruma::{
@@ -2221,7 +2352,10 @@ pub(crate) mod tests {
         events::{
             dummy::ToDeviceDummyEventContent,
             key::verification::VerificationMethod,
-            room::message::{MessageType, RoomMessageEventContent},
+            room::{
+                history_visibility::HistoryVisibility,
+                message::{MessageType, RoomMessageEventContent},
+            },
             AnyMessageLikeEvent, AnyMessageLikeEventContent, AnyTimelineEvent, AnyToDeviceEvent,
             MessageLikeEvent, OriginalMessageLikeEvent,
         },
@@ -2231,9 +2365,9 @@ pub(crate) mod tests {
         uint, user_id, DeviceId, DeviceKeyAlgorithm, DeviceKeyId, MilliSecondsSinceUnixEpoch,
         OwnedDeviceKeyId, SecondsSinceUnixEpoch, TransactionId, UserId,
     };
-    use serde_json::{json, value::to_raw_value};
+    use serde_json::{json, value::to_raw_value, Value};
     use vodozemac::{
-        megolm::{GroupSession, SessionConfig},
+        megolm::{DecryptionError, GroupSession, SessionConfig},
         Curve25519PublicKey, Ed25519PublicKey,
     };
 
@@ -2242,7 +2376,7 @@ pub(crate) mod tests {
         error::{EventError, SetRoomSettingsError},
         machine::{EncryptionSyncChanges, OlmMachine},
         olm::{InboundGroupSession, OutboundGroupSession, VerifyJson},
-        store::{Changes, RoomSettings},
+        store::{Changes, RoomKeyInfo, RoomSettings},
         types::{
             events::{
                 room::encrypted::{EncryptedToDeviceEvent, ToDeviceEncryptedEventContent},
@@ -2253,8 +2387,8 @@ pub(crate) mod tests {
         },
         utilities::json_convert,
         verification::tests::{bob_id, outgoing_request_to_event, request_to_event},
-        Account, EncryptionSettings, LocalTrust, MegolmError, OlmError, OutgoingRequests,
-        ReadOnlyDevice, ToDeviceRequest, UserIdentities,
+        Account, CollectStrategy, EncryptionSettings, LocalTrust, MegolmError, OlmError,
+        OutgoingRequests, ReadOnlyDevice, ToDeviceRequest, UserIdentities,
     };
 
     /// These keys need to be periodically uploaded to the server.
@@ -2907,6 +3041,140 @@ pub(crate) mod tests {
         assert_eq!(room_key_updates[0].session_id, alice_session.session_id());
     }
 
+    #[async_test]
+    async fn test_share_room_history_keys() {
+        async fn receive_history_keys(
+            sender: &UserId,
+            receiver: &OlmMachine,
+            requests: Vec<Arc<ToDeviceRequest>>,
+        ) -> Vec<RoomKeyInfo> {
+            let event =
+                ToDeviceEvent::new(sender.to_owned(), to_device_requests_to_content(requests));
+            let event = json_convert(&event).unwrap();
+
+            let (_, room_key_updates) = receiver
+                .receive_sync_changes(EncryptionSyncChanges {
+                    to_device_events: vec![event],
+                    changed_devices: &Default::default(),
+                    one_time_keys_counts: &Default::default(),
+                    unused_fallback_keys: None,
+                    next_batch_token: None,
+                })
+                .await
+                .unwrap();
+
+            room_key_updates
+        }
+
+        let (alice, bob) =
+            get_machine_pair_with_setup_sessions_test_helper(alice_id(), user_id(), false).await;
+        let room_id = room_id!("!test:example.org");
+
+        let settings = EncryptionSettings {
+            history_visibility: HistoryVisibility::Shared,
+            ..Default::default()
+        };
+        alice.share_room_key(room_id, iter::empty(), settings.clone()).await.unwrap();
+        let session_id = alice
+            .inner
+            .group_session_manager
+            .get_outbound_group_session(room_id)
+            .unwrap()
+            .session_id()
+            .to_owned();
+
+        // Nothing is shared unless it was enabled.
+        let requests = alice
+            .share_room_history_keys(room_id, bob.user_id(), HistoryVisibility::Shared)
+            .await
+            .unwrap();
+        assert!(requests.is_empty());
+
+        alice.store().set_share_history_on_invite(true).await.unwrap();
+
+        // Nothing is shared if new members can't see the history anymore.
+        let requests = alice
+            .share_room_history_keys(room_id, bob.user_id(), HistoryVisibility::Joined)
+            .await
+            .unwrap();
+        assert!(requests.is_empty());
+
+        // Nothing is shared with the devices excluded by the strategy of the room,
+        // even if the global one allows them.
+        let other_room_id = room_id!("!other:example.org");
+        alice.share_room_key(other_room_id, iter::empty(), settings.clone()).await.unwrap();
+        let room_settings = RoomSettings {
+            sharing_strategy: Some(CollectStrategy::CrossSignedOnly),
+            ..Default::default()
+        };
+        alice.set_room_settings(other_room_id, &room_settings).await.unwrap();
+        let requests = alice
+            .share_room_history_keys(other_room_id, bob.user_id(), HistoryVisibility::Shared)
+            .await
+            .unwrap();
+        assert!(requests.is_empty());
+
+        let requests = alice
+            .share_room_history_keys(room_id, bob.user_id(), HistoryVisibility::Shared)
+            .await
+            .unwrap();
+        assert_eq!(requests.len(), 1);
+
+        // The room key is kept aside until the invite is accepted.
+        let room_key_updates = receive_history_keys(alice.user_id(), &bob, requests).await;
+        assert!(room_key_updates.is_empty());
+        assert!(bob
+            .store()
+            .get_inbound_group_session(room_id, &session_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            bob.shared_history_room_key_senders().await.unwrap(),
+            BTreeMap::from([(room_id.to_owned(), BTreeSet::from([alice.user_id().to_owned()]))])
+        );
+
+        // Receiving the same room key again doesn't keep it twice.
+        let requests = alice
+            .share_room_history_keys(room_id, bob.user_id(), HistoryVisibility::Shared)
+            .await
+            .unwrap();
+        receive_history_keys(alice.user_id(), &bob, requests).await;
+        let pending: BTreeMap<String, Vec<Value>> =
+            bob.store().get_value("pending_shared_history_keys").await.unwrap().unwrap();
+        assert_eq!(pending[room_id.as_str()].len(), 1);
+
+        // The room keys that weren't forwarded by the inviter are discarded.
+        bob.accept_shared_history_room_keys(room_id, user_id!("@carl:example.org")).await.unwrap();
+        assert!(bob
+            .store()
+            .get_inbound_group_session(room_id, &session_id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(bob.shared_history_room_key_senders().await.unwrap().is_empty());
+
+        // As well as the ones discarded explicitly.
+        let requests = alice
+            .share_room_history_keys(room_id, bob.user_id(), HistoryVisibility::Shared)
+            .await
+            .unwrap();
+        receive_history_keys(alice.user_id(), &bob, requests).await;
+        bob.discard_shared_history_room_keys(room_id, alice.user_id()).await.unwrap();
+        assert!(bob.shared_history_room_key_senders().await.unwrap().is_empty());
+
+        let requests = alice
+            .share_room_history_keys(room_id, bob.user_id(), HistoryVisibility::Shared)
+            .await
+            .unwrap();
+        receive_history_keys(alice.user_id(), &bob, requests).await;
+
+        bob.accept_shared_history_room_keys(room_id, alice.user_id()).await.unwrap();
+        let session =
+            bob.store().get_inbound_group_session(room_id, &session_id).await.unwrap().unwrap();
+        assert!(session.shared_history());
+    }
+
     #[async_test]
     async fn test_request_missing_secrets() {
         let (alice, _) = get_machine_pair_with_session(alice_id(), bob_id(), false).await;
@@ -3120,6 +3388,61 @@ pub(crate) mod tests {
 
         let err = decrypt_result.err().unwrap();
         assert_matches!(err, MegolmError::MissingRoomKey(Some(WithheldCode::Unverified)));
+        assert_eq!(bob.utd_cause(&room_event, &err), UtdCause::UnverifiedDevice);
+    }
+
+    #[async_test]
+    async fn test_utd_cause() {
+        let machine = OlmMachine::new(user_id(), alice_device_id()).await;
+        let identity_keys = machine.identity_keys();
+
+        let event_sent_at = |origin_server_ts: MilliSecondsSinceUnixEpoch| {
+            json_convert(&json!({
+                "event_id": "$xxxxx:example.org",
+                "origin_server_ts": origin_server_ts,
+                "sender": alice_id(),
+                "type": "m.room.encrypted",
+                "content": {
+                    "algorithm": "m.megolm.v1.aes-sha2",
+                    "ciphertext": "AwgAEpAB",
+                    "device_id": "ABCDEFGH",
+                    "sender_key": identity_keys.curve25519.to_base64(),
+                    "session_id": "SESSIONID",
+                },
+            }))
+            .unwrap()
+        };
+        let old_event = event_sent_at(MilliSecondsSinceUnixEpoch(uint!(1_000_000_000_000)));
+        let new_event = event_sent_at(MilliSecondsSinceUnixEpoch::now());
+
+        // A missing room key is expected for events sent before our device existed.
+        let error = MegolmError::MissingRoomKey(None);
+        assert_eq!(machine.utd_cause(&old_event, &error), UtdCause::SentBeforeDeviceExisted);
+        assert_eq!(machine.utd_cause(&new_event, &error), UtdCause::MissingRoomKey);
+
+        let error = MegolmError::MissingRoomKey(Some(WithheldCode::Unverified));
+        assert_eq!(machine.utd_cause(&new_event, &error), UtdCause::UnverifiedDevice);
+
+        let error = MegolmError::MissingRoomKey(Some(WithheldCode::Blacklisted));
+        assert_eq!(
+            machine.utd_cause(&new_event, &error),
+            UtdCause::Withheld { code: "m.blacklisted".to_owned() }
+        );
+
+        let error = MegolmError::Decryption(DecryptionError::UnknownMessageIndex(3, 1));
+        assert_eq!(machine.utd_cause(&new_event, &error), UtdCause::UnknownMessageIndex);
+
+        // The room key was sent by a device with another Curve25519 key.
+        let error = MegolmError::MismatchedIdentityKeys {
+            key_ed25519: Box::new(identity_keys.ed25519),
+            device_ed25519: Some(Box::new(identity_keys.ed25519)),
+            key_curve25519: Box::new(identity_keys.curve25519),
+            device_curve25519: Some(Box::new(vodozemac::olm::Account::new().curve25519_key())),
+        };
+        assert_eq!(machine.utd_cause(&new_event, &error), UtdCause::MismatchedSenderKeys);
+
+        let error = MegolmError::EventError(EventError::UnsupportedAlgorithm);
+        assert_eq!(machine.utd_cause(&new_event, &error), UtdCause::Unknown);
     }
 
     #[async_test]
@@ -4166,6 +4489,7 @@ pub(crate) mod tests {
             only_allow_trusted_devices: true,
             session_rotation_period: Some(Duration::from_secs(10)),
             session_rotation_period_messages: Some(1234),
+            sharing_strategy: Some(CollectStrategy::CrossSignedOnly),
         };
 
         machine.set_room_settings(room_id, &settings).await.unwrap();
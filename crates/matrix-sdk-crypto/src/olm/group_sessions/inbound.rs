@@ -362,6 +362,20 @@ impl InboundGroupSession {
         self.imported
     }
 
+    /// Can this session be shared with users that are invited to the room
+    /// later on, as defined in [MSC3061]?
+    ///
+    /// This is the case if the history of the room was visible to new members
+    /// when the session was created.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub fn shared_history(&self) -> bool {
+        matches!(
+            self.history_visibility.as_ref(),
+            Some(HistoryVisibility::Shared | HistoryVisibility::WorldReadable)
+        )
+    }
+
     /// Check if the `InboundGroupSession` is better than the given other
     /// `InboundGroupSession`
     pub async fn compare(&self, other: &InboundGroupSession) -> SessionOrdering {
@@ -538,6 +552,15 @@ impl TryFrom<&ExportedRoomKey> for InboundGroupSession {
     }
 }
 
+/// The history visibility to record for a forwarded room key, depending on
+/// whether the forwarder marked it as shareable with invited users.
+///
+/// The exact history visibility isn't forwarded, but a room key can only be
+/// marked as such if the history was shared when it was created.
+fn shared_history_visibility(shared_history: bool) -> Option<HistoryVisibility> {
+    shared_history.then_some(HistoryVisibility::Shared)
+}
+
 impl From<&ForwardedMegolmV1AesSha2Content> for InboundGroupSession {
     fn from(value: &ForwardedMegolmV1AesSha2Content) -> Self {
         let session = InnerSession::import(&value.session_key, SessionConfig::version_1());
@@ -555,7 +578,7 @@ impl From<&ForwardedMegolmV1AesSha2Content> for InboundGroupSession {
                 )])
                 .into(),
             },
-            history_visibility: None.into(),
+            history_visibility: shared_history_visibility(value.shared_history).into(),
             first_known_index,
             room_id: value.room_id.to_owned(),
             imported: true,
@@ -578,7 +601,7 @@ impl From<&ForwardedMegolmV2AesSha2Content> for InboundGroupSession {
                 curve25519_key: value.claimed_sender_key,
                 signing_keys: value.claimed_signing_keys.to_owned().into(),
             },
-            history_visibility: None.into(),
+            history_visibility: shared_history_visibility(value.shared_history).into(),
             first_known_index,
             room_id: value.room_id.to_owned(),
             imported: true,
@@ -174,6 +174,7 @@ impl TryFrom<ExportedRoomKey> for ForwardedRoomKeyContent {
                             forwarding_curve25519_key_chain: room_key
                                 .forwarding_curve25519_key_chain
                                 .clone(),
+                            shared_history: false,
                             other: Default::default(),
                         }
                         .into(),
@@ -191,6 +192,7 @@ impl TryFrom<ExportedRoomKey> for ForwardedRoomKeyContent {
                         session_key: room_key.session_key,
                         claimed_sender_key: room_key.sender_key,
                         claimed_signing_keys: room_key.sender_claimed_keys,
+                        shared_history: false,
                         other: Default::default(),
                     }
                     .into(),
@@ -56,7 +56,7 @@ use crate::{
         },
         EventEncryptionAlgorithm,
     },
-    ReadOnlyDevice, ToDeviceRequest,
+    CollectStrategy, ReadOnlyDevice, ToDeviceRequest,
 };
 
 const ONE_HOUR: Duration = Duration::from_secs(60 * 60);
@@ -89,6 +89,19 @@ pub struct EncryptionSettings {
     /// excluded from the conversation.
     #[serde(default)]
     pub only_allow_trusted_devices: bool,
+    /// Should the room key fail to be shared if the identity of a member of
+    /// the room changed without the change being acknowledged, see
+    /// [`UserIdentity::identity_needs_user_approval()`].
+    ///
+    /// [`UserIdentity::identity_needs_user_approval()`]: crate::UserIdentity::identity_needs_user_approval
+    #[serde(default)]
+    pub error_on_identity_violation: bool,
+    /// The strategy to collect the devices that should receive the room key.
+    ///
+    /// It's applied on top of `only_allow_trusted_devices`, devices need to
+    /// satisfy both to receive the room key.
+    #[serde(default)]
+    pub sharing_strategy: CollectStrategy,
 }
 
 impl Default for EncryptionSettings {
@@ -99,6 +112,8 @@ impl Default for EncryptionSettings {
             rotation_period_msgs: ROTATION_MESSAGES,
             history_visibility: HistoryVisibility::Shared,
             only_allow_trusted_devices: false,
+            error_on_identity_violation: false,
+            sharing_strategy: CollectStrategy::AllDevices,
         }
     }
 }
@@ -123,6 +138,8 @@ impl EncryptionSettings {
             rotation_period_msgs,
             history_visibility,
             only_allow_trusted_devices,
+            error_on_identity_violation: false,
+            sharing_strategy: CollectStrategy::AllDevices,
         }
     }
 }
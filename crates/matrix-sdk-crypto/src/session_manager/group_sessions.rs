@@ -23,17 +23,20 @@ use futures_util::future::join_all;
 use itertools::{Either, Itertools};
 use matrix_sdk_common::executor::spawn;
 use ruma::{
-    events::{AnyMessageLikeEventContent, ToDeviceEventType},
+    events::{
+        room::history_visibility::HistoryVisibility, AnyMessageLikeEventContent, ToDeviceEventType,
+    },
     serde::Raw,
     to_device::DeviceIdOrAllDevices,
     DeviceId, OwnedDeviceId, OwnedRoomId, OwnedTransactionId, OwnedUserId, RoomId, TransactionId,
     UserId,
 };
+use serde::{Deserialize, Serialize};
 use tracing::{debug, error, info, instrument, trace};
 
 use crate::{
     error::{EventError, MegolmResult, OlmResult},
-    identities::device::MaybeEncryptedRoomKey,
+    identities::{device::MaybeEncryptedRoomKey, ReadOnlyOwnUserIdentity, ReadOnlyUserIdentities},
     olm::{InboundGroupSession, OutboundGroupSession, Session, ShareInfo, ShareState},
     store::{Changes, CryptoStoreWrapper, Result as StoreResult, Store},
     types::events::{room::encrypted::RoomEncryptedEventContent, room_key_withheld::WithheldCode},
@@ -133,6 +136,72 @@ pub(crate) struct CollectRecipientsResult {
     pub withheld_devices: Vec<(ReadOnlyDevice, WithheldCode)>,
 }
 
+/// Strategy to collect the devices that should receive the room keys of a
+/// room.
+///
+/// Devices that don't satisfy the strategy don't receive the room key and are
+/// sent a [`WithheldCode::Unverified`] code instead. Blacklisted devices never
+/// receive room keys, whatever the strategy.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum CollectStrategy {
+    /// Share the room key with all the devices of the members of the room.
+    #[default]
+    AllDevices,
+
+    /// Share the room key only with the devices that were cross-signed by
+    /// their owner.
+    ///
+    /// The devices of users that don't have a cross-signing identity are
+    /// excluded.
+    CrossSignedOnly,
+
+    /// Share the room key only with the devices that were cross-signed by
+    /// their owner, if we verified the identity of that owner.
+    ///
+    /// Our own devices are included if they were cross-signed and our own
+    /// identity is verified.
+    VerifiedIdentitiesOnly,
+}
+
+impl CollectStrategy {
+    /// Should the given device receive room keys with this strategy?
+    ///
+    /// The identities are only needed if the strategy isn't
+    /// [`CollectStrategy::AllDevices`].
+    fn is_device_allowed(
+        &self,
+        device: &ReadOnlyDevice,
+        own_identity: Option<&ReadOnlyOwnUserIdentity>,
+        device_owner_identity: Option<&ReadOnlyUserIdentities>,
+    ) -> bool {
+        let is_cross_signed_by_owner = || {
+            device_owner_identity.is_some_and(|identity| match identity {
+                ReadOnlyUserIdentities::Own(identity) => identity.is_device_signed(device).is_ok(),
+                ReadOnlyUserIdentities::Other(identity) => {
+                    identity.is_device_signed(device).is_ok()
+                }
+            })
+        };
+
+        let is_owner_verified = || {
+            device_owner_identity.is_some_and(|identity| match identity {
+                ReadOnlyUserIdentities::Own(identity) => identity.is_verified(),
+                ReadOnlyUserIdentities::Other(identity) => own_identity.is_some_and(|own| {
+                    own.is_verified() && own.is_identity_signed(identity).is_ok()
+                }),
+            })
+        };
+
+        match self {
+            CollectStrategy::AllDevices => true,
+            CollectStrategy::CrossSignedOnly => is_cross_signed_by_owner(),
+            CollectStrategy::VerifiedIdentitiesOnly => {
+                is_cross_signed_by_owner() && is_owner_verified()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct GroupSessionManager {
     /// Store for the encryption keys.
@@ -383,16 +452,32 @@ impl GroupSessionManager {
         let own_identity =
             self.store.get_user_identity(self.store.user_id()).await?.and_then(|i| i.into_own());
 
+        let mut users_with_identity_violation = Vec::new();
+
         for user_id in users {
             let user_devices = self.store.get_readonly_devices_filtered(user_id).await?;
 
-            // We only need the user identity if settings.only_allow_trusted_devices is set.
-            let device_owner_identity = if settings.only_allow_trusted_devices {
+            // We only need the user identity if settings.only_allow_trusted_devices or
+            // settings.error_on_identity_violation is set, or if the sharing strategy
+            // depends on it.
+            let device_owner_identity = if settings.only_allow_trusted_devices
+                || settings.error_on_identity_violation
+                || settings.sharing_strategy != CollectStrategy::AllDevices
+            {
                 self.store.get_user_identity(user_id).await?
             } else {
                 None
             };
 
+            if settings.error_on_identity_violation
+                && device_owner_identity
+                    .as_ref()
+                    .and_then(|i| i.other())
+                    .is_some_and(|i| i.needs_user_approval(own_identity.as_ref()))
+            {
+                users_with_identity_violation.push(user_id.to_owned());
+            }
+
             // From all the devices a user has, we're splitting them into two
             // buckets, a bucket of devices that should receive the
             // room key and a bucket of devices that should receive
@@ -407,6 +492,12 @@ impl GroupSessionManager {
                     && !d.is_verified(&own_identity, &device_owner_identity)
                 {
                     Either::Right((d, WithheldCode::Unverified))
+                } else if !settings.sharing_strategy.is_device_allowed(
+                    &d,
+                    own_identity.as_ref(),
+                    device_owner_identity.as_ref(),
+                ) {
+                    Either::Right((d, WithheldCode::Unverified))
                 } else {
                     Either::Left(d)
                 }
@@ -450,6 +541,10 @@ impl GroupSessionManager {
             withheld_devices.extend(withheld_recipients);
         }
 
+        if !users_with_identity_violation.is_empty() {
+            return Err(OlmError::IdentityViolation(users_with_identity_violation));
+        }
+
         if should_rotate {
             debug!(
                 should_rotate,
@@ -847,12 +942,139 @@ impl GroupSessionManager {
 
         Ok(requests)
     }
+
+    /// Get to-device requests to forward the room keys of a room with a
+    /// shared history to a user that was invited to the room, as defined in
+    /// [MSC3061].
+    ///
+    /// This only does something if sharing the room history on invite was
+    /// enabled with [`Store::set_share_history_on_invite`], and if the history
+    /// of the room is visible to new members.
+    ///
+    /// # Arguments
+    ///
+    /// `room_id` - The room id of the room the user was invited to.
+    ///
+    /// `user_id` - The user that was invited to the room.
+    ///
+    /// `history_visibility` - The current history visibility of the room.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    #[instrument(skip(self))]
+    pub async fn share_history_room_keys(
+        &self,
+        room_id: &RoomId,
+        user_id: &UserId,
+        history_visibility: HistoryVisibility,
+    ) -> OlmResult<Vec<Arc<ToDeviceRequest>>> {
+        if !self.store.get_share_history_on_invite().await? {
+            trace!("Sharing the room history on invite is disabled");
+            return Ok(Vec::new());
+        }
+
+        if !matches!(
+            history_visibility,
+            HistoryVisibility::Shared | HistoryVisibility::WorldReadable
+        ) {
+            debug!("The history of the room isn't shared, not sharing any room key");
+            return Ok(Vec::new());
+        }
+
+        let sessions: Vec<InboundGroupSession> = self
+            .store
+            .get_inbound_group_sessions_for_room(room_id)
+            .await?
+            .into_iter()
+            .filter(|s| s.shared_history())
+            .collect();
+
+        if sessions.is_empty() {
+            debug!("No room key with a shared history to share");
+            return Ok(Vec::new());
+        }
+
+        let only_allow_trusted_devices = self.store.get_only_allow_trusted_devices().await?;
+        // The strategy of the room overrides the global one.
+        let sharing_strategy = match self
+            .store
+            .get_room_settings(room_id)
+            .await?
+            .and_then(|settings| settings.sharing_strategy)
+        {
+            Some(strategy) => strategy,
+            None => self.store.get_room_key_sharing_strategy().await?,
+        };
+        let devices: Vec<_> = self
+            .store
+            .get_user_devices(user_id)
+            .await?
+            .devices()
+            .filter(|d| {
+                !d.is_blacklisted()
+                    && (!only_allow_trusted_devices || d.is_verified())
+                    && sharing_strategy.is_device_allowed(
+                        &d.inner,
+                        d.own_identity.as_ref(),
+                        d.device_owner_identity.as_ref(),
+                    )
+            })
+            .collect();
+
+        let mut changes = Changes::default();
+        let mut requests = Vec::new();
+
+        for session in sessions {
+            let mut messages = BTreeMap::new();
+
+            for device in &devices {
+                match device.encrypt_room_key_for_forwarding(session.clone(), None).await {
+                    Ok((used_session, content)) => {
+                        changes.sessions.push(used_session);
+
+                        messages
+                            .entry(device.user_id().to_owned())
+                            .or_insert_with(BTreeMap::new)
+                            .insert(
+                                DeviceIdOrAllDevices::DeviceId(device.device_id().to_owned()),
+                                content.cast(),
+                            );
+                    }
+                    Err(OlmError::MissingSession) => {
+                        debug!(
+                            device_id = ?device.device_id(),
+                            "Can't share a room key with a shared history, no Olm session \
+                             is established with the device",
+                        );
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            if !messages.is_empty() {
+                requests.push(Arc::new(ToDeviceRequest {
+                    event_type: ToDeviceEventType::RoomEncrypted,
+                    txn_id: TransactionId::new(),
+                    messages,
+                }));
+            }
+        }
+
+        Self::log_room_key_sharing_result(&requests);
+
+        // The Olm sessions were ratcheted forward, persist them.
+        if !changes.is_empty() {
+            self.store.save_changes(changes).await?;
+        }
+
+        Ok(requests)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::{collections::BTreeSet, iter, ops::Deref, sync::Arc};
 
+    use assert_matches2::assert_let;
     use matrix_sdk_test::{async_test, response_from_file};
     use ruma::{
         api::{
@@ -871,14 +1093,21 @@ mod tests {
     use serde_json::{json, Value};
 
     use crate::{
+        identities::{
+            manager::testing::own_key_query,
+            user::testing::{device, get_own_identity},
+            ReadOnlyUserIdentities, ReadOnlyUserIdentity,
+        },
+        olm::PrivateCrossSigningIdentity,
         session_manager::group_sessions::CollectRecipientsResult,
+        store::{Changes, IdentityChanges},
         types::{
             events::room_key_withheld::{
                 RoomKeyWithheldContent, RoomKeyWithheldContent::MegolmV1AesSha2, WithheldCode,
             },
             EventEncryptionAlgorithm,
         },
-        EncryptionSettings, LocalTrust, OlmMachine, ToDeviceRequest,
+        CollectStrategy, EncryptionSettings, LocalTrust, OlmError, OlmMachine, ToDeviceRequest,
     };
 
     fn alice_id() -> &'static UserId {
@@ -1351,6 +1580,116 @@ mod tests {
         assert!(has_blacklist);
     }
 
+    #[test]
+    fn test_collect_strategy_is_device_allowed() {
+        let response = own_key_query();
+        let identity = get_own_identity();
+        let (unsigned, signed) = device(&response);
+        let owner = Some(ReadOnlyUserIdentities::Own(identity.clone()));
+
+        let is_allowed = |strategy: CollectStrategy, device| {
+            strategy.is_device_allowed(device, Some(&identity), owner.as_ref())
+        };
+
+        assert!(is_allowed(CollectStrategy::AllDevices, &unsigned));
+        assert!(is_allowed(CollectStrategy::AllDevices, &signed));
+
+        assert!(!is_allowed(CollectStrategy::CrossSignedOnly, &unsigned));
+        assert!(is_allowed(CollectStrategy::CrossSignedOnly, &signed));
+        assert!(!CollectStrategy::CrossSignedOnly.is_device_allowed(&signed, None, None));
+
+        // The identity of the owner of the devices isn't verified yet.
+        assert!(!is_allowed(CollectStrategy::VerifiedIdentitiesOnly, &signed));
+
+        identity.mark_as_verified();
+        assert!(!is_allowed(CollectStrategy::VerifiedIdentitiesOnly, &unsigned));
+        assert!(is_allowed(CollectStrategy::VerifiedIdentitiesOnly, &signed));
+    }
+
+    #[async_test]
+    async fn test_sharing_withheld_cross_signed_only() {
+        let machine = machine().await;
+        let room_id = room_id!("!test:localhost");
+        let keys_claim = keys_claim_response();
+
+        let users = keys_claim.one_time_keys.keys().map(Deref::deref);
+        let settings = EncryptionSettings {
+            sharing_strategy: CollectStrategy::CrossSignedOnly,
+            ..Default::default()
+        };
+
+        let user_id = user_id!("@example:localhost");
+        machine
+            .get_device(user_id, "MWVTUXDNNM".into(), None)
+            .await
+            .unwrap()
+            .unwrap()
+            .set_local_trust(LocalTrust::BlackListed)
+            .await
+            .unwrap();
+
+        let requests = machine.share_room_key(room_id, users, settings).await.unwrap();
+
+        // Only one device was cross-signed by its owner.
+        let room_key_requests: Vec<_> =
+            requests.iter().filter(|r| r.event_type == "m.room.encrypted".into()).collect();
+        assert_eq!(room_key_requests.len(), 1);
+        assert_eq!(room_key_requests[0].message_count(), 1);
+        let device_key = DeviceIdOrAllDevices::from(device_id!("XOWLHHFSWM").to_owned());
+        assert!(room_key_requests[0].messages[user_id].contains_key(&device_key));
+
+        assert_eq!(count_withheld_from(&requests, WithheldCode::Blacklisted), 1);
+        assert_eq!(count_withheld_from(&requests, WithheldCode::Unverified), 148);
+    }
+
+    #[async_test]
+    async fn test_sharing_error_on_identity_violation() {
+        let machine = machine().await;
+        let room_id = room_id!("!test:localhost");
+        let keys_claim = keys_claim_response();
+        let users = keys_claim.one_time_keys.keys().map(Deref::deref);
+
+        // The master key of a member of the room changed since we first saw it.
+        let user_id = user_id!("@example:localhost");
+        let first = PrivateCrossSigningIdentity::new(user_id.to_owned());
+        let second = PrivateCrossSigningIdentity::new(user_id.to_owned());
+        let mut identity = ReadOnlyUserIdentity::from_private(&first).await;
+        let second_identity = ReadOnlyUserIdentity::from_private(&second).await;
+        identity
+            .update(
+                second_identity.master_key().clone(),
+                second_identity.self_signing_key().clone(),
+            )
+            .unwrap();
+
+        let changes = Changes {
+            identities: IdentityChanges { new: vec![identity.into()], ..Default::default() },
+            ..Default::default()
+        };
+        machine.store().save_changes(changes).await.unwrap();
+
+        // The violation is ignored by default.
+        machine
+            .share_room_key(room_id, users.clone(), EncryptionSettings::default())
+            .await
+            .unwrap();
+        machine.discard_room_key(room_id).await.unwrap();
+
+        let settings =
+            EncryptionSettings { error_on_identity_violation: true, ..Default::default() };
+        let result = machine.share_room_key(room_id, users.clone(), settings.clone()).await;
+        assert_let!(Err(OlmError::IdentityViolation(users_with_violation)) = result);
+        assert_eq!(users_with_violation, [user_id.to_owned()]);
+
+        // Once the change is acknowledged, the room key can be shared.
+        let identity = machine.get_identity(user_id, None).await.unwrap().unwrap().other().unwrap();
+        assert!(identity.identity_needs_user_approval());
+        identity.pin_current_identity().await.unwrap();
+        assert!(!identity.identity_needs_user_approval());
+
+        machine.share_room_key(room_id, users, settings).await.unwrap();
+    }
+
     #[async_test]
     async fn test_no_olm_withheld_only_sent_once() {
         let keys_query = keys_query_response();
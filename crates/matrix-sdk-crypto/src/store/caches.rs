@@ -113,6 +113,16 @@ impl GroupSessionStore {
         self.entries.read().unwrap().values().flat_map(HashMap::values).cloned().collect()
     }
 
+    /// Get all the group sessions of the given room.
+    pub fn get_for_room(&self, room_id: &RoomId) -> Vec<InboundGroupSession> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(room_id)
+            .map(|sessions| sessions.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
     /// Get the number of `InboundGroupSession`s we have.
     pub fn count(&self) -> usize {
         self.entries.read().unwrap().values().map(HashMap::len).sum()
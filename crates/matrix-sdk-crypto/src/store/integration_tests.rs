@@ -37,7 +37,8 @@ macro_rules! cryptostore_integration_tests {
                     },
                     EventEncryptionAlgorithm,
                 },
-                GossippedSecret, ReadOnlyDevice, SecretInfo, ToDeviceRequest, TrackedUser,
+                CollectStrategy, GossippedSecret, ReadOnlyDevice, SecretInfo, ToDeviceRequest,
+                TrackedUser,
             };
 
             use super::get_store;
@@ -330,6 +331,34 @@ macro_rules! cryptostore_integration_tests {
                 assert_eq!(to_back_up, vec![session])
             }
 
+            #[async_test]
+            async fn get_inbound_group_sessions_for_room() {
+                let (account, store) =
+                    get_loaded_store("get_inbound_group_sessions_for_room").await;
+
+                let room_id = &room_id!("!test:localhost");
+                let other_room_id = &room_id!("!other:localhost");
+                let (_, session) = account.create_group_session_pair_with_defaults(room_id).await;
+                let (_, other_session) =
+                    account.create_group_session_pair_with_defaults(other_room_id).await;
+
+                let changes = Changes {
+                    inbound_group_sessions: vec![session.clone(), other_session],
+                    ..Default::default()
+                };
+
+                store.save_changes(changes).await.expect("Can't save group session");
+
+                let sessions = store.get_inbound_group_sessions_for_room(room_id).await.unwrap();
+                assert_eq!(sessions, vec![session]);
+
+                let sessions = store
+                    .get_inbound_group_sessions_for_room(room_id!("!unknown:localhost"))
+                    .await
+                    .unwrap();
+                assert!(sessions.is_empty());
+            }
+
             #[async_test]
             async fn mark_inbound_group_sessions_as_backed_up() {
                 // Given a store exists with multiple unbacked-up sessions
@@ -894,6 +923,7 @@ macro_rules! cryptostore_integration_tests {
                     only_allow_trusted_devices: true,
                     session_rotation_period: Some(Duration::from_secs(10)),
                     session_rotation_period_messages: Some(123),
+                    sharing_strategy: Some(CollectStrategy::VerifiedIdentitiesOnly),
                 };
 
                 let room_2 = room_id!("!test_2:localhost");
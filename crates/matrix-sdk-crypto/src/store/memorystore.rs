@@ -270,6 +270,13 @@ impl CryptoStore for MemoryStore {
         Ok(self.inbound_group_sessions.get_all())
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        Ok(self.inbound_group_sessions.get_for_room(room_id))
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let backed_up =
             self.get_inbound_group_sessions().await?.into_iter().filter(|s| s.backed_up()).count();
@@ -758,6 +765,13 @@ mod integration_tests {
             self.0.get_inbound_group_sessions().await
         }
 
+        async fn get_inbound_group_sessions_for_room(
+            &self,
+            room_id: &RoomId,
+        ) -> Result<Vec<InboundGroupSession>, Self::Error> {
+            self.0.get_inbound_group_sessions_for_room(room_id).await
+        }
+
         async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts, Self::Error> {
             self.0.inbound_group_session_counts().await
         }
@@ -71,7 +71,7 @@ use crate::{
     },
     types::{events::room_key_withheld::RoomKeyWithheldEvent, EventEncryptionAlgorithm},
     verification::VerificationMachine,
-    CrossSigningStatus, ReadOnlyOwnUserIdentity, RoomKeyImportResult,
+    CollectStrategy, CrossSigningStatus, ReadOnlyOwnUserIdentity, RoomKeyImportResult,
 };
 
 pub mod caches;
@@ -845,6 +845,12 @@ pub struct RoomSettings {
     /// The maximum number of messages an encryption session should be used for,
     /// before it is rotated.
     pub session_rotation_period_messages: Option<usize>,
+
+    /// The strategy to collect the devices that should receive the room keys
+    /// of this room, overriding the global one set with
+    /// [`Store::set_room_key_sharing_strategy`].
+    #[serde(default)]
+    pub sharing_strategy: Option<CollectStrategy>,
 }
 
 impl Default for RoomSettings {
@@ -854,6 +860,7 @@ impl Default for RoomSettings {
             only_allow_trusted_devices: false,
             session_rotation_period: None,
             session_rotation_period_messages: None,
+            sharing_strategy: None,
         }
     }
 }
@@ -1275,6 +1282,54 @@ impl Store {
         self.set_value("only_allow_trusted_devices", &block_untrusted_devices).await
     }
 
+    /// Check whether there is a global flag to fail to encrypt messages in
+    /// rooms where the identity of a member changed without the change being
+    /// acknowledged.
+    ///
+    /// See [`EncryptionSettings::error_on_identity_violation`].
+    ///
+    /// [`EncryptionSettings::error_on_identity_violation`]: crate::EncryptionSettings::error_on_identity_violation
+    pub async fn get_error_on_identity_violation(&self) -> Result<bool> {
+        let value = self.get_value("error_on_identity_violation").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Set global flag whether to fail to encrypt messages in rooms where the
+    /// identity of a member changed without the change being acknowledged.
+    pub async fn set_error_on_identity_violation(&self, error_on_violation: bool) -> Result<()> {
+        self.set_value("error_on_identity_violation", &error_on_violation).await
+    }
+
+    /// Get the global strategy to collect the devices that should receive the
+    /// room keys of the rooms that don't override it in their
+    /// [`RoomSettings`].
+    pub async fn get_room_key_sharing_strategy(&self) -> Result<CollectStrategy> {
+        let value = self.get_value("room_key_sharing_strategy").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Set the global strategy to collect the devices that should receive the
+    /// room keys.
+    pub async fn set_room_key_sharing_strategy(&self, strategy: CollectStrategy) -> Result<()> {
+        self.set_value("room_key_sharing_strategy", &strategy).await
+    }
+
+    /// Check whether the room keys of rooms with a shared history should be
+    /// forwarded to the users we invite to those rooms, as defined in
+    /// [MSC3061].
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    pub async fn get_share_history_on_invite(&self) -> Result<bool> {
+        let value = self.get_value("share_history_on_invite").await?.unwrap_or_default();
+        Ok(value)
+    }
+
+    /// Set global flag whether to forward the room keys of rooms with a shared
+    /// history to the users we invite to those rooms.
+    pub async fn set_share_history_on_invite(&self, share_history: bool) -> Result<()> {
+        self.set_value("share_history_on_invite", &share_history).await
+    }
+
     /// Get custom stored value associated with a key
     pub async fn get_value<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>> {
         let Some(value) = self.get_custom_value(key).await? else {
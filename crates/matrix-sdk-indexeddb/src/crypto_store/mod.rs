@@ -873,6 +873,17 @@ impl_crypto_store! {
         ).await
     }
 
+    async fn get_inbound_group_sessions_for_room(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Vec<InboundGroupSession>> {
+        // The sessions aren't indexed by room, so we need to go through all of them.
+        let mut sessions = self.get_inbound_group_sessions().await?;
+        sessions.retain(|session| session.room_id() == room_id);
+
+        Ok(sessions)
+    }
+
     async fn inbound_group_session_counts(&self) -> Result<RoomKeyCounts> {
         let tx = self
             .inner
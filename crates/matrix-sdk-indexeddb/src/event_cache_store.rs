@@ -0,0 +1,379 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use gloo_utils::format::JsValueSerdeExt;
+use indexed_db_futures::{prelude::*, request::OpenDbRequest, IdbDatabase, IdbVersionChangeEvent};
+use matrix_sdk_base::event_cache_store::{
+    ChunkUpdate, EventCacheStore, EventCacheStoreError, IndexedBody, IndexedMessage, SearchHit,
+    StoredChunk,
+};
+use matrix_sdk_store_encryption::{Error as EncryptionError, StoreCipher};
+use ruma::{EventId, RoomId};
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::debug;
+use wasm_bindgen::JsValue;
+use web_sys::{IdbKeyRange, IdbTransactionMode};
+
+use crate::{safe_encode::SafeEncode, IndexeddbStateStore};
+
+const CURRENT_DB_VERSION: u32 = 2;
+
+mod keys {
+    pub const CHUNKS: &str = "chunks";
+    pub const SEARCH_INDEX: &str = "search_index";
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum IndexeddbEventCacheStoreError {
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Encryption(#[from] EncryptionError),
+    #[error("DomException {name} ({code}): {message}")]
+    DomException { name: String, message: String, code: u16 },
+    #[error("Creating the key range failed: {0}")]
+    KeyRange(String),
+}
+
+impl From<indexed_db_futures::web_sys::DomException> for IndexeddbEventCacheStoreError {
+    fn from(frm: indexed_db_futures::web_sys::DomException) -> IndexeddbEventCacheStoreError {
+        IndexeddbEventCacheStoreError::DomException {
+            name: frm.name(),
+            message: frm.message(),
+            code: frm.code(),
+        }
+    }
+}
+
+impl From<IndexeddbEventCacheStoreError> for EventCacheStoreError {
+    fn from(e: IndexeddbEventCacheStoreError) -> Self {
+        match e {
+            IndexeddbEventCacheStoreError::Json(e) => EventCacheStoreError::Json(e),
+            IndexeddbEventCacheStoreError::Encryption(e) => EventCacheStoreError::Encryption(e),
+            _ => EventCacheStoreError::backend(e),
+        }
+    }
+}
+
+type Result<A, E = IndexeddbEventCacheStoreError> = std::result::Result<A, E>;
+
+/// An IndexedDB based event cache store.
+pub struct IndexeddbEventCacheStore {
+    name: String,
+    inner: IdbDatabase,
+    store_cipher: Option<Arc<StoreCipher>>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl std::fmt::Debug for IndexeddbEventCacheStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IndexeddbEventCacheStore").field("name", &self.name).finish()
+    }
+}
+
+impl IndexeddbEventCacheStore {
+    /// Open the event cache store with the given name prefix, encrypting the
+    /// data with the given store cipher, if any.
+    pub(crate) async fn open_with_store_cipher(
+        prefix: &str,
+        store_cipher: Option<Arc<StoreCipher>>,
+    ) -> Result<Self> {
+        let name = format!("{prefix:0}::matrix-sdk-event-cache");
+
+        debug!("IndexeddbEventCacheStore: opening store {name}");
+        let mut db_req: OpenDbRequest = IdbDatabase::open_u32(&name, CURRENT_DB_VERSION)?;
+        db_req.set_on_upgrade_needed(Some(|evt: &IdbVersionChangeEvent| -> Result<(), JsValue> {
+            let old_version = evt.old_version() as u32;
+
+            if old_version < 1 {
+                evt.db().create_object_store(keys::CHUNKS)?;
+            }
+
+            if old_version < 2 {
+                evt.db().create_object_store(keys::SEARCH_INDEX)?;
+            }
+
+            Ok(())
+        }));
+
+        let inner = db_req.await?;
+
+        Ok(Self { name, inner, store_cipher })
+    }
+
+    /// Open the event cache store that goes along the given state store, i.e.
+    /// using the same name and encryption.
+    pub async fn open_with_state_store(state_store: &IndexeddbStateStore) -> Result<Self> {
+        Self::open_with_store_cipher(&state_store.name, state_store.store_cipher.clone()).await
+    }
+
+    /// Open a new `IndexeddbEventCacheStore` with the given name and no
+    /// passphrase.
+    pub async fn open_with_name(name: &str) -> Result<Self> {
+        Self::open_with_store_cipher(name, None).await
+    }
+
+    fn encode_chunk_key(&self, room_id: &RoomId, identifier: u64) -> JsValue {
+        let identifier = identifier as usize;
+
+        match &self.store_cipher {
+            Some(cipher) => {
+                room_id.as_str().encode_with_counter_secure(keys::CHUNKS, cipher, identifier)
+            }
+            None => room_id.as_str().encode_with_counter(identifier),
+        }
+    }
+
+    fn encode_chunks_range(&self, room_id: &RoomId) -> Result<IdbKeyRange> {
+        match &self.store_cipher {
+            Some(cipher) => room_id.as_str().encode_to_range_secure(keys::CHUNKS, cipher),
+            None => room_id.as_str().encode_to_range(),
+        }
+        .map_err(IndexeddbEventCacheStoreError::KeyRange)
+    }
+
+    fn encode_search_key(&self, room_id: &RoomId, event_id: &EventId) -> JsValue {
+        match &self.store_cipher {
+            Some(cipher) => (room_id, event_id).as_secure_string(keys::SEARCH_INDEX, cipher),
+            None => (room_id, event_id).as_encoded_string(),
+        }
+        .into()
+    }
+
+    fn serialize_value(&self, value: &impl Serialize) -> Result<JsValue> {
+        Ok(match &self.store_cipher {
+            Some(cipher) => JsValue::from_serde(&cipher.encrypt_value_typed(value)?)?,
+            None => JsValue::from_serde(value)?,
+        })
+    }
+
+    fn deserialize_value<T: DeserializeOwned>(&self, value: &JsValue) -> Result<T> {
+        match &self.store_cipher {
+            Some(cipher) => Ok(cipher.decrypt_value_typed(value.into_serde()?)?),
+            None => Ok(value.into_serde()?),
+        }
+    }
+}
+
+// See the comment on the same hack in the state store module.
+#[cfg(target_arch = "wasm32")]
+macro_rules! impl_event_cache_store {
+    ({ $($body:tt)* }) => {
+        #[async_trait(?Send)]
+        impl EventCacheStore for IndexeddbEventCacheStore {
+            type Error = IndexeddbEventCacheStoreError;
+
+            $($body)*
+        }
+    };
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+macro_rules! impl_event_cache_store {
+    ({ $($body:tt)* }) => {
+        impl IndexeddbEventCacheStore {
+            $($body)*
+        }
+    };
+}
+
+impl_event_cache_store!({
+    async fn update_chunks(&self, room_id: &RoomId, updates: Vec<ChunkUpdate>) -> Result<()> {
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::CHUNKS, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::CHUNKS)?;
+
+        for update in updates {
+            match update {
+                ChunkUpdate::Upsert(chunk) => {
+                    let key = self.encode_chunk_key(room_id, chunk.identifier);
+                    store.put_key_val(&key, &self.serialize_value(&chunk)?)?;
+                }
+                ChunkUpdate::Remove { identifier } => {
+                    store.delete(&self.encode_chunk_key(room_id, identifier))?;
+                }
+            }
+        }
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn load_chunks(&self, room_id: &RoomId) -> Result<Vec<StoredChunk>> {
+        let range = self.encode_chunks_range(room_id)?;
+
+        self.inner
+            .transaction_on_one_with_mode(keys::CHUNKS, IdbTransactionMode::Readonly)?
+            .object_store(keys::CHUNKS)?
+            .get_all_with_key(&range)?
+            .await?
+            .iter()
+            .map(|value| self.deserialize_value(&value))
+            .collect()
+    }
+
+    async fn remove_chunks(&self, room_id: &RoomId) -> Result<()> {
+        let range = self.encode_chunks_range(room_id)?;
+        let tx =
+            self.inner.transaction_on_one_with_mode(keys::CHUNKS, IdbTransactionMode::Readwrite)?;
+
+        tx.object_store(keys::CHUNKS)?.delete(&range)?;
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn index_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        body: IndexedBody,
+    ) -> Result<()> {
+        let key = self.encode_search_key(room_id, event_id);
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEARCH_INDEX, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEARCH_INDEX)?;
+
+        let indexed = store
+            .get(&key)?
+            .await?
+            .map(|value| self.deserialize_value::<IndexedMessage>(&value))
+            .transpose()?;
+
+        if let Some(message) =
+            IndexedMessage::with_body(indexed, room_id.to_owned(), event_id.to_owned(), body)
+        {
+            store.put_key_val(&key, &self.serialize_value(&message)?)?;
+        }
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn redact_indexed_message(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEARCH_INDEX, IdbTransactionMode::Readwrite)?;
+        let store = tx.object_store(keys::SEARCH_INDEX)?;
+
+        // The redacted event is either an indexed message, or the indexed edit of one,
+        // which can only be found by looking at all the messages, as when searching.
+        let message = match store.get(&self.encode_search_key(room_id, event_id))?.await? {
+            Some(value) => Some(self.deserialize_value::<IndexedMessage>(&value)?),
+            None => {
+                let mut found = None;
+
+                for value in store.get_all()?.await?.iter() {
+                    let message: IndexedMessage = self.deserialize_value(&value)?;
+                    if *message.room_id == *room_id
+                        && message.body.edit_id.as_deref() == Some(event_id)
+                    {
+                        found = Some(message);
+                        break;
+                    }
+                }
+
+                found
+            }
+        };
+
+        if let Some(message) = message {
+            let key = self.encode_search_key(&message.room_id, &message.event_id);
+
+            match message.without_redacted(event_id) {
+                Some(message) => store.put_key_val(&key, &self.serialize_value(&message)?)?,
+                None => store.delete(&key)?,
+            };
+        }
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn search_messages(
+        &self,
+        room_id: Option<&RoomId>,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        // IndexedDB doesn't have full-text search, and the messages might be
+        // encrypted anyway, so match all of them in memory.
+        let mut hits = Vec::new();
+        let values = self
+            .inner
+            .transaction_on_one_with_mode(keys::SEARCH_INDEX, IdbTransactionMode::Readonly)?
+            .object_store(keys::SEARCH_INDEX)?
+            .get_all()?
+            .await?;
+
+        for value in values.iter() {
+            let message: IndexedMessage = self.deserialize_value(&value)?;
+            if room_id.is_some_and(|room_id| *room_id != *message.room_id) {
+                continue;
+            }
+
+            hits.extend(SearchHit::from_match(
+                message.room_id,
+                message.event_id,
+                &message.body.body,
+                query,
+            ));
+        }
+
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank));
+        hits.truncate(limit);
+
+        Ok(hits)
+    }
+});
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    use matrix_sdk_base::event_cache_store_integration_tests;
+    use uuid::Uuid;
+
+    use super::{IndexeddbEventCacheStore, Result};
+
+    async fn get_event_cache_store() -> Result<IndexeddbEventCacheStore> {
+        let db_name = format!("test-event-cache-plain-{}", Uuid::new_v4().as_hyphenated());
+        IndexeddbEventCacheStore::open_with_name(&db_name).await
+    }
+
+    event_cache_store_integration_tests!();
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod encrypted_tests {
+    #[cfg(target_arch = "wasm32")]
+    wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+
+    use std::sync::Arc;
+
+    use matrix_sdk_base::event_cache_store_integration_tests;
+    use matrix_sdk_store_encryption::StoreCipher;
+    use uuid::Uuid;
+
+    use super::{IndexeddbEventCacheStore, Result};
+
+    async fn get_event_cache_store() -> Result<IndexeddbEventCacheStore> {
+        let db_name = format!("test-event-cache-encrypted-{}", Uuid::new_v4().as_hyphenated());
+        let store_cipher = Arc::new(StoreCipher::new()?);
+        IndexeddbEventCacheStore::open_with_store_cipher(&db_name, Some(store_cipher)).await
+    }
+
+    event_cache_store_integration_tests!();
+}
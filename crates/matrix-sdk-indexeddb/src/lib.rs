@@ -6,6 +6,8 @@ use thiserror::Error;
 
 #[cfg(feature = "e2e-encryption")]
 mod crypto_store;
+#[cfg(feature = "event-cache-store")]
+mod event_cache_store;
 mod safe_encode;
 #[cfg(feature = "e2e-encryption")]
 mod serialize_bool_for_indexeddb;
@@ -14,6 +16,8 @@ mod state_store;
 
 #[cfg(feature = "e2e-encryption")]
 pub use crypto_store::{IndexeddbCryptoStore, IndexeddbCryptoStoreError};
+#[cfg(feature = "event-cache-store")]
+pub use event_cache_store::{IndexeddbEventCacheStore, IndexeddbEventCacheStoreError};
 #[cfg(feature = "state-store")]
 pub use state_store::{
     IndexeddbStateStore, IndexeddbStateStoreBuilder, IndexeddbStateStoreError,
@@ -58,6 +62,15 @@ pub async fn open_state_store(
     Ok(state_store)
 }
 
+/// Create an [`IndexeddbEventCacheStore`] that uses the same name and
+/// encryption as the given [`IndexeddbStateStore`].
+#[cfg(feature = "event-cache-store")]
+pub async fn open_event_cache_store(
+    state_store: &IndexeddbStateStore,
+) -> Result<IndexeddbEventCacheStore, OpenStoreError> {
+    Ok(IndexeddbEventCacheStore::open_with_state_store(state_store).await?)
+}
+
 /// All the errors that can occur when opening an IndexedDB store.
 #[derive(Error, Debug)]
 pub enum OpenStoreError {
@@ -70,4 +83,9 @@ pub enum OpenStoreError {
     #[cfg(feature = "e2e-encryption")]
     #[error(transparent)]
     Crypto(#[from] IndexeddbCryptoStoreError),
+
+    /// An error occurred with the event cache store implementation.
+    #[cfg(feature = "event-cache-store")]
+    #[error(transparent)]
+    EventCache(#[from] IndexeddbEventCacheStoreError),
 }
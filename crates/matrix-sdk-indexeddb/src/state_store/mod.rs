@@ -24,7 +24,7 @@ use indexed_db_futures::prelude::*;
 use matrix_sdk_base::{
     deserialized_responses::RawAnySyncOrStrippedState,
     media::{MediaRequest, UniqueKey},
-    store::{StateChanges, StateStore, StoreError},
+    store::{QueuedRequest, StateChanges, StateStore, StoreError},
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateStoreDataKey,
     StateStoreDataValue,
 };
@@ -41,7 +41,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType, SyncStateEvent,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedUserId, RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, MxcUri, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId,
+    RoomVersionId, TransactionId, UserId,
 };
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use tracing::{debug, warn};
@@ -115,6 +116,8 @@ mod keys {
     pub const CUSTOM: &str = "custom";
     pub const KV: &str = "kv";
 
+    pub const ROOM_SEND_QUEUE: &str = "room_send_queue";
+
     /// All names of the current state stores for convenience.
     pub const ALL_STORES: &[&str] = &[
         ACCOUNT_DATA,
@@ -132,6 +135,7 @@ mod keys {
         MEDIA,
         CUSTOM,
         KV,
+        ROOM_SEND_QUEUE,
     ];
 
     // static keys
@@ -238,7 +242,7 @@ impl IndexeddbStateStoreBuilder {
 }
 
 pub struct IndexeddbStateStore {
-    name: String,
+    pub(crate) name: String,
     pub(crate) inner: IdbDatabase,
     pub(crate) meta: IdbDatabase,
     pub(crate) store_cipher: Option<Arc<StoreCipher>>,
@@ -1229,7 +1233,7 @@ impl_state_store!({
     }
 
     async fn remove_room(&self, room_id: &RoomId) -> Result<()> {
-        let direct_stores = [keys::ROOM_INFOS];
+        let direct_stores = [keys::ROOM_INFOS, keys::ROOM_SEND_QUEUE];
 
         let prefixed_stores = [
             keys::PROFILES,
@@ -1287,8 +1291,120 @@ impl_state_store!({
     async fn get_joined_user_ids(&self, room_id: &RoomId) -> Result<Vec<OwnedUserId>> {
         self.get_user_ids(room_id, RoomMemberships::JOIN).await
     }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let encoded_key = self.encode_key(keys::ROOM_SEND_QUEUE, room_id);
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::ROOM_SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let obj = tx.object_store(keys::ROOM_SEND_QUEUE)?;
+
+        let mut queue = match obj.get(&encoded_key)?.await? {
+            Some(value) => self.deserialize_event::<PersistedSendQueue>(&value)?,
+            None => PersistedSendQueue { room_id: room_id.to_owned(), requests: Vec::new() },
+        };
+        queue.requests.push(request);
+
+        obj.put_key_val(&encoded_key, &self.serialize_event(&queue)?)?;
+
+        tx.await.into_result().map_err(|e| e.into())
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let encoded_key = self.encode_key(keys::ROOM_SEND_QUEUE, room_id);
+
+        let tx = self
+            .inner
+            .transaction_on_one_with_mode(keys::ROOM_SEND_QUEUE, IdbTransactionMode::Readwrite)?;
+        let obj = tx.object_store(keys::ROOM_SEND_QUEUE)?;
+
+        let Some(value) = obj.get(&encoded_key)?.await? else {
+            return Ok(false);
+        };
+        let mut queue = self.deserialize_event::<PersistedSendQueue>(&value)?;
+
+        let Some(index) = queue.requests.iter().position(|r| r.transaction_id == transaction_id)
+        else {
+            return Ok(false);
+        };
+        queue.requests.remove(index);
+
+        if queue.requests.is_empty() {
+            obj.delete(&encoded_key)?;
+        } else {
+            obj.put_key_val(&encoded_key, &self.serialize_event(&queue)?)?;
+        }
+
+        tx.await.into_result()?;
+
+        Ok(true)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        let encoded_key = self.encode_key(keys::ROOM_SEND_QUEUE, room_id);
+
+        Ok(self
+            .inner
+            .transaction_on_one_with_mode(keys::ROOM_SEND_QUEUE, IdbTransactionMode::Readonly)?
+            .object_store(keys::ROOM_SEND_QUEUE)?
+            .get(&encoded_key)?
+            .await?
+            .map(|value| self.deserialize_event::<PersistedSendQueue>(&value))
+            .transpose()?
+            .map(|queue| queue.requests)
+            .unwrap_or_default())
+    }
+
+    async fn load_next_send_queue_request(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<QueuedRequest>> {
+        // The requests of a room are stored together.
+        Ok(self.load_send_queue_requests(room_id).await?.into_iter().next())
+    }
+
+    async fn load_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<Option<QueuedRequest>> {
+        Ok(self
+            .load_send_queue_requests(room_id)
+            .await?
+            .into_iter()
+            .find(|r| r.transaction_id == transaction_id))
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        self.inner
+            .transaction_on_one_with_mode(keys::ROOM_SEND_QUEUE, IdbTransactionMode::Readonly)?
+            .object_store(keys::ROOM_SEND_QUEUE)?
+            .get_all()?
+            .await?
+            .iter()
+            .map(|value| {
+                self.deserialize_event::<PersistedSendQueue>(&value).map(|queue| queue.room_id)
+            })
+            .collect()
+    }
 });
 
+/// The requests waiting in the send queue of a room.
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedSendQueue {
+    room_id: OwnedRoomId,
+    requests: Vec<QueuedRequest>,
+}
+
 /// A room member.
 #[derive(Debug, Serialize, Deserialize)]
 struct RoomMember {
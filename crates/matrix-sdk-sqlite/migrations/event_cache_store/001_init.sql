@@ -0,0 +1,15 @@
+-- basic kv data like the database version and store cipher
+CREATE TABLE "kv" (
+    "key" TEXT PRIMARY KEY NOT NULL,
+    "value" BLOB NOT NULL
+);
+
+-- the chunks of events and gaps of each room, each one linked to the previous
+-- chunk of its room
+CREATE TABLE "chunk" (
+    "room_id" BLOB NOT NULL,
+    "identifier" INTEGER NOT NULL,
+    "data" BLOB NOT NULL,
+
+    PRIMARY KEY ("room_id", "identifier")
+);
@@ -0,0 +1,17 @@
+-- the full-text search index of the messages
+--
+-- the bodies must be stored in plaintext for the full-text search to work, so
+-- this table isn't encrypted even if the store has a passphrase; the room and
+-- event IDs are hashed for filtering, and stored encrypted in `data` along with
+-- the metadata of the indexed message, like its sender and the version of its
+-- body, so that only the newest valid edit is indexed
+--
+-- `edit_id` is the hashed ID of the edit the indexed body comes from, if any, so
+-- that the original body can be indexed again when this edit is redacted
+CREATE VIRTUAL TABLE "message_search" USING fts5(
+    "room_id" UNINDEXED,
+    "event_id" UNINDEXED,
+    "edit_id" UNINDEXED,
+    "data" UNINDEXED,
+    "body"
+);
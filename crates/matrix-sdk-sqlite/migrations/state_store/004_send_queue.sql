@@ -0,0 +1,13 @@
+-- The requests waiting to be sent by the send queue of each room.
+CREATE TABLE "send_queue_request" (
+    -- Used to load the requests of a room in the order they were saved.
+    "id" INTEGER PRIMARY KEY AUTOINCREMENT,
+    "room_id" BLOB NOT NULL,
+    -- The room ID, serialized and encrypted like the data, so it can be listed.
+    "room_id_val" BLOB NOT NULL,
+    "transaction_id" BLOB NOT NULL,
+    "data" BLOB NOT NULL
+);
+
+CREATE INDEX "send_queue_request_room_id_idx"
+    ON "send_queue_request" ("room_id");
@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use deadpool_sqlite::{CreatePoolError, PoolError};
+#[cfg(feature = "event-cache-store")]
+use matrix_sdk_base::event_cache_store::EventCacheStoreError;
 #[cfg(feature = "state-store")]
 use matrix_sdk_base::store::StoreError as StateStoreError;
 #[cfg(feature = "crypto-store")]
@@ -136,4 +138,15 @@ impl From<Error> for StateStoreError {
     }
 }
 
+#[cfg(feature = "event-cache-store")]
+impl From<Error> for EventCacheStoreError {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Json(e) => EventCacheStoreError::Json(e),
+            Error::Encryption(e) => EventCacheStoreError::Encryption(e),
+            e => EventCacheStoreError::backend(e),
+        }
+    }
+}
+
 pub(crate) type Result<T, E = Error> = std::result::Result<T, E>;
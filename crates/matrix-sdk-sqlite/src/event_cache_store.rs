@@ -0,0 +1,453 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{borrow::Cow, fmt, path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
+use matrix_sdk_base::event_cache_store::{
+    ChunkUpdate, EventCacheStore, IndexedBody, IndexedMessage, SearchHit, StoredChunk,
+};
+use matrix_sdk_store_encryption::StoreCipher;
+use ruma::{EventId, RoomId};
+use rusqlite::{OptionalExtension, Transaction};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::fs;
+use tracing::debug;
+
+use crate::{
+    error::{Error, Result},
+    get_or_create_store_cipher,
+    utils::{load_db_version, Key, SqliteObjectExt},
+    OpenStoreError, SqliteObjectStoreExt,
+};
+
+mod keys {
+    // Tables
+    pub const CHUNK: &str = "chunk";
+    pub const MESSAGE_SEARCH: &str = "message_search";
+}
+
+const DATABASE_VERSION: u8 = 2;
+
+/// The number of tokens in the snippets of the search results.
+const SNIPPET_TOKENS: usize = 10;
+
+/// A sqlite based event cache store.
+///
+/// Note that the search index of the messages can't be encrypted, since sqlite
+/// needs to read the bodies of the messages to search them. When a passphrase
+/// is used, only the room and event IDs of the indexed messages, and their
+/// metadata, are encrypted.
+#[derive(Clone)]
+pub struct SqliteEventCacheStore {
+    store_cipher: Option<Arc<StoreCipher>>,
+    pool: SqlitePool,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SqliteEventCacheStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SqliteEventCacheStore").finish_non_exhaustive()
+    }
+}
+
+impl SqliteEventCacheStore {
+    /// Open the sqlite-based event cache store at the given path using the
+    /// given passphrase to encrypt private data.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let pool = create_pool(path.as_ref()).await?;
+
+        Self::open_with_pool(pool, passphrase).await
+    }
+
+    /// Create a sqlite-based event cache store using the given sqlite database
+    /// pool. The given passphrase will be used to encrypt private data.
+    pub async fn open_with_pool(
+        pool: SqlitePool,
+        passphrase: Option<&str>,
+    ) -> Result<Self, OpenStoreError> {
+        let conn = pool.get().await?;
+        let version = load_db_version(&conn).await?;
+        run_migrations(&conn, version).await?;
+
+        let store_cipher = match passphrase {
+            Some(p) => Some(Arc::new(get_or_create_store_cipher(p, &conn).await?)),
+            None => None,
+        };
+
+        Ok(Self { store_cipher, pool })
+    }
+
+    fn encode_key(&self, table_name: &str, key: impl AsRef<[u8]>) -> Key {
+        let bytes = key.as_ref();
+        if let Some(store_cipher) = &self.store_cipher {
+            Key::Hashed(store_cipher.hash_key(table_name, bytes))
+        } else {
+            Key::Plain(bytes.to_owned())
+        }
+    }
+
+    fn serialize_json(&self, value: &impl Serialize) -> Result<Vec<u8>> {
+        let serialized = serde_json::to_vec(value)?;
+
+        if let Some(key) = &self.store_cipher {
+            let encrypted = key.encrypt_value_data(serialized)?;
+            Ok(rmp_serde::to_vec_named(&encrypted)?)
+        } else {
+            Ok(serialized)
+        }
+    }
+
+    fn deserialize_json<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T> {
+        let decoded = if let Some(key) = &self.store_cipher {
+            let encrypted = rmp_serde::from_slice(data)?;
+            Cow::Owned(key.decrypt_value_data(encrypted)?)
+        } else {
+            Cow::Borrowed(data)
+        };
+
+        Ok(serde_json::from_slice(&decoded)?)
+    }
+
+    /// Insert a message in the search index, with the given hashed room and
+    /// event IDs.
+    fn insert_indexed_message(
+        &self,
+        txn: &Transaction<'_>,
+        room_id_key: &[u8],
+        event_id_key: &[u8],
+        message: &IndexedMessage,
+    ) -> Result<()> {
+        let edit_id_key = message
+            .body
+            .edit_id
+            .as_deref()
+            .map(|edit_id| self.encode_key(keys::MESSAGE_SEARCH, edit_id));
+
+        txn.prepare_cached(
+            "INSERT INTO message_search (room_id, event_id, edit_id, data, body) \
+             VALUES (?, ?, ?, ?, ?)",
+        )?
+        .execute((
+            room_id_key,
+            event_id_key,
+            edit_id_key,
+            self.serialize_json(message)?,
+            &message.body.body,
+        ))?;
+
+        Ok(())
+    }
+
+    async fn acquire(&self) -> Result<SqliteConn> {
+        Ok(self.pool.get().await?)
+    }
+}
+
+async fn create_pool(path: &Path) -> Result<SqlitePool, OpenStoreError> {
+    fs::create_dir_all(path).await.map_err(OpenStoreError::CreateDir)?;
+    let cfg = deadpool_sqlite::Config::new(path.join("matrix-sdk-event-cache.sqlite3"));
+    Ok(cfg.create_pool(Runtime::Tokio1)?)
+}
+
+/// Run migrations for the given version of the database.
+async fn run_migrations(conn: &SqliteConn, version: u8) -> Result<()> {
+    if version == 0 {
+        debug!("Creating database");
+    } else if version < DATABASE_VERSION {
+        debug!(version, new_version = DATABASE_VERSION, "Upgrading database");
+    }
+
+    if version < 1 {
+        // First turn on WAL mode, this can't be done in the transaction, it fails with
+        // the error message: "cannot change into wal mode from within a transaction".
+        conn.execute_batch("PRAGMA journal_mode = wal;").await?;
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/event_cache_store/001_init.sql"))
+        })
+        .await?;
+    }
+
+    if version < 2 {
+        conn.with_transaction(|txn| {
+            txn.execute_batch(include_str!("../migrations/event_cache_store/002_search_index.sql"))
+        })
+        .await?;
+    }
+
+    conn.set_kv("version", vec![DATABASE_VERSION]).await?;
+
+    Ok(())
+}
+
+#[async_trait]
+impl EventCacheStore for SqliteEventCacheStore {
+    type Error = Error;
+
+    async fn update_chunks(&self, room_id: &RoomId, updates: Vec<ChunkUpdate>) -> Result<()> {
+        let room_id = self.encode_key(keys::CHUNK, room_id);
+        let updates = updates
+            .into_iter()
+            .map(|update| match update {
+                ChunkUpdate::Upsert(chunk) => {
+                    Ok((chunk.identifier as i64, Some(self.serialize_json(&chunk)?)))
+                }
+                ChunkUpdate::Remove { identifier } => Ok((identifier as i64, None)),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                for (identifier, data) in updates {
+                    match data {
+                        Some(data) => txn
+                            .prepare_cached(
+                                "INSERT OR REPLACE INTO chunk (room_id, identifier, data) \
+                                 VALUES (?, ?, ?)",
+                            )?
+                            .execute((&room_id, identifier, data))?,
+                        None => txn
+                            .prepare_cached(
+                                "DELETE FROM chunk WHERE room_id = ? AND identifier = ?",
+                            )?
+                            .execute((&room_id, identifier))?,
+                    };
+                }
+
+                Ok(())
+            })
+            .await
+    }
+
+    async fn load_chunks(&self, room_id: &RoomId) -> Result<Vec<StoredChunk>> {
+        let room_id = self.encode_key(keys::CHUNK, room_id);
+
+        self.acquire()
+            .await?
+            .prepare("SELECT data FROM chunk WHERE room_id = ?", move |mut stmt| {
+                stmt.query((room_id,))?.mapped(|row| row.get::<_, Vec<u8>>(0)).collect()
+            })
+            .await?
+            .into_iter()
+            .map(|data| self.deserialize_json(&data))
+            .collect()
+    }
+
+    async fn remove_chunks(&self, room_id: &RoomId) -> Result<()> {
+        let room_id = self.encode_key(keys::CHUNK, room_id);
+
+        self.acquire().await?.execute("DELETE FROM chunk WHERE room_id = ?", (room_id,)).await?;
+
+        Ok(())
+    }
+
+    async fn index_message(
+        &self,
+        room_id: &RoomId,
+        event_id: &EventId,
+        body: IndexedBody,
+    ) -> Result<()> {
+        let this = self.clone();
+        let room_id_key = self.encode_key(keys::MESSAGE_SEARCH, room_id);
+        let event_id_key = self.encode_key(keys::MESSAGE_SEARCH, event_id);
+        let room_id = room_id.to_owned();
+        let event_id = event_id.to_owned();
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                let indexed = txn
+                    .prepare_cached(
+                        "SELECT data FROM message_search WHERE room_id = ? AND event_id = ?",
+                    )?
+                    .query_row((&room_id_key, &event_id_key), |row| row.get::<_, Vec<u8>>(0))
+                    .optional()?
+                    .map(|data| this.deserialize_json(&data))
+                    .transpose()?;
+
+                let Some(message) = IndexedMessage::with_body(indexed, room_id, event_id, body)
+                else {
+                    return Ok(());
+                };
+
+                // FTS tables don't support upserts, so remove the previous version first.
+                txn.prepare_cached(
+                    "DELETE FROM message_search WHERE room_id = ? AND event_id = ?",
+                )?
+                .execute((&room_id_key, &event_id_key))?;
+
+                this.insert_indexed_message(txn, &room_id_key, &event_id_key, &message)
+            })
+            .await
+    }
+
+    async fn redact_indexed_message(&self, room_id: &RoomId, event_id: &EventId) -> Result<()> {
+        let this = self.clone();
+        let room_id_key = self.encode_key(keys::MESSAGE_SEARCH, room_id);
+        let redacted_id_key = self.encode_key(keys::MESSAGE_SEARCH, event_id);
+        let event_id = event_id.to_owned();
+
+        self.acquire()
+            .await?
+            .with_transaction(move |txn| {
+                // The redacted event is either an indexed message, or the indexed edit of one.
+                let Some((event_id_key, data)) = txn
+                    .prepare_cached(
+                        "SELECT event_id, data FROM message_search \
+                         WHERE room_id = ?1 AND (event_id = ?2 OR edit_id = ?2)",
+                    )?
+                    .query_row((&room_id_key, &redacted_id_key), |row| {
+                        Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Vec<u8>>(1)?))
+                    })
+                    .optional()?
+                else {
+                    return Ok(());
+                };
+                let message: IndexedMessage = this.deserialize_json(&data)?;
+
+                txn.prepare_cached(
+                    "DELETE FROM message_search WHERE room_id = ? AND event_id = ?",
+                )?
+                .execute((&room_id_key, &event_id_key))?;
+
+                match message.without_redacted(&event_id) {
+                    Some(message) => {
+                        this.insert_indexed_message(txn, &room_id_key, &event_id_key, &message)
+                    }
+                    None => Ok(()),
+                }
+            })
+            .await
+    }
+
+    async fn search_messages(
+        &self,
+        room_id: Option<&RoomId>,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let Some(query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let room_id = room_id.map(|room_id| self.encode_key(keys::MESSAGE_SEARCH, room_id));
+
+        let rows = self
+            .acquire()
+            .await?
+            .prepare(
+                format!(
+                    "SELECT data, -bm25(message_search) AS score, \
+                            snippet(message_search, 3, '', '', '…', {SNIPPET_TOKENS}) \
+                     FROM message_search \
+                     WHERE message_search MATCH ?1 AND (?2 IS NULL OR room_id = ?2) \
+                     ORDER BY score DESC LIMIT ?3"
+                ),
+                move |mut stmt| {
+                    stmt.query((query, room_id, limit as i64))?
+                        .mapped(|row| {
+                            Ok((
+                                row.get::<_, Vec<u8>>(0)?,
+                                row.get::<_, f64>(1)?,
+                                row.get::<_, String>(2)?,
+                            ))
+                        })
+                        .collect::<rusqlite::Result<Vec<_>>>()
+                },
+            )
+            .await?;
+
+        rows.into_iter()
+            .map(|(data, rank, snippet)| {
+                let IndexedMessage { room_id, event_id, .. } = self.deserialize_json(&data)?;
+                Ok(SearchHit { room_id, event_id, rank, snippet })
+            })
+            .collect()
+    }
+}
+
+/// Build an FTS5 query from a search query typed by the user.
+///
+/// Every word is quoted so it can't be interpreted as FTS5 syntax, and is
+/// matched as a prefix. Returns `None` if the query has no words.
+fn fts_query(query: &str) -> Option<String> {
+    let terms = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{term}\"*"))
+        .collect::<Vec<_>>();
+
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache_store::{EventCacheStore, EventCacheStoreError},
+        event_cache_store_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::SqliteEventCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<impl EventCacheStore, EventCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        Ok(SqliteEventCacheStore::open(tmpdir_path.to_str().unwrap(), None).await.unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+}
+
+#[cfg(test)]
+mod encrypted_tests {
+    use std::sync::atomic::{AtomicU32, Ordering::SeqCst};
+
+    use matrix_sdk_base::{
+        event_cache_store::{EventCacheStore, EventCacheStoreError},
+        event_cache_store_integration_tests,
+    };
+    use once_cell::sync::Lazy;
+    use tempfile::{tempdir, TempDir};
+
+    use super::SqliteEventCacheStore;
+
+    static TMP_DIR: Lazy<TempDir> = Lazy::new(|| tempdir().unwrap());
+    static NUM: AtomicU32 = AtomicU32::new(0);
+
+    async fn get_event_cache_store() -> Result<impl EventCacheStore, EventCacheStoreError> {
+        let name = NUM.fetch_add(1, SeqCst).to_string();
+        let tmpdir_path = TMP_DIR.path().join(name);
+
+        Ok(SqliteEventCacheStore::open(
+            tmpdir_path.to_str().unwrap(),
+            Some("default_test_password"),
+        )
+        .await
+        .unwrap())
+    }
+
+    event_cache_store_integration_tests!();
+}
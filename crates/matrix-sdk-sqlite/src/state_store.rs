@@ -11,7 +11,7 @@ use deadpool_sqlite::{Object as SqliteConn, Pool as SqlitePool, Runtime};
 use matrix_sdk_base::{
     deserialized_responses::{RawAnySyncOrStrippedState, SyncOrStrippedState},
     media::{MediaRequest, UniqueKey},
-    store::migration_helpers::RoomInfoV1,
+    store::{migration_helpers::RoomInfoV1, QueuedRequest},
     MinimalRoomMemberEvent, RoomInfo, RoomMemberships, RoomState, StateChanges, StateStore,
     StateStoreDataKey, StateStoreDataValue,
 };
@@ -29,7 +29,8 @@ use ruma::{
         GlobalAccountDataEventType, RoomAccountDataEventType, StateEventType,
     },
     serde::Raw,
-    CanonicalJsonObject, EventId, OwnedEventId, OwnedUserId, RoomId, RoomVersionId, UserId,
+    CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
+    TransactionId, UserId,
 };
 use rusqlite::{OptionalExtension, Transaction};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -55,9 +56,10 @@ mod keys {
     pub const RECEIPT: &str = "receipt";
     pub const DISPLAY_NAME: &str = "display_name";
     pub const MEDIA: &str = "media";
+    pub const SEND_QUEUE: &str = "send_queue_request";
 }
 
-const DATABASE_VERSION: u8 = 3;
+const DATABASE_VERSION: u8 = 4;
 
 /// A sqlite based cryptostore.
 #[derive(Clone)]
@@ -213,6 +215,13 @@ impl SqliteStateStore {
             .await?;
         }
 
+        if from < 4 && to >= 4 {
+            conn.with_transaction(move |txn| {
+                txn.execute_batch(include_str!("../migrations/state_store/004_send_queue.sql"))
+            })
+            .await?;
+        }
+
         conn.set_kv("version", vec![to]).await?;
 
         Ok(())
@@ -394,6 +403,7 @@ trait SqliteConnectionStateStoreExt {
     fn set_display_name(&self, room_id: &[u8], name: &[u8], data: &[u8]) -> rusqlite::Result<()>;
     fn remove_display_name(&self, room_id: &[u8], name: &[u8]) -> rusqlite::Result<()>;
     fn remove_room_display_names(&self, room_id: &[u8]) -> rusqlite::Result<()>;
+    fn remove_room_send_queue(&self, room_id: &[u8]) -> rusqlite::Result<()>;
 }
 
 impl SqliteConnectionStateStoreExt for rusqlite::Connection {
@@ -600,6 +610,11 @@ impl SqliteConnectionStateStoreExt for rusqlite::Connection {
         self.prepare("DELETE FROM display_name WHERE room_id = ?")?.execute((room_id,))?;
         Ok(())
     }
+
+    fn remove_room_send_queue(&self, room_id: &[u8]) -> rusqlite::Result<()> {
+        self.prepare("DELETE FROM send_queue_request WHERE room_id = ?")?.execute((room_id,))?;
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -1611,10 +1626,122 @@ impl StateStore for SqliteStateStore {
                 let display_name_room_id = this.encode_key(keys::DISPLAY_NAME, &room_id);
                 txn.remove_room_display_names(&display_name_room_id)?;
 
+                let send_queue_room_id = this.encode_key(keys::SEND_QUEUE, &room_id);
+                txn.remove_room_send_queue(&send_queue_room_id)?;
+
                 Ok(())
             })
             .await
     }
+
+    async fn save_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        request: QueuedRequest,
+    ) -> Result<()> {
+        let room_id_key = self.encode_key(keys::SEND_QUEUE, room_id);
+        let room_id_value = self.serialize_value(&room_id)?;
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, &request.transaction_id);
+        let data = self.serialize_json(&request)?;
+
+        self.acquire()
+            .await?
+            .execute(
+                "INSERT INTO send_queue_request (room_id, room_id_val, transaction_id, data)
+                 VALUES (?, ?, ?, ?)",
+                (room_id_key, room_id_value, transaction_id, data),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn remove_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<bool> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, transaction_id);
+
+        let num_deleted = self
+            .acquire()
+            .await?
+            .execute(
+                "DELETE FROM send_queue_request WHERE room_id = ? AND transaction_id = ?",
+                (room_id, transaction_id),
+            )
+            .await?;
+
+        Ok(num_deleted > 0)
+    }
+
+    async fn load_send_queue_requests(&self, room_id: &RoomId) -> Result<Vec<QueuedRequest>> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        self.acquire()
+            .await?
+            .prepare(
+                "SELECT data FROM send_queue_request WHERE room_id = ? ORDER BY id",
+                |mut stmt| stmt.query((room_id,))?.mapped(|row| row.get::<_, Vec<u8>>(0)).collect(),
+            )
+            .await?
+            .into_iter()
+            .map(|data| self.deserialize_json(&data))
+            .collect()
+    }
+
+    async fn load_next_send_queue_request(
+        &self,
+        room_id: &RoomId,
+    ) -> Result<Option<QueuedRequest>> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+
+        self.acquire()
+            .await?
+            .query_row(
+                "SELECT data FROM send_queue_request WHERE room_id = ? ORDER BY id LIMIT 1",
+                (room_id,),
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .await
+            .optional()?
+            .map(|data| self.deserialize_json(&data))
+            .transpose()
+    }
+
+    async fn load_send_queue_request(
+        &self,
+        room_id: &RoomId,
+        transaction_id: &TransactionId,
+    ) -> Result<Option<QueuedRequest>> {
+        let room_id = self.encode_key(keys::SEND_QUEUE, room_id);
+        let transaction_id = self.encode_key(keys::SEND_QUEUE, transaction_id);
+
+        self.acquire()
+            .await?
+            .query_row(
+                "SELECT data FROM send_queue_request WHERE room_id = ? AND transaction_id = ?",
+                (room_id, transaction_id),
+                |row| row.get::<_, Vec<u8>>(0),
+            )
+            .await
+            .optional()?
+            .map(|data| self.deserialize_json(&data))
+            .transpose()
+    }
+
+    async fn load_rooms_with_unsent_requests(&self) -> Result<Vec<OwnedRoomId>> {
+        self.acquire()
+            .await?
+            .prepare("SELECT room_id_val FROM send_queue_request GROUP BY room_id", |mut stmt| {
+                stmt.query(())?.mapped(|row| row.get::<_, Vec<u8>>(0)).collect()
+            })
+            .await?
+            .into_iter()
+            .map(|data| self.deserialize_value(&data))
+            .collect()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,8 +19,10 @@ mod events;
 pub mod encryption_sync_service;
 pub mod notification_client;
 pub mod room_list_service;
+pub mod space_service;
 pub mod sync_service;
 pub mod timeline;
+pub mod typing_service;
 pub mod unable_to_decrypt_hook;
 
 pub use self::{room_list_service::RoomListService, timeline::Timeline};
@@ -64,6 +64,7 @@ mod non_left;
 mod none;
 mod normalized_match_room_name;
 mod not;
+mod space;
 mod unread;
 
 pub use all::new_filter as new_filter_all;
@@ -77,6 +78,7 @@ pub use non_left::new_filter as new_filter_non_left;
 pub use none::new_filter as new_filter_none;
 pub use normalized_match_room_name::new_filter as new_filter_normalized_match_room_name;
 pub use not::new_filter as new_filter_not;
+pub use space::new_filter as new_filter_space;
 use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 pub use unread::new_filter as new_filter_unread;
 
@@ -0,0 +1,96 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use matrix_sdk::RoomListEntry;
+use ruma::RoomId;
+
+use super::Filter;
+use crate::space_service::SpaceService;
+
+struct SpaceRoomMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    is_in_space: F,
+}
+
+impl<F> SpaceRoomMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    fn matches(&self, room_list_entry: &RoomListEntry) -> bool {
+        if !matches!(room_list_entry, RoomListEntry::Filled(_) | RoomListEntry::Invalidated(_)) {
+            return false;
+        }
+
+        (self.is_in_space)(room_list_entry).unwrap_or(false)
+    }
+}
+
+/// Create a new filter that will accept all filled or invalidated entries, but
+/// filters out rooms that are not children of the given space, according to
+/// the [`SpaceService`].
+///
+/// If `recursive` is true, the rooms of the subspaces of the space are accepted
+/// too.
+///
+/// The filter reads the current [`SpaceGraph`], and the `SpaceService` triggers
+/// an update of the room list for the rooms whose ancestors change, so the
+/// filter is applied again to them, as long as the `SpaceService` is alive.
+///
+/// [`SpaceGraph`]: crate::space_service::SpaceGraph
+pub fn new_filter(space_service: &SpaceService, space_id: &RoomId, recursive: bool) -> impl Filter {
+    let graph = space_service.shared_graph();
+    let space_id = space_id.to_owned();
+
+    let matcher = SpaceRoomMatcher {
+        is_in_space: move |room| {
+            let room_id = room.as_room_id()?;
+
+            Some(graph.read().contains(&space_id, room_id, recursive))
+        },
+    };
+
+    move |room_list_entry| -> bool { matcher.matches(room_list_entry) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::SpaceRoomMatcher;
+
+    #[test]
+    fn test_is_in_space() {
+        let matcher = SpaceRoomMatcher { is_in_space: |_| Some(true) };
+
+        assert!(matcher.matches(&RoomListEntry::Empty).not());
+        assert!(matcher.matches(&RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned())));
+        assert!(matcher.matches(&RoomListEntry::Invalidated(room_id!("!r0:bar.org").to_owned())));
+    }
+
+    #[test]
+    fn test_is_not_in_space() {
+        let matcher = SpaceRoomMatcher { is_in_space: |_| Some(false) };
+
+        assert!(matcher.matches(&RoomListEntry::Empty).not());
+        assert!(matcher.matches(&RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned())).not());
+        assert!(matcher
+            .matches(&RoomListEntry::Invalidated(room_id!("!r0:bar.org").to_owned()))
+            .not());
+    }
+}
@@ -64,6 +64,7 @@
 pub mod filters;
 mod room;
 mod room_list;
+pub mod sorters;
 mod state;
 
 use std::{future::ready, num::NonZeroUsize, sync::Arc, time::Duration};
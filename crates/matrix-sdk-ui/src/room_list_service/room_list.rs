@@ -12,7 +12,10 @@
 // See the License for that specific language governing permissions and
 // limitations under the License.
 
-use std::{future::ready, sync::Arc};
+use std::{
+    future::ready,
+    sync::{Arc, Mutex},
+};
 
 use async_cell::sync::AsyncCell;
 use async_rx::StreamExt as _;
@@ -20,7 +23,7 @@ use async_stream::stream;
 use eyeball::{SharedObservable, Subscriber};
 use eyeball_im::{Vector, VectorDiff};
 use eyeball_im_util::vector::VectorObserverExt;
-use futures_util::{pin_mut, stream, Stream, StreamExt as _};
+use futures_util::{future::Either, pin_mut, stream, Stream, StreamExt as _};
 use matrix_sdk::{
     executor::{spawn, JoinHandle},
     RoomListEntry, SlidingSync, SlidingSyncList,
@@ -28,7 +31,7 @@ use matrix_sdk::{
 use matrix_sdk_base::RoomInfoUpdate;
 use tokio::{select, sync::broadcast};
 
-use super::{filters::Filter, Error, State};
+use super::{filters::Filter, sorters::Sorter, Error, State};
 
 /// A `RoomList` represents a list of rooms, from a
 /// [`RoomListService`](super::RoomListService).
@@ -114,14 +117,18 @@ impl RoomList {
     }
 
     /// Similar to [`Self::entries`] except that it's possible to provide a
-    /// filter that will filter out room list entries, and that it's also
-    /// possible to “paginate” over the entries by `page_size`.
+    /// filter that will filter out room list entries, a sorter that will sort
+    /// them on the client side, and that it's also possible to “paginate” over
+    /// the entries by `page_size`.
     ///
     /// The returned stream will only start yielding diffs once a filter is set
     /// through the returned [`RoomListDynamicEntriesController`]. For every
-    /// call to [`RoomListDynamicEntriesController::set_filter`], the stream
-    /// will yield a [`VectorDiff::Clear`] followed by any updates of the
-    /// room list under that filter (until the next reset).
+    /// call to [`RoomListDynamicEntriesController::set_filter`] or
+    /// [`RoomListDynamicEntriesController::set_sorter`], the stream will yield
+    /// a [`VectorDiff::Reset`] followed by any updates of the room list under
+    /// that filter and sorter (until the next reset).
+    ///
+    /// Without a sorter, the entries are in the order computed by the server.
     pub fn entries_with_dynamic_adapters(
         &self,
         page_size: usize,
@@ -130,13 +137,13 @@ impl RoomList {
     {
         let list = self.sliding_sync_list.clone();
 
-        let filter_fn_cell = AsyncCell::shared();
+        let adapters_cell = AsyncCell::shared();
 
         let limit = SharedObservable::<usize>::new(page_size);
         let limit_stream = limit.subscribe();
 
         let dynamic_entries_controller = RoomListDynamicEntriesController::new(
-            filter_fn_cell.clone(),
+            adapters_cell.clone(),
             page_size,
             limit,
             list.maximum_number_of_rooms_stream(),
@@ -144,14 +151,28 @@ impl RoomList {
 
         let stream = stream! {
             loop {
-                let filter_fn = filter_fn_cell.take().await;
+                let DynamicAdapters { filter, sorter } = adapters_cell.take().await;
                 let (raw_values, raw_stream) = list.room_list_stream();
 
                 // Combine normal stream events with other updates from rooms
                 let merged_stream = merge_stream_and_receiver(raw_values.clone(), raw_stream, roominfo_update_recv.resubscribe());
 
                 let (values, stream) = (raw_values, merged_stream)
-                    .filter(filter_fn)
+                    .filter(move |entry: &RoomListEntry| filter(entry));
+
+                // Sort the filtered entries, if a sorter has been set.
+                let (values, stream) = match sorter {
+                    Some(sorter) => {
+                        let (values, stream) = (values, stream).sort_by(
+                            move |left: &RoomListEntry, right: &RoomListEntry| sorter(left, right),
+                        );
+
+                        (values, Either::Left(stream))
+                    }
+                    None => (values, Either::Right(stream)),
+                };
+
+                let (values, stream) = (values, stream)
                     .dynamic_limit_with_initial_value(page_size, limit_stream.clone());
 
                 // Clearing the stream before chaining with the real stream.
@@ -257,12 +278,25 @@ pub enum RoomListLoadingState {
 /// Type alias for a boxed filter function.
 pub type BoxedFilterFn = Box<dyn Filter + Send + Sync>;
 
+/// Type alias for a boxed sorter function.
+pub type BoxedSorterFn = Box<dyn Sorter + Send + Sync>;
+
+/// The adapters to apply on the room list entries, sent to the stream returned
+/// by [`RoomList::entries_with_dynamic_adapters`] every time one of them
+/// changes.
+struct DynamicAdapters {
+    filter: Arc<BoxedFilterFn>,
+    sorter: Option<Arc<BoxedSorterFn>>,
+}
+
 /// Controller for the [`RoomList`] dynamic entries.
 ///
 /// To get one value of this type, use
 /// [`RoomList::entries_with_dynamic_adapters`]
 pub struct RoomListDynamicEntriesController {
-    filter: Arc<AsyncCell<BoxedFilterFn>>,
+    adapters: Arc<AsyncCell<DynamicAdapters>>,
+    filter: Mutex<Option<Arc<BoxedFilterFn>>>,
+    sorter: Mutex<Option<Arc<BoxedSorterFn>>>,
     page_size: usize,
     limit: SharedObservable<usize>,
     maximum_number_of_rooms: Subscriber<Option<u32>>,
@@ -270,12 +304,27 @@ pub struct RoomListDynamicEntriesController {
 
 impl RoomListDynamicEntriesController {
     fn new(
-        filter: Arc<AsyncCell<BoxedFilterFn>>,
+        adapters: Arc<AsyncCell<DynamicAdapters>>,
         page_size: usize,
         limit_stream: SharedObservable<usize>,
         maximum_number_of_rooms: Subscriber<Option<u32>>,
     ) -> Self {
-        Self { filter, page_size, limit: limit_stream, maximum_number_of_rooms }
+        Self {
+            adapters,
+            filter: Mutex::new(None),
+            sorter: Mutex::new(None),
+            page_size,
+            limit: limit_stream,
+            maximum_number_of_rooms,
+        }
+    }
+
+    /// Whether the associated stream is still alive.
+    fn has_stream(&self) -> bool {
+        // If there is no other reference to the adapters cell, setting the
+        // adapters would be pointless (no new references can be created from
+        // self, either).
+        Arc::strong_count(&self.adapters) > 1
     }
 
     /// Set the filter.
@@ -283,15 +332,40 @@ impl RoomListDynamicEntriesController {
     /// If the associated stream has been dropped, returns `false` to indicate
     /// the operation didn't have an effect.
     pub fn set_filter(&self, filter: BoxedFilterFn) -> bool {
-        if Arc::strong_count(&self.filter) == 1 {
-            // there is no other reference to the boxed filter fn, setting it
-            // would be pointless (no new references can be created from self,
-            // either)
-            false
-        } else {
-            self.filter.set(filter);
-            true
+        if !self.has_stream() {
+            return false;
         }
+
+        let filter = Arc::new(filter);
+        *self.filter.lock().unwrap() = Some(filter.clone());
+
+        let sorter = self.sorter.lock().unwrap().clone();
+        self.adapters.set(DynamicAdapters { filter, sorter });
+
+        true
+    }
+
+    /// Set the sorter, to sort the entries on the client side instead of
+    /// using the order computed by the server.
+    ///
+    /// The sorter is applied on top of the filter. The stream only starts
+    /// yielding diffs once a filter has been set with [`Self::set_filter`].
+    ///
+    /// If the associated stream has been dropped, returns `false` to indicate
+    /// the operation didn't have an effect.
+    pub fn set_sorter(&self, sorter: BoxedSorterFn) -> bool {
+        if !self.has_stream() {
+            return false;
+        }
+
+        let sorter = Arc::new(sorter);
+        *self.sorter.lock().unwrap() = Some(sorter.clone());
+
+        if let Some(filter) = self.filter.lock().unwrap().clone() {
+            self.adapters.set(DynamicAdapters { filter, sorter: Some(sorter) });
+        }
+
+        true
     }
 
     /// Add one page, i.e. view `page_size` more entries in the room list if
@@ -0,0 +1,126 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::Sorter;
+
+/// The favourite state of a room: whether it's a favourite, and its order
+/// among the favourite rooms, if any.
+type FavouriteState = (bool, Option<f64>);
+
+struct FavouriteMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<FavouriteState>,
+{
+    favourite: F,
+}
+
+impl<F> FavouriteMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<FavouriteState>,
+{
+    fn compare(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        let (left_is_favourite, left_order) = (self.favourite)(left).unwrap_or((false, None));
+        let (right_is_favourite, right_order) = (self.favourite)(right).unwrap_or((false, None));
+
+        match (left_is_favourite, right_is_favourite) {
+            // A favourite room comes before a non-favourite room.
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+
+            (false, false) => Ordering::Equal,
+
+            (true, true) => match (left_order, right_order) {
+                // The room with the lowest order comes first.
+                (Some(left_order), Some(right_order)) => left_order.total_cmp(&right_order),
+
+                // A favourite room with an order comes before a favourite room
+                // without one.
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+
+                (None, None) => Ordering::Equal,
+            },
+        }
+    }
+}
+
+/// Create a new sorter that will put the favourite rooms first (see
+/// [`matrix_sdk_base::Room::is_favourite`]), in the manual order defined by the
+/// `order` of their `m.favourite` tag (see
+/// [`matrix_sdk_base::Room::favourite_order`]).
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = FavouriteMatcher {
+        favourite: move |room| {
+            let room_id = room.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            Some((room.is_favourite(), room.favourite_order()))
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.compare(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::{FavouriteMatcher, FavouriteState};
+
+    fn favourite(room: &RoomListEntry) -> Option<FavouriteState> {
+        match room.as_room_id()?.as_str() {
+            "!r0:bar.org" => Some((true, Some(0.5))),
+            "!r1:bar.org" => Some((true, Some(0.1))),
+            "!r2:bar.org" => Some((true, None)),
+            "!r3:bar.org" => Some((false, None)),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_favourite_first() {
+        let matcher = FavouriteMatcher { favourite };
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+        let r3 = RoomListEntry::Filled(room_id!("!r3:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r2, &r3), Ordering::Less);
+        assert_eq!(matcher.compare(&r3, &r2), Ordering::Greater);
+        assert_eq!(matcher.compare(&r3, &RoomListEntry::Empty), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_manual_order() {
+        let matcher = FavouriteMatcher { favourite };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.compare(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.compare(&r0, &r0), Ordering::Equal);
+
+        // A room without an order comes after the ones with an order.
+        assert_eq!(matcher.compare(&r0, &r2), Ordering::Less);
+        assert_eq!(matcher.compare(&r2, &r1), Ordering::Greater);
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use super::{super::room_list::BoxedSorterFn, Sorter};
+
+/// Create a new sorter that will run multiple sorters. When the n-th sorter
+/// returns [`Ordering::Equal`], the next sorter is called. It stops at the
+/// first sorter that returns something else than [`Ordering::Equal`].
+pub fn new_sorter(sorters: Vec<BoxedSorterFn>) -> impl Sorter {
+    move |left, right| -> Ordering {
+        for sorter in &sorters {
+            match sorter(left, right) {
+                Ordering::Equal => continue,
+                result => return result,
+            }
+        }
+
+        Ordering::Equal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::new_sorter;
+
+    #[test]
+    fn test_with_zero_sorter() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let sorter = new_sorter(vec![]);
+
+        assert_eq!(sorter(&left, &right), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_with_one_sorter() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        let sorter = new_sorter(vec![Box::new(|_: &_, _: &_| Ordering::Less)]);
+
+        assert_eq!(sorter(&left, &right), Ordering::Less);
+    }
+
+    #[test]
+    fn test_with_two_sorters() {
+        let left = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let right = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        {
+            let sorter = new_sorter(vec![
+                Box::new(|_: &_, _: &_| Ordering::Equal),
+                Box::new(|_: &_, _: &_| Ordering::Greater),
+            ]);
+
+            assert_eq!(sorter(&left, &right), Ordering::Greater);
+        }
+
+        {
+            let sorter = new_sorter(vec![
+                Box::new(|_: &_, _: &_| Ordering::Less),
+                Box::new(|_: &_, _: &_| Ordering::Greater),
+            ]);
+
+            assert_eq!(sorter(&left, &right), Ordering::Less);
+        }
+
+        {
+            let sorter = new_sorter(vec![
+                Box::new(|_: &_, _: &_| Ordering::Equal),
+                Box::new(|_: &_, _: &_| Ordering::Equal),
+            ]);
+
+            assert_eq!(sorter(&left, &right), Ordering::Equal);
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A collection of room sorters.
+//!
+//! The room list can provide an access to the rooms per list, like with
+//! [`super::RoomList::entries_with_dynamic_adapters`]. By default, the rooms
+//! are in the order computed by the server. The provided collection of rooms
+//! can be sorted on the client side with these sorters, which is useful when
+//! the server order is not relevant, e.g. when offline, or when a filter is
+//! applied. A classical usage would be the following:
+//!
+//! ```rust
+//! use matrix_sdk::Client;
+//! use matrix_sdk_ui::room_list_service::{
+//!     sorters, RoomListDynamicEntriesController,
+//! };
+//!
+//! fn configure_room_list(
+//!     client: &Client,
+//!     entries_controller: &RoomListDynamicEntriesController,
+//! ) {
+//!     // Unread rooms first,
+//!     // _then_ the most recent rooms first,
+//!     // _then_ by name.
+//!     entries_controller.set_sorter(Box::new(
+//!         // Lexicographic order
+//!         sorters::new_sorter_lexicographic(vec![
+//!             // Unread
+//!             Box::new(sorters::new_sorter_unread(client)),
+//!             // Recency
+//!             Box::new(sorters::new_sorter_recency(client)),
+//!             // Name
+//!             Box::new(sorters::new_sorter_name(client)),
+//!         ]),
+//!     ));
+//! }
+//! ```
+
+mod favourite;
+mod lexicographic;
+mod name;
+mod recency;
+mod unread;
+
+use std::cmp::Ordering;
+
+pub use favourite::new_sorter as new_sorter_favourite;
+pub use lexicographic::new_sorter as new_sorter_lexicographic;
+use matrix_sdk::RoomListEntry;
+pub use name::new_sorter as new_sorter_name;
+pub use recency::new_sorter as new_sorter_recency;
+pub use unread::new_sorter as new_sorter_unread;
+
+/// A trait “alias” that represents a _sorter_.
+///
+/// A sorter is simply a function that receives two `&RoomListEntry`s and
+/// returns an [`Ordering`] between them.
+pub trait Sorter: Fn(&RoomListEntry, &RoomListEntry) -> Ordering {}
+
+impl<F> Sorter for F where F: Fn(&RoomListEntry, &RoomListEntry) -> Ordering {}
@@ -0,0 +1,103 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::Sorter;
+
+struct NameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    name: F,
+}
+
+impl<F> NameMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<String>,
+{
+    fn compare(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        match ((self.name)(left), (self.name)(right)) {
+            (Some(left_name), Some(right_name)) => {
+                left_name.to_lowercase().cmp(&right_name.to_lowercase())
+            }
+
+            // A room with a name comes before a room without one.
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+/// Create a new sorter that will sort two [`RoomListEntry`] by name, in a
+/// case-insensitive alphabetical order (see [`matrix_sdk_base::Room::name`]).
+/// A room without a name falls back to its canonical alias.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = NameMatcher {
+        name: move |room| {
+            let room_id = room.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            room.name().or_else(|| room.canonical_alias().map(|alias| alias.to_string()))
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.compare(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::NameMatcher;
+
+    fn name(room: &RoomListEntry) -> Option<String> {
+        match room.as_room_id()?.as_str() {
+            "!r0:bar.org" => Some("Hello".to_owned()),
+            "!r1:bar.org" => Some("bonjour".to_owned()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_with_two_names() {
+        let matcher = NameMatcher { name };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.compare(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.compare(&r0, &r0), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_with_one_name() {
+        let matcher = NameMatcher { name };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &r2), Ordering::Less);
+        assert_eq!(matcher.compare(&r2, &r0), Ordering::Greater);
+        assert_eq!(matcher.compare(&r2, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
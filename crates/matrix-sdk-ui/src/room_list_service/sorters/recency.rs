@@ -0,0 +1,115 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+use ruma::MilliSecondsSinceUnixEpoch;
+
+use super::Sorter;
+
+struct RecencyMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch>,
+{
+    timestamp: F,
+}
+
+impl<F> RecencyMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch>,
+{
+    fn compare(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        match ((self.timestamp)(left), (self.timestamp)(right)) {
+            // The most recent room comes first.
+            (Some(left_timestamp), Some(right_timestamp)) => right_timestamp.cmp(&left_timestamp),
+
+            // A room with a latest event comes before a room without one.
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+
+            (None, None) => Ordering::Equal,
+        }
+    }
+}
+
+/// Create a new sorter that will sort two [`RoomListEntry`] by recency, i.e.
+/// by comparing the timestamps of their latest event (see
+/// [`matrix_sdk_base::Room::latest_event`]). The most recent room comes first.
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = RecencyMatcher {
+        timestamp: move |room| {
+            let room_id = room.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            room.latest_event()?
+                .event()
+                .event
+                .get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts")
+                .ok()
+                .flatten()
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.compare(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::{room_id, uint, MilliSecondsSinceUnixEpoch};
+
+    use super::RecencyMatcher;
+
+    fn timestamp(room: &RoomListEntry) -> Option<MilliSecondsSinceUnixEpoch> {
+        match room.as_room_id()?.as_str() {
+            "!r0:bar.org" => Some(MilliSecondsSinceUnixEpoch(uint!(42))),
+            "!r1:bar.org" => Some(MilliSecondsSinceUnixEpoch(uint!(153))),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_with_two_timestamps() {
+        let matcher = RecencyMatcher { timestamp };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &r1), Ordering::Greater);
+        assert_eq!(matcher.compare(&r1, &r0), Ordering::Less);
+        assert_eq!(matcher.compare(&r0, &r0), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_with_one_timestamp() {
+        let matcher = RecencyMatcher { timestamp };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &r2), Ordering::Less);
+        assert_eq!(matcher.compare(&r2, &r0), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_with_zero_timestamp() {
+        let matcher = RecencyMatcher { timestamp };
+        let r2 = RoomListEntry::Filled(room_id!("!r2:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r2, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
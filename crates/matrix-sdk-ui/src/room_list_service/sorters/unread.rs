@@ -0,0 +1,97 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp::Ordering;
+
+use matrix_sdk::{Client, RoomListEntry};
+
+use super::Sorter;
+
+struct UnreadMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    is_unread: F,
+}
+
+impl<F> UnreadMatcher<F>
+where
+    F: Fn(&RoomListEntry) -> Option<bool>,
+{
+    fn compare(&self, left: &RoomListEntry, right: &RoomListEntry) -> Ordering {
+        let left = (self.is_unread)(left).unwrap_or(false);
+        let right = (self.is_unread)(right).unwrap_or(false);
+
+        // An unread room comes first, i.e. `true` before `false`.
+        right.cmp(&left)
+    }
+}
+
+/// Create a new sorter that will put the unread rooms first, i.e. the rooms
+/// with unread messages, or that have been manually marked as unread (see
+/// [`matrix_sdk_base::Room::is_marked_unread`]).
+pub fn new_sorter(client: &Client) -> impl Sorter {
+    let client = client.clone();
+
+    let matcher = UnreadMatcher {
+        is_unread: move |room| {
+            let room_id = room.as_room_id()?;
+            let room = client.get_room(room_id)?;
+
+            Some(room.num_unread_messages() > 0 || room.is_marked_unread())
+        },
+    };
+
+    move |left, right| -> Ordering { matcher.compare(left, right) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use matrix_sdk::RoomListEntry;
+    use ruma::room_id;
+
+    use super::UnreadMatcher;
+
+    fn is_unread(room: &RoomListEntry) -> Option<bool> {
+        match room.as_room_id()?.as_str() {
+            "!r0:bar.org" => Some(true),
+            "!r1:bar.org" => Some(false),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_unread_first() {
+        let matcher = UnreadMatcher { is_unread };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &r1), Ordering::Less);
+        assert_eq!(matcher.compare(&r1, &r0), Ordering::Greater);
+        assert_eq!(matcher.compare(&r0, &r0), Ordering::Equal);
+        assert_eq!(matcher.compare(&r1, &r1), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_unread_state_cannot_be_found() {
+        let matcher = UnreadMatcher { is_unread };
+        let r0 = RoomListEntry::Filled(room_id!("!r0:bar.org").to_owned());
+        let r1 = RoomListEntry::Filled(room_id!("!r1:bar.org").to_owned());
+
+        assert_eq!(matcher.compare(&r0, &RoomListEntry::Empty), Ordering::Less);
+        assert_eq!(matcher.compare(&r1, &RoomListEntry::Empty), Ordering::Equal);
+    }
+}
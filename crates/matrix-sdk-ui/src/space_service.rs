@@ -0,0 +1,729 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A high-level API to browse the spaces the user is in.
+//!
+//! The [`SpaceService`] maintains a [`SpaceGraph`] of the relations between
+//! spaces and their children. It's built from the `m.space.child` and
+//! `m.space.parent` state events of the joined rooms, and kept up to date as
+//! new state events are received from sync, or as rooms are left.
+//!
+//! Like the spec recommends, an `m.space.parent` state event is only taken into
+//! account if its sender is allowed to send `m.space.child` state events in
+//! the parent space, which requires the parent space to be joined.
+//!
+//! The children of a space that the user hasn't joined can be discovered with
+//! the `/hierarchy` endpoint, with a [`SpaceHierarchy`] paginator: the results
+//! are added to the graph too.
+//!
+//! The rooms of the room list can be restricted to those of a space, with
+//! [`crate::room_list_service::filters::new_filter_space`].
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+use eyeball::{SharedObservable, Subscriber};
+use matrix_sdk::{
+    deserialized_responses::SyncOrStrippedState, event_handler::EventHandlerDropGuard, Client,
+    HttpError, Room, RoomState,
+};
+use ruma::{
+    api::client::space::{get_hierarchy, SpaceHierarchyRoomsChunk},
+    events::{
+        room::{member::RoomMemberEventContent, power_levels::RoomPowerLevelsEventContent},
+        space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
+        StateEventType, SyncStateEvent,
+    },
+    room::RoomType,
+    space::SpaceRoomJoinRule,
+    OwnedMxcUri, OwnedRoomAliasId, OwnedRoomId, RoomId, UInt, UserId,
+};
+use thiserror::Error;
+use tracing::{debug, instrument, warn};
+
+/// The relations between the spaces known by the client and their children.
+///
+/// A room is a child of a space if the space declares it with an
+/// `m.space.child` state event, or if the room declares the space as its
+/// parent with an `m.space.parent` state event.
+#[derive(Clone, Debug, Default)]
+pub struct SpaceGraph {
+    /// The known spaces, joined or discovered with `/hierarchy`.
+    spaces: BTreeSet<OwnedRoomId>,
+
+    /// The children declared by each space, with `m.space.child` state events.
+    declared_children: BTreeMap<OwnedRoomId, BTreeSet<OwnedRoomId>>,
+
+    /// The parents declared by each room, with `m.space.parent` state events
+    /// sent by users allowed to declare children in the parent.
+    declared_parents: BTreeMap<OwnedRoomId, BTreeSet<OwnedRoomId>>,
+
+    /// All the parents claimed by each room with `m.space.parent` state events,
+    /// even by users that aren't allowed to, so they can be checked again when
+    /// the state of the parent changes.
+    claimed_parents: BTreeMap<OwnedRoomId, BTreeSet<OwnedRoomId>>,
+
+    /// The children of each space, computed from the declared relations.
+    children: BTreeMap<OwnedRoomId, BTreeSet<OwnedRoomId>>,
+
+    /// The parents of each room, computed from the declared relations.
+    parents: BTreeMap<OwnedRoomId, BTreeSet<OwnedRoomId>>,
+}
+
+impl SpaceGraph {
+    /// All the known spaces, joined or not.
+    pub fn spaces(&self) -> impl Iterator<Item = &RoomId> {
+        self.spaces.iter().map(|space_id| space_id.as_ref())
+    }
+
+    /// Whether the given room is a known space.
+    pub fn is_space(&self, room_id: &RoomId) -> bool {
+        self.spaces.contains(room_id)
+    }
+
+    /// The spaces that don't have a known parent space.
+    pub fn top_level_spaces(&self) -> Vec<&RoomId> {
+        self.spaces()
+            .filter(|space_id| {
+                self.parents(space_id).all(|parent_id| !self.spaces.contains(parent_id))
+            })
+            .collect()
+    }
+
+    /// The direct children of the given space, rooms or spaces.
+    pub fn children(&self, space_id: &RoomId) -> impl Iterator<Item = &RoomId> {
+        self.children.get(space_id).into_iter().flatten().map(|room_id| room_id.as_ref())
+    }
+
+    /// The direct parents of the given room.
+    pub fn parents(&self, room_id: &RoomId) -> impl Iterator<Item = &RoomId> {
+        self.parents.get(room_id).into_iter().flatten().map(|space_id| space_id.as_ref())
+    }
+
+    /// Whether the given room is in the given space.
+    ///
+    /// If `recursive` is true, the rooms of the subspaces of the space are
+    /// considered to be in the space too.
+    pub fn contains(&self, space_id: &RoomId, room_id: &RoomId, recursive: bool) -> bool {
+        if !recursive {
+            return self.children.get(space_id).is_some_and(|children| children.contains(room_id));
+        }
+
+        // Walk up the ancestors of the room, there are usually a lot fewer of them than
+        // descendants of the space. Keep track of the visited ones, as the graph can
+        // have cycles.
+        let mut visited = BTreeSet::new();
+        let mut queue = VecDeque::from([room_id]);
+
+        while let Some(room_id) = queue.pop_front() {
+            for parent_id in self.parents(room_id) {
+                if parent_id == space_id {
+                    return true;
+                }
+
+                if visited.insert(parent_id) {
+                    queue.push_back(parent_id);
+                }
+            }
+        }
+
+        false
+    }
+
+    /// The rooms that claim to be children of the given space.
+    fn rooms_claiming_parent(&self, space_id: &RoomId) -> Vec<OwnedRoomId> {
+        self.claimed_parents
+            .iter()
+            .filter(|(_, parents)| parents.contains(space_id))
+            .map(|(room_id, _)| room_id.clone())
+            .collect()
+    }
+
+    /// Replace the relations declared by the given room.
+    ///
+    /// Returns the rooms whose ancestors might have changed.
+    fn set_room_relations(
+        &mut self,
+        room_id: &RoomId,
+        relations: RoomRelations,
+    ) -> BTreeSet<OwnedRoomId> {
+        let RoomRelations { is_space, children, parents } = relations;
+
+        if is_space {
+            self.spaces.insert(room_id.to_owned());
+        } else {
+            self.spaces.remove(room_id);
+        }
+
+        if children.is_empty() {
+            self.declared_children.remove(room_id);
+        } else {
+            self.declared_children.insert(room_id.to_owned(), children);
+        }
+
+        // Unjoined rooms discovered with `/hierarchy` don't have their parents.
+        if let Some(RoomParents { allowed, claimed }) = parents {
+            if allowed.is_empty() {
+                self.declared_parents.remove(room_id);
+            } else {
+                self.declared_parents.insert(room_id.to_owned(), allowed);
+            }
+
+            if claimed.is_empty() {
+                self.claimed_parents.remove(room_id);
+            } else {
+                self.claimed_parents.insert(room_id.to_owned(), claimed);
+            }
+        }
+
+        let previous_children = self.children.clone();
+        let previous_parents = self.parents.clone();
+
+        self.compute_relations();
+
+        let mut changed = previous_parents
+            .keys()
+            .chain(self.parents.keys())
+            .filter(|room_id| previous_parents.get(*room_id) != self.parents.get(*room_id))
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        // The descendants of the rooms whose parents changed have new ancestors too.
+        let mut queue = changed.iter().cloned().collect::<VecDeque<_>>();
+        while let Some(room_id) = queue.pop_front() {
+            let children = previous_children.get(&room_id).into_iter().flatten();
+            let new_children = self.children.get(&room_id).into_iter().flatten();
+
+            for child_id in children.chain(new_children) {
+                if changed.insert(child_id.clone()) {
+                    queue.push_back(child_id.clone());
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Recompute the children and parents of all the rooms, from the declared
+    /// relations.
+    fn compute_relations(&mut self) {
+        let mut children = BTreeMap::<_, BTreeSet<_>>::new();
+        let mut parents = BTreeMap::<_, BTreeSet<_>>::new();
+
+        let declared_by_spaces = self.declared_children.iter().flat_map(|(space_id, children)| {
+            children.iter().map(move |child_id| (space_id, child_id))
+        });
+        let declared_by_children = self.declared_parents.iter().flat_map(|(child_id, parents)| {
+            parents.iter().map(move |space_id| (space_id, child_id))
+        });
+
+        for (space_id, child_id) in declared_by_spaces.chain(declared_by_children) {
+            children.entry(space_id.clone()).or_default().insert(child_id.clone());
+            parents.entry(child_id.clone()).or_default().insert(space_id.clone());
+        }
+
+        self.children = children;
+        self.parents = parents;
+    }
+}
+
+/// The space relations declared by a room.
+#[derive(Default)]
+struct RoomRelations {
+    is_space: bool,
+    children: BTreeSet<OwnedRoomId>,
+    /// `None` if the parents of the room are unknown.
+    parents: Option<RoomParents>,
+}
+
+/// The parents declared by a room.
+#[derive(Default)]
+struct RoomParents {
+    /// The parents declared by users allowed to declare children in them.
+    allowed: BTreeSet<OwnedRoomId>,
+    /// All the parents declared by the room.
+    claimed: BTreeSet<OwnedRoomId>,
+}
+
+impl RoomRelations {
+    /// The relations of a room that isn't joined anymore, which are forgotten.
+    fn left() -> Self {
+        Self { parents: Some(RoomParents::default()), ..Default::default() }
+    }
+
+    /// Load the relations declared in the state of the given joined room.
+    async fn load(room: &Room) -> matrix_sdk::Result<Self> {
+        let children = room
+            .get_state_events_static::<SpaceChildEventContent>()
+            .await?
+            .into_iter()
+            .filter_map(|event| match event.deserialize() {
+                // A child without `via` is not a child anymore.
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event)))
+                    if !event.content.via.is_empty() =>
+                {
+                    Some(event.state_key)
+                }
+                Ok(_) => None,
+                Err(error) => {
+                    warn!(room_id = ?room.room_id(), "Could not deserialize m.space.child: {error}");
+                    None
+                }
+            })
+            .collect();
+
+        let claims = room
+            .get_state_events_static::<SpaceParentEventContent>()
+            .await?
+            .into_iter()
+            .filter_map(|event| match event.deserialize() {
+                // A parent without `via` is not a parent anymore.
+                Ok(SyncOrStrippedState::Sync(SyncStateEvent::Original(event)))
+                    if !event.content.via.is_empty() =>
+                {
+                    Some((event.state_key, event.sender))
+                }
+                Ok(_) => None,
+                Err(error) => {
+                    warn!(room_id = ?room.room_id(), "Could not deserialize m.space.parent: {error}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut parents = RoomParents::default();
+        for (parent_id, sender) in claims {
+            if can_declare_child(&room.client(), &parent_id, &sender).await {
+                parents.allowed.insert(parent_id.clone());
+            } else {
+                debug!(room_id = ?room.room_id(), ?parent_id, ?sender, "Ignoring m.space.parent");
+            }
+            parents.claimed.insert(parent_id);
+        }
+
+        Ok(Self { is_space: room.is_space(), children, parents: Some(parents) })
+    }
+
+    /// Get the relations declared by a room returned by `/hierarchy`.
+    fn from_chunk(chunk: &SpaceHierarchyRoomsChunk) -> Self {
+        let children = chunk
+            .children_state
+            .iter()
+            .filter_map(|event| match event.deserialize() {
+                Ok(event) if !event.content.via.is_empty() => Some(event.state_key),
+                Ok(_) => None,
+                Err(error) => {
+                    warn!(room_id = ?chunk.room_id, "Could not deserialize m.space.child: {error}");
+                    None
+                }
+            })
+            .collect();
+
+        Self { is_space: chunk.room_type == Some(RoomType::Space), children, parents: None }
+    }
+}
+
+/// Whether the given user is allowed to declare children of the given space,
+/// i.e. to send `m.space.child` state events in it.
+///
+/// Returns `false` if the space isn't joined, since its state is unknown.
+async fn can_declare_child(client: &Client, space_id: &RoomId, user_id: &UserId) -> bool {
+    let Some(space) = client.get_room(space_id) else {
+        return false;
+    };
+
+    if space.state() != RoomState::Joined {
+        return false;
+    }
+
+    match space.can_user_send_state(user_id, StateEventType::SpaceChild).await {
+        Ok(allowed) => allowed,
+        Err(error) => {
+            warn!(?space_id, "Could not load the power levels of the space: {error}");
+            false
+        }
+    }
+}
+
+/// A service to browse the spaces the user is in.
+///
+/// The [`SpaceGraph`] is kept up to date with the state events received from
+/// sync, as long as the service is alive. Whenever the ancestors of a room
+/// change, an update of the room list is triggered for it, so the room list
+/// filters using the graph are applied again.
+pub struct SpaceService {
+    client: Client,
+
+    /// The relations between the spaces and their children.
+    graph: SharedObservable<SpaceGraph>,
+
+    /// The event handlers updating the graph, removed when the service is
+    /// dropped.
+    _event_handlers: Vec<EventHandlerDropGuard>,
+}
+
+impl SpaceService {
+    /// Create a new `SpaceService`, building the [`SpaceGraph`] from the state
+    /// of the joined rooms.
+    pub async fn new(client: Client) -> Result<Self, Error> {
+        let graph = SharedObservable::new(SpaceGraph::default());
+
+        // Register the event handlers first, so that no update is missed while the
+        // graph is being built.
+        let child_handle = client.add_event_handler({
+            let graph = graph.clone();
+            move |_: SyncStateEvent<SpaceChildEventContent>, room: Room| {
+                let graph = graph.clone();
+                async move { update_room_relations(&graph, &room).await }
+            }
+        });
+        let parent_handle = client.add_event_handler({
+            let graph = graph.clone();
+            move |_: SyncStateEvent<SpaceParentEventContent>, room: Room| {
+                let graph = graph.clone();
+                async move { update_room_relations(&graph, &room).await }
+            }
+        });
+        // The claims of the children of a space depend on its power levels.
+        let power_levels_handle = client.add_event_handler({
+            let graph = graph.clone();
+            move |_: SyncStateEvent<RoomPowerLevelsEventContent>, room: Room| {
+                let graph = graph.clone();
+                async move { update_room_relations(&graph, &room).await }
+            }
+        });
+        // The relations of a room are forgotten when it's left.
+        let member_handle = client.add_event_handler({
+            let graph = graph.clone();
+            move |event: SyncStateEvent<RoomMemberEventContent>, room: Room| {
+                let graph = graph.clone();
+                async move {
+                    if event.state_key() == room.own_user_id() {
+                        update_room_relations(&graph, &room).await;
+                    }
+                }
+            }
+        });
+        let event_handlers = vec![
+            client.event_handler_drop_guard(child_handle),
+            client.event_handler_drop_guard(parent_handle),
+            client.event_handler_drop_guard(power_levels_handle),
+            client.event_handler_drop_guard(member_handle),
+        ];
+
+        for room in client.joined_rooms() {
+            let relations = RoomRelations::load(&room).await.map_err(Error::LoadingRelations)?;
+            graph.update(|graph| {
+                graph.set_room_relations(room.room_id(), relations);
+            });
+        }
+
+        Ok(Self { client, graph, _event_handlers: event_handlers })
+    }
+
+    /// Get the current relations between the spaces and their children.
+    pub fn graph(&self) -> SpaceGraph {
+        self.graph.get()
+    }
+
+    /// Subscribe to the changes of the relations between the spaces and their
+    /// children.
+    pub fn subscribe(&self) -> Subscriber<SpaceGraph> {
+        self.graph.subscribe()
+    }
+
+    /// The spaces that don't have a known parent space.
+    pub fn top_level_spaces(&self) -> Vec<OwnedRoomId> {
+        self.graph.read().top_level_spaces().into_iter().map(ToOwned::to_owned).collect()
+    }
+
+    /// Whether the given room is in the given space.
+    ///
+    /// See [`SpaceGraph::contains`].
+    pub fn contains(&self, space_id: &RoomId, room_id: &RoomId, recursive: bool) -> bool {
+        self.graph.read().contains(space_id, room_id, recursive)
+    }
+
+    /// Create a paginator of the hierarchy of the given space, to discover the
+    /// children that the user hasn't joined.
+    pub fn hierarchy(&self, space_id: &RoomId) -> SpaceHierarchy {
+        SpaceHierarchy {
+            client: self.client.clone(),
+            graph: self.graph.clone(),
+            space_id: space_id.to_owned(),
+            max_depth: None,
+            suggested_only: false,
+            next_batch: None,
+            is_at_end: false,
+        }
+    }
+
+    /// Handle to the observable graph, for the room list filters.
+    pub(crate) fn shared_graph(&self) -> SharedObservable<SpaceGraph> {
+        self.graph.clone()
+    }
+}
+
+/// Update the relations of the given room, and of the rooms claiming it as
+/// their parent.
+#[instrument(skip_all, fields(room_id = ?room.room_id()))]
+async fn update_room_relations(graph: &SharedObservable<SpaceGraph>, room: &Room) {
+    let client = room.client();
+
+    update_relations_of(&client, graph, room).await;
+
+    let claiming_rooms = graph.read().rooms_claiming_parent(room.room_id());
+    for room_id in claiming_rooms {
+        if let Some(room) = client.get_room(&room_id) {
+            update_relations_of(&client, graph, &room).await;
+        }
+    }
+}
+
+/// Update the relations of the given room only.
+async fn update_relations_of(client: &Client, graph: &SharedObservable<SpaceGraph>, room: &Room) {
+    let relations = if room.state() == RoomState::Joined {
+        match RoomRelations::load(room).await {
+            Ok(relations) => relations,
+            Err(error) => {
+                warn!(room_id = ?room.room_id(), "Failed to load the space relations: {error}");
+                return;
+            }
+        }
+    } else {
+        RoomRelations::left()
+    };
+
+    debug!(room_id = ?room.room_id(), "Updating the space relations of the room");
+    let mut changed = BTreeSet::new();
+    graph.update(|graph| changed = graph.set_room_relations(room.room_id(), relations));
+
+    trigger_room_list_updates(client, changed);
+}
+
+/// Trigger an update of the room list for the given rooms, so the filters
+/// depending on the [`SpaceGraph`] are applied again.
+fn trigger_room_list_updates(client: &Client, room_ids: BTreeSet<OwnedRoomId>) {
+    for room_id in room_ids {
+        if let Some(room) = client.get_room(&room_id) {
+            room.trigger_room_list_update();
+        }
+    }
+}
+
+/// A paginator of the hierarchy of a space, with the `/hierarchy` endpoint.
+///
+/// The rooms returned by the server are added to the [`SpaceGraph`] of the
+/// [`SpaceService`] that created this paginator.
+#[derive(Debug)]
+pub struct SpaceHierarchy {
+    client: Client,
+    graph: SharedObservable<SpaceGraph>,
+    space_id: OwnedRoomId,
+    max_depth: Option<UInt>,
+    suggested_only: bool,
+    next_batch: Option<String>,
+    is_at_end: bool,
+}
+
+impl SpaceHierarchy {
+    /// Only return the rooms up to the given depth of subspaces.
+    pub fn max_depth(mut self, max_depth: UInt) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Only return the rooms that are marked as suggested by their space.
+    pub fn suggested_only(mut self) -> Self {
+        self.suggested_only = true;
+        self
+    }
+
+    /// Whether all the rooms of the hierarchy have been returned.
+    pub fn is_at_end(&self) -> bool {
+        self.is_at_end
+    }
+
+    /// Fetch the next page of rooms of the hierarchy, in depth-first order.
+    ///
+    /// The first room of the first page is the space itself. Returns an empty
+    /// list once the end of the hierarchy has been reached.
+    #[instrument(skip(self), fields(space_id = ?self.space_id))]
+    pub async fn paginate(
+        &mut self,
+        limit: Option<UInt>,
+    ) -> Result<Vec<SpaceHierarchyRoom>, Error> {
+        if self.is_at_end {
+            return Ok(Vec::new());
+        }
+
+        let mut request = get_hierarchy::v1::Request::new(self.space_id.clone());
+        request.from = self.next_batch.clone();
+        request.limit = limit;
+        request.max_depth = self.max_depth;
+        request.suggested_only = self.suggested_only;
+
+        let response = self.client.send(request, None).await.map_err(Error::FetchingHierarchy)?;
+
+        self.next_batch = response.next_batch;
+        self.is_at_end = self.next_batch.is_none();
+
+        let mut rooms = Vec::with_capacity(response.rooms.len());
+        let mut relations = Vec::new();
+
+        for chunk in response.rooms {
+            let state = self.client.get_room(&chunk.room_id).map(|room| room.state());
+
+            // The state of the joined rooms is more up to date than the one returned by
+            // the server, only add the relations of the other ones to the graph.
+            if state != Some(RoomState::Joined) {
+                relations.push((chunk.room_id.clone(), RoomRelations::from_chunk(&chunk)));
+            }
+
+            rooms.push(SpaceHierarchyRoom {
+                is_space: chunk.room_type == Some(RoomType::Space),
+                room_id: chunk.room_id,
+                name: chunk.name,
+                topic: chunk.topic,
+                avatar_url: chunk.avatar_url,
+                canonical_alias: chunk.canonical_alias,
+                num_joined_members: chunk.num_joined_members,
+                join_rule: chunk.join_rule,
+                state,
+            });
+        }
+
+        if !relations.is_empty() {
+            let mut changed = BTreeSet::new();
+            self.graph.update(|graph| {
+                for (room_id, relations) in relations {
+                    changed.extend(graph.set_room_relations(&room_id, relations));
+                }
+            });
+
+            trigger_room_list_updates(&self.client, changed);
+        }
+
+        Ok(rooms)
+    }
+}
+
+/// A room of the hierarchy of a space, as returned by the server.
+#[derive(Debug)]
+pub struct SpaceHierarchyRoom {
+    /// The ID of the room.
+    pub room_id: OwnedRoomId,
+
+    /// The name of the room, if any.
+    pub name: Option<String>,
+
+    /// The topic of the room, if any.
+    pub topic: Option<String>,
+
+    /// The avatar of the room, if any.
+    pub avatar_url: Option<OwnedMxcUri>,
+
+    /// The canonical alias of the room, if any.
+    pub canonical_alias: Option<OwnedRoomAliasId>,
+
+    /// The number of members joined to the room.
+    pub num_joined_members: UInt,
+
+    /// The join rule of the room.
+    pub join_rule: SpaceRoomJoinRule,
+
+    /// Whether the room is a space.
+    pub is_space: bool,
+
+    /// The state of the room for the current user, or `None` if the user has
+    /// never been in the room.
+    pub state: Option<RoomState>,
+}
+
+/// Errors of the [`SpaceService`].
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The space relations of a room couldn't be loaded from the store.
+    #[error("Failed to load the space relations of a room: {0}")]
+    LoadingRelations(#[source] matrix_sdk::Error),
+
+    /// The hierarchy of a space couldn't be fetched from the server.
+    #[error("Failed to fetch the hierarchy of the space: {0}")]
+    FetchingHierarchy(#[source] HttpError),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, ops::Not};
+
+    use ruma::room_id;
+
+    use super::{RoomParents, RoomRelations, SpaceGraph};
+
+    fn relations(is_space: bool, children: &[&str], parents: &[&str]) -> RoomRelations {
+        let to_set =
+            |ids: &[&str]| ids.iter().map(|id| id.parse().unwrap()).collect::<BTreeSet<_>>();
+        RoomRelations {
+            is_space,
+            children: to_set(children),
+            parents: Some(RoomParents { allowed: to_set(parents), claimed: to_set(parents) }),
+        }
+    }
+
+    #[test]
+    fn test_space_graph() {
+        let mut graph = SpaceGraph::default();
+
+        // !space declares !sub and !a as children, !b declares !sub as its parent.
+        graph.set_room_relations(
+            room_id!("!space:b.c"),
+            relations(true, &["!sub:b.c", "!a:b.c"], &[]),
+        );
+        graph.set_room_relations(room_id!("!sub:b.c"), relations(true, &[], &[]));
+        graph.set_room_relations(room_id!("!b:b.c"), relations(false, &[], &["!sub:b.c"]));
+
+        assert_eq!(graph.top_level_spaces(), vec![room_id!("!space:b.c")]);
+        assert_eq!(
+            graph.children(room_id!("!space:b.c")).collect::<Vec<_>>(),
+            vec![room_id!("!a:b.c"), room_id!("!sub:b.c")]
+        );
+        assert_eq!(
+            graph.children(room_id!("!sub:b.c")).collect::<Vec<_>>(),
+            vec![room_id!("!b:b.c")]
+        );
+
+        assert!(graph.contains(room_id!("!space:b.c"), room_id!("!a:b.c"), false));
+        assert!(graph.contains(room_id!("!space:b.c"), room_id!("!b:b.c"), false).not());
+        assert!(graph.contains(room_id!("!space:b.c"), room_id!("!b:b.c"), true));
+        assert!(graph.contains(room_id!("!sub:b.c"), room_id!("!a:b.c"), true).not());
+
+        // Removing a child removes it from the space.
+        graph.set_room_relations(room_id!("!space:b.c"), relations(true, &["!sub:b.c"], &[]));
+        assert!(graph.contains(room_id!("!space:b.c"), room_id!("!a:b.c"), true).not());
+        assert!(graph.contains(room_id!("!space:b.c"), room_id!("!b:b.c"), true));
+    }
+
+    #[test]
+    fn test_space_graph_with_cycle() {
+        let mut graph = SpaceGraph::default();
+
+        graph.set_room_relations(room_id!("!s1:b.c"), relations(true, &["!s2:b.c"], &[]));
+        graph.set_room_relations(room_id!("!s2:b.c"), relations(true, &["!s1:b.c"], &[]));
+
+        assert!(graph.contains(room_id!("!s1:b.c"), room_id!("!s2:b.c"), true));
+        assert!(graph.contains(room_id!("!s1:b.c"), room_id!("!s1:b.c"), true));
+        assert!(graph.contains(room_id!("!s1:b.c"), room_id!("!a:b.c"), true).not());
+        // None of them is at the top level.
+        assert!(graph.top_level_spaces().is_empty());
+    }
+}
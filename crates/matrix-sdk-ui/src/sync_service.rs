@@ -22,20 +22,32 @@
 //! [`state`](SyncService::state) that the user
 //! MUST observe. Whenever an error/termination is observed, the user MUST call
 //! [`SyncService::start()`] again to restart the room list sync.
-
-use std::sync::{Arc, Mutex};
+//!
+//! When the offline mode is enabled with
+//! [`SyncServiceBuilder::with_offline_mode`], network errors don't terminate
+//! the sync service: it goes into the [`State::Offline`] state instead, and
+//! restarts the underlying syncs automatically, with an exponential backoff.
+//! The platform can also signal connectivity changes with
+//! [`SyncService::set_network_reachable`].
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 use eyeball::{SharedObservable, Subscriber};
 use futures_core::Future;
 use futures_util::{pin_mut, StreamExt as _};
-use matrix_sdk::Client;
+use matrix_sdk::{Client, HttpError};
 use thiserror::Error;
 use tokio::{
+    select,
     sync::{
         mpsc::{Receiver, Sender},
         Mutex as AsyncMutex, OwnedMutexGuard,
     },
     task::{spawn, JoinHandle},
+    time::sleep,
 };
 use tracing::{error, info, instrument, trace, warn, Instrument, Level};
 
@@ -44,6 +56,14 @@ use crate::{
     room_list_service::{self, RoomListService},
 };
 
+/// The delay before the first attempt to restart the underlying syncs, after
+/// they ran into a network error, in the offline mode.
+const OFFLINE_INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two attempts to restart the underlying syncs, in
+/// the offline mode.
+const OFFLINE_MAX_RETRY_DELAY: Duration = Duration::from_secs(5 * 60);
+
 /// Current state of the application.
 ///
 /// This is a high-level state indicating what's the status of the underlying
@@ -51,6 +71,10 @@ use crate::{
 /// state `Terminated` (if it gracefully exited) or `Error` (in case any of the
 /// underlying syncs ran into an error).
 ///
+/// In the offline mode, or when the platform signalled the network is
+/// unreachable, the application may also go into the `Offline` state, from
+/// which it gets back to `Running` automatically.
+///
 /// It is the responsibility of the caller to restart the application using the
 /// [`SyncService::start`] method, in case it terminated, gracefully or not.
 ///
@@ -65,6 +89,29 @@ pub enum State {
     Terminated,
     /// Any of the underlying syncs has ran into an error.
     Error,
+    /// The underlying syncs have been stopped because the server couldn't be
+    /// reached, and will be restarted automatically.
+    ///
+    /// The reason can be observed with [`SyncService::offline_reason`].
+    Offline,
+}
+
+/// The reason why the [`SyncService`] is in the [`State::Offline`] state.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OfflineReason {
+    /// Any of the underlying syncs ran into a network error. The syncs will be
+    /// restarted after `retry_in`, or as soon as the platform signals the
+    /// network is reachable again.
+    NetworkError {
+        /// The message of the network error.
+        message: String,
+        /// The delay before the next attempt to restart the syncs.
+        retry_in: Duration,
+    },
+    /// The platform signalled the network is unreachable, with
+    /// [`SyncService::set_network_reachable`]. The syncs will be restarted as
+    /// soon as the platform signals the network is reachable again.
+    NetworkUnreachable,
 }
 
 pub struct SyncService {
@@ -77,6 +124,16 @@ pub struct SyncService {
     /// What's the state of this sync service?
     state: SharedObservable<State>,
 
+    /// Why is this sync service offline, if it is?
+    offline_reason: SharedObservable<Option<OfflineReason>>,
+
+    /// Whether the network is reachable, as signalled by the platform.
+    network_reachable: SharedObservable<bool>,
+
+    /// Whether network errors should put the service in the
+    /// [`State::Offline`] state, instead of [`State::Error`].
+    with_offline_mode: bool,
+
     /// Use a mutex everytime to modify the `state` value, otherwise it would be
     /// possible to have race conditions when starting or pausing the
     /// service multiple times really quickly.
@@ -128,89 +185,263 @@ impl SyncService {
         self.state.subscribe()
     }
 
+    /// Returns the reason why the sync service is in the [`State::Offline`]
+    /// state, or `None` if it's not offline.
+    pub fn offline_reason(&self) -> Subscriber<Option<OfflineReason>> {
+        self.offline_reason.subscribe()
+    }
+
+    /// Signal a change of the network connectivity, as detected by the
+    /// platform.
+    ///
+    /// When the network becomes unreachable, the underlying syncs are stopped
+    /// and the service goes into the [`State::Offline`] state. When the
+    /// network becomes reachable again, they are restarted immediately.
+    pub async fn set_network_reachable(&self, reachable: bool) {
+        let _guard = self.modifying_state.lock().await;
+
+        if self.network_reachable.set_if_not_eq(reachable).is_none() {
+            return;
+        }
+
+        trace!(reachable, "network connectivity changed");
+
+        // When offline, the scheduler task observes the network connectivity by
+        // itself. When running, it must be requested to stop the syncs.
+        if !reachable && matches!(self.state.get(), State::Running) {
+            let sender = self.scheduler_sender.lock().unwrap().clone();
+
+            if let Some(sender) = sender {
+                if let Err(err) = sender
+                    .send(TerminationReport {
+                        is_error: false,
+                        has_expired: false,
+                        network_error: None,
+                        has_synced: false,
+                        origin: TerminationOrigin::NetworkUnreachable,
+                    })
+                    .await
+                {
+                    error!("when sending termination report: {err}");
+                }
+            }
+        }
+    }
+
     /// The role of the scheduler task is to wait for a termination message
     /// (`TerminationReport`), sent either because we wanted to stop both
     /// syncs, or because one of the syncs failed (in which case we'll stop
     /// the other one too).
+    ///
+    /// If the syncs have been stopped because the server couldn't be reached,
+    /// the scheduler task restarts them once it's deemed reachable again.
     fn spawn_scheduler_task(
         &self,
+        sender: Sender<TerminationReport>,
         mut receiver: Receiver<TerminationReport>,
     ) -> impl Future<Output = ()> {
         let encryption_sync_task = self.encryption_sync_task.clone();
         let encryption_sync = self.encryption_sync_service.clone();
+        let encryption_sync_permit = self.encryption_sync_permit.clone();
         let room_list_service = self.room_list_service.clone();
         let room_list_task = self.room_list_task.clone();
         let state = self.state.clone();
+        let offline_reason = self.offline_reason.clone();
+        let mut network_reachable = self.network_reachable.subscribe();
+        let with_offline_mode = self.with_offline_mode;
 
         async move {
-            let Some(report) = receiver.recv().await else {
-                info!("internal channel has been closed?");
-                return;
-            };
-
-            // If one service failed, make sure to request stopping the other one.
-            let (stop_room_list, stop_encryption) = match &report.origin {
-                TerminationOrigin::EncryptionSync => (true, false),
-                TerminationOrigin::RoomList => (false, true),
-                TerminationOrigin::Scheduler => (true, true),
-            };
+            let mut retry_delay = OFFLINE_INITIAL_RETRY_DELAY;
+
+            loop {
+                let Some(report) = receiver.recv().await else {
+                    info!("internal channel has been closed?");
+                    return;
+                };
+
+                Self::stop_syncs(
+                    &report.origin,
+                    &room_list_service,
+                    &room_list_task,
+                    &encryption_sync,
+                    &encryption_sync_task,
+                )
+                .await;
+
+                let reason = match (&report.origin, report.network_error) {
+                    (TerminationOrigin::NetworkUnreachable, _) => OfflineReason::NetworkUnreachable,
+
+                    (_, Some(message)) if with_offline_mode => {
+                        if report.has_synced {
+                            retry_delay = OFFLINE_INITIAL_RETRY_DELAY;
+                        }
+
+                        OfflineReason::NetworkError { message, retry_in: retry_delay }
+                    }
 
-            // Stop both services, and wait for the streams to properly finish: at some
-            // point they'll return `None` and will exit their infinite loops,
-            // and their tasks will gracefully terminate.
+                    _ => {
+                        if report.is_error {
+                            if report.has_expired {
+                                if !matches!(report.origin, TerminationOrigin::RoomList) {
+                                    room_list_service.expire_sync_session().await;
+                                }
+                                if !matches!(report.origin, TerminationOrigin::EncryptionSync) {
+                                    encryption_sync.expire_sync_session().await;
+                                }
+                            }
+
+                            state.set(State::Error);
+                        } else if matches!(report.origin, TerminationOrigin::Scheduler) {
+                            state.set(State::Idle);
+                        } else {
+                            state.set(State::Terminated);
+                        }
 
-            if stop_room_list {
-                if let Err(err) = room_list_service.stop_sync() {
-                    error!("unable to stop room list service: {err:#}");
+                        return;
+                    }
+                };
+
+                // Both syncs may have reported an error: forget about the reports that are
+                // now irrelevant, except for a request to stop.
+                while let Ok(report) = receiver.try_recv() {
+                    if matches!(report.origin, TerminationOrigin::Scheduler) {
+                        state.set(State::Idle);
+                        return;
+                    }
                 }
-            }
 
-            {
-                let task = room_list_task.lock().unwrap().take();
-                if let Some(task) = task {
-                    if let Err(err) = task.await {
-                        error!("when awaiting room list service: {err:#}");
+                info!(?reason, "sync service is offline");
+
+                offline_reason.set(Some(reason));
+                state.set(State::Offline);
+
+                // Wait for the server to be deemed reachable again.
+                loop {
+                    let reachable = network_reachable.get();
+
+                    let retry_in = match offline_reason.get() {
+                        Some(OfflineReason::NetworkError { retry_in, .. }) if reachable => {
+                            Some(retry_in)
+                        }
+                        Some(OfflineReason::NetworkUnreachable) if reachable => {
+                            // The network is back, retry immediately.
+                            retry_delay = OFFLINE_INITIAL_RETRY_DELAY;
+                            break;
+                        }
+                        _ => {
+                            offline_reason.set_if_not_eq(Some(OfflineReason::NetworkUnreachable));
+                            None
+                        }
+                    };
+
+                    select! {
+                        report = receiver.recv() => {
+                            // Only a request to stop can be received while offline.
+                            let is_stop_request = report.map_or(true, |report| {
+                                matches!(report.origin, TerminationOrigin::Scheduler)
+                            });
+
+                            if is_stop_request {
+                                offline_reason.set(None);
+                                state.set(State::Idle);
+                                return;
+                            }
+                        }
+
+                        _ = network_reachable.next() => {}
+
+                        _ = sleep(retry_in.unwrap_or_default()), if retry_in.is_some() => {
+                            retry_delay = (retry_delay * 2).min(OFFLINE_MAX_RETRY_DELAY);
+                            break;
+                        }
                     }
                 }
+
+                trace!("restarting the syncs after being offline");
+
+                // The permit may be held by another encryption sync for a while, e.g. the
+                // one of a notification client: don't let that block a request to stop.
+                let sync_permit_guard = select! {
+                    guard = encryption_sync_permit.clone().lock_owned() => guard,
+
+                    _ = receiver.recv() => {
+                        // Only a request to stop can be received while offline.
+                        offline_reason.set(None);
+                        state.set(State::Idle);
+                        return;
+                    }
+                };
+
+                *room_list_task.lock().unwrap() = Some(spawn(Self::spawn_room_list_sync(
+                    room_list_service.clone(),
+                    sender.clone(),
+                )));
+
+                *encryption_sync_task.lock().unwrap() = Some(spawn(Self::spawn_encryption_sync(
+                    encryption_sync.clone(),
+                    sender.clone(),
+                    sync_permit_guard,
+                )));
+
+                offline_reason.set(None);
+                state.set(State::Running);
             }
+        }
+        .instrument(tracing::span!(Level::WARN, "scheduler task"))
+    }
 
-            if stop_encryption {
-                if let Err(err) = encryption_sync.stop_sync() {
-                    warn!("unable to stop encryption sync: {err:#}");
-                }
+    /// Stop the underlying syncs after a termination report from the given
+    /// origin, and wait for their tasks to finish.
+    async fn stop_syncs(
+        origin: &TerminationOrigin,
+        room_list_service: &RoomListService,
+        room_list_task: &Mutex<Option<JoinHandle<()>>>,
+        encryption_sync: &EncryptionSyncService,
+        encryption_sync_task: &Mutex<Option<JoinHandle<()>>>,
+    ) {
+        // If one service failed, make sure to request stopping the other one.
+        let (stop_room_list, stop_encryption) = match origin {
+            TerminationOrigin::EncryptionSync => (true, false),
+            TerminationOrigin::RoomList => (false, true),
+            TerminationOrigin::Scheduler | TerminationOrigin::NetworkUnreachable => (true, true),
+        };
+
+        // Stop both services, and wait for the streams to properly finish: at some
+        // point they'll return `None` and will exit their infinite loops,
+        // and their tasks will gracefully terminate.
+
+        if stop_room_list {
+            if let Err(err) = room_list_service.stop_sync() {
+                error!("unable to stop room list service: {err:#}");
             }
+        }
 
-            {
-                let task = encryption_sync_task.lock().unwrap().take();
-                if let Some(task) = task {
-                    if let Err(err) = task.await {
-                        error!("when awaiting encryption sync: {err:#}");
-                    }
+        {
+            let task = room_list_task.lock().unwrap().take();
+            if let Some(task) = task {
+                if let Err(err) = task.await {
+                    error!("when awaiting room list service: {err:#}");
                 }
             }
+        }
 
-            if report.is_error {
-                if report.has_expired {
-                    if stop_room_list {
-                        room_list_service.expire_sync_session().await;
-                    }
-                    if stop_encryption {
-                        encryption_sync.expire_sync_session().await;
-                    }
-                }
+        if stop_encryption {
+            if let Err(err) = encryption_sync.stop_sync() {
+                warn!("unable to stop encryption sync: {err:#}");
+            }
+        }
 
-                state.set(State::Error);
-            } else if matches!(report.origin, TerminationOrigin::Scheduler) {
-                state.set(State::Idle);
-            } else {
-                state.set(State::Terminated);
+        {
+            let task = encryption_sync_task.lock().unwrap().take();
+            if let Some(task) = task {
+                if let Err(err) = task.await {
+                    error!("when awaiting encryption sync: {err:#}");
+                }
             }
         }
-        .instrument(tracing::span!(Level::WARN, "scheduler task"))
     }
 
     fn spawn_encryption_sync(
-        &self,
         encryption_sync: Arc<EncryptionSyncService>,
         sender: Sender<TerminationReport>,
         sync_permit_guard: OwnedMutexGuard<EncryptionSyncPermit>,
@@ -219,11 +450,14 @@ impl SyncService {
             let encryption_sync_stream = encryption_sync.sync(sync_permit_guard);
             pin_mut!(encryption_sync_stream);
 
-            let (is_error, has_expired) = loop {
+            let mut has_synced = false;
+
+            let (is_error, has_expired, network_error) = loop {
                 let res = encryption_sync_stream.next().await;
                 match res {
                     Some(Ok(())) => {
                         // Carry on.
+                        has_synced = true;
                     }
                     Some(Err(err)) => {
                         // If the encryption sync error was an expired session, also expire the
@@ -235,12 +469,18 @@ impl SyncService {
                             } else {
                                 false
                             };
+                        let network_error =
+                            if let encryption_sync_service::Error::SlidingSync(err) = &err {
+                                as_network_error(err)
+                            } else {
+                                None
+                            };
                         error!("Error while processing encryption in sync service: {err:#}");
-                        break (true, has_expired);
+                        break (true, has_expired, network_error);
                     }
                     None => {
                         // The stream has ended.
-                        break (false, false);
+                        break (false, false, None);
                     }
                 }
             };
@@ -249,6 +489,8 @@ impl SyncService {
                 .send(TerminationReport {
                     is_error,
                     has_expired,
+                    network_error,
+                    has_synced,
                     origin: TerminationOrigin::EncryptionSync,
                 })
                 .await
@@ -258,18 +500,22 @@ impl SyncService {
         }
     }
 
-    fn spawn_room_list_sync(&self, sender: Sender<TerminationReport>) -> impl Future<Output = ()> {
-        let room_list_service = self.room_list_service.clone();
-
+    fn spawn_room_list_sync(
+        room_list_service: Arc<RoomListService>,
+        sender: Sender<TerminationReport>,
+    ) -> impl Future<Output = ()> {
         async move {
             let room_list_stream = room_list_service.sync();
             pin_mut!(room_list_stream);
 
-            let (is_error, has_expired) = loop {
+            let mut has_synced = false;
+
+            let (is_error, has_expired, network_error) = loop {
                 let res = room_list_stream.next().await;
                 match res {
                     Some(Ok(())) => {
                         // Carry on.
+                        has_synced = true;
                     }
                     Some(Err(err)) => {
                         // If the room list error was an expired session, also expire the
@@ -280,12 +526,18 @@ impl SyncService {
                         } else {
                             false
                         };
+                        let network_error = if let room_list_service::Error::SlidingSync(err) = &err
+                        {
+                            as_network_error(err)
+                        } else {
+                            None
+                        };
                         error!("Error while processing room list in sync service: {err:#}");
-                        break (true, has_expired);
+                        break (true, has_expired, network_error);
                     }
                     None => {
                         // The stream has ended.
-                        break (false, false);
+                        break (false, false, None);
                     }
                 }
             };
@@ -294,6 +546,8 @@ impl SyncService {
                 .send(TerminationReport {
                     is_error,
                     has_expired,
+                    network_error,
+                    has_synced,
                     origin: TerminationOrigin::RoomList,
                 })
                 .await
@@ -313,8 +567,9 @@ impl SyncService {
         let _guard = self.modifying_state.lock().await;
 
         // Only (re)start the tasks if any was stopped.
-        if matches!(self.state.get(), State::Running) {
-            // It was already true, so we can skip the restart.
+        if matches!(self.state.get(), State::Running | State::Offline) {
+            // It was already true, or the tasks will be restarted automatically, so we
+            // can skip the restart.
             return;
         }
 
@@ -324,19 +579,20 @@ impl SyncService {
 
         // First, take care of the room list.
         *self.room_list_task.lock().unwrap() =
-            Some(spawn(self.spawn_room_list_sync(sender.clone())));
+            Some(spawn(Self::spawn_room_list_sync(self.room_list_service.clone(), sender.clone())));
 
         // Then, take care of the encryption sync.
         let sync_permit_guard = self.encryption_sync_permit.clone().lock_owned().await;
-        *self.encryption_sync_task.lock().unwrap() = Some(spawn(self.spawn_encryption_sync(
+        *self.encryption_sync_task.lock().unwrap() = Some(spawn(Self::spawn_encryption_sync(
             self.encryption_sync_service.clone(),
             sender.clone(),
             sync_permit_guard,
         )));
 
         // Spawn the scheduler task.
-        *self.scheduler_sender.lock().unwrap() = Some(sender);
-        *self.scheduler_task.lock().unwrap() = Some(spawn(self.spawn_scheduler_task(receiver)));
+        *self.scheduler_sender.lock().unwrap() = Some(sender.clone());
+        *self.scheduler_task.lock().unwrap() =
+            Some(spawn(self.spawn_scheduler_task(sender, receiver)));
 
         self.state.set(State::Running);
     }
@@ -355,7 +611,7 @@ impl SyncService {
                 // No need to stop if we were not running.
                 return Ok(());
             }
-            State::Running => {}
+            State::Running | State::Offline => {}
         };
 
         trace!("pausing sync service");
@@ -373,6 +629,8 @@ impl SyncService {
             .send(TerminationReport {
                 is_error: false,
                 has_expired: false,
+                network_error: None,
+                has_synced: false,
                 origin: TerminationOrigin::Scheduler,
             })
             .await
@@ -406,15 +664,29 @@ impl SyncService {
     }
 }
 
+/// If the given error is caused by the server being unreachable, returns its
+/// message.
+fn as_network_error(error: &matrix_sdk::Error) -> Option<String> {
+    match error {
+        matrix_sdk::Error::Http(err @ HttpError::Reqwest(_)) => Some(err.to_string()),
+        _ => None,
+    }
+}
+
 enum TerminationOrigin {
     EncryptionSync,
     RoomList,
     Scheduler,
+    NetworkUnreachable,
 }
 
 struct TerminationReport {
     is_error: bool,
     has_expired: bool,
+    /// The message of the network error that caused the termination, if any.
+    network_error: Option<String>,
+    /// Whether the terminated sync has successfully run at least once.
+    has_synced: bool,
     origin: TerminationOrigin,
 }
 
@@ -442,6 +714,9 @@ pub struct SyncServiceBuilder {
     /// Is the cross-process lock for the crypto store enabled?
     with_cross_process_lock: bool,
 
+    /// Should network errors put the sync service offline?
+    with_offline_mode: bool,
+
     /// Application identifier, used as the cross-process lock value, if
     /// applicable.
     identifier: String,
@@ -454,6 +729,7 @@ impl SyncServiceBuilder {
             #[cfg(feature = "experimental-room-list-with-unified-invites")]
             with_unified_invites_in_room_list: false,
             with_cross_process_lock: false,
+            with_offline_mode: false,
             identifier: "app".to_owned(),
         }
     }
@@ -484,6 +760,17 @@ impl SyncServiceBuilder {
         self
     }
 
+    /// Enables the offline mode.
+    ///
+    /// When any of the underlying syncs runs into a network error, the sync
+    /// service goes into the [`State::Offline`] state instead of
+    /// [`State::Error`], and restarts the syncs automatically, with an
+    /// exponential backoff.
+    pub fn with_offline_mode(mut self) -> Self {
+        self.with_offline_mode = true;
+        self
+    }
+
     /// Finish setting up the `SyncService`.
     ///
     /// This creates the underlying sliding syncs, and will *not* start them in
@@ -520,6 +807,9 @@ impl SyncServiceBuilder {
             scheduler_task: Arc::new(Mutex::new(None)),
             scheduler_sender: Mutex::new(None),
             state: SharedObservable::new(State::Idle),
+            offline_reason: SharedObservable::new(None),
+            network_reachable: SharedObservable::new(true),
+            with_offline_mode: self.with_offline_mode,
             modifying_state: AsyncMutex::new(()),
             encryption_sync_permit,
         })
@@ -12,28 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeSet, sync::Arc};
+use std::{
+    collections::BTreeSet,
+    sync::{Arc, Mutex},
+};
 
+use as_variant::as_variant;
 use eyeball::SharedObservable;
 use futures_util::{pin_mut, StreamExt};
 use matrix_sdk::{
-    event_cache::{self, RoomEventCacheUpdate},
+    event_cache::{self, EventCacheError, RoomEventCacheUpdate},
     executor::spawn,
+    send_queue::RoomSendQueueUpdate,
     Room,
 };
 use ruma::{
     events::{receipt::ReceiptType, AnySyncTimelineEvent},
     RoomVersionId,
 };
-use tokio::sync::{broadcast, mpsc};
+use tokio::sync::{broadcast, Mutex as AsyncMutex};
 use tracing::{info, info_span, trace, warn, Instrument, Span};
 
 #[cfg(feature = "e2e-encryption")]
 use super::to_device::{handle_forwarded_room_key_event, handle_room_key_event};
 use super::{
     inner::{TimelineInner, TimelineInnerSettings},
-    queue::send_queued_messages,
-    BackPaginationStatus, Timeline, TimelineDropHandle,
+    pinned_events_loader::PinnedEventsLoader,
+    BackPaginationStatus, ForwardPaginationStatus, Timeline, TimelineDropHandle, TimelineFocus,
 };
 use crate::{timeline::inner::TimelineEnd, unable_to_decrypt_hook::UtdHookManager};
 
@@ -44,6 +49,7 @@ use crate::{timeline::inner::TimelineEnd, unable_to_decrypt_hook::UtdHookManager
 pub struct TimelineBuilder {
     room: Room,
     settings: TimelineInnerSettings,
+    focus: TimelineFocus,
 
     /// An optional hook to call whenever we run into an unable-to-decrypt or a
     /// late-decryption event.
@@ -55,6 +61,7 @@ impl TimelineBuilder {
         Self {
             room: room.clone(),
             settings: TimelineInnerSettings::default(),
+            focus: TimelineFocus::Live,
             unable_to_decrypt_hook: None,
         }
     }
@@ -68,6 +75,14 @@ impl TimelineBuilder {
         self
     }
 
+    /// Sets what the timeline should focus on.
+    ///
+    /// Defaults to [`TimelineFocus::Live`].
+    pub fn with_focus(mut self, focus: TimelineFocus) -> Self {
+        self.focus = focus;
+        self
+    }
+
     /// Enable tracking of the fully-read marker and the read receipts on the
     /// timeline.
     pub fn track_read_marker_and_receipts(mut self) -> Self {
@@ -116,16 +131,36 @@ impl TimelineBuilder {
         self
     }
 
+    /// Summarise consecutive membership changes, profile changes and other
+    /// state events.
+    ///
+    /// Each group of consecutive state events is preceded by a
+    /// [`VirtualTimelineItem::CollapsedStateEvents`] item, that is kept up to
+    /// date as items are added to or removed from the group, so they can be
+    /// displayed collapsed, e.g. as "5 joins, 2 leaves".
+    ///
+    /// Defaults to `false`.
+    ///
+    /// [`VirtualTimelineItem::CollapsedStateEvents`]: super::VirtualTimelineItem::CollapsedStateEvents
+    pub fn group_state_events(mut self) -> Self {
+        self.settings.group_state_events = true;
+        self
+    }
+
     /// Create a [`Timeline`] with the options set on this builder.
     #[tracing::instrument(
         skip(self),
         fields(
             room_id = ?self.room.room_id(),
             track_read_receipts = self.settings.track_read_receipts,
+            focus = ?self.focus,
         )
     )]
     pub async fn build(self) -> event_cache::Result<Timeline> {
-        let Self { room, settings, unable_to_decrypt_hook } = self;
+        let Self { room, mut settings, focus, unable_to_decrypt_hook } = self;
+
+        settings.thread_root = focus.thread_root().map(ToOwned::to_owned);
+        let is_live = matches!(focus, TimelineFocus::Live);
 
         let client = room.client();
         let event_cache = client.event_cache();
@@ -134,7 +169,69 @@ impl TimelineBuilder {
         event_cache.subscribe()?;
 
         let (room_event_cache, event_cache_drop) = room.event_cache().await?;
-        let (events, mut event_subscriber) = room_event_cache.subscribe().await?;
+        let (mut events, mut event_subscriber) = room_event_cache.subscribe().await?;
+
+        let mut back_pagination_token = None;
+        let mut back_pagination_status = BackPaginationStatus::Idle;
+        let mut forward_pagination_token = None;
+        let mut forward_pagination_status = ForwardPaginationStatus::TimelineEndReached;
+
+        let pinned_events_loader = as_variant!(
+            &focus,
+            TimelineFocus::PinnedEvents { max_events_to_load } => {
+                Arc::new(PinnedEventsLoader::new(room.clone(), (*max_events_to_load).into()))
+            }
+        );
+
+        match &focus {
+            TimelineFocus::Live => {}
+
+            TimelineFocus::Thread { .. } => {
+                // The events of a thread are loaded with `/relations` when paginating; the
+                // cached events can't be merged with those without breaking the
+                // ordering.
+                events.clear();
+            }
+
+            TimelineFocus::Event { target, num_context_events } => {
+                let response = room
+                    .event_with_context(target, false, (*num_context_events).into())
+                    .await
+                    .map_err(EventCacheError::SdkError)?;
+
+                if response.event.is_none() {
+                    warn!("The server didn't return the focused event");
+                }
+
+                // The events before the target event are in reverse chronological order.
+                events = response
+                    .events_before
+                    .into_iter()
+                    .rev()
+                    .chain(response.event)
+                    .chain(response.events_after)
+                    .map(Into::into)
+                    .collect();
+
+                if response.prev_batch_token.is_none() {
+                    back_pagination_status = BackPaginationStatus::TimelineStartReached;
+                }
+                back_pagination_token = response.prev_batch_token;
+
+                // Until forward pagination reaches the end of the room's timeline, the live
+                // events can't be added to the timeline.
+                if response.next_batch_token.is_some() {
+                    forward_pagination_status = ForwardPaginationStatus::Idle;
+                }
+                forward_pagination_token = response.next_batch_token;
+            }
+
+            TimelineFocus::PinnedEvents { .. } => {
+                let loader = pinned_events_loader.as_ref().expect("the loader has been created");
+                events = loader.load_events().await;
+                back_pagination_status = BackPaginationStatus::TimelineStartReached;
+            }
+        }
 
         let has_events = !events.is_empty();
         let track_read_marker_and_receipts = settings.track_read_receipts;
@@ -147,7 +244,8 @@ impl TimelineBuilder {
         }
 
         if has_events {
-            inner.add_events_at(events, TimelineEnd::Back { from_cache: true }).await;
+            // The events around the focused event come from the server, not the cache.
+            inner.add_events_at(events, TimelineEnd::Back { from_cache: is_live }).await;
         }
         if track_read_marker_and_receipts {
             inner.load_fully_read_event().await;
@@ -156,8 +254,16 @@ impl TimelineBuilder {
         let room = inner.room();
         let client = room.client();
 
+        let pending_live_events = Arc::new(AsyncMutex::new(
+            (forward_pagination_status != ForwardPaginationStatus::TimelineEndReached)
+                .then(Vec::new),
+        ));
+        let forward_pagination_status = SharedObservable::new(forward_pagination_status);
+
         let room_update_join_handle = spawn({
             let inner = inner.clone();
+            let pending_live_events = pending_live_events.clone();
+            let pinned_events_loader = pinned_events_loader.clone();
 
             let span =
                 info_span!(parent: Span::none(), "room_update_handler", room_id = ?room.room_id());
@@ -174,28 +280,73 @@ impl TimelineBuilder {
                         Err(broadcast::error::RecvError::Closed) => break,
                         Err(broadcast::error::RecvError::Lagged(_)) => {
                             warn!("Lagged behind sync responses, resetting timeline");
-                            inner.clear().await;
+                            match &pinned_events_loader {
+                                Some(loader) => loader.reload(&inner).await,
+                                None => inner.clear().await,
+                            }
                             continue;
                         }
                     };
 
                     match update {
                         RoomEventCacheUpdate::Clear => {
+                            if !is_live {
+                                // The items of a focused timeline don't (only) come from the event
+                                // cache.
+                                trace!("Ignoring event cache clear for a focused timeline.");
+                                continue;
+                            }
+
                             trace!("Clearing the timeline.");
                             inner.clear().await;
                         }
 
+                        RoomEventCacheUpdate::ReplacedEvents { events } => {
+                            #[cfg(feature = "e2e-encryption")]
+                            {
+                                trace!("Received decrypted events");
+                                inner.handle_decrypted_events(events).await;
+                            }
+
+                            #[cfg(not(feature = "e2e-encryption"))]
+                            drop(events);
+                        }
+
                         RoomEventCacheUpdate::UpdateReadMarker { event_id } => {
                             trace!(target = %event_id, "Handling fully read marker.");
                             inner.handle_fully_read_marker(event_id).await;
                         }
 
                         RoomEventCacheUpdate::Append { events, ephemeral, ambiguity_changes } => {
-                            trace!("Received new events");
+                            if let Some(loader) = &pinned_events_loader {
+                                // Only keep the events updating the pinned events. Changes of the
+                                // pinned events themselves are handled by the pinned events task.
+                                let events = events
+                                    .into_iter()
+                                    .filter(|event| loader.is_related_to_pinned_event(event))
+                                    .collect();
+                                inner.handle_sync_events(events, ephemeral).await;
+
+                                continue;
+                            }
+
+                            let mut pending_live_events = pending_live_events.lock().await;
 
-                            // TODO: (bnjbvr) ephemeral should be handled by the event cache, and
-                            // we should replace this with a simple `add_events_at`.
-                            inner.handle_sync_events(events, ephemeral).await;
+                            if let Some(pending_events) = pending_live_events.as_mut() {
+                                trace!(
+                                    "Buffering new events until the timeline catches up with them."
+                                );
+                                pending_events.extend(events);
+                                inner.handle_sync_events(Vec::new(), ephemeral).await;
+                            } else {
+                                trace!("Received new events");
+
+                                // TODO: (bnjbvr) ephemeral should be handled by the event cache,
+                                // and we should replace this with a simple `add_events_at`.
+                                inner.handle_sync_events(events, ephemeral).await;
+                            }
+
+                            drop(pending_live_events);
 
                             let member_ambiguity_changes = ambiguity_changes
                                 .values()
@@ -209,16 +360,48 @@ impl TimelineBuilder {
             .instrument(span)
         });
 
+        // Reload the pinned events when they change, whether the new
+        // `m.room.pinned_events` event was received in the timeline or in the state
+        // of a sync response.
+        let pinned_events_join_handle = pinned_events_loader.clone().map(|loader| {
+            let inner = inner.clone();
+            let mut room_info = room.subscribe_info();
+
+            let span =
+                info_span!(parent: Span::none(), "pinned_events_handler", room_id = ?room.room_id());
+            span.follows_from(Span::current());
+
+            spawn(
+                async move {
+                    loop {
+                        if loader.have_pinned_events_changed() {
+                            trace!("The pinned events have changed, reloading them.");
+                            loader.reload(&inner).await;
+                        }
+
+                        if room_info.next().await.is_none() {
+                            break;
+                        }
+                    }
+                }
+                .instrument(span),
+            )
+        });
+
         let mut ignore_user_list_stream = client.subscribe_to_ignore_user_list_changes();
         let ignore_user_list_update_join_handle = spawn({
             let inner = inner.clone();
+            let pinned_events_loader = pinned_events_loader.clone();
 
             let span = info_span!(parent: Span::none(), "ignore_user_list_update_handler", room_id = ?room.room_id());
             span.follows_from(Span::current());
 
             async move {
                 while ignore_user_list_stream.next().await.is_some() {
-                    inner.clear().await;
+                    match &pinned_events_loader {
+                        Some(loader) => loader.reload(&inner).await,
+                        None => inner.clear().await,
+                    }
                 }
             }
             .instrument(span)
@@ -272,21 +455,67 @@ impl TimelineBuilder {
             })
         };
 
-        let (msg_sender, msg_receiver) = mpsc::channel(1);
-        info!("Starting message-sending loop");
-        spawn(send_queued_messages(inner.clone(), room.clone(), msg_receiver));
+        let (local_echoes, mut send_queue_listener) =
+            room.send_queue().subscribe().await.map_err(EventCacheError::SdkError)?;
+
+        // Only the live timeline renders the requests queued from elsewhere, as local
+        // echoes; other timelines only get their own local echoes updated.
+        if is_live {
+            for request in local_echoes {
+                inner.handle_queued_request(request).await;
+            }
+        }
+
+        let send_queue_join_handle = spawn({
+            let inner = inner.clone();
+
+            let span =
+                info_span!(parent: Span::none(), "send_queue_handler", room_id = ?room.room_id());
+            span.follows_from(Span::current());
+
+            async move {
+                info!("Listening to the send queue updates");
+
+                loop {
+                    let update = match send_queue_listener.recv().await {
+                        Ok(update) => update,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                        Err(broadcast::error::RecvError::Lagged(num_skipped)) => {
+                            warn!(num_skipped, "Lagged behind the send queue updates");
+                            continue;
+                        }
+                    };
+
+                    match update {
+                        RoomSendQueueUpdate::NewLocalEvent(request) => {
+                            if is_live {
+                                inner.handle_queued_request(request).await;
+                            }
+                        }
+                        update => inner.handle_send_queue_update(update).await,
+                    }
+                }
+            }
+            .instrument(span)
+        });
 
         let timeline = Timeline {
             inner,
-            back_pagination_status: SharedObservable::new(BackPaginationStatus::Idle),
-            msg_sender,
+            back_pagination_status: SharedObservable::new(back_pagination_status),
+            forward_pagination_status,
             event_cache: room_event_cache,
+            focus,
+            back_pagination_token: Mutex::new(back_pagination_token),
+            forward_pagination_token: Mutex::new(forward_pagination_token),
+            pending_live_events,
             drop_handle: Arc::new(TimelineDropHandle {
                 client,
                 event_handler_handles: handles,
                 room_update_join_handle,
+                pinned_events_join_handle,
                 ignore_user_list_update_join_handle,
                 room_key_from_backups_join_handle,
+                send_queue_join_handle,
                 _event_cache_drop_handle: event_cache_drop,
             }),
         };
@@ -102,7 +102,9 @@ impl DayDividerAdjuster {
                     latest_event_ts = Some(ts);
                 }
 
-                TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker) => {
+                TimelineItemKind::Virtual(
+                    VirtualTimelineItem::ReadMarker | VirtualTimelineItem::CollapsedStateEvents(_),
+                ) => {
                     // Nothing to do.
                 }
             }
@@ -172,8 +174,10 @@ impl DayDividerAdjuster {
                 self.ops.push(DayDividerOperation::Remove(i));
             }
 
-            TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker) => {
-                // Nothing to do for read markers.
+            TimelineItemKind::Virtual(
+                VirtualTimelineItem::ReadMarker | VirtualTimelineItem::CollapsedStateEvents(_),
+            ) => {
+                // Nothing to do for read markers and summaries of state events.
             }
         }
     }
@@ -236,7 +240,9 @@ impl DayDividerAdjuster {
                 }
             }
 
-            TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker) => {
+            TimelineItemKind::Virtual(
+                VirtualTimelineItem::ReadMarker | VirtualTimelineItem::CollapsedStateEvents(_),
+            ) => {
                 // Nothing to do.
             }
         }
@@ -548,12 +554,14 @@ mod tests {
         let event_kind = EventTimelineItemKind::Remote(RemoteEventTimelineItem {
             event_id: owned_event_id!("$1"),
             reactions: Default::default(),
+            thread_summary: None,
             read_receipts: Default::default(),
             is_own: false,
             is_highlighted: false,
             encryption_info: None,
             original_json: None,
             latest_edit_json: None,
+            edit_history: Vec::new(),
             origin: crate::timeline::event_item::RemoteEventOrigin::Sync,
         });
         EventTimelineItem::new(
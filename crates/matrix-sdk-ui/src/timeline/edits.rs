@@ -0,0 +1,153 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module handles the edit history of messages in the timeline.
+
+use std::fmt;
+
+use ruma::{
+    events::{
+        room::message::{MessageType, Relation, SyncRoomMessageEvent},
+        AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    html::RemoveReplyFallback,
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, UserId,
+};
+
+use crate::DEFAULT_SANITIZER_MODE;
+
+/// An `m.replace` event that has been applied to a message, as part of its
+/// edit history.
+#[derive(Clone)]
+pub struct EditHistoryEntry {
+    event_id: OwnedEventId,
+    sender: OwnedUserId,
+    timestamp: MilliSecondsSinceUnixEpoch,
+    content: MessageType,
+}
+
+impl EditHistoryEntry {
+    pub(super) fn new(
+        event_id: OwnedEventId,
+        sender: OwnedUserId,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        content: MessageType,
+    ) -> Self {
+        Self { event_id, sender, timestamp, content }
+    }
+
+    /// Create an entry from an edit event of a message.
+    ///
+    /// Returns `None` if the event isn't an edit of a message, along with the
+    /// ID of the edited message otherwise.
+    pub(super) fn from_event(event: &AnySyncMessageLikeEvent) -> Option<(&EventId, Self)> {
+        let AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Original(event)) = event
+        else {
+            return None;
+        };
+        let Some(Relation::Replacement(replacement)) = &event.content.relates_to else {
+            return None;
+        };
+
+        let mut content = replacement.new_content.msgtype.clone();
+        // Edit's content is never supposed to contain the reply fallback.
+        content.sanitize(DEFAULT_SANITIZER_MODE, RemoveReplyFallback::No);
+
+        let entry = Self::new(
+            event.event_id.clone(),
+            event.sender.clone(),
+            event.origin_server_ts,
+            content,
+        );
+
+        Some((&replacement.event_id, entry))
+    }
+
+    /// Create an entry from an edit event of a message, like
+    /// [`Self::from_event`], for any timeline event.
+    pub(super) fn from_timeline_event(event: &AnySyncTimelineEvent) -> Option<(&EventId, Self)> {
+        match event {
+            AnySyncTimelineEvent::MessageLike(event) => Self::from_event(event),
+            AnySyncTimelineEvent::State(_) => None,
+        }
+    }
+
+    /// The ID of the edit event.
+    pub fn event_id(&self) -> &EventId {
+        &self.event_id
+    }
+
+    /// The sender of the edit event.
+    pub fn sender(&self) -> &UserId {
+        &self.sender
+    }
+
+    /// The timestamp of the edit event.
+    pub fn timestamp(&self) -> MilliSecondsSinceUnixEpoch {
+        self.timestamp
+    }
+
+    /// The new content of the message, as set by this edit.
+    pub fn content(&self) -> &MessageType {
+        &self.content
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for EditHistoryEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { event_id, sender, timestamp, content: _ } = self;
+        // since timeline items are logged, don't include the content here so
+        // people don't leak personal data in bug reports
+        f.debug_struct("EditHistoryEntry")
+            .field("event_id", event_id)
+            .field("sender", sender)
+            .field("timestamp", timestamp)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Add an entry to the given edit history, keeping it sorted from the oldest
+/// edit to the most recent one.
+///
+/// Entries that are already in the history are ignored, so re-receiving the
+/// same edit doesn't duplicate it.
+///
+/// Returns whether the history was updated.
+pub(super) fn add_to_edit_history(
+    history: &mut Vec<EditHistoryEntry>,
+    entry: EditHistoryEntry,
+) -> bool {
+    if history.iter().any(|existing| existing.event_id == entry.event_id) {
+        return false;
+    }
+
+    let position = history.partition_point(|existing| existing.timestamp <= entry.timestamp);
+    history.insert(position, entry);
+
+    true
+}
+
+/// Remove the entry of the given edit from the given edit history, e.g.
+/// because the edit has been redacted.
+///
+/// Returns whether the history was updated.
+pub(super) fn remove_from_edit_history(
+    history: &mut Vec<EditHistoryEntry>,
+    edit_id: &EventId,
+) -> bool {
+    let len = history.len();
+    history.retain(|entry| entry.event_id != edit_id);
+    history.len() != len
+}
@@ -55,6 +55,10 @@ pub enum Error {
     /// Could not get user
     #[error("User ID is not available")]
     UserIdNotAvailable,
+
+    /// The edit history of an event could not be fetched.
+    #[error("Failed fetching the edit history")]
+    FailedToFetchEditHistory(#[source] matrix_sdk::Error),
 }
 
 #[derive(Error)]
@@ -17,9 +17,11 @@ use std::sync::Arc;
 use as_variant::as_variant;
 use eyeball_im::{ObservableVectorTransaction, ObservableVectorTransactionEntry};
 use indexmap::{map::Entry, IndexMap};
-use matrix_sdk::deserialized_responses::EncryptionInfo;
+use matrix_sdk::deserialized_responses::{EncryptionInfo, UtdCause};
 use ruma::{
     events::{
+        beacon::BeaconEventContent,
+        beacon_info::BeaconInfoEventContent,
         poll::{
             unstable_end::UnstablePollEndEventContent,
             unstable_response::UnstablePollResponseEventContent,
@@ -49,16 +51,19 @@ use tracing::{debug, error, field::debug, info, instrument, trace, warn};
 
 use super::{
     day_dividers::DayDividerAdjuster,
+    edits::{add_to_edit_history, remove_from_edit_history},
     event_item::{
         AnyOtherFullStateEventContent, BundledReactions, EventItemIdentifier, EventSendState,
         EventTimelineItemKind, LocalEventTimelineItem, Profile, RemoteEventOrigin,
         RemoteEventTimelineItem,
     },
     inner::{TimelineInnerMetadata, TimelineInnerStateTransaction},
+    location::{BeaconLocation, LiveLocationState, Location},
     polls::PollState,
+    threads::thread_root_of_content,
     util::{rfind_event_by_id, rfind_event_item},
-    EventTimelineItem, InReplyToDetails, Message, OtherState, ReactionGroup, ReactionSenderData,
-    Sticker, TimelineDetails, TimelineItem, TimelineItemContent,
+    EditHistoryEntry, EventTimelineItem, InReplyToDetails, Message, OtherState, ReactionGroup,
+    ReactionSenderData, Sticker, ThreadSummary, TimelineDetails, TimelineItem, TimelineItemContent,
 };
 use crate::{events::SyncTimelineEventWithoutContent, DEFAULT_SANITIZER_MODE};
 
@@ -85,6 +90,9 @@ pub(super) enum Flow {
         position: TimelineItemPosition,
         /// Should this event actually be added, based on the event filters.
         should_add: bool,
+        /// Why the event couldn't be decrypted, if it's an encrypted event that
+        /// we failed to decrypt.
+        utd_cause: Option<UtdCause>,
     },
 }
 
@@ -126,6 +134,10 @@ pub(super) enum TimelineEventKind {
         state_key: String,
         content: AnyOtherFullStateEventContent,
     },
+    BeaconInfo {
+        user_id: OwnedUserId,
+        content: BeaconInfoEventContent,
+    },
     FailedToParseMessageLike {
         event_type: MessageLikeEventType,
         error: Arc<serde_json::Error>,
@@ -173,6 +185,9 @@ impl TimelineEventKind {
                         sender: ev.sender,
                     },
                 },
+                AnySyncStateEvent::BeaconInfo(SyncStateEvent::Original(ev)) => {
+                    Self::BeaconInfo { user_id: ev.state_key, content: ev.content }
+                }
                 ev => Self::OtherState {
                     state_key: ev.state_key().to_owned(),
                     content: AnyOtherFullStateEventContent::with_event_content(ev.content()),
@@ -259,6 +274,15 @@ pub(super) struct TimelineEventHandler<'a, 'o> {
     meta: &'a mut TimelineInnerMetadata,
     ctx: TimelineEventContext,
     result: HandleEventResult,
+
+    /// The thread summary bundled with the event by the server, if it is the
+    /// root of a thread. It's attached to the item created for the event, if
+    /// any.
+    thread_summary: Option<ThreadSummary>,
+
+    /// The edit bundled with the event by the server, if any. It starts the
+    /// edit history of the item created for the event, if any.
+    bundled_edit_history: Vec<EditHistoryEntry>,
 }
 
 impl<'a, 'o> TimelineEventHandler<'a, 'o> {
@@ -267,7 +291,14 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         ctx: TimelineEventContext,
     ) -> Self {
         let TimelineInnerStateTransaction { items, meta, .. } = state;
-        Self { items, meta, ctx, result: HandleEventResult::default() }
+        Self {
+            items,
+            meta,
+            ctx,
+            result: HandleEventResult::default(),
+            thread_summary: None,
+            bundled_edit_history: Vec::new(),
+        }
     }
 
     /// Handle an event.
@@ -303,6 +334,24 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
             }
         };
 
+        if let TimelineEventKind::Message { content, relations } = &event_kind {
+            self.thread_summary = relations.thread.as_deref().map(ThreadSummary::from_bundled);
+
+            // Edits from other users are ignored, see `handle_room_message_edit`.
+            self.bundled_edit_history = relations
+                .replace
+                .as_deref()
+                .and_then(EditHistoryEntry::from_event)
+                .map(|(_, entry)| entry)
+                .filter(|entry| entry.sender() == self.ctx.sender)
+                .into_iter()
+                .collect();
+
+            if let Some(thread_root) = thread_root_of_content(content) {
+                self.handle_thread_reply(thread_root.to_owned());
+            }
+        }
+
         match event_kind {
             TimelineEventKind::Message { content, relations } => match content {
                 AnyMessageLikeEventContent::Reaction(c) => {
@@ -318,14 +367,19 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                     self.add(should_add, TimelineItemContent::message(c, relations, self.items));
                 }
                 AnyMessageLikeEventContent::RoomEncrypted(c) => {
+                    let cause = match &self.ctx.flow {
+                        Flow::Remote { utd_cause, .. } => utd_cause.clone().unwrap_or_default(),
+                        Flow::Local { .. } => UtdCause::Unknown,
+                    };
+
                     // TODO: Handle replacements if the replaced event is also UTD
-                    self.add(true, TimelineItemContent::unable_to_decrypt(c));
+                    self.add(true, TimelineItemContent::unable_to_decrypt(c, cause.clone()));
 
                     // Let the hook know that we ran into an unable-to-decrypt that is added to the
                     // timeline.
                     if let Some(hook) = self.meta.unable_to_decrypt_hook.as_ref() {
                         if let Flow::Remote { event_id, .. } = &self.ctx.flow {
-                            hook.on_utd(event_id);
+                            hook.on_utd(event_id, cause);
                         }
                     }
                 }
@@ -343,6 +397,10 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                 AnyMessageLikeEventContent::CallInvite(_) => {
                     self.add(should_add, TimelineItemContent::CallInvite);
                 }
+                AnyMessageLikeEventContent::Location(content) => {
+                    self.add(should_add, TimelineItemContent::Location(Location { content }));
+                }
+                AnyMessageLikeEventContent::Beacon(c) => self.handle_beacon(c),
 
                 // TODO
                 _ => {
@@ -377,6 +435,10 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                 );
             }
 
+            TimelineEventKind::BeaconInfo { user_id, content } => {
+                self.handle_beacon_info(user_id, content, should_add);
+            }
+
             TimelineEventKind::FailedToParseMessageLike { event_type, error } => {
                 self.add(
                     should_add,
@@ -439,7 +501,7 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
             msgtype.sanitize(DEFAULT_SANITIZER_MODE, RemoveReplyFallback::No);
 
             let new_content = TimelineItemContent::Message(Message {
-                msgtype,
+                msgtype: msgtype.clone(),
                 in_reply_to: msg.in_reply_to.clone(),
                 thread_root: msg.thread_root.clone(),
                 edited: true,
@@ -451,7 +513,23 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
             };
 
             trace!("Applying edit");
-            Some(event_item.with_content(new_content, edit_json))
+            let mut new_item = event_item.with_content(new_content, edit_json);
+
+            // Local echoes of edits are added to the history once they come back
+            // from the server.
+            if let (Flow::Remote { event_id, .. }, Some(remote)) =
+                (&this.ctx.flow, new_item.as_remote_mut())
+            {
+                let entry = EditHistoryEntry::new(
+                    event_id.clone(),
+                    this.ctx.sender.clone(),
+                    this.ctx.timestamp,
+                    msgtype,
+                );
+                add_to_edit_history(&mut remote.edit_history, entry);
+            }
+
+            Some(new_item)
         });
 
         if !found {
@@ -459,6 +537,33 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         }
     }
 
+    /// Update the summary of the thread the current event is a reply to, if
+    /// the thread root is in the timeline.
+    ///
+    /// Only replies received at the end of the timeline are counted: older
+    /// ones are already part of the summary bundled with the root event.
+    #[instrument(skip_all, fields(thread_root = ?thread_root))]
+    fn handle_thread_reply(&mut self, thread_root: OwnedEventId) {
+        let Flow::Remote { event_id, position: TimelineItemPosition::End { .. }, .. } =
+            &self.ctx.flow
+        else {
+            return;
+        };
+        let event_id = event_id.clone();
+
+        self.update_timeline_item(&thread_root, |this, event_item| {
+            let remote_event_item = event_item.as_remote()?;
+
+            let mut thread_summary = remote_event_item.thread_summary.clone().unwrap_or_default();
+            if !thread_summary.add_reply(&event_id, &this.ctx.sender) {
+                return None;
+            }
+
+            trace!("Updating thread summary");
+            Some(event_item.with_kind(remote_event_item.with_thread_summary(Some(thread_summary))))
+        });
+    }
+
     // Redacted reaction events are no-ops so don't need to be handled
     #[instrument(skip_all, fields(relates_to_event_id = ?c.relates_to.event_id))]
     fn handle_reaction(&mut self, c: ReactionEventContent) {
@@ -637,6 +742,84 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
         }
     }
 
+    fn handle_beacon_info(
+        &mut self,
+        user_id: OwnedUserId,
+        content: BeaconInfoEventContent,
+        should_add: bool,
+    ) {
+        if content.live {
+            let mut state = LiveLocationState::new(content);
+            if let Flow::Remote { event_id, .. } = self.ctx.flow.clone() {
+                self.meta.live_location_pending_events.apply(
+                    &event_id,
+                    &user_id,
+                    self.ctx.timestamp,
+                    &mut state,
+                );
+            }
+            self.add(should_add, TimelineItemContent::LiveLocation(state));
+            return;
+        }
+
+        // A share is stopped by a new `beacon_info` state event for the same user, that
+        // applies to their latest share. When back-paginating, the shares in the
+        // timeline are more recent than this event.
+        let is_back_paginated =
+            matches!(self.ctx.flow, Flow::Remote { position: TimelineItemPosition::Start, .. });
+        let latest_share = if is_back_paginated {
+            None
+        } else {
+            rfind_event_item(self.items, |item| {
+                item.sender() == user_id
+                    && matches!(item.content(), TimelineItemContent::LiveLocation(_))
+            })
+        };
+
+        let Some((idx, item)) = latest_share else {
+            self.meta.live_location_pending_events.add_stop(&user_id, self.ctx.timestamp);
+            return;
+        };
+
+        let internal_id = item.internal_id;
+        let Some(state) = as_variant!(item.content(), TimelineItemContent::LiveLocation)
+            .and_then(|state| state.stop())
+        else {
+            info!("Got multiple stops of a live location share, discarding");
+            return;
+        };
+
+        trace!("Stopping live location share");
+        let new_item = item.with_content(TimelineItemContent::LiveLocation(state), None);
+        self.items.set(idx, TimelineItem::new(new_item, internal_id));
+        self.result.items_updated += 1;
+    }
+
+    fn handle_beacon(&mut self, c: BeaconEventContent) {
+        let beacon_info_id = c.relates_to.event_id.clone();
+        let location = BeaconLocation::from(c);
+
+        let found = self.update_timeline_item(&beacon_info_id, |this, event_item| {
+            let state = as_variant!(event_item.content(), TimelineItemContent::LiveLocation)?;
+
+            if this.ctx.sender != event_item.sender() {
+                info!("Beacon applies to another user's live location share, discarding");
+                return None;
+            }
+
+            let state = state.add_location(location.clone())?;
+            Some(event_item.with_content(TimelineItemContent::LiveLocation(state), None))
+        });
+
+        if !found {
+            self.meta.live_location_pending_events.add_location(
+                &beacon_info_id,
+                &self.ctx.sender,
+                location,
+            );
+        }
+    }
+
     // Redacted redactions are no-ops (unfortunately)
     #[instrument(skip_all, fields(redacts_event_id = ?redacts))]
     fn handle_redaction(&mut self, redacts: OwnedEventId, _content: RoomRedactionEventContent) {
@@ -726,6 +909,23 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
             debug!("redaction affected no event");
         }
 
+        // If the redacted event is an edit, remove it from the edit history of the
+        // message it edited.
+        self.items.for_each(|mut entry| {
+            let Some(event_item) = entry.as_event() else { return };
+            let Some(remote) = event_item.as_remote() else { return };
+            if !remote.edit_history.iter().any(|edit| edit.event_id() == redacts) {
+                return;
+            }
+
+            let mut remote = remote.clone();
+            remove_from_edit_history(&mut remote.edit_history, &redacts);
+            trace!("Removing redacted edit from the edit history");
+
+            let new_item = entry.with_kind(event_item.with_kind(remote));
+            ObservableVectorTransactionEntry::set(&mut entry, new_item);
+        });
+
         self.items.for_each(|mut entry| {
             let Some(event_item) = entry.as_event() else { return };
             let Some(message) = event_item.content.as_message() else { return };
@@ -828,7 +1028,8 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                 let origin = match position {
                     TimelineItemPosition::Start => RemoteEventOrigin::Pagination,
 
-                    // We only paginate backwards for now, so End only happens for syncs
+                    // Forward pagination only happens for event-focused timelines, and is
+                    // treated like a sync.
                     TimelineItemPosition::End { from_cache: true } => RemoteEventOrigin::Cache,
 
                     TimelineItemPosition::End { from_cache: false } => RemoteEventOrigin::Sync,
@@ -843,15 +1044,37 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
                         }),
                 };
 
+                let thread_summary = match position {
+                    #[cfg(feature = "e2e-encryption")]
+                    TimelineItemPosition::Update(idx) => self.thread_summary.take().or_else(|| {
+                        self.items[*idx].as_event()?.as_remote()?.thread_summary.clone()
+                    }),
+                    _ => self.thread_summary.take(),
+                };
+
+                let mut edit_history = std::mem::take(&mut self.bundled_edit_history);
+                #[cfg(feature = "e2e-encryption")]
+                if let TimelineItemPosition::Update(idx) = position {
+                    if let Some(old_remote) =
+                        self.items[*idx].as_event().and_then(|ev| ev.as_remote())
+                    {
+                        for entry in &old_remote.edit_history {
+                            add_to_edit_history(&mut edit_history, entry.clone());
+                        }
+                    }
+                }
+
                 RemoteEventTimelineItem {
                     event_id: event_id.clone(),
                     reactions,
+                    thread_summary,
                     read_receipts: self.ctx.read_receipts.clone(),
                     is_own: self.ctx.is_own_event,
                     is_highlighted: self.ctx.is_highlighted,
                     encryption_info: self.ctx.encryption_info.clone(),
                     original_json: Some(raw_event.clone()),
                     latest_edit_json: None,
+                    edit_history,
                     origin,
                 }
                 .into()
@@ -1055,9 +1278,22 @@ impl<'a, 'o> TimelineEventHandler<'a, 'o> {
 /// have been fetched separately (only `reply_to` for now) from `old_item` to
 /// `item`, given two items for an event that was re-received.
 ///
+/// The thread summary aggregated on `old_item` is kept too, if the new item
+/// doesn't come with one, and so are the edits of its edit history.
+///
 /// `old_item` *should* always be a local echo usually, but with the sliding
 /// sync proxy, we often re-receive remote events that aren't remote echoes.
 fn transfer_details(item: &mut EventTimelineItem, old_item: &EventTimelineItem) {
+    if let (Some(remote), Some(old_remote)) = (item.as_remote_mut(), old_item.as_remote()) {
+        if remote.thread_summary.is_none() {
+            remote.thread_summary = old_remote.thread_summary.clone();
+        }
+
+        for entry in &old_remote.edit_history {
+            add_to_edit_history(&mut remote.edit_history, entry.clone());
+        }
+    }
+
     let TimelineItemContent::Message(msg) = &mut item.content else { return };
     let TimelineItemContent::Message(old_msg) = &old_item.content else { return };
 
@@ -14,7 +14,7 @@
 
 //! Timeline item content bits for `m.room.message` events.
 
-use std::{fmt, sync::Arc};
+use std::{fmt, sync::Arc, time::Duration};
 
 use imbl::{vector, Vector};
 use matrix_sdk::deserialized_responses::TimelineEvent;
@@ -25,7 +25,7 @@ use ruma::{
         room::{
             message,
             message::{
-                MessageType, Relation, RoomMessageEventContent,
+                AudioMessageEventContent, MessageType, Relation, RoomMessageEventContent,
                 RoomMessageEventContentWithoutRelation, SyncRoomMessageEvent,
             },
         },
@@ -33,7 +33,7 @@ use ruma::{
         BundledMessageLikeRelations,
     },
     html::RemoveReplyFallback,
-    OwnedEventId, OwnedUserId, RoomVersionId, UserId,
+    EventId, OwnedEventId, OwnedUserId, RoomVersionId, UserId,
 };
 use tracing::error;
 
@@ -141,12 +141,31 @@ impl Message {
         self.thread_root.is_some()
     }
 
+    /// Get the event ID of the root of the thread this message is part of, if
+    /// any.
+    pub fn thread_root(&self) -> Option<&EventId> {
+        self.thread_root.as_deref()
+    }
+
     /// Get the edit state of this message (has been edited: `true` /
     /// `false`).
     pub fn is_edited(&self) -> bool {
         self.edited
     }
 
+    /// Get the voice-specific metadata of this message, if it is a voice
+    /// message as defined in [MSC3245].
+    ///
+    /// [MSC3245]: https://github.com/matrix-org/matrix-spec-proposals/pull/3245
+    pub fn voice_message_info(&self) -> Option<VoiceMessageInfo> {
+        match &self.msgtype {
+            MessageType::Audio(content) if content.voice.is_some() => {
+                Some(VoiceMessageInfo::from_content(content))
+            }
+            _ => None,
+        }
+    }
+
     pub(in crate::timeline) fn to_content(&self) -> RoomMessageEventContent {
         // Like the `impl From<Message> for RoomMessageEventContent` below, but
         // takes &self and only copies what's needed.
@@ -206,6 +225,35 @@ impl fmt::Debug for Message {
     }
 }
 
+/// The metadata of a voice message, allowing to render it without downloading
+/// the audio file first.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoiceMessageInfo {
+    /// The duration of the voice message, if known.
+    pub duration: Option<Duration>,
+    /// The amplitudes of the voice message over time, between 0 and 1024.
+    ///
+    /// Empty if the sender didn't include a waveform.
+    pub waveform: Vec<u16>,
+}
+
+impl VoiceMessageInfo {
+    fn from_content(content: &AudioMessageEventContent) -> Self {
+        let details = content.audio.as_ref();
+
+        let duration = details
+            .map(|details| details.duration)
+            .or_else(|| content.info.as_ref().and_then(|info| info.duration));
+        let waveform = details
+            .map(|details| {
+                details.waveform.iter().map(|a| u16::try_from(a.get()).unwrap_or(0)).collect()
+            })
+            .unwrap_or_default();
+
+        Self { duration, waveform }
+    }
+}
+
 /// Details about an event being replied to.
 #[derive(Clone, Debug)]
 pub struct InReplyToDetails {
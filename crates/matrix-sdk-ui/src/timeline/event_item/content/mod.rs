@@ -16,6 +16,7 @@ use std::sync::Arc;
 
 use as_variant::as_variant;
 use imbl::Vector;
+use matrix_sdk::deserialized_responses::UtdCause;
 use matrix_sdk_base::latest_event::{is_suitable_for_latest_event, PossibleLatestEvent};
 use ruma::{
     events::{
@@ -54,11 +55,15 @@ use ruma::{
 };
 use tracing::warn;
 
-use crate::timeline::{polls::PollState, TimelineItem};
+use crate::timeline::{
+    location::{LiveLocationState, Location},
+    polls::PollState,
+    TimelineItem,
+};
 
 mod message;
 
-pub use self::message::{InReplyToDetails, Message, RepliedToEvent};
+pub use self::message::{InReplyToDetails, Message, RepliedToEvent, VoiceMessageInfo};
 
 /// The content of an [`EventTimelineItem`][super::EventTimelineItem].
 #[derive(Clone, Debug)]
@@ -110,6 +115,13 @@ pub enum TimelineItemContent {
 
     /// An `m.call.invite` event
     CallInvite,
+
+    /// An `m.location` event.
+    Location(Location),
+
+    /// A `beacon_info` state event starting a live location share, aggregated
+    /// with the locations shared afterwards.
+    LiveLocation(LiveLocationState),
 }
 
 impl TimelineItemContent {
@@ -245,11 +257,13 @@ impl TimelineItemContent {
             | TimelineItemContent::FailedToParseState { .. } => "an event that couldn't be parsed",
             TimelineItemContent::Poll(_) => "a poll",
             TimelineItemContent::CallInvite => "a call invite",
+            TimelineItemContent::Location(_) => "a location",
+            TimelineItemContent::LiveLocation(_) => "a live location share",
         }
     }
 
-    pub(crate) fn unable_to_decrypt(content: RoomEncryptedEventContent) -> Self {
-        Self::UnableToDecrypt(content.into())
+    pub(crate) fn unable_to_decrypt(content: RoomEncryptedEventContent, cause: UtdCause) -> Self {
+        Self::UnableToDecrypt(EncryptedMessage::new(content, cause))
     }
 
     pub(crate) fn room_member(
@@ -324,6 +338,8 @@ impl TimelineItemContent {
             | Self::Sticker(_)
             | Self::Poll(_)
             | Self::CallInvite
+            | Self::Location(_)
+            | Self::LiveLocation(_)
             | Self::UnableToDecrypt(_) => Self::RedactedMessage,
             Self::MembershipChange(ev) => Self::MembershipChange(ev.redact(room_version)),
             Self::ProfileChange(ev) => Self::ProfileChange(ev.redact()),
@@ -356,13 +372,16 @@ pub enum EncryptedMessage {
 
         /// The ID of the session used to encrypt the message.
         session_id: String,
+
+        /// Why the message couldn't be decrypted.
+        cause: UtdCause,
     },
     /// No metadata because the event uses an unknown algorithm.
     Unknown,
 }
 
-impl From<RoomEncryptedEventContent> for EncryptedMessage {
-    fn from(c: RoomEncryptedEventContent) -> Self {
+impl EncryptedMessage {
+    fn new(c: RoomEncryptedEventContent, cause: UtdCause) -> Self {
         match c.scheme {
             EncryptedEventScheme::OlmV1Curve25519AesSha2(s) => {
                 Self::OlmV1Curve25519AesSha2 { sender_key: s.sender_key }
@@ -370,7 +389,7 @@ impl From<RoomEncryptedEventContent> for EncryptedMessage {
             #[allow(deprecated)]
             EncryptedEventScheme::MegolmV1AesSha2(s) => {
                 let MegolmV1AesSha2Content { sender_key, device_id, session_id, .. } = s;
-                Self::MegolmV1AesSha2 { sender_key, device_id, session_id }
+                Self::MegolmV1AesSha2 { sender_key, device_id, session_id, cause }
             }
             _ => Self::Unknown,
         }
@@ -54,8 +54,11 @@ pub enum EventSendState {
         /// Details about how sending the event failed.
         error: Arc<Error>,
     },
-    /// Sending has been cancelled because an earlier event in the
-    /// message-sending queue failed.
+    /// Sending has been paused because an earlier event in the
+    /// message-sending queue failed with an unrecoverable error.
+    ///
+    /// The event will be sent once the failed event has been retried or
+    /// cancelled.
     Cancelled,
     /// The local event has been sent successfully to the server.
     Sent {
@@ -26,6 +26,8 @@ use ruma::{
 };
 use tracing::warn;
 
+use super::{EditHistoryEntry, ThreadSummary};
+
 mod content;
 mod local;
 mod reactions;
@@ -35,7 +37,7 @@ pub use self::{
     content::{
         AnyOtherFullStateEventContent, EncryptedMessage, InReplyToDetails, MemberProfileChange,
         MembershipChange, Message, OtherState, RepliedToEvent, RoomMembershipChange, Sticker,
-        TimelineItemContent,
+        TimelineItemContent, VoiceMessageInfo,
     },
     local::EventSendState,
     reactions::{BundledReactions, ReactionGroup},
@@ -139,12 +141,14 @@ impl EventTimelineItem {
         let event_kind = RemoteEventTimelineItem {
             event_id,
             reactions,
+            thread_summary: None,
             read_receipts,
             is_own,
             is_highlighted,
             encryption_info,
             original_json: Some(raw_sync_event),
             latest_edit_json,
+            edit_history: Vec::new(),
             origin,
         }
         .into();
@@ -260,6 +264,14 @@ impl EventTimelineItem {
         }
     }
 
+    /// Get the summary of the thread this item is the root of, if any.
+    ///
+    /// Only remote events can be thread roots, so this is always `None` for
+    /// local echoes.
+    pub fn thread_summary(&self) -> Option<&ThreadSummary> {
+        self.as_remote()?.thread_summary.as_ref()
+    }
+
     /// Get the read receipts of this item.
     ///
     /// The key is the ID of a room member and the value are details about the
@@ -362,6 +374,20 @@ impl EventTimelineItem {
         }
     }
 
+    /// Get the edits that have been applied to this item, from the oldest to
+    /// the most recent one.
+    ///
+    /// Only the edits that have been received by the timeline are included;
+    /// use [`Timeline::fetch_edit_history`] to fetch the older ones.
+    ///
+    /// [`Timeline::fetch_edit_history`]: super::Timeline::fetch_edit_history
+    pub fn edit_history(&self) -> &[EditHistoryEntry] {
+        match &self.kind {
+            EventTimelineItemKind::Local(_) => &[],
+            EventTimelineItemKind::Remote(remote_event) => &remote_event.edit_history,
+        }
+    }
+
     /// Shorthand for
     /// `item.latest_edit_json().or_else(|| item.original_json())`.
     pub fn latest_json(&self) -> Option<&Raw<AnySyncTimelineEvent>> {
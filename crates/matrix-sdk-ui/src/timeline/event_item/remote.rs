@@ -23,6 +23,7 @@ use ruma::{
 };
 
 use super::BundledReactions;
+use crate::timeline::{EditHistoryEntry, ThreadSummary};
 
 /// An item for an event that was received from the homeserver.
 #[derive(Clone)]
@@ -33,6 +34,9 @@ pub(in crate::timeline) struct RemoteEventTimelineItem {
     /// All bundled reactions about the event.
     pub reactions: BundledReactions,
 
+    /// The summary of the thread this event is the root of, if any.
+    pub thread_summary: Option<ThreadSummary>,
+
     /// All read receipts for the event.
     ///
     /// The key is the ID of a room member and the value are details about the
@@ -65,6 +69,10 @@ pub(in crate::timeline) struct RemoteEventTimelineItem {
     /// JSON of the latest edit to this item.
     pub latest_edit_json: Option<Raw<AnySyncTimelineEvent>>,
 
+    /// The edits that have been applied to this item, from the oldest to the
+    /// most recent one.
+    pub edit_history: Vec<EditHistoryEntry>,
+
     /// Where we got this event from: A sync response or pagination.
     pub origin: RemoteEventOrigin,
 }
@@ -75,6 +83,11 @@ impl RemoteEventTimelineItem {
         Self { reactions, ..self.clone() }
     }
 
+    /// Clone the current event item, and update its `thread_summary`.
+    pub fn with_thread_summary(&self, thread_summary: Option<ThreadSummary>) -> Self {
+        Self { thread_summary, ..self.clone() }
+    }
+
     /// Clone the current event item, and clear its `reactions` as well as the
     /// JSON representation fields.
     pub fn redact(&self) -> Self {
@@ -82,6 +95,7 @@ impl RemoteEventTimelineItem {
             reactions: BundledReactions::default(),
             original_json: None,
             latest_edit_json: None,
+            edit_history: Vec::new(),
             ..self.clone()
         }
     }
@@ -108,11 +122,13 @@ impl fmt::Debug for RemoteEventTimelineItem {
         let Self {
             event_id,
             reactions,
+            thread_summary,
             read_receipts,
             is_own,
             encryption_info,
             original_json: _,
             latest_edit_json: _,
+            edit_history,
             is_highlighted,
             origin,
         } = self;
@@ -120,10 +136,12 @@ impl fmt::Debug for RemoteEventTimelineItem {
         f.debug_struct("RemoteEventTimelineItem")
             .field("event_id", event_id)
             .field("reactions", reactions)
+            .field("thread_summary", thread_summary)
             .field("read_receipts", read_receipts)
             .field("is_own", is_own)
             .field("is_highlighted", is_highlighted)
             .field("encryption_info", encryption_info)
+            .field("edit_history", edit_history)
             .field("origin", origin)
             .finish_non_exhaustive()
     }
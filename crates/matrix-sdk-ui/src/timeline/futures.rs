@@ -1,9 +1,13 @@
-use std::{fs, future::IntoFuture, path::Path};
+use std::{fs, future::IntoFuture, path::Path, time::Duration};
 
 use eyeball::{SharedObservable, Subscriber};
-use matrix_sdk::{attachment::AttachmentConfig, TransmissionProgress};
+use matrix_sdk::{
+    attachment::{AttachmentConfig, AttachmentInfo, BaseAudioInfo},
+    TransmissionProgress,
+};
 use matrix_sdk_base::boxed_into_future;
 use mime::Mime;
+use ruma::UInt;
 use tracing::{Instrument as _, Span};
 
 use super::{Error, Timeline};
@@ -69,3 +73,67 @@ impl<'a> IntoFuture for SendAttachment<'a> {
         Box::pin(fut.instrument(tracing_span))
     }
 }
+
+pub struct SendVoiceMessage<'a> {
+    timeline: &'a Timeline,
+    body: String,
+    mime_type: Mime,
+    data: Vec<u8>,
+    config: AttachmentConfig,
+    tracing_span: Span,
+    pub(crate) send_progress: SharedObservable<TransmissionProgress>,
+}
+
+impl<'a> SendVoiceMessage<'a> {
+    pub(crate) fn new(
+        timeline: &'a Timeline,
+        body: String,
+        mime_type: Mime,
+        data: Vec<u8>,
+        duration: Duration,
+        waveform: Vec<u16>,
+    ) -> Self {
+        let audio_info =
+            BaseAudioInfo { duration: Some(duration), size: UInt::new(data.len() as u64) };
+        let config = AttachmentConfig::new()
+            .info(AttachmentInfo::Voice { audio_info, waveform: Some(waveform) });
+
+        Self {
+            timeline,
+            body,
+            mime_type,
+            data,
+            config,
+            tracing_span: Span::current(),
+            send_progress: Default::default(),
+        }
+    }
+
+    /// Get a subscriber to observe the progress of sending the request
+    /// body.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn subscribe_to_send_progress(&self) -> Subscriber<TransmissionProgress> {
+        self.send_progress.subscribe()
+    }
+}
+
+impl<'a> IntoFuture for SendVoiceMessage<'a> {
+    type Output = Result<(), Error>;
+    boxed_into_future!(extra_bounds: 'a);
+
+    fn into_future(self) -> Self::IntoFuture {
+        let Self { timeline, body, mime_type, data, config, tracing_span, send_progress } = self;
+        let fut = async move {
+            timeline
+                .room()
+                .send_attachment(&body, &mime_type, data, config)
+                .with_send_progress_observable(send_progress)
+                .await
+                .map_err(|_| Error::FailedSendingAttachment)?;
+
+            Ok(())
+        };
+
+        Box::pin(fut.instrument(tracing_span))
+    }
+}
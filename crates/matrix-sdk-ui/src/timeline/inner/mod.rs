@@ -13,18 +13,25 @@
 // limitations under the License.
 
 #[cfg(feature = "e2e-encryption")]
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::{fmt, sync::Arc};
 
 use as_variant::as_variant;
-use eyeball_im::{ObservableVectorEntry, VectorDiff};
+use eyeball_im::{ObservableVectorEntry, ObservableVectorTransaction, VectorDiff};
 use eyeball_im_util::vector::VectorObserverExt;
 use futures_core::Stream;
 use imbl::Vector;
 use itertools::Itertools;
 #[cfg(all(test, feature = "e2e-encryption"))]
 use matrix_sdk::crypto::OlmMachine;
-use matrix_sdk::{deserialized_responses::SyncTimelineEvent, Error, Result, Room};
+#[cfg(feature = "e2e-encryption")]
+use matrix_sdk::deserialized_responses::TimelineEvent;
+use matrix_sdk::{
+    deserialized_responses::SyncTimelineEvent,
+    room::RelationsOptions,
+    send_queue::{QueuedRequest, QueuedRequestKind, RoomSendQueueUpdate},
+    Error, Result, Room,
+};
 #[cfg(test)]
 use ruma::events::receipt::ReceiptEventContent;
 #[cfg(all(test, feature = "e2e-encryption"))]
@@ -36,13 +43,13 @@ use ruma::{
         poll::unstable_start::UnstablePollStartEventContent,
         reaction::ReactionEventContent,
         receipt::{Receipt, ReceiptThread, ReceiptType},
-        relation::Annotation,
+        relation::{Annotation, RelationType},
         room::{
             message::{MessageType, Relation},
             redaction::RoomRedactionEventContent,
         },
         AnyMessageLikeEventContent, AnySyncEphemeralRoomEvent, AnySyncMessageLikeEvent,
-        AnySyncTimelineEvent, MessageLikeEventType,
+        AnySyncStateEvent, AnySyncTimelineEvent, MessageLikeEventType, SyncStateEvent,
     },
     serde::Raw,
     EventId, OwnedEventId, OwnedTransactionId, RoomVersionId, TransactionId, UserId,
@@ -55,12 +62,13 @@ use tracing::{field, info_span, Instrument as _};
 #[cfg(feature = "e2e-encryption")]
 use super::traits::Decryptor;
 use super::{
+    edits::add_to_edit_history,
     event_item::EventItemIdentifier,
     reactions::ReactionToggleResult,
     traits::RoomDataProvider,
     util::{rfind_event_by_id, rfind_event_item, RelativePosition},
-    AnnotationKey, EventSendState, EventTimelineItem, InReplyToDetails, Message, Profile,
-    RepliedToEvent, TimelineDetails, TimelineItem, TimelineItemContent, TimelineItemKind,
+    AnnotationKey, EditHistoryEntry, EventSendState, EventTimelineItem, InReplyToDetails, Message,
+    Profile, RepliedToEvent, TimelineDetails, TimelineItem, TimelineItemContent, TimelineItemKind,
 };
 use crate::{
     timeline::{day_dividers::DayDividerAdjuster, TimelineEventFilterFn},
@@ -108,6 +116,11 @@ pub(super) struct TimelineInnerSettings {
     pub(super) event_filter: Arc<TimelineEventFilterFn>,
     /// Are unparsable events added as timeline items of their own kind?
     pub(super) add_failed_to_parse: bool,
+    /// If set, only the root of this thread and its replies are rendered as
+    /// timeline items.
+    pub(super) thread_root: Option<OwnedEventId>,
+    /// Are consecutive state events summarised by a virtual item?
+    pub(super) group_state_events: bool,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -116,6 +129,8 @@ impl fmt::Debug for TimelineInnerSettings {
         f.debug_struct("TimelineInnerSettings")
             .field("track_read_receipts", &self.track_read_receipts)
             .field("add_failed_to_parse", &self.add_failed_to_parse)
+            .field("thread_root", &self.thread_root)
+            .field("group_state_events", &self.group_state_events)
             .finish_non_exhaustive()
     }
 }
@@ -126,6 +141,8 @@ impl Default for TimelineInnerSettings {
             track_read_receipts: false,
             event_filter: Arc::new(default_event_filter),
             add_failed_to_parse: true,
+            thread_root: None,
+            group_state_events: false,
         }
     }
 }
@@ -193,6 +210,7 @@ pub fn default_event_filter(event: &AnySyncTimelineEvent, room_version: &RoomVer
                             UnstablePollStartEventContent::New(_),
                         )
                         | AnyMessageLikeEventContent::CallInvite(_)
+                        | AnyMessageLikeEventContent::Location(_)
                         | AnyMessageLikeEventContent::RoomEncrypted(_) => true,
 
                         _ => false,
@@ -201,6 +219,14 @@ pub fn default_event_filter(event: &AnySyncTimelineEvent, room_version: &RoomVer
             }
         }
 
+        AnySyncTimelineEvent::State(AnySyncStateEvent::BeaconInfo(SyncStateEvent::Original(
+            ev,
+        ))) => {
+            // Only the start of a live location share is displayed, its end updates the
+            // existing item.
+            ev.content.live
+        }
+
         AnySyncTimelineEvent::State(_) => {
             // All the state events may get displayed by default.
             true
@@ -223,6 +249,14 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     }
 
     pub(super) fn with_settings(mut self, settings: TimelineInnerSettings) -> Self {
+        // The grouping of state events happens when committing changes to the state,
+        // so it needs to know about it.
+        Arc::get_mut(&mut self.state)
+            .expect("the state must not be shared before the settings are applied")
+            .get_mut()
+            .meta
+            .group_state_events = settings.group_state_events;
+
         self.settings = settings;
         self
     }
@@ -484,6 +518,84 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         state.handle_local_redaction(sender, profile, txn_id, to_redact, content);
     }
 
+    /// Add a local echo for a request of the room's send queue, unless there's
+    /// one for it already.
+    ///
+    /// Only message-like events are rendered as local echoes; reactions are
+    /// handled separately.
+    pub(super) async fn handle_queued_request(&self, request: QueuedRequest) {
+        let QueuedRequest { transaction_id, kind } = request;
+
+        let QueuedRequestKind::Event { event_type, content } = kind else {
+            return;
+        };
+
+        if event_type == MessageLikeEventType::Reaction.to_string() {
+            return;
+        }
+
+        let content = match content.deserialize_with_type(event_type.as_str().into()) {
+            Ok(content) => content,
+            Err(err) => {
+                warn!(%transaction_id, "Couldn't deserialize the content of a queued event: {err}");
+                return;
+            }
+        };
+
+        let sender = self.room_data_provider.own_user_id().to_owned();
+        let profile = self.room_data_provider.profile_from_user_id(&sender).await;
+
+        let mut state = self.state.write().await;
+
+        if rfind_event_item(&state.items, |it| it.transaction_id() == Some(&transaction_id))
+            .is_some()
+        {
+            trace!(%transaction_id, "Local echo already present");
+            return;
+        }
+
+        state.handle_local_event(sender, profile, transaction_id, content);
+    }
+
+    /// Reflect an update of the room's send queue on the matching local echo.
+    pub(super) async fn handle_send_queue_update(&self, update: RoomSendQueueUpdate) {
+        match update {
+            RoomSendQueueUpdate::NewLocalEvent(request) => {
+                self.handle_queued_request(request).await;
+            }
+
+            RoomSendQueueUpdate::CancelledLocalEvent { transaction_id } => {
+                self.discard_local_echo(&transaction_id).await;
+            }
+
+            RoomSendQueueUpdate::SendError { transaction_id, error, is_recoverable } => {
+                self.update_event_send_state(
+                    &transaction_id,
+                    EventSendState::SendingFailed { error },
+                )
+                .await;
+
+                if !is_recoverable {
+                    // The send queue doesn't send anything until the failed event is retried
+                    // or cancelled, which should be reflected in the timeline.
+                    let mut state = self.state.write().await;
+                    let mut txn = state.items.transaction();
+                    replace_local_echoes_send_state(
+                        &mut txn,
+                        |send_state| matches!(send_state, EventSendState::NotSentYet),
+                        EventSendState::Cancelled,
+                    );
+                    txn.commit();
+                }
+            }
+
+            RoomSendQueueUpdate::SentEvent { transaction_id, event_id } => {
+                self.update_event_send_state(&transaction_id, EventSendState::Sent { event_id })
+                    .await;
+            }
+        }
+    }
+
     /// Update the send state of a local event represented by a transaction ID.
     ///
     /// If no local event is found, a warning is raised.
@@ -553,29 +665,9 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             error!(?existing_event_id, ?new_event_id, "Local echo already marked as sent");
         }
 
-        let is_error = matches!(send_state, EventSendState::SendingFailed { .. });
-
         let new_item = item.with_inner_kind(local_item.with_send_state(send_state));
         txn.items.set(idx, new_item);
 
-        if is_error {
-            // When there is an error, sending further messages is paused. This
-            // should be reflected in the timeline, so we set all other pending
-            // events to cancelled.
-            let items = &mut txn.items;
-            let num_items = items.len();
-            for idx in 0..num_items {
-                let item = &items[idx];
-                let Some(event_item) = item.as_event() else { continue };
-                let Some(local_item) = event_item.as_local() else { continue };
-                if matches!(&local_item.send_state, EventSendState::NotSentYet) {
-                    let new_event_item =
-                        event_item.with_kind(local_item.with_send_state(EventSendState::Cancelled));
-                    items.set(idx, item.with_kind(new_event_item));
-                }
-            }
-        }
-
         txn.commit();
     }
 
@@ -659,12 +751,19 @@ impl<P: RoomDataProvider> TimelineInner<P> {
             EventSendState::SendingFailed { .. } | EventSendState::Cancelled => {}
         }
 
+        let is_failed = matches!(local_item.send_state, EventSendState::SendingFailed { .. });
         let new_item = item.with_inner_kind(local_item.with_send_state(EventSendState::NotSentYet));
         let content = item.content.clone();
 
+        // The request keeps its position in the send queue, so does its local echo.
         let mut txn = state.items.transaction();
-        txn.remove(idx);
-        txn.push_back(new_item);
+        txn.set(idx, new_item);
+
+        if is_failed {
+            // Retrying the failed event resumes the send queue.
+            resume_cancelled_local_echoes(&mut txn);
+        }
+
         txn.commit();
 
         Some(content)
@@ -673,10 +772,20 @@ impl<P: RoomDataProvider> TimelineInner<P> {
     pub(super) async fn discard_local_echo(&self, txn_id: &TransactionId) -> bool {
         let mut state = self.state.write().await;
 
-        if let Some((idx, _)) =
+        if let Some((idx, item)) =
             rfind_event_item(&state.items, |it| it.transaction_id() == Some(txn_id))
         {
-            state.items.remove(idx);
+            let is_failed = matches!(item.send_state(), Some(EventSendState::SendingFailed { .. }));
+
+            let mut txn = state.items.transaction();
+            txn.remove(idx);
+
+            if is_failed {
+                // Discarding the failed event resumes the send queue.
+                resume_cancelled_local_echoes(&mut txn);
+            }
+
+            txn.commit();
             debug!("Discarded local echo");
             true
         } else {
@@ -825,6 +934,73 @@ impl<P: RoomDataProvider> TimelineInner<P> {
         });
     }
 
+    /// Replace the items of the events that couldn't be decrypted with the
+    /// given events, that have been decrypted in the meantime.
+    #[cfg(feature = "e2e-encryption")]
+    #[instrument(skip_all)]
+    pub(super) async fn handle_decrypted_events(&self, events: Vec<SyncTimelineEvent>) {
+        let events_by_id = events
+            .into_iter()
+            .filter_map(|event| Some((event.event_id()?, event)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut state = self.state.write().await;
+
+        let retry_indices: Vec<_> = state
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, item)| {
+                let event_item = item.as_event()?;
+                event_item.content().as_unable_to_decrypt()?;
+                events_by_id.contains_key(event_item.event_id()?).then_some(idx)
+            })
+            .collect();
+
+        if retry_indices.is_empty() {
+            return;
+        }
+
+        debug!("Replacing decrypted events");
+
+        let settings = self.settings.clone();
+        let room_data_provider = self.room_data_provider.clone();
+        let push_rules_context = room_data_provider.push_rules_and_context().await;
+        let unable_to_decrypt_hook = state.meta.unable_to_decrypt_hook.clone();
+
+        let retry_one = |item: Arc<TimelineItem>| {
+            let event_id = item.as_event().and_then(|event_item| event_item.event_id());
+            let event = event_id.and_then(|event_id| events_by_id.get(event_id)).cloned();
+            let unable_to_decrypt_hook = unable_to_decrypt_hook.clone();
+
+            async move {
+                let event = event?;
+
+                // Notify observers that the event has eventually been decrypted.
+                if let (Some(hook), Some(event_id)) = (unable_to_decrypt_hook, event.event_id()) {
+                    hook.on_late_decrypt(&event_id);
+                }
+
+                Some(TimelineEvent {
+                    event: event.event.cast(),
+                    encryption_info: event.encryption_info,
+                    push_actions: None,
+                    utd_cause: None,
+                })
+            }
+        };
+
+        state
+            .retry_event_decryption(
+                retry_one,
+                retry_indices,
+                push_rules_context,
+                &room_data_provider,
+                &settings,
+            )
+            .await;
+    }
+
     pub(super) async fn set_sender_profiles_pending(&self) {
         self.set_non_ready_sender_profiles(TimelineDetails::Pending).await;
     }
@@ -1055,6 +1231,77 @@ impl TimelineInner {
         Ok(())
     }
 
+    /// Fetch all the edits of the given event with the `/relations` endpoint,
+    /// and add them to the edit history of its timeline item.
+    pub(super) async fn fetch_edit_history(&self, event_id: &EventId) -> Result<(), super::Error> {
+        let sender = {
+            let state = self.state.read().await;
+            let (_, item) = rfind_event_by_id(&state.items, event_id)
+                .ok_or(super::Error::RemoteEventNotInTimeline)?;
+            item.as_remote().ok_or(super::Error::RemoteEventNotInTimeline)?;
+            item.sender().to_owned()
+        };
+
+        let mut entries = Vec::new();
+        let mut from = None;
+
+        loop {
+            let mut options = RelationsOptions::with_rel_type(RelationType::Replacement);
+            options.from = from;
+
+            let relations = self
+                .room()
+                .relations(event_id, options)
+                .await
+                .map_err(super::Error::FailedToFetchEditHistory)?;
+
+            for event in relations.chunk {
+                let Ok(event) = event.event.deserialize_as::<AnySyncTimelineEvent>() else {
+                    continue;
+                };
+
+                // Edits from other users are ignored, see `handle_room_message_edit`.
+                match EditHistoryEntry::from_timeline_event(&event) {
+                    Some((replaced_event_id, entry))
+                        if replaced_event_id == event_id && entry.sender() == sender =>
+                    {
+                        entries.push(entry);
+                    }
+                    _ => {}
+                }
+            }
+
+            match relations.next_batch {
+                Some(next_batch) => from = Some(next_batch),
+                None => break,
+            }
+        }
+
+        trace!("Fetched {} edits", entries.len());
+
+        // The item might have moved while waiting for the requests.
+        let mut state = self.state.write().await;
+        let (index, item) = rfind_event_by_id(&state.items, event_id)
+            .ok_or(super::Error::RemoteEventNotInTimeline)?;
+
+        let internal_id = item.internal_id;
+        let mut item = item.clone();
+        let Some(remote) = item.as_remote_mut() else {
+            return Ok(());
+        };
+
+        let mut updated = false;
+        for entry in entries {
+            updated |= add_to_edit_history(&mut remote.edit_history, entry);
+        }
+
+        if updated {
+            state.items.set(index, TimelineItem::new(item, internal_id));
+        }
+
+        Ok(())
+    }
+
     /// Check whether the given receipt should be sent.
     ///
     /// Returns `false` if the given receipt is older than the current one.
@@ -1180,3 +1427,33 @@ async fn fetch_replied_to_event(
     };
     Ok(res)
 }
+
+/// Replace the send state of the local echoes whose send state matches
+/// `predicate` with `send_state`.
+fn replace_local_echoes_send_state(
+    items: &mut ObservableVectorTransaction<'_, Arc<TimelineItem>>,
+    predicate: impl Fn(&EventSendState) -> bool,
+    send_state: EventSendState,
+) {
+    for idx in 0..items.len() {
+        let item = &items[idx];
+        let Some(event_item) = item.as_event() else { continue };
+        let Some(local_item) = event_item.as_local() else { continue };
+
+        if predicate(&local_item.send_state) {
+            let new_event_item =
+                event_item.with_kind(local_item.with_send_state(send_state.clone()));
+            items.set(idx, item.with_kind(new_event_item));
+        }
+    }
+}
+
+/// Mark the local echoes that were cancelled because an earlier event failed
+/// to be sent as not sent yet, since the send queue is going to send them.
+fn resume_cancelled_local_echoes(items: &mut ObservableVectorTransaction<'_, Arc<TimelineItem>>) {
+    replace_local_echoes_send_state(
+        items,
+        |send_state| matches!(send_state, EventSendState::Cancelled),
+        EventSendState::NotSentYet,
+    );
+}
@@ -15,6 +15,7 @@
 use std::{collections::VecDeque, future::Future, sync::Arc};
 
 use eyeball_im::{ObservableVector, ObservableVectorTransaction, ObservableVectorTransactionEntry};
+use imbl::Vector;
 use indexmap::IndexMap;
 use matrix_sdk::deserialized_responses::SyncTimelineEvent;
 use matrix_sdk_base::deserialized_responses::TimelineEvent;
@@ -23,7 +24,8 @@ use ruma::events::receipt::ReceiptEventContent;
 use ruma::{
     events::{
         relation::Annotation, room::redaction::RoomRedactionEventContent,
-        AnyMessageLikeEventContent, AnySyncEphemeralRoomEvent,
+        AnyMessageLikeEventContent, AnySyncEphemeralRoomEvent, AnySyncMessageLikeEvent,
+        AnySyncTimelineEvent,
     },
     push::Action,
     serde::Raw,
@@ -42,9 +44,12 @@ use crate::{
             TimelineItemPosition,
         },
         event_item::EventItemIdentifier,
+        location::LiveLocationPendingEvents,
         polls::PollPendingEvents,
         reactions::{ReactionToggleResult, Reactions},
         read_receipts::ReadReceipts,
+        state_event_groups::group_state_events,
+        threads::is_event_in_thread,
         traits::RoomDataProvider,
         util::{rfind_event_by_id, rfind_event_item, RelativePosition},
         AnnotationKey, Error as TimelineError, Profile, ReactionSenderData, TimelineItem,
@@ -411,9 +416,12 @@ impl TimelineInnerState {
     }
 
     pub(super) fn transaction(&mut self) -> TimelineInnerStateTransaction<'_> {
+        // Only the grouping of state events needs to know what changed in the
+        // transaction. Cloning the items is cheap anyway.
+        let previous_items = self.meta.group_state_events.then(|| (*self.items).clone());
         let items = self.items.transaction();
         let meta = self.meta.clone();
-        TimelineInnerStateTransaction { items, previous_meta: &mut self.meta, meta }
+        TimelineInnerStateTransaction { items, previous_items, previous_meta: &mut self.meta, meta }
     }
 }
 
@@ -427,6 +435,10 @@ pub(in crate::timeline) struct TimelineInnerStateTransaction<'a> {
     /// [`Self::commit`].
     pub meta: TimelineInnerMetadata,
 
+    /// The items before the transaction, if state events are grouped, only used
+    /// during [`Self::commit`].
+    previous_items: Option<Vector<Arc<TimelineItem>>>,
+
     /// Pointer to the previous meta, only used during [`Self::commit`].
     previous_meta: &'a mut TimelineInnerMetadata,
 }
@@ -486,7 +498,38 @@ impl TimelineInnerStateTransaction<'_> {
         {
             Ok(event) => {
                 let room_version = room_data_provider.room_version();
-                let should_add = (settings.event_filter)(&event, &room_version);
+
+                let is_in_focus = settings
+                    .thread_root
+                    .as_deref()
+                    .map_or(true, |thread_root| is_event_in_thread(&event, thread_root));
+
+                if !is_in_focus
+                    && matches!(
+                        event,
+                        AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+                            _
+                        ))
+                    )
+                {
+                    // Encrypted events are always added to the timeline, so that they can be
+                    // decrypted later; but this one isn't part of the thread the timeline is
+                    // focused on, so it can't contribute anything to it.
+                    trace!("Ignoring encrypted event outside of the focused thread");
+
+                    let event_meta = FullEventMeta {
+                        event_id: event.event_id(),
+                        sender: Some(event.sender()),
+                        is_own_event: event.sender() == room_data_provider.own_user_id(),
+                        timestamp: Some(event.origin_server_ts()),
+                        visible: false,
+                    };
+                    self.add_event(event_meta, position, room_data_provider, settings).await;
+
+                    return HandleEventResult::default();
+                }
+
+                let should_add = is_in_focus && (settings.event_filter)(&event, &room_version);
                 (
                     event.event_id().to_owned(),
                     event.sender().to_owned(),
@@ -587,6 +630,7 @@ impl TimelineInnerStateTransaction<'_> {
                 txn_id,
                 position,
                 should_add,
+                utd_cause: event.utd_cause,
             },
         };
 
@@ -646,7 +690,11 @@ impl TimelineInnerStateTransaction<'_> {
     }
 
     pub(super) fn commit(self) {
-        let Self { items, previous_meta, meta } = self;
+        let Self { mut items, previous_items, previous_meta, mut meta } = self;
+
+        if let Some(previous_items) = previous_items {
+            group_state_events(&mut items, &previous_items, &mut meta);
+        }
 
         // Replace the pointer to the previous meta with the new one.
         *previous_meta = meta;
@@ -717,6 +765,7 @@ pub(in crate::timeline) struct TimelineInnerMetadata {
 
     pub reactions: Reactions,
     pub poll_pending_events: PollPendingEvents,
+    pub live_location_pending_events: LiveLocationPendingEvents,
     pub fully_read_event: Option<OwnedEventId>,
 
     /// Whether we have a fully read-marker item in the timeline, that's up to
@@ -739,6 +788,12 @@ pub(in crate::timeline) struct TimelineInnerMetadata {
 
     /// Matrix room version of the timeline's room, or a sensible default.
     pub room_version: RoomVersionId,
+
+    /// Whether consecutive state events should be summarised by a
+    /// [`VirtualTimelineItem::CollapsedStateEvents`] item.
+    ///
+    /// [`VirtualTimelineItem::CollapsedStateEvents`]: crate::timeline::VirtualTimelineItem::CollapsedStateEvents
+    pub group_state_events: bool,
 }
 
 impl TimelineInnerMetadata {
@@ -751,6 +806,7 @@ impl TimelineInnerMetadata {
             next_internal_id: Default::default(),
             reactions: Default::default(),
             poll_pending_events: Default::default(),
+            live_location_pending_events: Default::default(),
             fully_read_event: Default::default(),
             // It doesn't make sense to set this to false until we fill the `fully_read_event`
             // field, otherwise we'll keep on exiting early in `Self::update_read_marker`.
@@ -760,6 +816,7 @@ impl TimelineInnerMetadata {
             in_flight_reaction: Default::default(),
             room_version,
             unable_to_decrypt_hook,
+            group_state_events: false,
         }
     }
 
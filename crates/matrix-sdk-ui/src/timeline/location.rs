@@ -0,0 +1,224 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module handles rendering of MSC3488 locations and MSC3489 live location
+//! shares in the timeline.
+
+use std::{collections::HashMap, time::Duration};
+
+use ruma::{
+    events::{
+        beacon::BeaconEventContent,
+        beacon_info::BeaconInfoEventContent,
+        location::{AssetType, LocationContent, LocationEventContent},
+    },
+    EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, UserId,
+};
+
+/// An `m.location` event, sharing a static location.
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub(super) content: LocationEventContent,
+}
+
+impl Location {
+    /// The textual representation of the location.
+    pub fn body(&self) -> Option<&str> {
+        self.content.text.find_plain()
+    }
+
+    /// The location, as a `geo:` URI.
+    pub fn geo_uri(&self) -> &str {
+        &self.content.location.uri
+    }
+
+    /// The description of the location, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.content.location.description.as_deref()
+    }
+
+    /// What the location represents, e.g. the location of the sender
+    /// themselves, or of a pin they dropped on a map.
+    pub fn asset_type(&self) -> &AssetType {
+        &self.content.asset.type_
+    }
+
+    /// The time at which the location was determined, if any.
+    pub fn ts(&self) -> Option<MilliSecondsSinceUnixEpoch> {
+        self.content.ts
+    }
+}
+
+/// A location shared as part of a live location share.
+#[derive(Clone, Debug)]
+pub struct BeaconLocation {
+    location: LocationContent,
+    ts: MilliSecondsSinceUnixEpoch,
+}
+
+impl BeaconLocation {
+    /// The location, as a `geo:` URI.
+    pub fn geo_uri(&self) -> &str {
+        &self.location.uri
+    }
+
+    /// The description of the location, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.location.description.as_deref()
+    }
+
+    /// The time at which the location was determined.
+    pub fn ts(&self) -> MilliSecondsSinceUnixEpoch {
+        self.ts
+    }
+}
+
+impl From<BeaconEventContent> for BeaconLocation {
+    fn from(content: BeaconEventContent) -> Self {
+        Self { location: content.location, ts: content.ts }
+    }
+}
+
+/// Holds the state of a live location share.
+///
+/// This struct is created for each `beacon_info` state event starting a live
+/// location share, and then updated whenever handling a `beacon` event sharing
+/// a location for it, or a `beacon_info` state event stopping it.
+#[derive(Clone, Debug)]
+pub struct LiveLocationState {
+    pub(super) beacon_info: BeaconInfoEventContent,
+    pub(super) latest_location: Option<BeaconLocation>,
+}
+
+impl LiveLocationState {
+    pub(super) fn new(beacon_info: BeaconInfoEventContent) -> Self {
+        Self { beacon_info, latest_location: None }
+    }
+
+    /// Returns a copy of this state with the given location, unless a more
+    /// recent one has been shared already.
+    pub(super) fn add_location(&self, location: BeaconLocation) -> Option<Self> {
+        if self.latest_location.as_ref().is_some_and(|latest| latest.ts >= location.ts) {
+            return None;
+        }
+
+        let mut clone = self.clone();
+        clone.latest_location = Some(location);
+        Some(clone)
+    }
+
+    /// Returns a copy of this state marked as stopped.
+    ///
+    /// If the share has already been stopped, returns `None`.
+    pub(super) fn stop(&self) -> Option<Self> {
+        if !self.beacon_info.live {
+            return None;
+        }
+
+        let mut clone = self.clone();
+        clone.beacon_info.stop();
+        Some(clone)
+    }
+
+    /// The description of the live location share, if any.
+    pub fn description(&self) -> Option<&str> {
+        self.beacon_info.description.as_deref()
+    }
+
+    /// Whether the location is still being shared, i.e. the share hasn't been
+    /// stopped and hasn't expired.
+    pub fn is_live(&self) -> bool {
+        self.beacon_info.is_live()
+    }
+
+    /// When the live location share started.
+    pub fn start_ts(&self) -> MilliSecondsSinceUnixEpoch {
+        self.beacon_info.ts
+    }
+
+    /// How long the location is shared for, from [`Self::start_ts`].
+    pub fn timeout(&self) -> Duration {
+        self.beacon_info.timeout
+    }
+
+    /// The most recent location shared, if any.
+    pub fn latest_location(&self) -> Option<&BeaconLocation> {
+        self.latest_location.as_ref()
+    }
+}
+
+/// Acts as a cache for beacon events and `beacon_info` state events stopping a
+/// live location share, handled before the `beacon_info` state event starting
+/// the share has been handled.
+///
+/// This happens notably with back-pagination.
+#[derive(Clone, Debug, Default)]
+pub(super) struct LiveLocationPendingEvents {
+    /// The most recent location of the shares, by ID of their `beacon_info`
+    /// state event and by sender of the beacons.
+    ///
+    /// Only the beacons of the sender of the `beacon_info` state event apply to
+    /// the share, but the latter is not known yet.
+    pending_locations: HashMap<OwnedEventId, HashMap<OwnedUserId, BeaconLocation>>,
+
+    /// The users whose live location share has been stopped.
+    pending_stops: HashMap<OwnedUserId, MilliSecondsSinceUnixEpoch>,
+}
+
+impl LiveLocationPendingEvents {
+    pub(super) fn add_location(
+        &mut self,
+        beacon_info_id: &EventId,
+        sender: &UserId,
+        location: BeaconLocation,
+    ) {
+        let locations = self.pending_locations.entry(beacon_info_id.to_owned()).or_default();
+
+        match locations.get(sender) {
+            Some(latest) if latest.ts >= location.ts => {}
+            _ => {
+                locations.insert(sender.to_owned(), location);
+            }
+        }
+    }
+
+    pub(super) fn add_stop(&mut self, user_id: &UserId, timestamp: MilliSecondsSinceUnixEpoch) {
+        self.pending_stops.insert(user_id.to_owned(), timestamp);
+    }
+
+    /// Applies the pending events that belong to the given `beacon_info` state
+    /// event, sent by the given user at the given time, to the given state.
+    pub(super) fn apply(
+        &mut self,
+        beacon_info_id: &EventId,
+        user_id: &UserId,
+        timestamp: MilliSecondsSinceUnixEpoch,
+        state: &mut LiveLocationState,
+    ) {
+        // Beacons from other users than the one sharing their location are dropped.
+        if let Some(location) = self
+            .pending_locations
+            .remove(beacon_info_id)
+            .and_then(|mut locations| locations.remove(user_id))
+        {
+            state.latest_location = Some(location);
+        }
+
+        // Only a stop sent after the start of this share can apply to it.
+        if self.pending_stops.get(user_id).is_some_and(|stop_ts| *stop_ts >= timestamp) {
+            self.pending_stops.remove(user_id);
+            state.beacon_info.stop();
+        }
+    }
+}
@@ -16,18 +16,26 @@
 //!
 //! See [`Timeline`] for details.
 
-use std::{pin::Pin, sync::Arc, task::Poll};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::Duration,
+};
 
+use as_variant::as_variant;
 use eyeball::{SharedObservable, Subscriber};
 use eyeball_im::VectorDiff;
 use futures_core::Stream;
 use imbl::Vector;
 use matrix_sdk::{
     attachment::AttachmentConfig,
+    deserialized_responses::SyncTimelineEvent,
     event_cache::{EventCacheDropHandles, RoomEventCache},
     event_handler::EventHandlerHandle,
     executor::JoinHandle,
     room::{Receipts, Room},
+    send_queue::{QueuedRequest, QueuedRequestKind},
     Client, Result,
 };
 use matrix_sdk_base::RoomState;
@@ -42,27 +50,30 @@ use ruma::{
         },
         reaction::ReactionEventContent,
         receipt::{Receipt, ReceiptThread},
-        relation::Annotation,
+        relation::{Annotation, Thread},
         room::{
             message::{
-                AddMentions, ForwardThread, OriginalRoomMessageEvent, ReplacementMetadata,
-                RoomMessageEventContent, RoomMessageEventContentWithoutRelation,
+                AddMentions, ForwardThread, OriginalRoomMessageEvent, Relation,
+                ReplacementMetadata, RoomMessageEventContent,
+                RoomMessageEventContentWithoutRelation,
             },
             redaction::RoomRedactionEventContent,
         },
-        AnyMessageLikeEventContent, AnySyncTimelineEvent,
+        AnyMessageLikeEventContent, AnySyncTimelineEvent, EventContent as _,
     },
+    serde::Raw,
     uint, EventId, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedTransactionId, RoomVersionId,
     TransactionId, UserId,
 };
 use thiserror::Error;
-use tokio::sync::mpsc::Sender;
+use tokio::sync::Mutex as AsyncMutex;
 use tracing::{debug, error, instrument, trace, warn};
 
-use self::futures::SendAttachment;
+use self::futures::{SendAttachment, SendVoiceMessage};
 
 mod builder;
 mod day_dividers;
+mod edits;
 mod error;
 mod event_handler;
 mod event_item;
@@ -70,14 +81,17 @@ pub mod event_type_filter;
 pub mod futures;
 mod inner;
 mod item;
+mod location;
 mod pagination;
+mod pinned_events_loader;
 mod polls;
-mod queue;
 mod reactions;
 mod read_receipts;
 mod sliding_sync_ext;
+mod state_event_groups;
 #[cfg(test)]
 mod tests;
+mod threads;
 #[cfg(feature = "e2e-encryption")]
 mod to_device;
 mod traits;
@@ -86,26 +100,30 @@ mod virtual_item;
 
 pub use self::{
     builder::TimelineBuilder,
+    edits::EditHistoryEntry,
     error::{Error, UnsupportedEditItem, UnsupportedReplyItem},
     event_item::{
         AnyOtherFullStateEventContent, BundledReactions, EncryptedMessage, EventItemOrigin,
         EventSendState, EventTimelineItem, InReplyToDetails, MemberProfileChange, MembershipChange,
         Message, OtherState, Profile, ReactionGroup, RepliedToEvent, RoomMembershipChange, Sticker,
-        TimelineDetails, TimelineItemContent,
+        TimelineDetails, TimelineItemContent, VoiceMessageInfo,
     },
     event_type_filter::TimelineEventTypeFilter,
     inner::default_event_filter,
     item::{TimelineItem, TimelineItemKind},
-    pagination::{BackPaginationStatus, PaginationOptions, PaginationOutcome},
+    location::{BeaconLocation, LiveLocationState, Location},
+    pagination::{
+        BackPaginationStatus, ForwardPaginationStatus, PaginationOptions, PaginationOutcome,
+    },
     polls::PollResult,
     reactions::ReactionSenderData,
     sliding_sync_ext::SlidingSyncRoomExt,
+    threads::ThreadSummary,
     traits::RoomExt,
-    virtual_item::VirtualTimelineItem,
+    virtual_item::{CollapsedStateEvents, VirtualTimelineItem},
 };
 use self::{
     inner::{ReactionAction, TimelineInner},
-    queue::LocalMessage,
     reactions::ReactionToggleResult,
     util::rfind_event_by_id,
 };
@@ -127,14 +145,84 @@ pub struct Timeline {
     /// Observable for whether a pagination is currently running
     back_pagination_status: SharedObservable<BackPaginationStatus>,
 
-    /// A sender to the task which responsibility is to send messages to the
-    /// current room.
-    msg_sender: Sender<LocalMessage>,
+    /// Observable for whether a forward pagination is currently running, or
+    /// whether the timeline has caught up with the live events.
+    forward_pagination_status: SharedObservable<ForwardPaginationStatus>,
+
+    /// What the timeline is focused on.
+    focus: TimelineFocus,
+
+    /// The token to continue back-paginating a timeline that isn't focused on
+    /// the live events from, if any.
+    back_pagination_token: Mutex<Option<String>>,
+
+    /// The token to continue paginating an event-focused timeline forwards
+    /// from, if any.
+    forward_pagination_token: Mutex<Option<String>>,
+
+    /// The live events received while an event-focused timeline hasn't caught
+    /// up with the end of the room's timeline yet, or `None` once it has.
+    pending_live_events: Arc<AsyncMutex<Option<Vec<SyncTimelineEvent>>>>,
 
     /// References to long-running tasks held by the timeline.
     drop_handle: Arc<TimelineDropHandle>,
 }
 
+/// What should the timeline focus on?
+#[derive(Clone, Debug, Default)]
+pub enum TimelineFocus {
+    /// Focus on live events, i.e. the events received via sync, and the
+    /// room's history when paginating backwards.
+    #[default]
+    Live,
+
+    /// Focus on a thread: only the thread root and its replies are shown.
+    ///
+    /// The replies are loaded with the `/relations` endpoint when paginating
+    /// backwards, and the thread root is added once all of them have been
+    /// loaded. New replies received via sync are added live.
+    Thread {
+        /// The event ID of the thread root.
+        root_event_id: OwnedEventId,
+    },
+
+    /// Focus on a specific event, e.g. after following a permalink.
+    ///
+    /// The timeline starts with the target event and the events around it,
+    /// loaded with the `/context` endpoint. It can then be paginated in both
+    /// directions; once forward pagination reaches the end of the room's
+    /// timeline, events received via sync are added to it like in a live
+    /// timeline.
+    Event {
+        /// The event ID of the event to focus on.
+        target: OwnedEventId,
+
+        /// The maximum number of events to load around the target event,
+        /// split between the events before and after it.
+        num_context_events: u16,
+    },
+
+    /// Focus on the pinned events of the room (see
+    /// [`Room::pinned_event_ids`]).
+    ///
+    /// The pinned events are loaded with [`Room::event`], and reloaded every
+    /// time the `m.room.pinned_events` state event changes. The edits,
+    /// reactions and redactions of the pinned events received via sync are
+    /// applied live. This timeline can't be paginated.
+    PinnedEvents {
+        /// The maximum number of pinned events to load, starting from the
+        /// most recently pinned one.
+        max_events_to_load: u16,
+    },
+}
+
+impl TimelineFocus {
+    /// The root of the thread this timeline is focused on, if any.
+    fn thread_root(&self) -> Option<&EventId> {
+        as_variant!(self, Self::Thread { root_event_id } => root_event_id)
+    }
+}
+
 // Implements hash etc
 #[derive(Clone, Hash, PartialEq, Eq, Debug)]
 struct AnnotationKey {
@@ -159,6 +247,11 @@ impl Timeline {
         self.inner.room()
     }
 
+    /// Returns what this timeline is focused on.
+    pub fn focus(&self) -> &TimelineFocus {
+        &self.focus
+    }
+
     /// Clear all timeline items.
     pub async fn clear(&self) {
         self.inner.clear().await;
@@ -169,6 +262,15 @@ impl Timeline {
         self.back_pagination_status.subscribe()
     }
 
+    /// Subscribe to the forward-pagination status of the timeline.
+    ///
+    /// This is only ever different from
+    /// [`ForwardPaginationStatus::TimelineEndReached`] for a timeline focused
+    /// on an event that hasn't caught up with the live events yet.
+    pub fn forward_pagination_status(&self) -> Subscriber<ForwardPaginationStatus> {
+        self.forward_pagination_status.subscribe()
+    }
+
     /// Retry decryption of previously un-decryptable events given a list of
     /// session IDs whose keys have been imported.
     ///
@@ -264,6 +366,9 @@ impl Timeline {
     /// If sending the message fails, the local echo item will change its
     /// `send_state` to [`EventSendState::SendingFailed`].
     ///
+    /// If the timeline is focused on a thread, room messages that aren't
+    /// already part of a thread or an edit are sent in that thread.
+    ///
     /// # Arguments
     ///
     /// * `content` - The content of the message event.
@@ -272,10 +377,40 @@ impl Timeline {
     /// [`SyncMessageLikeEvent`]: ruma::events::SyncMessageLikeEvent
     #[instrument(skip(self, content), fields(room_id = ?self.room().room_id()))]
     pub async fn send(&self, content: AnyMessageLikeEventContent) {
+        let content = self.in_focused_thread(content).await;
         let txn_id = TransactionId::new();
         self.inner.handle_local_event(txn_id.clone(), content.clone()).await;
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
-            error!("Internal error: timeline message receiver is closed");
+        self.queue_local_event(txn_id, content).await;
+    }
+
+    /// Push a local event, whose local echo is already in the timeline, to the
+    /// room's send queue.
+    async fn queue_local_event(
+        &self,
+        txn_id: OwnedTransactionId,
+        content: AnyMessageLikeEventContent,
+    ) {
+        let event_type = content.event_type().to_string();
+
+        let result = match Raw::new(&content) {
+            Ok(content) => {
+                let request = QueuedRequest {
+                    transaction_id: txn_id.clone(),
+                    kind: QueuedRequestKind::Event { event_type, content: content.cast() },
+                };
+                self.room().send_queue().push(request).await.map(|_| ())
+            }
+            Err(err) => Err(err.into()),
+        };
+
+        if let Err(error) = result {
+            error!("Couldn't queue the local event: {error}");
+            self.inner
+                .update_event_send_state(
+                    &txn_id,
+                    EventSendState::SendingFailed { error: Arc::new(error) },
+                )
+                .await;
         }
     }
 
@@ -298,7 +433,8 @@ impl Timeline {
     ///
     /// * `forward_thread` - Usually `Yes`, unless you explicitly want to the
     ///   reply to show up in the main timeline even though the `reply_item` is
-    ///   part of a thread
+    ///   part of a thread. Ignored if the timeline is focused on a thread, in
+    ///   which case the reply is always sent in that thread.
     #[instrument(skip(self, content, reply_item))]
     pub async fn send_reply(
         &self,
@@ -346,6 +482,46 @@ impl Timeline {
         Ok(())
     }
 
+    /// Make sure the given content is sent in the thread this timeline is
+    /// focused on, if any.
+    ///
+    /// Room messages without a relation are sent as a new message at the end of
+    /// the thread, replies are turned into in-thread replies; other contents
+    /// are left untouched.
+    async fn in_focused_thread(
+        &self,
+        content: AnyMessageLikeEventContent,
+    ) -> AnyMessageLikeEventContent {
+        let Some(root_event_id) = self.focus.thread_root() else {
+            return content;
+        };
+        let AnyMessageLikeEventContent::RoomMessage(mut content) = content else {
+            return content;
+        };
+
+        match content.relates_to.take() {
+            None => {
+                let latest_event_id =
+                    self.inner.latest_event_id().await.unwrap_or_else(|| root_event_id.to_owned());
+                content.relates_to = Some(Relation::Thread(Thread::plain(
+                    root_event_id.to_owned(),
+                    latest_event_id,
+                )));
+            }
+            Some(Relation::Reply { in_reply_to }) => {
+                content.relates_to = Some(Relation::Thread(Thread::reply(
+                    root_event_id.to_owned(),
+                    in_reply_to.event_id,
+                )));
+            }
+            relates_to => {
+                content.relates_to = relates_to;
+            }
+        }
+
+        content.into()
+    }
+
     /// Send an edit to the given event.
     ///
     /// Currently only supports `m.room.message` events whose event ID is known.
@@ -533,6 +709,39 @@ impl Timeline {
         SendAttachment::new(self, filename, mime_type, config)
     }
 
+    /// Sends a voice message to the room, as defined in [MSC3245]. It does not
+    /// currently support local echoes.
+    ///
+    /// If the encryption feature is enabled, this method will transparently
+    /// encrypt the room message if the room is encrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `body` - The textual representation of the voice message, usually a
+    ///   filename
+    ///
+    /// * `mime_type` - The mime type of the audio data
+    ///
+    /// * `data` - The recorded audio data
+    ///
+    /// * `duration` - The duration of the recording
+    ///
+    /// * `waveform` - The amplitudes of the recording over time, between 0 and
+    ///   1024, to render the voice message without downloading it
+    ///
+    /// [MSC3245]: https://github.com/matrix-org/matrix-spec-proposals/pull/3245
+    #[instrument(skip_all)]
+    pub fn send_voice_message(
+        &self,
+        body: String,
+        mime_type: Mime,
+        data: Vec<u8>,
+        duration: Duration,
+        waveform: Vec<u16>,
+    ) -> SendVoiceMessage<'_> {
+        SendVoiceMessage::new(self, body, mime_type, data, duration, waveform)
+    }
+
     /// Retry sending a message that previously failed to send.
     ///
     /// # Arguments
@@ -577,13 +786,16 @@ impl Timeline {
             TimelineItemContent::CallInvite => {
                 error_return!("Retrying call events is not currently supported");
             }
+            TimelineItemContent::Location(location) => {
+                AnyMessageLikeEventContent::Location(location.content.clone())
+            }
+            TimelineItemContent::LiveLocation(_) => {
+                error_return!("Retrying live location shares is not currently supported");
+            }
         };
 
         debug!("Retrying failed local echo");
-        let txn_id = txn_id.to_owned();
-        if self.msg_sender.send(LocalMessage { content, txn_id }).await.is_err() {
-            error!("Internal error: timeline message receiver is closed");
-        }
+        self.queue_local_event(txn_id.to_owned(), content).await;
 
         Ok(())
     }
@@ -601,6 +813,10 @@ impl Timeline {
     ///   event from reaching the server.
     #[instrument(skip(self))]
     pub async fn cancel_send(&self, txn_id: &TransactionId) -> bool {
+        if let Err(err) = self.room().send_queue().cancel(txn_id).await {
+            warn!("Couldn't remove the event from the send queue: {err}");
+        }
+
         self.inner.discard_local_echo(txn_id).await
     }
 
@@ -628,6 +844,23 @@ impl Timeline {
         self.inner.fetch_in_reply_to_details(event_id).await
     }
 
+    /// Fetch all the edits of the event with the given `EventId`, using the
+    /// `/relations` endpoint, and add them to the
+    /// [`EventTimelineItem::edit_history`] of its item.
+    ///
+    /// This is useful to get the edits that were sent before the synced or
+    /// paginated window of the timeline. Only the edits of the sender of the
+    /// event are kept, like for the edits that are applied to the item.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the identifier doesn't match any event with a remote
+    /// echo in the timeline, or if fetching the edits fails.
+    #[instrument(skip(self), fields(room_id = ?self.room().room_id()))]
+    pub async fn fetch_edit_history(&self, event_id: &EventId) -> Result<(), Error> {
+        self.inner.fetch_edit_history(event_id).await
+    }
+
     /// Fetch all member events for the room this timeline is displaying.
     ///
     /// If the full member list is not known, sender profiles are currently
@@ -793,8 +1026,10 @@ struct TimelineDropHandle {
     client: Client,
     event_handler_handles: Vec<EventHandlerHandle>,
     room_update_join_handle: JoinHandle<()>,
+    pinned_events_join_handle: Option<JoinHandle<()>>,
     ignore_user_list_update_join_handle: JoinHandle<()>,
     room_key_from_backups_join_handle: JoinHandle<()>,
+    send_queue_join_handle: JoinHandle<()>,
     _event_cache_drop_handle: Arc<EventCacheDropHandles>,
 }
 
@@ -804,8 +1039,12 @@ impl Drop for TimelineDropHandle {
             self.client.remove_event_handler(handle);
         }
         self.room_update_join_handle.abort();
+        if let Some(handle) = &self.pinned_events_join_handle {
+            handle.abort();
+        }
         self.ignore_user_list_update_join_handle.abort();
         self.room_key_from_backups_join_handle.abort();
+        self.send_queue_join_handle.abort();
     }
 }
 
@@ -14,9 +14,14 @@
 
 use std::{fmt, ops::ControlFlow, sync::Arc, time::Duration};
 
-use matrix_sdk::event_cache::{self, BackPaginationOutcome};
+use matrix_sdk::{
+    event_cache::{self, BackPaginationOutcome, EventCacheError},
+    room::{MessagesOptions, RelationsOptions},
+};
+use ruma::{api::Direction, events::relation::RelationType, EventId};
 use tracing::{instrument, trace, warn};
 
+use super::TimelineFocus;
 use crate::timeline::inner::TimelineEnd;
 
 impl super::Timeline {
@@ -36,6 +41,17 @@ impl super::Timeline {
             return Ok(());
         }
 
+        if let Some(result) = self.paginate_focused_backwards(options.clone()).await {
+            let status = if matches!(result, Ok(true)) {
+                BackPaginationStatus::TimelineStartReached
+            } else {
+                BackPaginationStatus::Idle
+            };
+            self.back_pagination_status.set_if_not_eq(status);
+
+            return result.map(|_| ());
+        }
+
         // The first time, we allow to wait a bit for *a* back-pagination token to come
         // over via sync.
         const WAIT_FOR_TOKEN_TIMEOUT: Duration = Duration::from_secs(3);
@@ -98,6 +114,188 @@ impl super::Timeline {
         self.back_pagination_status.set_if_not_eq(BackPaginationStatus::Idle);
         Ok(())
     }
+
+    /// Add more events to the end of the timeline.
+    ///
+    /// This only does something for a timeline focused on an event, see
+    /// [`TimelineFocus::Event`]. Once the end of the room's timeline has been
+    /// reached, the events received via sync are added to the timeline.
+    #[instrument(skip_all, fields(room_id = ?self.room().room_id(), ?options))]
+    pub async fn paginate_forwards(
+        &self,
+        options: PaginationOptions<'_>,
+    ) -> event_cache::Result<()> {
+        if self.forward_pagination_status.get() == ForwardPaginationStatus::TimelineEndReached {
+            warn!("End of timeline reached, ignoring forwards-pagination request");
+            return Ok(());
+        }
+
+        if self
+            .forward_pagination_status
+            .set_if_not_eq(ForwardPaginationStatus::Paginating)
+            .is_none()
+        {
+            warn!("Another forward-pagination is already running in the background");
+            return Ok(());
+        }
+
+        let result = self.paginate_messages(Direction::Forward, options).await;
+
+        if matches!(result, Ok(true)) {
+            // Add the live events received since the last response, and let the task
+            // listening to the event cache updates add the next ones. The lock is held
+            // until the status is updated so no live event can slip in between.
+            let mut pending_live_events = self.pending_live_events.lock().await;
+
+            if let Some(events) = pending_live_events.take() {
+                trace!("Adding {} live events received while paginating", events.len());
+                self.inner.handle_sync_events(events, Vec::new()).await;
+            }
+
+            self.forward_pagination_status
+                .set_if_not_eq(ForwardPaginationStatus::TimelineEndReached);
+        } else {
+            self.forward_pagination_status.set_if_not_eq(ForwardPaginationStatus::Idle);
+        }
+
+        result.map(|_| ())
+    }
+
+    /// Add more events to the start of a timeline that isn't focused on the
+    /// live events.
+    ///
+    /// Returns `None` for a live timeline, which is back-paginated with the
+    /// event cache, or whether the start of the timeline has been reached
+    /// otherwise.
+    async fn paginate_focused_backwards(
+        &self,
+        options: PaginationOptions<'_>,
+    ) -> Option<event_cache::Result<bool>> {
+        Some(match &self.focus {
+            TimelineFocus::Live => return None,
+            TimelineFocus::Thread { root_event_id } => {
+                self.paginate_thread_backwards(root_event_id, options).await
+            }
+            TimelineFocus::Event { .. } => {
+                self.paginate_messages(Direction::Backward, options).await
+            }
+            // All the pinned events are loaded when building the timeline.
+            TimelineFocus::PinnedEvents { .. } => Ok(true),
+        })
+    }
+
+    /// Add more events to the given end of an event-focused timeline, using the
+    /// `/messages` endpoint.
+    ///
+    /// Returns whether the start or end of the timeline, depending on the
+    /// direction, has been reached.
+    async fn paginate_messages(
+        &self,
+        dir: Direction,
+        mut options: PaginationOptions<'_>,
+    ) -> event_cache::Result<bool> {
+        let token = match dir {
+            Direction::Backward => &self.back_pagination_token,
+            Direction::Forward => &self.forward_pagination_token,
+        };
+
+        let mut outcome = PaginationOutcome::default();
+
+        while let Some(batch_size) = options.next_event_limit(outcome) {
+            let (messages_options, position) = match dir {
+                Direction::Backward => (MessagesOptions::backward(), TimelineEnd::Front),
+                Direction::Forward => {
+                    (MessagesOptions::forward(), TimelineEnd::Back { from_cache: false })
+                }
+            };
+            if matches!(dir, Direction::Forward) {
+                // The response includes the live events received until now, only keep
+                // those received while waiting for it.
+                if let Some(pending_events) = self.pending_live_events.lock().await.as_mut() {
+                    pending_events.clear();
+                }
+            }
+
+            let mut messages_options = messages_options.from(token.lock().unwrap().as_deref());
+            messages_options.limit = batch_size.into();
+
+            let messages =
+                self.room().messages(messages_options).await.map_err(EventCacheError::SdkError)?;
+
+            let num_events = messages.chunk.len();
+            trace!("Pagination succeeded with {num_events} events");
+
+            let handle_many_res = self.inner.add_events_at(messages.chunk, position).await;
+
+            outcome.events_received = num_events as u64;
+            outcome.total_events_received += outcome.events_received;
+
+            outcome.items_added = handle_many_res.items_added;
+            outcome.items_updated = handle_many_res.items_updated;
+            outcome.total_items_added += outcome.items_added;
+            outcome.total_items_updated += outcome.items_updated;
+
+            match messages.end {
+                Some(end) if num_events > 0 => *token.lock().unwrap() = Some(end),
+                _ => return Ok(true),
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Add more replies to the start of a thread-focused timeline, using the
+    /// `/relations` endpoint.
+    ///
+    /// Once all the replies have been loaded, the thread root is added too.
+    ///
+    /// Returns whether the start of the thread has been reached.
+    async fn paginate_thread_backwards(
+        &self,
+        root_event_id: &EventId,
+        mut options: PaginationOptions<'_>,
+    ) -> event_cache::Result<bool> {
+        let mut outcome = PaginationOutcome::default();
+
+        while let Some(batch_size) = options.next_event_limit(outcome) {
+            let mut relations_options = RelationsOptions::with_rel_type(RelationType::Thread);
+            relations_options.from = self.back_pagination_token.lock().unwrap().clone();
+            relations_options.limit = Some(batch_size.into());
+
+            let relations = self
+                .room()
+                .relations(root_event_id, relations_options)
+                .await
+                .map_err(EventCacheError::SdkError)?;
+
+            let num_events = relations.chunk.len();
+            trace!("Thread back-pagination succeeded with {num_events} events");
+
+            let handle_many_res =
+                self.inner.add_events_at(relations.chunk, TimelineEnd::Front).await;
+
+            outcome.events_received = num_events as u64;
+            outcome.total_events_received += outcome.events_received;
+
+            outcome.items_added = handle_many_res.items_added;
+            outcome.items_updated = handle_many_res.items_updated;
+            outcome.total_items_added += outcome.items_added;
+            outcome.total_items_updated += outcome.items_updated;
+
+            let Some(next_batch) = relations.next_batch else {
+                // All the replies have been loaded, the thread root comes next.
+                let root_event =
+                    self.room().event(root_event_id).await.map_err(EventCacheError::SdkError)?;
+                self.inner.add_events_at(vec![root_event], TimelineEnd::Front).await;
+
+                return Ok(true);
+            };
+
+            *self.back_pagination_token.lock().unwrap() = Some(next_batch);
+        }
+
+        Ok(false)
+    }
 }
 
 /// Options for pagination.
@@ -248,14 +446,32 @@ pub struct PaginationOutcome {
     pub total_items_updated: u64,
 }
 
+/// The status of the back-pagination of a timeline.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 pub enum BackPaginationStatus {
+    /// No back-pagination is running.
     Idle,
+    /// A back-pagination is running.
     Paginating,
+    /// The start of the timeline was reached, there are no more events to
+    /// paginate.
     TimelineStartReached,
 }
 
+/// The status of the forward-pagination of a timeline focused on an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
+pub enum ForwardPaginationStatus {
+    /// No forward-pagination is running.
+    Idle,
+    /// A forward-pagination is running.
+    Paginating,
+    /// The end of the timeline was reached, the events received via sync are
+    /// now added to the timeline.
+    TimelineEndReached,
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
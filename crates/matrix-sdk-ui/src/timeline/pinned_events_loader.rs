@@ -0,0 +1,152 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loading of the pinned events of a room, for a timeline focused on them (see
+//! [`TimelineFocus::PinnedEvents`](super::TimelineFocus::PinnedEvents)).
+
+use std::sync::Mutex;
+
+use futures_util::{stream, StreamExt};
+use matrix_sdk::{deserialized_responses::SyncTimelineEvent, Room};
+use ruma::{events::relation::RelationType, MilliSecondsSinceUnixEpoch, OwnedEventId};
+use serde::Deserialize;
+use tracing::{trace, warn};
+
+use super::inner::{TimelineEnd, TimelineInner};
+
+/// The maximum number of pinned events requested at the same time.
+const MAX_CONCURRENT_REQUESTS: usize = 10;
+
+/// Loads the pinned events of a room, with [`Room::event`].
+pub(super) struct PinnedEventsLoader {
+    room: Room,
+
+    /// The maximum number of pinned events to load, starting from the most
+    /// recently pinned one.
+    max_events_to_load: usize,
+
+    /// The IDs of the pinned events of the room, the last time they were
+    /// loaded.
+    loaded_event_ids: Mutex<Vec<OwnedEventId>>,
+}
+
+impl PinnedEventsLoader {
+    pub(super) fn new(room: Room, max_events_to_load: usize) -> Self {
+        Self { room, max_events_to_load, loaded_event_ids: Mutex::new(Vec::new()) }
+    }
+
+    /// Load the most recently pinned events of the room, in chronological
+    /// order.
+    ///
+    /// The events that couldn't be loaded are skipped.
+    pub(super) async fn load_events(&self) -> Vec<SyncTimelineEvent> {
+        let pinned_event_ids = self.room.pinned_event_ids();
+        *self.loaded_event_ids.lock().unwrap() = pinned_event_ids.clone();
+
+        let num_skipped = pinned_event_ids.len().saturating_sub(self.max_events_to_load);
+        let pinned_event_ids = &pinned_event_ids[num_skipped..];
+
+        trace!(num_events = pinned_event_ids.len(), "Loading the pinned events");
+
+        let responses = stream::iter(pinned_event_ids)
+            .map(|event_id| self.room.event(event_id))
+            .buffered(MAX_CONCURRENT_REQUESTS)
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut events = pinned_event_ids
+            .iter()
+            .zip(responses)
+            .filter_map(|(event_id, response)| match response {
+                Ok(event) => Some(SyncTimelineEvent::from(event)),
+                Err(err) => {
+                    warn!("Couldn't load the pinned event {event_id}: {err}");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        events.sort_by_key(|event| {
+            event.event.get_field::<MilliSecondsSinceUnixEpoch>("origin_server_ts").ok().flatten()
+        });
+
+        events
+    }
+
+    /// Replace the items of the timeline with the pinned events of the room.
+    pub(super) async fn reload(&self, inner: &TimelineInner) {
+        let events = self.load_events().await;
+
+        inner.clear().await;
+        inner.add_events_at(events, TimelineEnd::Back { from_cache: false }).await;
+    }
+
+    /// Whether the pinned events of the room have changed since they were
+    /// last loaded, e.g. because a new `m.room.pinned_events` event was
+    /// received in the timeline or in the state of a sync response.
+    pub(super) fn have_pinned_events_changed(&self) -> bool {
+        *self.loaded_event_ids.lock().unwrap() != self.room.pinned_event_ids()
+    }
+
+    /// Whether the given event, received via sync, updates one of the pinned
+    /// events of the room, i.e. it's an edit, a reaction, a reference or a
+    /// redaction of a pinned event.
+    ///
+    /// Other relations, like thread replies, are separate events that don't
+    /// belong in the timeline of the pinned events.
+    pub(super) fn is_related_to_pinned_event(&self, event: &SyncTimelineEvent) -> bool {
+        let Ok(event) = event.event.deserialize_as::<RelatedEvent>() else {
+            return false;
+        };
+
+        let relation = event.content.relates_to.filter(|relation| {
+            matches!(
+                relation.rel_type,
+                Some(
+                    RelationType::Replacement | RelationType::Annotation | RelationType::Reference
+                )
+            )
+        });
+        let related_event_id = event
+            .redacts
+            .or(event.content.redacts)
+            .or(relation.and_then(|relation| relation.event_id));
+
+        related_event_id.is_some_and(|related_event_id| {
+            self.room.pinned_event_ids().contains(&related_event_id)
+        })
+    }
+}
+
+/// The parts of an event that tell which event it relates to.
+#[derive(Deserialize)]
+struct RelatedEvent {
+    /// The redacted event, for redactions in room versions before 11.
+    redacts: Option<OwnedEventId>,
+    content: RelatedEventContent,
+}
+
+#[derive(Deserialize)]
+struct RelatedEventContent {
+    /// The redacted event, for redactions in room versions 11 and later.
+    redacts: Option<OwnedEventId>,
+    #[serde(rename = "m.relates_to")]
+    relates_to: Option<Relation>,
+}
+
+#[derive(Deserialize)]
+struct Relation {
+    rel_type: Option<RelationType>,
+    event_id: Option<OwnedEventId>,
+}
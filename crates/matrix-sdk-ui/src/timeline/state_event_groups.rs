@@ -0,0 +1,232 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Algorithm to adjust (insert/replace/remove) the summaries of consecutive
+//! state events, after the items of the timeline have changed.
+
+use std::{ops::Range, sync::Arc};
+
+use eyeball_im::ObservableVectorTransaction;
+use imbl::Vector;
+use tracing::{instrument, trace};
+
+use super::{
+    inner::TimelineInnerMetadata, CollapsedStateEvents, TimelineItem, TimelineItemKind,
+    VirtualTimelineItem,
+};
+
+/// The minimum number of consecutive state events that get a summary.
+const MIN_GROUP_SIZE: usize = 2;
+
+/// An operation on the items of the timeline, recorded while analyzing them.
+#[derive(Debug)]
+enum GroupOperation {
+    Insert(usize, CollapsedStateEvents),
+    Replace(usize, CollapsedStateEvents),
+    Remove(usize),
+}
+
+/// Ensures that every group of consecutive state events is preceded by an up to
+/// date [`VirtualTimelineItem::CollapsedStateEvents`] item, and that there are
+/// no stale ones.
+///
+/// Only the groups around the items that changed since `previous_items` are
+/// looked at, the others are assumed to be up to date already.
+#[instrument(skip_all)]
+pub(super) fn group_state_events(
+    items: &mut ObservableVectorTransaction<'_, Arc<TimelineItem>>,
+    previous_items: &Vector<Arc<TimelineItem>>,
+    meta: &mut TimelineInnerMetadata,
+) {
+    let Some(changed) = changed_range(items, previous_items) else {
+        return;
+    };
+    let Range { start, end } = extend_to_groups(items, changed);
+
+    // Like for day dividers, the operations are recorded in non-decreasing order
+    // of the indices, so they can be applied by keeping track of the offset
+    // introduced by the previous ones.
+    let mut ops = Vec::new();
+    let mut i = start;
+
+    while i < end {
+        let group = summarise_group(items, i);
+        if group.end == i {
+            i += 1;
+            continue;
+        }
+
+        // The group gets a single summary, at its start: the first existing one is
+        // kept if possible, the others are stale.
+        let mut summary_indices = group.summary_indices.iter();
+
+        match (as_collapsed_state_events(&items[i]), group.summary) {
+            (Some(existing), Some(summary)) => {
+                summary_indices.next();
+                if *existing != summary {
+                    trace!("updating the summary of state events @ {i}");
+                    ops.push(GroupOperation::Replace(i, summary));
+                }
+            }
+            (None, Some(summary)) => {
+                trace!("inserting a summary of state events @ {i}");
+                ops.push(GroupOperation::Insert(i, summary));
+            }
+            (_, None) => {}
+        }
+
+        for &index in summary_indices {
+            trace!("removing stale summary of state events @ {index}");
+            ops.push(GroupOperation::Remove(index));
+        }
+
+        i = group.end;
+    }
+
+    let mut offset = 0i64;
+
+    for op in ops {
+        match op {
+            GroupOperation::Insert(i, summary) => {
+                let at = (i64::try_from(i).unwrap() + offset) as usize;
+                let item =
+                    meta.new_timeline_item(VirtualTimelineItem::CollapsedStateEvents(summary));
+                items.insert(at, item);
+                offset += 1;
+            }
+
+            GroupOperation::Replace(i, summary) => {
+                let at = (i64::try_from(i).unwrap() + offset) as usize;
+                let item = items[at].with_kind(VirtualTimelineItem::CollapsedStateEvents(summary));
+                items.set(at, item);
+            }
+
+            GroupOperation::Remove(i) => {
+                let at = (i64::try_from(i).unwrap() + offset) as usize;
+                items.remove(at);
+                offset -= 1;
+            }
+        }
+    }
+}
+
+/// Get the range of the items that differ from the previous ones, by skipping
+/// the items that are shared at both ends.
+///
+/// Returns `None` if nothing changed.
+fn changed_range(
+    items: &Vector<Arc<TimelineItem>>,
+    previous_items: &Vector<Arc<TimelineItem>>,
+) -> Option<Range<usize>> {
+    let common_prefix_len = items
+        .iter()
+        .zip(previous_items.iter())
+        .take_while(|(item, previous)| Arc::ptr_eq(item, previous))
+        .count();
+
+    if common_prefix_len == items.len() && common_prefix_len == previous_items.len() {
+        return None;
+    }
+
+    let max_common_suffix_len = items.len().min(previous_items.len()) - common_prefix_len;
+    let common_suffix_len = items
+        .iter()
+        .rev()
+        .zip(previous_items.iter().rev())
+        .take(max_common_suffix_len)
+        .take_while(|(item, previous)| Arc::ptr_eq(item, previous))
+        .count();
+
+    Some(common_prefix_len..items.len() - common_suffix_len)
+}
+
+/// Extend the given range of items so it covers the whole groups of state
+/// events it touches, including their summaries.
+///
+/// A group can span several existing summaries, e.g. when state events are
+/// back-paginated right before a summarised group.
+fn extend_to_groups(items: &Vector<Arc<TimelineItem>>, range: Range<usize>) -> Range<usize> {
+    let Range { mut start, mut end } = range;
+
+    while start > 0 && is_part_of_group(&items[start - 1]) {
+        start -= 1;
+    }
+
+    while end < items.len() && is_part_of_group(&items[end]) {
+        end += 1;
+    }
+
+    start..end
+}
+
+fn can_summarise(item: &TimelineItem) -> bool {
+    item.as_event().is_some_and(CollapsedStateEvents::can_summarise)
+}
+
+fn is_part_of_group(item: &TimelineItem) -> bool {
+    can_summarise(item) || as_collapsed_state_events(item).is_some()
+}
+
+fn as_collapsed_state_events(item: &TimelineItem) -> Option<&CollapsedStateEvents> {
+    match item.kind() {
+        TimelineItemKind::Virtual(VirtualTimelineItem::CollapsedStateEvents(summary)) => {
+            Some(summary)
+        }
+        _ => None,
+    }
+}
+
+/// A group of consecutive state events, along with the summaries between
+/// them.
+struct Group {
+    /// The summary of all the state events of the group, or `None` if the
+    /// group is too small to be summarised.
+    summary: Option<CollapsedStateEvents>,
+
+    /// The indices of the existing summaries in the group.
+    summary_indices: Vec<usize>,
+
+    /// The index right after the last item of the group.
+    end: usize,
+}
+
+/// Summarise the group of consecutive state events starting at the given
+/// index, going across the existing summaries.
+///
+/// The group is empty, with `end == start`, if the item at the given index
+/// isn't part of a group.
+fn summarise_group(
+    items: &ObservableVectorTransaction<'_, Arc<TimelineItem>>,
+    start: usize,
+) -> Group {
+    let mut summary = CollapsedStateEvents::default();
+    let mut summary_indices = Vec::new();
+    let mut end = start;
+
+    for item in items.skip(start).iter() {
+        if as_collapsed_state_events(item).is_some() {
+            summary_indices.push(end);
+        } else {
+            match item.as_event() {
+                Some(event) if CollapsedStateEvents::can_summarise(event) => summary.add(event),
+                _ => break,
+            }
+        }
+
+        end += 1;
+    }
+
+    let summary = (summary.num_events() >= MIN_GROUP_SIZE).then_some(summary);
+    Group { summary, summary_indices, end }
+}
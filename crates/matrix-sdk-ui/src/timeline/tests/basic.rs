@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use eyeball_im::VectorDiff;
@@ -100,6 +102,66 @@ async fn test_sticker() {
     assert_matches!(item.content(), TimelineItemContent::Sticker(_));
 }
 
+#[async_test]
+async fn test_voice_message() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_custom_event(sync_timeline_event!({
+            "content": {
+                "body": "Voice message.ogg",
+                "info": {
+                    "duration": 2140,
+                    "mimetype": "audio/ogg",
+                    "size": 3000,
+                },
+                "msgtype": "m.audio",
+                "org.matrix.msc1767.audio": {
+                    "duration": 2140,
+                    "waveform": [0, 256, 1024, 512],
+                },
+                "org.matrix.msc3245.voice": {},
+                "url": "mxc://server.name/VoiceMessage",
+            },
+            "event_id": "$voice",
+            "origin_server_ts": 143273582,
+            "sender": "@alice:server.name",
+            "type": "m.room.message",
+        }))
+        .await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_let!(TimelineItemContent::Message(message) = item.content());
+    let voice = message.voice_message_info().unwrap();
+    assert_eq!(voice.duration, Some(Duration::from_millis(2140)));
+    assert_eq!(voice.waveform, vec![0, 256, 1024, 512]);
+
+    // A regular audio clip isn't a voice message.
+    timeline
+        .handle_live_custom_event(sync_timeline_event!({
+            "content": {
+                "body": "Song.ogg",
+                "info": {
+                    "duration": 180000,
+                    "mimetype": "audio/ogg",
+                },
+                "msgtype": "m.audio",
+                "url": "mxc://server.name/Song",
+            },
+            "event_id": "$audio",
+            "origin_server_ts": 143273583,
+            "sender": "@alice:server.name",
+            "type": "m.room.message",
+        }))
+        .await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_let!(TimelineItemContent::Message(message) = item.content());
+    assert_matches!(message.msgtype(), MessageType::Audio(_));
+    assert_eq!(message.voice_message_info(), None);
+}
+
 #[async_test]
 async fn test_room_member() {
     let timeline = TestTimeline::new();
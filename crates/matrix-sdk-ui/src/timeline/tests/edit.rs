@@ -14,7 +14,7 @@
 
 use assert_matches2::assert_let;
 use eyeball_im::VectorDiff;
-use matrix_sdk_test::{async_test, sync_timeline_event, ALICE};
+use matrix_sdk_test::{async_test, sync_timeline_event, ALICE, BOB};
 use ruma::{
     assign,
     events::{
@@ -160,6 +160,91 @@ async fn test_aggregated_sanitized() {
     assert_eq!(text.body, "!!edited!! **better** message");
     assert_eq!(text.formatted.as_ref().unwrap().body, " <strong>better</strong> message");
 
+    // The bundled edit is in the history.
+    let edit_history = first_event.edit_history();
+    assert_eq!(edit_history.len(), 1);
+    assert_eq!(edit_history[0].sender(), *ALICE);
+    assert_let!(MessageType::Text(text) = edit_history[0].content());
+    assert_eq!(text.body, "!!edited!! **better** message");
+
     let day_divider = assert_next_matches!(stream, VectorDiff::PushFront { value } => value);
     assert!(day_divider.is_day_divider());
 }
+
+fn make_edit(original_event_id: &EventId, body: &str) -> RoomMessageEventContent {
+    assign!(RoomMessageEventContent::text_plain(format!("* {body}")), {
+        relates_to: Some(message::Relation::Replacement(Replacement::new(
+            original_event_id.to_owned(),
+            MessageType::text_plain(body).into(),
+        ))),
+    })
+}
+
+#[async_test]
+async fn test_edit_history() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("original"))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.edit_history().is_empty());
+    let original_event_id = item.event_id().unwrap().to_owned();
+
+    timeline.handle_live_message_event(&ALICE, make_edit(&original_event_id, "first")).await;
+    timeline.handle_live_message_event(&ALICE, make_edit(&original_event_id, "second")).await;
+
+    // An edit from another user isn't applied, nor added to the history.
+    timeline.handle_live_message_event(&BOB, make_edit(&original_event_id, "hijacked")).await;
+
+    let item = timeline.inner.items().await[1].as_event().unwrap().clone();
+    assert_let!(TimelineItemContent::Message(message) = item.content());
+    assert_eq!(message.body(), "second");
+    assert!(message.is_edited());
+
+    let edit_history = item.edit_history();
+    assert_eq!(edit_history.len(), 2);
+    assert_let!(MessageType::Text(text) = edit_history[0].content());
+    assert_eq!(text.body, "first");
+    assert_let!(MessageType::Text(text) = edit_history[1].content());
+    assert_eq!(text.body, "second");
+    assert!(edit_history[0].timestamp() < edit_history[1].timestamp());
+    assert!(edit_history.iter().all(|entry| entry.sender() == *ALICE));
+
+    // Redacting the message clears its edit history.
+    timeline.handle_live_redaction(&ALICE, &original_event_id).await;
+
+    let item = timeline.inner.items().await[1].as_event().unwrap().clone();
+    assert!(item.content().is_redacted());
+    assert!(item.edit_history().is_empty());
+}
+
+#[async_test]
+async fn test_redacted_edit_is_removed_from_edit_history() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("original"))
+        .await;
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let original_event_id = item.event_id().unwrap().to_owned();
+
+    timeline.handle_live_message_event(&ALICE, make_edit(&original_event_id, "first")).await;
+    timeline.handle_live_message_event(&ALICE, make_edit(&original_event_id, "second")).await;
+
+    let item = timeline.inner.items().await[1].as_event().unwrap().clone();
+    assert_eq!(item.edit_history().len(), 2);
+    let first_edit_id = item.edit_history()[0].event_id().to_owned();
+
+    // Redacting an edit removes it from the edit history.
+    timeline.handle_live_redaction(&ALICE, &first_edit_id).await;
+
+    let item = timeline.inner.items().await[1].as_event().unwrap().clone();
+    assert!(!item.content().is_redacted());
+    let edit_history = item.edit_history();
+    assert_eq!(edit_history.len(), 1);
+    assert_let!(MessageType::Text(text) = edit_history[0].content());
+    assert_eq!(text.body, "second");
+}
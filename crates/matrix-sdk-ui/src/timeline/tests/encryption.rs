@@ -23,7 +23,10 @@ use std::{
 use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use eyeball_im::VectorDiff;
-use matrix_sdk::crypto::{decrypt_room_key_export, OlmMachine};
+use matrix_sdk::{
+    crypto::{decrypt_room_key_export, OlmMachine},
+    deserialized_responses::{SyncTimelineEvent, UtdCause},
+};
 use matrix_sdk_test::{async_test, BOB};
 use ruma::{
     assign,
@@ -31,9 +34,11 @@ use ruma::{
         EncryptedEventScheme, MegolmV1AesSha2ContentInit, Relation, Replacement,
         RoomEncryptedEventContent,
     },
-    room_id, user_id,
+    room_id,
+    serde::Raw,
+    user_id,
 };
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
 use super::TestTimeline;
 use crate::{
@@ -161,6 +166,93 @@ async fn test_retry_message_decryption() {
     }
 }
 
+#[async_test]
+async fn test_handle_decrypted_events() {
+    const SESSION_ID: &str = "gM8i47Xhu0q52xLfgUXzanCMpLinoyVyH7R58cBuVBU";
+    const SESSION_KEY: &[u8] = b"\
+        -----BEGIN MEGOLM SESSION DATA-----\n\
+        ASKcWoiAVUM97482UAi83Avce62hSLce7i5JhsqoF6xeAAAACqt2Cg3nyJPRWTTMXxXH7TXnkfdlmBXbQtq5\
+        bpHo3LRijcq2Gc6TXilESCmJN14pIsfKRJrWjZ0squ/XsoTFytuVLWwkNaW3QF6obeg2IoVtJXLMPdw3b2vO\
+        vgwGY3OMP0XafH13j1vcb6YLzvgLkZQLnYvd47hv3yK/9GmKS9tokuaQ7dCVYckYcIOS09EDTs70YdxUd5WG\
+        rQynATCLFP1p/NAGv70r9MK7Cy/mNpjD0r4qC7UEDIoi1kOWzHgnLo19wtvwsb8Fg8ATxcs3Wmtj8hIUYpDx\
+        ia4sM10zbytUuaPUAfCDf42IyxdmOnGe1CueXhgI71y+RW0s0argNqUt7jB70JT0o9CyX6UBGRaqLk2MPY9T\
+        hUu5J8X3UgIa6rcbWigzohzWm9rdbEHFrSWqjpfQYMaAKQQgETrjSy4XTrp2RhC2oNqG/hylI4ab+F4X6fpH\
+        DYP1NqNMP5g36xNu7LhDnrUB5qsPjYOmWORxGLfudpF3oLYCSlr3DgHqEIB6HjQblLZ3KQuPBse3zxyROTnS\
+        AhdPH4a/z1wioFtKNVph3hecsiKEdqnz4Y2coSIdhz58mJ9JWNQoFAENE5CSsoEZAGvafYZVpW4C75YY2zq1\
+        wIeiFi1dT43/jLAUGkslsi1VvnyfUu8qO404RxYO3XHoGLMFoFLOO+lZ+VGci2Vz10AhxJhEBHxRKxw4k2uB\
+        HztoSJUr/2Y\n\
+        -----END MEGOLM SESSION DATA-----";
+
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe().await;
+
+    let encrypted = timeline.event_builder.make_sync_message_event(
+        &BOB,
+        RoomEncryptedEventContent::new(
+            EncryptedEventScheme::MegolmV1AesSha2(
+                MegolmV1AesSha2ContentInit {
+                    ciphertext: "\
+                        AwgAEtABPRMavuZMDJrPo6pGQP4qVmpcuapuXtzKXJyi3YpEsjSWdzuRKIgJzD4P\
+                        cSqJM1A8kzxecTQNJsC5q22+KSFEPxPnI4ltpm7GFowSoPSW9+bFdnlfUzEP1jPq\
+                        YevHAsMJp2fRKkzQQbPordrUk1gNqEpGl4BYFeRqKl9GPdKFwy45huvQCLNNueql\
+                        CFZVoYMuhxrfyMiJJAVNTofkr2um2mKjDTlajHtr39pTG8k0eOjSXkLOSdZvNOMz\
+                        hGhSaFNeERSA2G2YbeknOvU7MvjiO0AKuxaAe1CaVhAI14FCgzrJ8g0y5nly+n7x\
+                        QzL2G2Dn8EoXM5Iqj8W99iokQoVsSrUEnaQ1WnSIfewvDDt4LCaD/w7PGETMCQ"
+                        .to_owned(),
+                    sender_key: "DeHIg4gwhClxzFYcmNntPNF9YtsdZbmMy8+3kzCMXHA".to_owned(),
+                    device_id: "NLAZCWIOCO".into(),
+                    session_id: SESSION_ID.into(),
+                }
+                .into(),
+            ),
+            None,
+        ),
+    );
+    timeline.handle_live_event(encrypted.clone()).await;
+
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_matches!(item.as_event().unwrap().content(), TimelineItemContent::UnableToDecrypt(_));
+    assert_next_matches!(stream, VectorDiff::PushFront { value } => {
+        assert!(value.is_day_divider());
+    });
+
+    // The event is decrypted elsewhere, e.g. by the event cache.
+    let own_user_id = user_id!("@example:morheus.localhost");
+    let exported_keys = decrypt_room_key_export(Cursor::new(SESSION_KEY), "1234").unwrap();
+
+    let olm_machine = OlmMachine::new(own_user_id, "SomeDeviceId".into()).await;
+    olm_machine.store().import_exported_room_keys(exported_keys, |_, _| {}).await.unwrap();
+
+    let decrypted = olm_machine
+        .decrypt_room_event(
+            encrypted.cast_ref(),
+            room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost"),
+        )
+        .await
+        .unwrap();
+
+    timeline.inner.handle_decrypted_events(vec![decrypted.into()]).await;
+
+    assert_eq!(timeline.inner.items().await.len(), 2);
+
+    let item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    let event = item.as_event().unwrap();
+    assert_matches!(event.encryption_info(), Some(_));
+    assert_let!(TimelineItemContent::Message(message) = event.content());
+    assert_eq!(message.body(), "It's a secret to everybody");
+
+    // Handling the same decrypted event again doesn't update the timeline.
+    let decrypted = olm_machine
+        .decrypt_room_event(
+            encrypted.cast_ref(),
+            room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost"),
+        )
+        .await
+        .unwrap();
+    timeline.inner.handle_decrypted_events(vec![decrypted.into()]).await;
+    assert_pending!(stream);
+}
+
 #[async_test]
 async fn test_retry_edit_decryption() {
     const SESSION1_KEY: &[u8] = b"\
@@ -455,3 +547,61 @@ async fn test_retry_message_decryption_highlighted() {
     assert_eq!(message.body(), "A secret to everybody but Alice");
     assert!(event.is_highlighted());
 }
+
+#[async_test]
+async fn test_utd_cause() {
+    #[derive(Debug, Default)]
+    struct DummyUtdHook {
+        utds: Mutex<Vec<UnableToDecryptInfo>>,
+    }
+
+    impl UnableToDecryptHook for DummyUtdHook {
+        fn on_utd(&self, info: UnableToDecryptInfo) {
+            self.utds.lock().unwrap().push(info);
+        }
+    }
+
+    let hook = Arc::new(DummyUtdHook::default());
+    let utd_hook = Arc::new(UtdHookManager::new(hook.clone()));
+
+    let timeline = TestTimeline::with_unable_to_decrypt_hook(utd_hook);
+    let mut stream = timeline.subscribe().await;
+
+    let event = timeline.event_builder.make_sync_message_event(
+        &BOB,
+        RoomEncryptedEventContent::new(
+            EncryptedEventScheme::MegolmV1AesSha2(
+                MegolmV1AesSha2ContentInit {
+                    ciphertext: "AwgAEpABNOd7Rxpc".to_owned(),
+                    sender_key: "peI8cfSKqZvTOAfY0Od2e7doDpJ1cxdBsOhSceTLU3E".to_owned(),
+                    device_id: "KDCTEHOVSS".into(),
+                    session_id: "C25PoE+4MlNidQD0YU5ibZqHawV0zZ/up7R8vYJBYTY".into(),
+                }
+                .into(),
+            ),
+            None,
+        ),
+    );
+    timeline
+        .inner
+        .handle_live_event(SyncTimelineEvent {
+            utd_cause: Some(UtdCause::SentBeforeDeviceExisted),
+            ..SyncTimelineEvent::new(Raw::new(&event).unwrap().cast())
+        })
+        .await;
+
+    // The cause is available on the timeline item,
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let event = item.as_event().unwrap();
+    assert_let!(
+        TimelineItemContent::UnableToDecrypt(EncryptedMessage::MegolmV1AesSha2 { cause, .. }) =
+            event.content()
+    );
+    assert_eq!(*cause, UtdCause::SentBeforeDeviceExisted);
+
+    // And it's reported to the UTD hook.
+    let utds = hook.utds.lock().unwrap();
+    assert_eq!(utds.len(), 1);
+    assert_eq!(utds[0].event_id, event.event_id().unwrap());
+    assert_eq!(utds[0].cause, UtdCause::SentBeforeDeviceExisted);
+}
@@ -0,0 +1,196 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use matrix_sdk_test::{async_test, sync_timeline_event, ALICE, BOB};
+use ruma::{
+    events::{
+        beacon::BeaconEventContent,
+        beacon_info::BeaconInfoEventContent,
+        location::{LocationContent, LocationEventContent},
+    },
+    owned_event_id, uint, MilliSecondsSinceUnixEpoch,
+};
+
+use super::TestTimeline;
+use crate::timeline::{EventTimelineItem, TimelineItemContent};
+
+impl TestTimeline {
+    async fn last_event(&self) -> EventTimelineItem {
+        self.inner.items().await.last().unwrap().as_event().unwrap().clone()
+    }
+}
+
+#[async_test]
+async fn test_location_is_displayed() {
+    let timeline = TestTimeline::new();
+
+    let content = LocationEventContent::with_plain_text(
+        "Alice was at geo:51.5008,0.1247 as of 2024-04-24",
+        LocationContent::new("geo:51.5008,0.1247".to_owned()),
+    );
+    timeline.handle_live_message_event(&ALICE, content).await;
+
+    let item = timeline.last_event().await;
+    assert_let!(TimelineItemContent::Location(location) = item.content());
+    assert_eq!(location.geo_uri(), "geo:51.5008,0.1247");
+    assert_eq!(location.body(), Some("Alice was at geo:51.5008,0.1247 as of 2024-04-24"));
+}
+
+#[async_test]
+async fn test_live_location_share() {
+    let timeline = TestTimeline::new();
+
+    let start = BeaconInfoEventContent::new(
+        Some("Going home".to_owned()),
+        Duration::from_secs(3600),
+        true,
+        None,
+    );
+    timeline
+        .handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), start.clone(), None)
+        .await;
+
+    let item = timeline.last_event().await;
+    assert_let!(TimelineItemContent::LiveLocation(state) = item.content());
+    assert!(state.is_live());
+    assert_eq!(state.description(), Some("Going home"));
+    assert!(state.latest_location().is_none());
+    let beacon_info_id = item.event_id().unwrap().to_owned();
+
+    // Alice shares her location.
+    let beacon =
+        BeaconEventContent::new(beacon_info_id.clone(), "geo:51.5008,0.1247".to_owned(), None);
+    timeline.handle_live_message_event(&ALICE, beacon).await;
+
+    // Another user can't update the location of Alice.
+    let beacon = BeaconEventContent::new(beacon_info_id, "geo:48.8584,2.2945".to_owned(), None);
+    timeline.handle_live_message_event(&BOB, beacon).await;
+
+    // The beacons don't get their own items.
+    assert_eq!(timeline.len().await, 2);
+
+    let item = timeline.last_event().await;
+    assert_let!(TimelineItemContent::LiveLocation(state) = item.content());
+    assert_eq!(state.latest_location().unwrap().geo_uri(), "geo:51.5008,0.1247");
+
+    // Alice stops sharing her location.
+    let mut stop = start;
+    stop.stop();
+    timeline.handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), stop, None).await;
+
+    // The stop doesn't get its own item, but updates the share.
+    assert_eq!(timeline.len().await, 2);
+
+    let item = timeline.last_event().await;
+    assert_let!(TimelineItemContent::LiveLocation(state) = item.content());
+    assert!(!state.is_live());
+    assert_eq!(state.latest_location().unwrap().geo_uri(), "geo:51.5008,0.1247");
+}
+
+#[async_test]
+async fn test_back_paginated_live_location_share() {
+    let timeline = TestTimeline::new();
+    let beacon_info_id = owned_event_id!("$beacon_info");
+
+    let start = BeaconInfoEventContent::new(
+        Some("Going home".to_owned()),
+        Duration::from_secs(3600),
+        true,
+        Some(MilliSecondsSinceUnixEpoch(uint!(1000))),
+    );
+    let mut stop = start.clone();
+    stop.stop();
+
+    // When back-paginating, the most recent events are handled first, before the
+    // start of the share.
+    timeline
+        .handle_back_paginated_custom_event(
+            sync_timeline_event!({
+                "content": stop,
+                "event_id": "$stop",
+                "origin_server_ts": 4000,
+                "sender": *ALICE,
+                "state_key": *ALICE,
+                "type": "org.matrix.msc3672.beacon_info",
+            })
+            .cast(),
+        )
+        .await;
+
+    // Bob's beacon is the most recent one, but it doesn't apply to Alice's share.
+    let beacon = BeaconEventContent::new(
+        beacon_info_id.clone(),
+        "geo:48.8584,2.2945".to_owned(),
+        Some(MilliSecondsSinceUnixEpoch(uint!(3000))),
+    );
+    timeline
+        .handle_back_paginated_custom_event(
+            sync_timeline_event!({
+                "content": beacon,
+                "event_id": "$bob_beacon",
+                "origin_server_ts": 3000,
+                "sender": *BOB,
+                "type": "org.matrix.msc3672.beacon",
+            })
+            .cast(),
+        )
+        .await;
+
+    let beacon = BeaconEventContent::new(
+        beacon_info_id.clone(),
+        "geo:51.5008,0.1247".to_owned(),
+        Some(MilliSecondsSinceUnixEpoch(uint!(2000))),
+    );
+    timeline
+        .handle_back_paginated_custom_event(
+            sync_timeline_event!({
+                "content": beacon,
+                "event_id": "$alice_beacon",
+                "origin_server_ts": 2000,
+                "sender": *ALICE,
+                "type": "org.matrix.msc3672.beacon",
+            })
+            .cast(),
+        )
+        .await;
+
+    // Neither the stop nor the beacons get their own items.
+    assert_eq!(timeline.len().await, 0);
+
+    timeline
+        .handle_back_paginated_custom_event(
+            sync_timeline_event!({
+                "content": start,
+                "event_id": beacon_info_id,
+                "origin_server_ts": 1000,
+                "sender": *ALICE,
+                "state_key": *ALICE,
+                "type": "org.matrix.msc3672.beacon_info",
+            })
+            .cast(),
+        )
+        .await;
+
+    // The pending events of Alice are applied to her share.
+    let items = timeline.inner.items().await;
+    assert_eq!(items.len(), 2);
+    let item = items[1].as_event().unwrap();
+    assert_let!(TimelineItemContent::LiveLocation(state) = item.content());
+    // The share has been stopped, and not only expired.
+    assert!(!state.beacon_info.live);
+    assert_eq!(state.latest_location().unwrap().geo_uri(), "geo:51.5008,0.1247");
+}
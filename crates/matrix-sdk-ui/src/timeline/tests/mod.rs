@@ -63,11 +63,13 @@ mod edit;
 mod encryption;
 mod event_filter;
 mod invalid;
+mod location;
 mod polls;
 mod reaction_group;
 mod reactions;
 mod read_receipts;
 mod redaction;
+mod threads;
 mod virt;
 
 struct TestTimeline {
@@ -208,7 +210,12 @@ impl TestTimeline {
     }
 
     async fn handle_live_event(&self, event: Raw<AnySyncTimelineEvent>) {
-        let event = SyncTimelineEvent { event, encryption_info: None, push_actions: vec![] };
+        let event = SyncTimelineEvent {
+            event,
+            encryption_info: None,
+            push_actions: vec![],
+            utd_cause: None,
+        };
         self.inner.handle_live_event(event).await
     }
 
@@ -0,0 +1,213 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use eyeball_im::VectorDiff;
+use matrix_sdk_test::{async_test, sync_timeline_event, ALICE, BOB};
+use ruma::{
+    assign, event_id,
+    events::{
+        relation::Thread,
+        room::message::{Relation, RoomMessageEventContent},
+    },
+    owned_event_id, EventId,
+};
+use stream_assert::{assert_next_matches, assert_pending};
+
+use super::TestTimeline;
+use crate::timeline::inner::TimelineInnerSettings;
+
+fn thread_reply(body: &str, latest_event_id: &EventId) -> RoomMessageEventContent {
+    assign!(RoomMessageEventContent::text_plain(body), {
+        relates_to: Some(Relation::Thread(Thread::plain(
+            owned_event_id!("$root"),
+            latest_event_id.to_owned(),
+        ))),
+    })
+}
+
+#[async_test]
+async fn test_live_reply_updates_thread_summary() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    let root_id = event_id!("$root");
+    timeline
+        .handle_live_message_event_with_id(
+            &ALICE,
+            root_id,
+            RoomMessageEventContent::text_plain("root"),
+        )
+        .await;
+
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(root.thread_summary().is_none());
+
+    let reply_id = event_id!("$reply");
+    timeline
+        .handle_live_message_event_with_id(&BOB, reply_id, thread_reply("reply", root_id))
+        .await;
+
+    let root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let summary = root.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 1);
+    assert_eq!(summary.latest_reply(), Some(reply_id));
+    assert_eq!(summary.participants(), [BOB.to_owned()]);
+
+    let reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(reply.event_id(), Some(reply_id));
+    assert!(reply.thread_summary().is_none());
+
+    // Receiving the same reply again doesn't count it twice.
+    timeline
+        .handle_live_message_event_with_id(&BOB, reply_id, thread_reply("reply", root_id))
+        .await;
+
+    let reply = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    assert_eq!(reply.event_id(), Some(reply_id));
+    assert_pending!(stream);
+
+    let root = timeline.inner.items().await[1].as_event().unwrap().clone();
+    assert_eq!(root.thread_summary().unwrap().num_replies(), 1);
+}
+
+#[async_test]
+async fn test_older_reply_received_again_is_not_counted() {
+    let timeline = TestTimeline::new();
+
+    let root_id = event_id!("$root");
+    timeline
+        .handle_live_message_event_with_id(
+            &ALICE,
+            root_id,
+            RoomMessageEventContent::text_plain("root"),
+        )
+        .await;
+
+    let first_id = event_id!("$first");
+    let second_id = event_id!("$second");
+    timeline
+        .handle_live_message_event_with_id(&BOB, first_id, thread_reply("first", root_id))
+        .await;
+    timeline
+        .handle_live_message_event_with_id(&ALICE, second_id, thread_reply("second", first_id))
+        .await;
+
+    // The first reply is received again, after the second one.
+    timeline
+        .handle_live_message_event_with_id(&BOB, first_id, thread_reply("first", root_id))
+        .await;
+
+    let root = timeline.inner.items().await[1].as_event().unwrap().clone();
+    let summary = root.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 2);
+    assert_eq!(summary.latest_reply(), Some(second_id));
+    assert_eq!(summary.participants(), [BOB.to_owned(), ALICE.to_owned()]);
+}
+
+#[async_test]
+async fn test_bundled_thread_summary() {
+    let timeline = TestTimeline::new();
+    let mut stream = timeline.subscribe_events().await;
+
+    timeline
+        .handle_live_custom_event(sync_timeline_event!({
+            "content": {
+                "body": "root",
+                "msgtype": "m.text",
+            },
+            "event_id": "$root",
+            "origin_server_ts": 10,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+            "unsigned": {
+                "m.relations": {
+                    "m.thread": {
+                        "latest_event": {
+                            "content": {
+                                "body": "latest reply",
+                                "msgtype": "m.text",
+                                "m.relates_to": {
+                                    "rel_type": "m.thread",
+                                    "event_id": "$root",
+                                },
+                            },
+                            "event_id": "$latest",
+                            "origin_server_ts": 20,
+                            "room_id": "!a98sd12bjh:example.org",
+                            "sender": "@bob:example.org",
+                            "type": "m.room.message",
+                        },
+                        "count": 7,
+                        "current_user_participated": false,
+                    },
+                },
+            },
+        }))
+        .await;
+
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    let summary = root.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 7);
+    assert_eq!(summary.latest_reply(), Some(event_id!("$latest")));
+    assert_eq!(summary.participants(), [BOB.to_owned()]);
+}
+
+#[async_test]
+async fn test_thread_focus_only_shows_thread_events() {
+    let settings =
+        TimelineInnerSettings { thread_root: Some(owned_event_id!("$root")), ..Default::default() };
+    let timeline = TestTimeline::new().with_settings(settings);
+    let mut stream = timeline.subscribe_events().await;
+
+    let root_id = event_id!("$root");
+    timeline
+        .handle_live_message_event_with_id(
+            &ALICE,
+            root_id,
+            RoomMessageEventContent::text_plain("root"),
+        )
+        .await;
+    let root = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(root.event_id(), Some(root_id));
+
+    // A message in the main timeline isn't shown.
+    timeline
+        .handle_live_message_event(&BOB, RoomMessageEventContent::text_plain("unrelated"))
+        .await;
+    assert_pending!(stream);
+
+    // A reply in the thread is.
+    let reply_id = event_id!("$reply");
+    timeline
+        .handle_live_message_event_with_id(&BOB, reply_id, thread_reply("reply", root_id))
+        .await;
+
+    let _root = assert_next_matches!(stream, VectorDiff::Set { index: 0, value } => value);
+    let reply = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(reply.event_id(), Some(reply_id));
+
+    // A reply in another thread isn't.
+    timeline
+        .handle_live_message_event(
+            &BOB,
+            assign!(RoomMessageEventContent::text_plain("elsewhere"), {
+                relates_to: Some(Relation::Thread(Thread::plain(
+                    owned_event_id!("$other_root"),
+                    owned_event_id!("$other_root"),
+                ))),
+            }),
+        )
+        .await;
+    assert_pending!(stream);
+}
@@ -16,15 +16,23 @@ use assert_matches::assert_matches;
 use assert_matches2::assert_let;
 use chrono::{Datelike, Local, TimeZone};
 use eyeball_im::VectorDiff;
-use matrix_sdk_test::{async_test, ALICE, BOB};
+use matrix_sdk_test::{async_test, ALICE, BOB, CAROL};
 use ruma::{
     event_id,
-    events::{room::message::RoomMessageEventContent, AnyMessageLikeEventContent},
+    events::{
+        room::{
+            member::{MembershipState, RoomMemberEventContent},
+            message::RoomMessageEventContent,
+            name::RoomNameEventContent,
+        },
+        AnyMessageLikeEventContent,
+    },
+    room_id,
 };
-use stream_assert::assert_next_matches;
+use stream_assert::{assert_next_matches, assert_pending};
 
 use super::TestTimeline;
-use crate::timeline::{TimelineItemKind, VirtualTimelineItem};
+use crate::timeline::{inner::TimelineInnerSettings, TimelineItemKind, VirtualTimelineItem};
 
 #[async_test]
 async fn test_day_divider() {
@@ -153,3 +161,109 @@ async fn test_update_read_marker() {
     let marker = assert_next_matches!(stream, VectorDiff::Insert { index: 4, value } => value);
     assert_matches!(marker.kind, TimelineItemKind::Virtual(VirtualTimelineItem::ReadMarker));
 }
+
+#[async_test]
+async fn test_collapsed_state_events() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineInnerSettings { group_state_events: true, ..Default::default() });
+    let mut stream = timeline.subscribe().await;
+
+    let join = RoomMemberEventContent::new(MembershipState::Join);
+    timeline
+        .handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), join.clone(), None)
+        .await;
+
+    // A single state event isn't summarised.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+    let day_divider = assert_next_matches!(stream, VectorDiff::PushFront { value } => value);
+    assert!(day_divider.is_day_divider());
+    assert_pending!(stream);
+
+    timeline.handle_live_state_event_with_state_key(&BOB, BOB.to_owned(), join.clone(), None).await;
+
+    // The two state events are summarised.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+    let summary_item =
+        assert_next_matches!(stream, VectorDiff::Insert { index: 1, value } => value);
+    assert_let!(
+        Some(VirtualTimelineItem::CollapsedStateEvents(summary)) = summary_item.as_virtual()
+    );
+    assert_eq!(summary.num_events(), 2);
+    assert_eq!(summary.joined(), 2);
+    assert_eq!(summary.left(), 0);
+    assert_pending!(stream);
+
+    timeline
+        .handle_live_state_event(&ALICE, RoomNameEventContent::new("Wonderland".to_owned()), None)
+        .await;
+
+    // The summary is updated in place.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+    let updated_item = assert_next_matches!(stream, VectorDiff::Set { index: 1, value } => value);
+    assert_eq!(updated_item.unique_id(), summary_item.unique_id());
+    assert_let!(
+        Some(VirtualTimelineItem::CollapsedStateEvents(summary)) = updated_item.as_virtual()
+    );
+    assert_eq!(summary.num_events(), 3);
+    assert_eq!(summary.joined(), 2);
+    assert_eq!(summary.other_changes(), 1);
+    assert_pending!(stream);
+
+    timeline.handle_live_message_event(&ALICE, RoomMessageEventContent::text_plain("Hi!")).await;
+
+    // A message ends the group, and doesn't touch the summary.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    assert!(item.as_event().unwrap().content().as_message().is_some());
+    assert_pending!(stream);
+
+    let leave = RoomMemberEventContent::new(MembershipState::Leave);
+    timeline.handle_live_state_event_with_state_key(&BOB, BOB.to_owned(), leave, Some(join)).await;
+
+    // A single state event after the message isn't summarised.
+    let item = assert_next_matches!(stream, VectorDiff::PushBack { value } => value);
+    item.as_event().unwrap();
+    assert_pending!(stream);
+
+    assert_eq!(timeline.inner.items().await.len(), 7);
+}
+
+#[async_test]
+async fn test_collapsed_state_events_back_pagination() {
+    let timeline = TestTimeline::new()
+        .with_settings(TimelineInnerSettings { group_state_events: true, ..Default::default() });
+
+    let join = RoomMemberEventContent::new(MembershipState::Join);
+    timeline
+        .handle_live_state_event_with_state_key(&ALICE, ALICE.to_owned(), join.clone(), None)
+        .await;
+    timeline.handle_live_state_event_with_state_key(&BOB, BOB.to_owned(), join.clone(), None).await;
+
+    let items = timeline.inner.items().await;
+    assert_eq!(items.len(), 4);
+    assert_let!(Some(VirtualTimelineItem::CollapsedStateEvents(summary)) = items[1].as_virtual());
+    assert_eq!(summary.num_events(), 2);
+
+    // A state event is back-paginated right before the summarised group.
+    let event = timeline.event_builder.make_state_event(
+        &CAROL,
+        room_id!("!room:localhost"),
+        CAROL.as_str(),
+        join,
+        None,
+    );
+    timeline.handle_back_paginated_custom_event(event).await;
+
+    // It joins the group, which still has a single summary.
+    let items = timeline.inner.items().await;
+    assert_eq!(items.len(), 5);
+    assert!(items[0].is_day_divider());
+    assert_let!(Some(VirtualTimelineItem::CollapsedStateEvents(summary)) = items[1].as_virtual());
+    assert_eq!(summary.num_events(), 3);
+    assert_eq!(summary.joined(), 3);
+    assert_eq!(items[2].as_event().unwrap().sender(), *CAROL);
+    assert!(items[3].is_event());
+    assert!(items[4].is_event());
+}
@@ -0,0 +1,136 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! This module handles the aggregation of threads (MSC3440) in the timeline.
+
+use std::collections::BTreeSet;
+
+use ruma::{
+    events::{
+        relation::BundledThread,
+        room::{encrypted, message},
+        AnyMessageLikeEventContent, AnySyncMessageLikeEvent, AnySyncTimelineEvent,
+    },
+    EventId, OwnedEventId, OwnedUserId, UserId,
+};
+
+/// A summary of the replies to a thread, attached to the timeline item of the
+/// thread root.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ThreadSummary {
+    num_replies: u64,
+    latest_reply: Option<OwnedEventId>,
+    participants: Vec<OwnedUserId>,
+    /// The replies that were already counted, to avoid counting them again
+    /// when they are received more than once.
+    seen_replies: BTreeSet<OwnedEventId>,
+}
+
+impl ThreadSummary {
+    /// Create a summary from the thread aggregation bundled by the server
+    /// with the root event.
+    pub(super) fn from_bundled(thread: &BundledThread) -> Self {
+        let latest_reply = thread.latest_event.get_field::<OwnedEventId>("event_id").ok().flatten();
+        let participants = thread
+            .latest_event
+            .get_field::<OwnedUserId>("sender")
+            .ok()
+            .flatten()
+            .into_iter()
+            .collect();
+
+        let seen_replies = latest_reply.iter().cloned().collect();
+
+        Self { num_replies: thread.count.into(), latest_reply, participants, seen_replies }
+    }
+
+    /// Account for a new reply to the thread.
+    ///
+    /// Replies that have already been counted are ignored, so re-receiving
+    /// the same event doesn't inflate the count.
+    ///
+    /// Returns whether the summary was updated.
+    pub(super) fn add_reply(&mut self, event_id: &EventId, sender: &UserId) -> bool {
+        if !self.seen_replies.insert(event_id.to_owned()) {
+            return false;
+        }
+
+        self.num_replies += 1;
+        self.latest_reply = Some(event_id.to_owned());
+
+        if !self.participants.iter().any(|participant| participant == sender) {
+            self.participants.push(sender.to_owned());
+        }
+
+        true
+    }
+
+    /// The number of replies in the thread, as far as we know.
+    pub fn num_replies(&self) -> u64 {
+        self.num_replies
+    }
+
+    /// The ID of the latest reply in the thread, if known.
+    pub fn latest_reply(&self) -> Option<&EventId> {
+        self.latest_reply.as_deref()
+    }
+
+    /// The users that have replied in the thread, as far as we know, in the
+    /// order we've seen them reply.
+    pub fn participants(&self) -> &[OwnedUserId] {
+        &self.participants
+    }
+}
+
+/// Returns the root of the thread the given event is a reply to, if any.
+///
+/// This also works for encrypted events, since their relation is not
+/// encrypted.
+fn thread_root_of(event: &AnySyncTimelineEvent) -> Option<&EventId> {
+    let AnySyncTimelineEvent::MessageLike(event) = event else { return None };
+
+    match event {
+        AnySyncMessageLikeEvent::RoomMessage(event) => {
+            match &event.as_original()?.content.relates_to {
+                Some(message::Relation::Thread(thread)) => Some(&thread.event_id),
+                _ => None,
+            }
+        }
+        AnySyncMessageLikeEvent::RoomEncrypted(event) => {
+            match &event.as_original()?.content.relates_to {
+                Some(encrypted::Relation::Thread(thread)) => Some(&thread.event_id),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Returns the root of the thread the given message content is a reply to, if
+/// any.
+pub(super) fn thread_root_of_content(content: &AnyMessageLikeEventContent) -> Option<&EventId> {
+    match content {
+        AnyMessageLikeEventContent::RoomMessage(content) => match &content.relates_to {
+            Some(message::Relation::Thread(thread)) => Some(&thread.event_id),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the given event is either the root of the thread, or a reply in
+/// it.
+pub(super) fn is_event_in_thread(event: &AnySyncTimelineEvent, root_event_id: &EventId) -> bool {
+    event.event_id() == root_event_id || thread_root_of(event) == Some(root_event_id)
+}
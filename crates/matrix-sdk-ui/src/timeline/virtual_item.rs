@@ -14,6 +14,8 @@
 
 use ruma::MilliSecondsSinceUnixEpoch;
 
+use super::{EventTimelineItem, MembershipChange, TimelineItemContent};
+
 /// A [`TimelineItem`](super::TimelineItem) that doesn't correspond to an event.
 #[derive(Clone, Debug)]
 pub enum VirtualTimelineItem {
@@ -25,4 +27,116 @@ pub enum VirtualTimelineItem {
 
     /// The user's own read marker.
     ReadMarker,
+
+    /// A summary of the consecutive state events following this item.
+    ///
+    /// It's only added if [`TimelineBuilder::group_state_events`] has been
+    /// used, so the summarised items can be collapsed behind it.
+    ///
+    /// [`TimelineBuilder::group_state_events`]: super::TimelineBuilder::group_state_events
+    CollapsedStateEvents(CollapsedStateEvents),
+}
+
+/// A summary of consecutive membership, profile and other state changes in the
+/// timeline.
+///
+/// The summarised items are the [`Self::num_events`] items directly following
+/// the [`VirtualTimelineItem::CollapsedStateEvents`] item.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CollapsedStateEvents {
+    num_events: usize,
+    joined: usize,
+    left: usize,
+    invited: usize,
+    removed: usize,
+    profile_changes: usize,
+    other_changes: usize,
+}
+
+impl CollapsedStateEvents {
+    /// Whether the given item can be summarised.
+    pub(super) fn can_summarise(item: &EventTimelineItem) -> bool {
+        matches!(
+            item.content(),
+            TimelineItemContent::MembershipChange(_)
+                | TimelineItemContent::ProfileChange(_)
+                | TimelineItemContent::OtherState(_)
+        )
+    }
+
+    /// Add the given item to the summary.
+    ///
+    /// The item must be one that [can be summarised](Self::can_summarise).
+    pub(super) fn add(&mut self, item: &EventTimelineItem) {
+        self.num_events += 1;
+
+        match item.content() {
+            TimelineItemContent::MembershipChange(change) => match change.change() {
+                Some(MembershipChange::Joined | MembershipChange::InvitationAccepted) => {
+                    self.joined += 1;
+                }
+                Some(
+                    MembershipChange::Left
+                    | MembershipChange::InvitationRejected
+                    | MembershipChange::KnockRetracted,
+                ) => {
+                    self.left += 1;
+                }
+                Some(MembershipChange::Invited | MembershipChange::KnockAccepted) => {
+                    self.invited += 1;
+                }
+                Some(
+                    MembershipChange::Kicked
+                    | MembershipChange::Banned
+                    | MembershipChange::KickedAndBanned,
+                ) => {
+                    self.removed += 1;
+                }
+                _ => self.other_changes += 1,
+            },
+            TimelineItemContent::ProfileChange(_) => self.profile_changes += 1,
+            _ => self.other_changes += 1,
+        }
+    }
+
+    /// The number of summarised events.
+    pub fn num_events(&self) -> usize {
+        self.num_events
+    }
+
+    /// The number of membership events of users joining the room, including by
+    /// accepting an invite.
+    ///
+    /// The same user joining several times is counted several times.
+    pub fn joined(&self) -> usize {
+        self.joined
+    }
+
+    /// The number of membership events of users leaving the room, including by
+    /// rejecting an invite or retracting a knock.
+    pub fn left(&self) -> usize {
+        self.left
+    }
+
+    /// The number of membership events of users being invited to the room,
+    /// including by having their knock accepted.
+    pub fn invited(&self) -> usize {
+        self.invited
+    }
+
+    /// The number of membership events of users being kicked or banned from the
+    /// room.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+
+    /// The number of changes of display names or avatars.
+    pub fn profile_changes(&self) -> usize {
+        self.profile_changes
+    }
+
+    /// The number of other membership changes and state events.
+    pub fn other_changes(&self) -> usize {
+        self.other_changes
+    }
 }
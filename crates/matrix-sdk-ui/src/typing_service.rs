@@ -0,0 +1,260 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for that specific language governing permissions and
+// limitations under the License.
+
+//! High-level API for typing notifications in a room.
+//!
+//! The [`TypingService`] takes care of both sides of typing notifications:
+//!
+//! - on the receiving side, [`TypingService::subscribe`] yields the users who
+//!   are typing, with their display name and avatar, excluding ignored users
+//!   and ourselves;
+//! - on the sending side, [`TypingService::on_keystroke`] must be called
+//!   whenever the user edits their message. The service sends a typing notice,
+//!   renews it for as long as the user keeps typing, and stops it once the user
+//!   hasn't typed anything for a while, or when [`TypingService::stop_typing`]
+//!   is called (e.g. when the message is sent).
+
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use async_stream::stream;
+use futures_core::Stream;
+use matrix_sdk::{
+    executor::{spawn, JoinHandle},
+    Room,
+};
+use ruma::{OwnedMxcUri, OwnedUserId};
+use tokio::{
+    select,
+    sync::{
+        broadcast::error::RecvError,
+        mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
+    },
+    time::sleep,
+};
+use tracing::{instrument, trace, warn};
+
+/// The interval at which a typing notice is renewed while the user is typing,
+/// counted from the time the previous typing notice was sent.
+///
+/// It must be lower than the timeout of the typing notices sent by
+/// [`Room::typing_notice`], which is 4 seconds, and greater than the 3 seconds
+/// under which [`Room::typing_notice`] doesn't send a new typing notice.
+const TYPING_RENEWAL_INTERVAL: Duration = Duration::from_millis(3500);
+
+/// The time after which the user is considered to have stopped typing, if
+/// there hasn't been any keystroke.
+const TYPING_IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A user typing in a room.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Typer {
+    /// The ID of the user.
+    pub user_id: OwnedUserId,
+    /// The display name of the user in the room, if known.
+    pub display_name: Option<String>,
+    /// The avatar of the user in the room, if known.
+    pub avatar_url: Option<OwnedMxcUri>,
+}
+
+/// A service to receive and send typing notifications in a room.
+pub struct TypingService {
+    /// The room of this service.
+    room: Room,
+
+    /// The task sending the typing notices of the user, if they're typing.
+    typing_task: Mutex<Option<TypingTask>>,
+}
+
+/// A task sending the typing notices of the user, and renewing them until the
+/// user stops typing.
+struct TypingTask {
+    /// Sender of the keystrokes of the user to the task.
+    keystrokes: UnboundedSender<()>,
+
+    /// The handle of the task.
+    handle: JoinHandle<()>,
+}
+
+impl TypingService {
+    /// Create a new `TypingService` for the given room.
+    pub fn new(room: Room) -> Self {
+        Self { room, typing_task: Mutex::new(None) }
+    }
+
+    /// Subscribe to the users typing in the room.
+    ///
+    /// The stream yields the full list of users typing every time it changes.
+    /// Our own user and the users that are ignored are excluded from it.
+    pub fn subscribe(&self) -> impl Stream<Item = Vec<Typer>> {
+        let room = self.room.clone();
+        let (drop_guard, mut receiver) = room.subscribe_to_typing_notifications();
+
+        stream! {
+            // Keep the event handler alive as long as the stream is.
+            let _drop_guard = drop_guard;
+
+            loop {
+                let user_ids = match receiver.recv().await {
+                    Ok(user_ids) => user_ids,
+                    Err(RecvError::Lagged(num_skipped)) => {
+                        warn!(num_skipped, "lagged behind typing notifications");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+
+                yield resolve_typers(&room, user_ids).await;
+            }
+        }
+    }
+
+    /// Signal that the user has edited their message.
+    ///
+    /// If they weren't typing, a typing notice is sent. It is then renewed
+    /// automatically for as long as this method keeps being called, and
+    /// stopped once it hasn't been called for a while.
+    pub fn on_keystroke(&self) {
+        let mut typing_task = self.typing_task.lock().unwrap();
+
+        // If the task is still running, just notify it.
+        if let Some(task) = typing_task.as_ref() {
+            if task.keystrokes.send(()).is_ok() {
+                return;
+            }
+        }
+
+        let (sender, receiver) = unbounded_channel();
+        let handle = spawn(send_typing_notices(self.room.clone(), receiver));
+
+        *typing_task = Some(TypingTask { keystrokes: sender, handle });
+    }
+
+    /// Signal that the user has stopped typing, e.g. because they have sent
+    /// their message or cleared the composer.
+    ///
+    /// If a typing notice was active, it's stopped right away.
+    pub async fn stop_typing(&self) -> Result<(), matrix_sdk::Error> {
+        let typing_task = self.typing_task.lock().unwrap().take();
+
+        if let Some(task) = typing_task {
+            task.handle.abort();
+        }
+
+        self.room.typing_notice(false).await
+    }
+}
+
+impl Drop for TypingService {
+    fn drop(&mut self) {
+        if let Some(task) = self.typing_task.lock().unwrap().take() {
+            task.handle.abort();
+        }
+    }
+}
+
+/// Send a typing notice, and renew it until no keystroke has been received for
+/// [`TYPING_IDLE_TIMEOUT`], in which case the typing notice is stopped.
+#[instrument(skip_all, fields(room_id = ?room.room_id()))]
+async fn send_typing_notices(room: Room, mut keystrokes: UnboundedReceiver<()>) {
+    let mut last_keystroke = Instant::now();
+    let mut last_notice = send_typing_notice(&room).await;
+
+    loop {
+        let idle_for = last_keystroke.elapsed();
+
+        if idle_for >= TYPING_IDLE_TIMEOUT {
+            trace!("the user has stopped typing");
+
+            if let Err(err) = room.typing_notice(false).await {
+                warn!("couldn't stop the typing notice: {err}");
+            }
+
+            return;
+        }
+
+        let notice_age = last_notice.elapsed();
+
+        if notice_age >= TYPING_RENEWAL_INTERVAL {
+            last_notice = send_typing_notice(&room).await;
+            continue;
+        }
+
+        let wait_for = (TYPING_RENEWAL_INTERVAL - notice_age).min(TYPING_IDLE_TIMEOUT - idle_for);
+
+        select! {
+            keystroke = keystrokes.recv() => {
+                if keystroke.is_none() {
+                    // The service has been dropped.
+                    return;
+                }
+
+                last_keystroke = Instant::now();
+            }
+
+            _ = sleep(wait_for) => {}
+        }
+    }
+}
+
+/// Send a typing notice, and return the time at which it was sent.
+async fn send_typing_notice(room: &Room) -> Instant {
+    let sent_at = Instant::now();
+
+    if let Err(err) = room.typing_notice(true).await {
+        warn!("couldn't send the typing notice: {err}");
+    }
+
+    sent_at
+}
+
+/// Get the typers from the IDs of the users typing in a room.
+async fn resolve_typers(room: &Room, user_ids: Vec<OwnedUserId>) -> Vec<Typer> {
+    let ignored_users = room.client().subscribe_to_ignore_user_list_changes().get();
+    let mut typers = Vec::with_capacity(user_ids.len());
+
+    for user_id in user_ids {
+        if user_id == room.own_user_id()
+            || ignored_users.iter().any(|ignored| ignored == user_id.as_str())
+        {
+            continue;
+        }
+
+        let member = match room.get_member_no_sync(&user_id).await {
+            Ok(member) => member,
+            Err(err) => {
+                warn!("couldn't load the member {user_id}: {err}");
+                None
+            }
+        };
+
+        if member.as_ref().is_some_and(|member| member.is_ignored()) {
+            continue;
+        }
+
+        typers.push(Typer {
+            display_name: member
+                .as_ref()
+                .and_then(|member| member.display_name().map(ToOwned::to_owned)),
+            avatar_url: member
+                .as_ref()
+                .and_then(|member| member.avatar_url().map(ToOwned::to_owned)),
+            user_id,
+        });
+    }
+
+    typers
+}
@@ -24,6 +24,7 @@ use std::{
     time::{Duration, Instant},
 };
 
+use matrix_sdk::deserialized_responses::UtdCause;
 use ruma::{EventId, OwnedEventId};
 use tokio::{spawn, task::JoinHandle, time::sleep};
 
@@ -50,6 +51,9 @@ pub struct UnableToDecryptInfo {
     /// time it took to decrypt the event. If it is not set, this is
     /// considered a definite UTD.
     pub time_to_decrypt: Option<Duration>,
+
+    /// Why the event couldn't be decrypted when it was first observed.
+    pub cause: UtdCause,
 }
 
 type PendingUtdReports = Vec<(OwnedEventId, JoinHandle<()>)>;
@@ -68,12 +72,12 @@ pub struct UtdHookManager {
     parent: Arc<dyn UnableToDecryptHook>,
 
     /// A mapping of events we've marked as UTDs, and the time at which we
-    /// observed those UTDs.
+    /// observed those UTDs, along with the reason they couldn't be decrypted.
     ///
     /// Note: this is unbounded, because we have absolutely no idea how long it
     /// will take for a UTD to resolve, or if it will even resolve at any
     /// point.
-    known_utds: Arc<Mutex<HashMap<OwnedEventId, Instant>>>,
+    known_utds: Arc<Mutex<HashMap<OwnedEventId, (Instant, UtdCause)>>>,
 
     /// An optional delay before marking the event as UTD ("grace period").
     max_delay: Option<Duration>,
@@ -111,7 +115,7 @@ impl UtdHookManager {
     /// The function to call whenever a UTD is seen for the first time.
     ///
     /// Pipe in any information that needs to be included in the final report.
-    pub(crate) fn on_utd(&self, event_id: &EventId) {
+    pub(crate) fn on_utd(&self, event_id: &EventId, cause: UtdCause) {
         // Only let the parent hook know if the event wasn't already handled.
         {
             let mut known_utds = self.known_utds.lock().unwrap();
@@ -120,10 +124,11 @@ impl UtdHookManager {
             if known_utds.contains_key(event_id) {
                 return;
             }
-            known_utds.insert(event_id.to_owned(), Instant::now());
+            known_utds.insert(event_id.to_owned(), (Instant::now(), cause.clone()));
         }
 
-        let info = UnableToDecryptInfo { event_id: event_id.to_owned(), time_to_decrypt: None };
+        let info =
+            UnableToDecryptInfo { event_id: event_id.to_owned(), time_to_decrypt: None, cause };
 
         let Some(max_delay) = self.max_delay else {
             // No delay: immediately report the event to the parent hook.
@@ -165,13 +170,14 @@ impl UtdHookManager {
     /// before, it has no effect.
     pub(crate) fn on_late_decrypt(&self, event_id: &EventId) {
         // Only let the parent hook know if the event was known to be a UTDs.
-        let Some(marked_utd_at) = self.known_utds.lock().unwrap().remove(event_id) else {
+        let Some((marked_utd_at, cause)) = self.known_utds.lock().unwrap().remove(event_id) else {
             return;
         };
 
         let info = UnableToDecryptInfo {
             event_id: event_id.to_owned(),
             time_to_decrypt: Some(marked_utd_at.elapsed()),
+            cause,
         };
 
         // Cancel and remove the task from the outstanding set immediately.
@@ -226,12 +232,12 @@ mod tests {
         let wrapper = UtdHookManager::new(hook.clone());
 
         // And I call the `on_utd` method multiple times, sometimes on the same event,
-        wrapper.on_utd(event_id!("$1"));
-        wrapper.on_utd(event_id!("$1"));
-        wrapper.on_utd(event_id!("$2"));
-        wrapper.on_utd(event_id!("$1"));
-        wrapper.on_utd(event_id!("$2"));
-        wrapper.on_utd(event_id!("$3"));
+        wrapper.on_utd(event_id!("$1"), UtdCause::Unknown);
+        wrapper.on_utd(event_id!("$1"), UtdCause::Unknown);
+        wrapper.on_utd(event_id!("$2"), UtdCause::Unknown);
+        wrapper.on_utd(event_id!("$1"), UtdCause::Unknown);
+        wrapper.on_utd(event_id!("$2"), UtdCause::Unknown);
+        wrapper.on_utd(event_id!("$3"), UtdCause::Unknown);
 
         // Then the event ids have been deduplicated,
         {
@@ -273,7 +279,7 @@ mod tests {
         let wrapper = UtdHookManager::new(hook.clone());
 
         // And I call the `on_utd` method for an event,
-        wrapper.on_utd(event_id!("$1"));
+        wrapper.on_utd(event_id!("$1"), UtdCause::MissingRoomKey);
 
         // Then the UTD has been notified, but not as late-decrypted event.
         {
@@ -281,6 +287,7 @@ mod tests {
             assert_eq!(utds.len(), 1);
             assert_eq!(utds[0].event_id, event_id!("$1"));
             assert!(utds[0].time_to_decrypt.is_none());
+            assert_eq!(utds[0].cause, UtdCause::MissingRoomKey);
         }
 
         // And when I call the `on_late_decrypt` method,
@@ -295,9 +302,10 @@ mod tests {
             assert_eq!(utds[0].event_id, event_id!("$1"));
             assert!(utds[0].time_to_decrypt.is_none());
 
-            // The new report with a late-decryption is there.
+            // The new report with a late-decryption is there, with the initial cause.
             assert_eq!(utds[1].event_id, event_id!("$1"));
             assert!(utds[1].time_to_decrypt.is_some());
+            assert_eq!(utds[1].cause, UtdCause::MissingRoomKey);
         }
     }
 
@@ -312,7 +320,7 @@ mod tests {
         let wrapper = UtdHookManager::new(hook.clone()).with_max_delay(Duration::from_secs(2));
 
         // And I call the `on_utd` method for an event,
-        wrapper.on_utd(event_id!("$1"));
+        wrapper.on_utd(event_id!("$1"), UtdCause::Unknown);
 
         // Then the UTD is not being reported immediately.
         assert!(hook.utds.lock().unwrap().is_empty());
@@ -348,7 +356,7 @@ mod tests {
         let wrapper = UtdHookManager::new(hook.clone()).with_max_delay(Duration::from_secs(2));
 
         // And I call the `on_utd` method for an event,
-        wrapper.on_utd(event_id!("$1"));
+        wrapper.on_utd(event_id!("$1"), UtdCause::Unknown);
 
         // Then the UTD has not been notified quite yet.
         assert!(hook.utds.lock().unwrap().is_empty());
@@ -23,8 +23,10 @@ mod encryption_sync_service;
 mod notification_client;
 mod room_list_service;
 mod sliding_sync;
+mod space_service;
 mod sync_service;
 mod timeline;
+mod typing_service;
 
 matrix_sdk_test::init_tracing_for_tests!();
 
@@ -13,6 +13,7 @@ use matrix_sdk_test::async_test;
 use matrix_sdk_ui::{
     room_list_service::{
         filters::{new_filter_fuzzy_match_room_name, new_filter_non_left, new_filter_none},
+        sorters::new_sorter_name,
         Error, Input, InputResult, RoomListEntry, RoomListLoadingState, State, SyncIndicator,
         ALL_ROOMS_LIST_NAME as ALL_ROOMS, INVITES_LIST_NAME as INVITES,
         VISIBLE_ROOMS_LIST_NAME as VISIBLE_ROOMS,
@@ -2091,6 +2092,138 @@ async fn test_dynamic_entries_stream_manual_update() -> Result<(), Error> {
     Ok(())
 }
 
+#[async_test]
+async fn test_dynamic_entries_stream_with_sorter() -> Result<(), Error> {
+    let (client, server, room_list) = new_room_list_service().await?;
+
+    let sync = room_list.sync();
+    pin_mut!(sync);
+
+    let all_rooms = room_list.all_rooms().await?;
+
+    let (dynamic_entries_stream, dynamic_entries) =
+        all_rooms.entries_with_dynamic_adapters(5, client.roominfo_update_receiver());
+    pin_mut!(dynamic_entries_stream);
+
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = Init => SettingUp,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "ranges": [[0, 19]],
+                },
+            },
+        },
+        respond with = {
+            "pos": "0",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 3,
+                    "ops": [
+                        {
+                            "op": "SYNC",
+                            "range": [0, 2],
+                            "room_ids": [
+                                "!r2:bar.org",
+                                "!r1:bar.org",
+                                "!r0:bar.org",
+                            ],
+                        },
+                    ],
+                },
+            },
+            "rooms": {
+                "!r0:bar.org": {
+                    "name": "Alpha",
+                    "initial": true,
+                    "timeline": [],
+                },
+                "!r1:bar.org": {
+                    "name": "Charlie",
+                    "initial": true,
+                    "timeline": [],
+                },
+                "!r2:bar.org": {
+                    "name": "Echo",
+                    "initial": true,
+                    "timeline": [],
+                },
+            },
+        },
+    };
+
+    // Setting a sorter alone doesn't start the stream.
+    dynamic_entries.set_sorter(Box::new(new_sorter_name(&client)));
+    assert_pending!(dynamic_entries_stream);
+
+    dynamic_entries.set_filter(Box::new(new_filter_non_left(&client)));
+
+    // The entries are sorted by name, not in the server order.
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        reset [ F("!r0:bar.org"), F("!r1:bar.org"), F("!r2:bar.org") ];
+        end;
+    };
+    assert_pending!(dynamic_entries_stream);
+
+    // The names of the rooms change, without any change of the server order.
+    sync_then_assert_request_and_fake_response! {
+        [server, room_list, sync]
+        states = SettingUp => Running,
+        assert request >= {
+            "lists": {
+                ALL_ROOMS: {
+                    "ranges": [[0, 2]],
+                },
+            },
+        },
+        respond with = {
+            "pos": "1",
+            "lists": {
+                ALL_ROOMS: {
+                    "count": 3,
+                },
+                VISIBLE_ROOMS: {
+                    "count": 0,
+                },
+                INVITES: {
+                    "count": 0,
+                },
+            },
+            "rooms": {
+                "!r0:bar.org": {
+                    "name": "Delta",
+                    "timeline": [],
+                },
+                "!r2:bar.org": {
+                    "name": "Foxtrot",
+                    "timeline": [],
+                },
+            },
+        },
+    };
+
+    // `!r0` moves between `!r1` and `!r2`.
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        remove[0];
+        insert[1] [ F("!r0:bar.org") ];
+        end;
+    };
+
+    // `!r2` stays the last room.
+    assert_entries_batch! {
+        [dynamic_entries_stream]
+        set[2] [ F("!r2:bar.org") ];
+        end;
+    };
+
+    assert_pending!(dynamic_entries_stream);
+
+    Ok(())
+}
+
 #[async_test]
 async fn test_invites_stream() -> Result<(), Error> {
     let (_, server, room_list) = new_room_list_service().await?;
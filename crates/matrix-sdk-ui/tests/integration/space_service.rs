@@ -0,0 +1,287 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{ops::Not, time::Duration};
+
+use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{
+    async_test, sync_state_event, sync_timeline_event, JoinedRoomBuilder, LeftRoomBuilder,
+    SyncResponseBuilder,
+};
+use matrix_sdk_ui::space_service::SpaceService;
+use ruma::{room_id, RoomId};
+use serde_json::json;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::mock_sync;
+
+fn space_child_event(child_id: &RoomId) -> serde_json::Value {
+    json!({
+        "content": { "via": ["example.org"] },
+        "event_id": format!("$child_{}", child_id.localpart()),
+        "origin_server_ts": 151800140,
+        "sender": "@example:localhost",
+        "state_key": child_id,
+        "type": "m.space.child",
+    })
+}
+
+#[async_test]
+async fn test_space_graph_follows_sync() {
+    let space_id = room_id!("!space:example.org");
+    let child_id = room_id!("!child:example.org");
+    let other_child_id = room_id!("!other_child:example.org");
+
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(space_id).add_state_bulk([
+        sync_state_event!({
+            "content": { "creator": "@example:localhost", "room_version": "9", "type": "m.space" },
+            "event_id": "$create",
+            "origin_server_ts": 151800140,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.create",
+        }),
+        sync_state_event!(space_child_event(child_id)),
+    ]));
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(child_id));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let space_service = SpaceService::new(client.clone()).await.unwrap();
+    let mut graph_stream = space_service.subscribe();
+
+    assert_eq!(space_service.top_level_spaces(), vec![space_id.to_owned()]);
+    assert!(space_service.contains(space_id, child_id, false));
+    assert!(space_service.contains(space_id, other_child_id, false).not());
+
+    // A new child is added with a state event from sync.
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(space_id)
+            .add_state_bulk([sync_state_event!(space_child_event(other_child_id))]),
+    );
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let graph = graph_stream.next().await.unwrap();
+    assert!(graph.contains(space_id, other_child_id, false));
+    assert!(space_service.contains(space_id, child_id, false));
+}
+
+fn space_parent_event(parent_id: &RoomId, sender: &str) -> serde_json::Value {
+    json!({
+        "content": { "via": ["example.org"] },
+        "event_id": format!("$parent_{}", parent_id.localpart()),
+        "origin_server_ts": 151800140,
+        "sender": sender,
+        "state_key": parent_id,
+        "type": "m.space.parent",
+    })
+}
+
+fn space_create_event() -> serde_json::Value {
+    json!({
+        "content": { "creator": "@example:localhost", "room_version": "9", "type": "m.space" },
+        "event_id": "$create",
+        "origin_server_ts": 151800140,
+        "sender": "@example:localhost",
+        "state_key": "",
+        "type": "m.room.create",
+    })
+}
+
+#[async_test]
+async fn test_left_space_is_removed() {
+    let space_id = room_id!("!space:example.org");
+    let child_id = room_id!("!child:example.org");
+
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(space_id).add_state_bulk([
+        sync_state_event!(space_create_event()),
+        sync_state_event!(space_child_event(child_id)),
+    ]));
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(child_id));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let space_service = SpaceService::new(client.clone()).await.unwrap();
+    let mut graph_stream = space_service.subscribe();
+    let mut room_info_updates = client.roominfo_update_receiver();
+
+    assert!(space_service.contains(space_id, child_id, false));
+
+    // The user leaves the space.
+    sync_builder.add_left_room(LeftRoomBuilder::new(space_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": { "membership": "leave" },
+            "event_id": "$leave",
+            "origin_server_ts": 151800141,
+            "sender": "@example:localhost",
+            "state_key": "@example:localhost",
+            "type": "m.room.member",
+        }),
+    ));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let graph = graph_stream.next().await.unwrap();
+    assert!(graph.is_space(space_id).not());
+    assert!(graph.contains(space_id, child_id, false).not());
+    assert!(space_service.top_level_spaces().is_empty());
+
+    // An update of the room list is triggered for the child, whose ancestors
+    // changed.
+    loop {
+        let update = room_info_updates.recv().await.unwrap();
+        if update.room_id == child_id {
+            assert!(update.trigger_room_list_update);
+            break;
+        }
+    }
+}
+
+#[async_test]
+async fn test_space_parent_needs_permission_in_parent() {
+    let space_id = room_id!("!space:example.org");
+    let allowed_child_id = room_id!("!allowed:example.org");
+    let other_child_id = room_id!("!other:example.org");
+
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(space_id).add_state_bulk([
+        sync_state_event!(space_create_event()),
+        sync_state_event!({
+            "content": {
+                "state_default": 50,
+                "users": { "@example:localhost": 100 },
+                "users_default": 0,
+            },
+            "event_id": "$power_levels",
+            "origin_server_ts": 151800140,
+            "sender": "@example:localhost",
+            "state_key": "",
+            "type": "m.room.power_levels",
+        }),
+    ]));
+    // Only the first room's claim is sent by a user allowed to add children to the
+    // space.
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(allowed_child_id).add_state_bulk([sync_state_event!(
+            space_parent_event(space_id, "@example:localhost")
+        )]),
+    );
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(other_child_id).add_state_bulk([sync_state_event!(
+            space_parent_event(space_id, "@mallory:localhost")
+        )]),
+    );
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let space_service = SpaceService::new(client.clone()).await.unwrap();
+
+    assert!(space_service.contains(space_id, allowed_child_id, false));
+    assert!(space_service.contains(space_id, other_child_id, false).not());
+}
+
+#[async_test]
+async fn test_space_hierarchy() {
+    let space_id = room_id!("!space:example.org");
+    let subspace_id = room_id!("!subspace:example.org");
+    let deep_child_id = room_id!("!deep:example.org");
+
+    let (client, server) = logged_in_client_with_server().await;
+    let space_service = SpaceService::new(client.clone()).await.unwrap();
+
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/v1/rooms/{space_id}/hierarchy")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "rooms": [
+                {
+                    "room_id": space_id,
+                    "name": "Space",
+                    "num_joined_members": 2,
+                    "world_readable": false,
+                    "guest_can_join": false,
+                    "join_rule": "public",
+                    "room_type": "m.space",
+                    "children_state": [{
+                        "content": { "via": ["example.org"] },
+                        "origin_server_ts": 151800140,
+                        "sender": "@example:localhost",
+                        "state_key": subspace_id,
+                        "type": "m.space.child",
+                    }],
+                },
+                {
+                    "room_id": subspace_id,
+                    "name": "Subspace",
+                    "num_joined_members": 1,
+                    "world_readable": false,
+                    "guest_can_join": false,
+                    "join_rule": "public",
+                    "room_type": "m.space",
+                    "children_state": [{
+                        "content": { "via": ["example.org"] },
+                        "origin_server_ts": 151800140,
+                        "sender": "@example:localhost",
+                        "state_key": deep_child_id,
+                        "type": "m.space.child",
+                    }],
+                },
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut hierarchy = space_service.hierarchy(space_id);
+    let rooms = hierarchy.paginate(None).await.unwrap();
+
+    assert!(hierarchy.is_at_end());
+    assert_eq!(rooms.len(), 2);
+    assert_eq!(rooms[0].room_id, space_id);
+    assert_eq!(rooms[1].room_id, subspace_id);
+    assert!(rooms[1].is_space);
+    assert_eq!(rooms[1].state, None);
+
+    // The rooms of the hierarchy have been added to the graph.
+    assert!(space_service.contains(space_id, subspace_id, false));
+    assert!(space_service.contains(space_id, deep_child_id, false).not());
+    assert!(space_service.contains(space_id, deep_child_id, true));
+
+    // There's nothing more to fetch.
+    assert!(hierarchy.paginate(None).await.unwrap().is_empty());
+}
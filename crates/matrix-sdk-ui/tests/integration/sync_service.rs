@@ -13,13 +13,15 @@
 // limitations under the License.
 
 use std::{
+    net::TcpListener,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use matrix_sdk::test_utils::logged_in_client_with_server;
+use assert_matches::assert_matches;
+use matrix_sdk::test_utils::{logged_in_client, logged_in_client_with_server};
 use matrix_sdk_test::async_test;
-use matrix_sdk_ui::sync_service::{State, SyncService};
+use matrix_sdk_ui::sync_service::{OfflineReason, State, SyncService};
 use serde_json::json;
 use stream_assert::{assert_next_matches, assert_pending};
 use wiremock::{Match as _, Mock, MockGuard, MockServer, Request, ResponseTemplate};
@@ -202,3 +204,122 @@ async fn test_sync_service_state() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[async_test]
+async fn test_sync_service_offline_mode() -> anyhow::Result<()> {
+    // Use the address of a closed port, so the server can't be reached.
+    let address = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let client = logged_in_client(Some(format!("http://{address}"))).await;
+
+    let sync_service = SyncService::builder(client).with_offline_mode().build().await?;
+
+    let mut state_stream = sync_service.state();
+    let offline_reason = sync_service.offline_reason();
+
+    sync_service.start().await;
+    assert_next_matches!(state_stream, State::Running);
+
+    // The syncs run into a network error, so the service goes offline instead of
+    // erroring.
+    let state = tokio::time::timeout(Duration::from_secs(1), state_stream.next()).await?;
+    assert_eq!(state, Some(State::Offline));
+    assert_matches!(
+        offline_reason.get(),
+        Some(OfflineReason::NetworkError { retry_in, .. }) => {
+            assert_eq!(retry_in, Duration::from_secs(1));
+        }
+    );
+
+    // Starting again while offline doesn't change the current state.
+    sync_service.start().await;
+    assert_pending!(state_stream);
+
+    // When the platform signals the network is unreachable, the reason changes.
+    sync_service.set_network_reachable(false).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(offline_reason.get(), Some(OfflineReason::NetworkUnreachable));
+    assert_pending!(state_stream);
+
+    // Stopping while offline works as usual.
+    sync_service.stop().await?;
+    assert_next_matches!(state_stream, State::Idle);
+    assert_eq!(offline_reason.get(), None);
+    assert_eq!(sync_service.task_states(), (false, false));
+
+    Ok(())
+}
+
+#[async_test]
+async fn test_sync_service_network_reachability() -> anyhow::Result<()> {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let encryption_pos = Arc::new(Mutex::new(0));
+    let room_pos = Arc::new(Mutex::new(0));
+    let _guard =
+        setup_mocking_sliding_sync_server(&server, encryption_pos.clone(), room_pos.clone()).await;
+
+    let sync_service = SyncService::builder(client).build().await?;
+
+    let mut state_stream = sync_service.state();
+    let offline_reason = sync_service.offline_reason();
+
+    sync_service.start().await;
+    assert_next_matches!(state_stream, State::Running);
+
+    // When the network becomes unreachable, the syncs are stopped.
+    sync_service.set_network_reachable(false).await;
+    let state = tokio::time::timeout(Duration::from_secs(1), state_stream.next()).await?;
+    assert_eq!(state, Some(State::Offline));
+    assert_eq!(offline_reason.get(), Some(OfflineReason::NetworkUnreachable));
+    assert_eq!(sync_service.task_states(), (false, false));
+
+    // When it becomes reachable again, they are restarted immediately.
+    sync_service.set_network_reachable(true).await;
+    let state = tokio::time::timeout(Duration::from_secs(1), state_stream.next()).await?;
+    assert_eq!(state, Some(State::Running));
+    assert_eq!(offline_reason.get(), None);
+    assert_eq!(sync_service.task_states(), (true, true));
+
+    sync_service.stop().await?;
+    assert_next_matches!(state_stream, State::Idle);
+
+    Ok(())
+}
+
+#[async_test]
+async fn test_sync_service_stop_while_waiting_for_encryption_sync_permit() -> anyhow::Result<()> {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let encryption_pos = Arc::new(Mutex::new(0));
+    let room_pos = Arc::new(Mutex::new(0));
+    let _guard =
+        setup_mocking_sliding_sync_server(&server, encryption_pos.clone(), room_pos.clone()).await;
+
+    let sync_service = SyncService::builder(client).build().await?;
+
+    let mut state_stream = sync_service.state();
+
+    sync_service.start().await;
+    assert_next_matches!(state_stream, State::Running);
+
+    sync_service.set_network_reachable(false).await;
+    let state = tokio::time::timeout(Duration::from_secs(1), state_stream.next()).await?;
+    assert_eq!(state, Some(State::Offline));
+
+    // Another encryption sync holds the permit while the syncs are restarted.
+    let permit = sync_service.try_get_encryption_sync_permit();
+    assert!(permit.is_some());
+
+    sync_service.set_network_reachable(true).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_pending!(state_stream);
+
+    // Stopping doesn't wait for the permit.
+    tokio::time::timeout(Duration::from_secs(1), sync_service.stop()).await??;
+    assert_next_matches!(state_stream, State::Idle);
+    assert_eq!(sync_service.task_states(), (false, false));
+
+    drop(permit);
+
+    Ok(())
+}
@@ -20,7 +20,8 @@ use eyeball_im::VectorDiff;
 use futures_util::StreamExt;
 use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
 use matrix_sdk_test::{
-    async_test, EventBuilder, JoinedRoomBuilder, SyncResponseBuilder, ALICE, BOB,
+    async_test, sync_timeline_event, EventBuilder, JoinedRoomBuilder, SyncResponseBuilder, ALICE,
+    BOB,
 };
 use matrix_sdk_ui::timeline::{RoomExt, TimelineDetails, TimelineItemContent};
 use ruma::{
@@ -398,3 +399,112 @@ async fn test_send_edit_poll() {
 
     server.verify().await;
 }
+
+#[async_test]
+async fn test_fetch_edit_history_skips_redacted_edits() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let event_builder = EventBuilder::new();
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room.timeline().await.unwrap();
+    let (_, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+
+    let event_id = event_id!("$original");
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        event_builder.make_sync_message_event_with_id(
+            &ALICE,
+            event_id,
+            RoomMessageEventContent::text_plain("hello"),
+        ),
+    ));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    assert_let!(Some(VectorDiff::PushBack { value: item }) = timeline_stream.next().await);
+    assert!(item.edit_history().is_empty());
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/rooms/.*/relations/\$original/m.replace$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [
+                {
+                    "content": {
+                        "body": "* hi",
+                        "msgtype": "m.text",
+                        "m.new_content": { "body": "hi", "msgtype": "m.text" },
+                        "m.relates_to": { "rel_type": "m.replace", "event_id": "$original" },
+                    },
+                    "event_id": "$edit1",
+                    "origin_server_ts": 152038,
+                    "room_id": room_id,
+                    "sender": "@alice:server.name",
+                    "type": "m.room.message",
+                },
+                // An edit that's been redacted already.
+                {
+                    "content": {},
+                    "event_id": "$edit2",
+                    "origin_server_ts": 152039,
+                    "room_id": room_id,
+                    "sender": "@alice:server.name",
+                    "type": "m.room.message",
+                    "unsigned": {
+                        "redacted_because": {
+                            "content": {},
+                            "redacts": "$edit2",
+                            "event_id": "$redaction2",
+                            "origin_server_ts": 152040,
+                            "room_id": room_id,
+                            "sender": "@alice:server.name",
+                            "type": "m.room.redaction",
+                        },
+                    },
+                },
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    timeline.fetch_edit_history(event_id).await.unwrap();
+
+    assert_let!(Some(VectorDiff::Set { index: 0, value: item }) = timeline_stream.next().await);
+    let edit_history = item.edit_history();
+    assert_eq!(edit_history.len(), 1);
+    assert_eq!(edit_history[0].event_id(), "$edit1");
+
+    // Redacting the remaining edit removes it from the history too.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {},
+            "redacts": "$edit1",
+            "event_id": "$redaction1",
+            "sender": "@alice:server.name",
+            "origin_server_ts": 152041,
+            "type": "m.room.redaction",
+        }),
+    ));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    // Wait for the redaction to be handled by the timeline.
+    sleep(Duration::from_millis(200)).await;
+
+    let item = timeline.item_by_event_id(event_id).await.unwrap();
+    assert!(item.edit_history().is_empty());
+    assert!(!item.content().is_redacted());
+}
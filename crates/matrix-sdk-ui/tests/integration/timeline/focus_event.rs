@@ -0,0 +1,294 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use futures_util::StreamExt;
+use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{async_test, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder};
+use matrix_sdk_ui::timeline::{
+    BackPaginationStatus, ForwardPaginationStatus, PaginationOptions, RoomExt, TimelineFocus,
+};
+use ruma::{event_id, owned_event_id, room_id};
+use serde_json::json;
+use stream_assert::{assert_next_eq, assert_next_matches, assert_pending};
+use wiremock::{
+    matchers::{header, method, path_regex, query_param},
+    Mock, ResponseTemplate,
+};
+
+use crate::mock_sync;
+
+fn message(event_id: &str, body: &str, ts: u64) -> serde_json::Value {
+    json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "room_id": "!a98sd12bjh:example.org",
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+    })
+}
+
+#[async_test]
+async fn test_event_focused_timeline() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/context/\$target"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "event": message("$target", "target", 152038),
+            "events_before": [
+                message("$before2", "before 2", 152037),
+                message("$before1", "before 1", 152036),
+            ],
+            "events_after": [
+                message("$after1", "after 1", 152039),
+            ],
+            "start": "start_token",
+            "end": "end_token",
+            "state": [],
+        })))
+        .expect(1)
+        .named("context")
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Event {
+            target: owned_event_id!("$target"),
+            num_context_events: 4,
+        })
+        .build()
+        .await
+        .unwrap();
+    server.reset().await;
+
+    let (items, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    let event_ids = items.iter().map(|item| item.event_id().unwrap()).collect::<Vec<_>>();
+    assert_eq!(
+        event_ids,
+        [event_id!("$before1"), event_id!("$before2"), event_id!("$target"), event_id!("$after1")]
+    );
+
+    let mut back_pagination_status = timeline.back_pagination_status();
+    let mut forward_pagination_status = timeline.forward_pagination_status();
+    assert_eq!(forward_pagination_status.get(), ForwardPaginationStatus::Idle);
+
+    // Events received via sync aren't added until the timeline has caught up.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {
+                "body": "live",
+                "msgtype": "m.text",
+            },
+            "event_id": "$live",
+            "origin_server_ts": 152041,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+        }),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+    assert_pending!(timeline_stream);
+
+    // Paginating backwards uses the token from the `/context` response.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("dir", "b"))
+        .and(query_param("from", "start_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [message("$before0", "before 0", 152035)],
+            "start": "start_token",
+        })))
+        .expect(1)
+        .named("messages_backwards")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_backwards(PaginationOptions::simple_request(10)).await.unwrap();
+    assert_next_eq!(back_pagination_status, BackPaginationStatus::TimelineStartReached);
+
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushFront { value } => value);
+    assert_eq!(item.event_id(), Some(event_id!("$before0")));
+    assert_pending!(timeline_stream);
+
+    // Paginating forwards uses the other token, until the end of the timeline is
+    // reached.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "end_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [
+                message("$after2", "after 2", 152040),
+                message("$live", "live", 152041),
+            ],
+            "start": "end_token",
+        })))
+        .expect(1)
+        .named("messages_forwards")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_forwards(PaginationOptions::simple_request(10)).await.unwrap();
+    assert_next_eq!(forward_pagination_status, ForwardPaginationStatus::TimelineEndReached);
+
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.event_id(), Some(event_id!("$after2")));
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.event_id(), Some(event_id!("$live")));
+    assert_pending!(timeline_stream);
+    server.reset().await;
+
+    // Now that the timeline has caught up, live events are added to it.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {
+                "body": "live 2",
+                "msgtype": "m.text",
+            },
+            "event_id": "$live2",
+            "origin_server_ts": 152042,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+        }),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    assert_let!(Some(VectorDiff::PushBack { value: item }) = timeline_stream.next().await);
+    assert_eq!(item.event_id(), Some(event_id!("$live2")));
+}
+
+#[async_test]
+async fn test_event_focused_timeline_keeps_live_events_received_while_paginating() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/context/\$target"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "event": message("$target", "target", 152038),
+            "events_before": [],
+            "events_after": [],
+            "start": "start_token",
+            "end": "end_token",
+            "state": [],
+        })))
+        .expect(1)
+        .named("context")
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Event {
+            target: owned_event_id!("$target"),
+            num_context_events: 4,
+        })
+        .build()
+        .await
+        .unwrap();
+    server.reset().await;
+
+    let (items, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    assert_eq!(items.len(), 1);
+
+    // The last page doesn't contain the event received via sync while waiting for
+    // it.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("dir", "f"))
+        .and(query_param("from", "end_token"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({
+                    "chunk": [message("$after1", "after 1", 152039)],
+                    "start": "end_token",
+                }))
+                .set_delay(Duration::from_millis(500)),
+        )
+        .expect(1)
+        .named("messages_forwards")
+        .mount(&server)
+        .await;
+
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {
+                "body": "live",
+                "msgtype": "m.text",
+            },
+            "event_id": "$live",
+            "origin_server_ts": 152040,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+        }),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+
+    let (pagination_result, sync_result) =
+        tokio::join!(timeline.paginate_forwards(PaginationOptions::simple_request(10)), async {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            client.sync_once(sync_settings.clone()).await
+        });
+    pagination_result.unwrap();
+    sync_result.unwrap();
+
+    assert_eq!(
+        timeline.forward_pagination_status().get(),
+        ForwardPaginationStatus::TimelineEndReached
+    );
+
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.event_id(), Some(event_id!("$after1")));
+    let item = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    assert_eq!(item.event_id(), Some(event_id!("$live")));
+    assert_pending!(timeline_stream);
+}
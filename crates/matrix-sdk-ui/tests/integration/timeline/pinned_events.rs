@@ -0,0 +1,198 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use futures_util::StreamExt;
+use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{
+    async_test, sync_state_event, sync_timeline_event, JoinedRoomBuilder, SyncResponseBuilder,
+};
+use matrix_sdk_ui::timeline::{BackPaginationStatus, PaginationOptions, RoomExt, TimelineFocus};
+use ruma::{event_id, room_id};
+use serde_json::json;
+use stream_assert::{assert_next_matches, assert_pending};
+use wiremock::{
+    matchers::{header, method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::mock_sync;
+
+fn message(event_id: &str, body: &str, ts: u64) -> serde_json::Value {
+    json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "room_id": "!a98sd12bjh:example.org",
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+    })
+}
+
+fn pinned_events(event_ids: &[&str]) -> serde_json::Value {
+    json!({
+        "content": {
+            "pinned": event_ids,
+        },
+        "event_id": "$pinned_events",
+        "origin_server_ts": 152040,
+        "sender": "@alice:example.org",
+        "state_key": "",
+        "type": "m.room.pinned_events",
+    })
+}
+
+async fn mock_event(server: &MockServer, event: serde_json::Value) {
+    let event_id = event["event_id"].as_str().unwrap().to_owned();
+
+    Mock::given(method("GET"))
+        .and(path_regex(format!(r"^/_matrix/client/r0/rooms/.*/event/\{event_id}$")))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(event))
+        .named("event")
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn test_pinned_events_timeline() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_state_bulk([sync_state_event!(pinned_events(&["$second", "$first"]))]),
+    );
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    assert_eq!(room.pinned_event_ids(), [event_id!("$second"), event_id!("$first")]);
+
+    mock_event(&server, message("$first", "first", 152037)).await;
+    mock_event(&server, message("$second", "second", 152038)).await;
+
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::PinnedEvents { max_events_to_load: 10 })
+        .build()
+        .await
+        .unwrap();
+
+    // The pinned events are sorted chronologically.
+    let (items, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    let event_ids = items.iter().map(|item| item.event_id().unwrap()).collect::<Vec<_>>();
+    assert_eq!(event_ids, [event_id!("$first"), event_id!("$second")]);
+
+    // There is nothing to paginate.
+    assert_eq!(timeline.back_pagination_status().get(), BackPaginationStatus::TimelineStartReached);
+    timeline.paginate_backwards(PaginationOptions::simple_request(10)).await.unwrap();
+    assert_pending!(timeline_stream);
+
+    // New messages received via sync aren't added to the timeline, but the edits of
+    // the pinned events are applied.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(sync_timeline_event!(message("$live", "live", 152041)))
+            .add_timeline_event(sync_timeline_event!({
+                "content": {
+                    "body": "* edited",
+                    "msgtype": "m.text",
+                    "m.new_content": {
+                        "body": "edited",
+                        "msgtype": "m.text",
+                    },
+                    "m.relates_to": {
+                        "rel_type": "m.replace",
+                        "event_id": "$first",
+                    },
+                },
+                "event_id": "$edit",
+                "origin_server_ts": 152042,
+                "sender": "@alice:example.org",
+                "type": "m.room.message",
+            })),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+
+    let item = assert_next_matches!(timeline_stream, VectorDiff::Set { index: 0, value } => value);
+    assert_eq!(item.event_id(), Some(event_id!("$first")));
+    assert_eq!(item.content().as_message().unwrap().body(), "edited");
+    assert_pending!(timeline_stream);
+
+    // A reply in the thread of a pinned event isn't added to the timeline either.
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "content": {
+                "body": "in thread",
+                "msgtype": "m.text",
+                "m.relates_to": {
+                    "rel_type": "m.thread",
+                    "event_id": "$second",
+                    "is_falling_back": true,
+                    "m.in_reply_to": { "event_id": "$second" },
+                },
+            },
+            "event_id": "$thread_reply",
+            "origin_server_ts": 152043,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+        }),
+    ));
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+
+    assert_pending!(timeline_stream);
+    assert_eq!(timeline.items().await.iter().filter(|item| item.as_event().is_some()).count(), 2);
+
+    // When the pinned events change, they are reloaded.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_timeline_event(sync_timeline_event!(pinned_events(&["$second"]))),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+
+    assert_let!(Some(VectorDiff::Clear) = timeline_stream.next().await);
+    assert_let!(Some(VectorDiff::PushBack { value: item }) = timeline_stream.next().await);
+    assert_eq!(item.event_id(), Some(event_id!("$second")));
+    assert_pending!(timeline_stream);
+
+    // They are also reloaded when the new pinned events are in the state of a
+    // limited sync response, instead of its timeline.
+    ev_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .set_timeline_limited()
+            .add_state_bulk([sync_state_event!(pinned_events(&["$first"]))]),
+    );
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+
+    assert_let!(Some(VectorDiff::Clear) = timeline_stream.next().await);
+    assert_let!(Some(VectorDiff::PushBack { value: item }) = timeline_stream.next().await);
+    assert_eq!(item.event_id(), Some(event_id!("$first")));
+    assert_pending!(timeline_stream);
+}
@@ -180,34 +180,32 @@ async fn test_retry_order() {
     timeline.retry_send(&txn_id_2).await.unwrap();
     timeline.retry_send(&txn_id_1).await.unwrap();
 
-    // Both items are immediately updated and moved to the bottom in the order
-    // of the function calls to indicate they are being sent
-    assert_next_matches!(timeline_stream, VectorDiff::Remove { index: 1 });
-    assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => {
+    // Both items are immediately updated in place, in the order of the function
+    // calls, to indicate they are being sent
+    assert_next_matches!(timeline_stream, VectorDiff::Set { index: 1, value } => {
         assert_matches!(value.send_state().unwrap(), EventSendState::NotSentYet);
         assert_eq!(value.content().as_message().unwrap().body(), "Second.");
     });
-    assert_next_matches!(timeline_stream, VectorDiff::Remove { index: 0 });
-    assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => {
+    assert_next_matches!(timeline_stream, VectorDiff::Set { index: 0, value } => {
         assert_matches!(value.send_state().unwrap(), EventSendState::NotSentYet);
         assert_eq!(value.content().as_message().unwrap().body(), "First!");
     });
 
-    // Wait 200ms for the first msg, 100ms for the second, 300ms for overhead
+    // Wait 100ms for the first msg, 200ms for the second, 300ms for overhead
     sleep(Duration::from_millis(600)).await;
 
-    // The second item (now at index 0) should be updated first, since it was
-    // retried first
+    // The first item should be updated first, since the requests keep their
+    // order in the send queue, even though it was retried last
     assert_next_matches!(timeline_stream, VectorDiff::Set { index: 0, value } => {
-        assert_eq!(value.content().as_message().unwrap().body(), "Second.");
+        assert_eq!(value.content().as_message().unwrap().body(), "First!");
         assert_matches!(value.send_state().unwrap(), EventSendState::Sent { .. });
-        assert_eq!(value.event_id().unwrap(), "$5E2kLK/Sg342bgBU9ceEIEPYpbFaqJpZ");
+        assert_eq!(value.event_id().unwrap(), "$PyHxV5mYzjetBUT3qZq7V95GOzxb02EP");
     });
-    // Then the first one
+    // Then the second one
     assert_next_matches!(timeline_stream, VectorDiff::Set { index: 1, value } => {
-        assert_eq!(value.content().as_message().unwrap().body(), "First!");
+        assert_eq!(value.content().as_message().unwrap().body(), "Second.");
         assert_matches!(value.send_state().unwrap(), EventSendState::Sent { .. });
-        assert_eq!(value.event_id().unwrap(), "$PyHxV5mYzjetBUT3qZq7V95GOzxb02EP");
+        assert_eq!(value.event_id().unwrap(), "$5E2kLK/Sg342bgBU9ceEIEPYpbFaqJpZ");
     });
     assert_pending!(timeline_stream);
 }
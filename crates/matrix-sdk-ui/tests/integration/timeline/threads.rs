@@ -0,0 +1,189 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use eyeball_im::VectorDiff;
+use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder};
+use matrix_sdk_ui::timeline::{
+    BackPaginationStatus, PaginationOptions, RoomExt, TimelineFocus, TimelineItemContent,
+};
+use ruma::{
+    event_id,
+    events::room::message::{MessageType, RoomMessageEventContent},
+    owned_event_id, room_id,
+};
+use serde_json::json;
+use stream_assert::{assert_next_eq, assert_next_matches, assert_pending};
+use wiremock::{
+    matchers::{header, method, path_regex, query_param, query_param_is_missing},
+    Mock, ResponseTemplate,
+};
+
+use crate::mock_sync;
+
+fn thread_reply(event_id: &str, body: &str, ts: u64) -> serde_json::Value {
+    json!({
+        "content": {
+            "body": body,
+            "msgtype": "m.text",
+            "m.relates_to": {
+                "rel_type": "m.thread",
+                "event_id": "$root",
+                "is_falling_back": true,
+                "m.in_reply_to": { "event_id": "$root" },
+            },
+        },
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "room_id": "!a98sd12bjh:example.org",
+        "sender": "@bob:example.org",
+        "type": "m.room.message",
+    })
+}
+
+#[async_test]
+async fn test_thread_back_pagination() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Thread { root_event_id: owned_event_id!("$root") })
+        .build()
+        .await
+        .unwrap();
+    let (_, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+    let mut back_pagination_status = timeline.back_pagination_status();
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/rooms/.*/relations/\$root/m.thread$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [
+                thread_reply("$reply2", "second reply", 152039),
+                thread_reply("$reply1", "first reply", 152038),
+            ],
+            "next_batch": "next_batch_token",
+        })))
+        .expect(1)
+        .named("relations_batch_1")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_backwards(PaginationOptions::simple_request(2)).await.unwrap();
+    assert_next_eq!(back_pagination_status, BackPaginationStatus::Idle);
+
+    let reply = assert_next_matches!(timeline_stream, VectorDiff::PushFront { value } => value);
+    assert_eq!(reply.event_id(), Some(event_id!("$reply2")));
+    let reply = assert_next_matches!(timeline_stream, VectorDiff::PushFront { value } => value);
+    assert_eq!(reply.event_id(), Some(event_id!("$reply1")));
+    assert_let!(TimelineItemContent::Message(msg) = reply.content());
+    assert_eq!(msg.thread_root(), Some(event_id!("$root")));
+    assert_pending!(timeline_stream);
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/.*/rooms/.*/relations/\$root/m.thread$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "next_batch_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "chunk": [] })))
+        .expect(1)
+        .named("relations_batch_2")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/event/\$root"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "content": {
+                "body": "thread root",
+                "msgtype": "m.text",
+            },
+            "event_id": "$root",
+            "origin_server_ts": 152037,
+            "room_id": room_id,
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+            "unsigned": {
+                "m.relations": {
+                    "m.thread": {
+                        "latest_event": thread_reply("$reply2", "second reply", 152039),
+                        "count": 2,
+                        "current_user_participated": false,
+                    },
+                },
+            },
+        })))
+        .expect(1)
+        .named("thread_root")
+        .mount(&server)
+        .await;
+
+    timeline.paginate_backwards(PaginationOptions::simple_request(2)).await.unwrap();
+    assert_next_eq!(back_pagination_status, BackPaginationStatus::TimelineStartReached);
+
+    let root = assert_next_matches!(timeline_stream, VectorDiff::PushFront { value } => value);
+    assert_eq!(root.event_id(), Some(event_id!("$root")));
+    assert_let!(TimelineItemContent::Message(msg) = root.content());
+    assert_let!(MessageType::Text(text) = msg.msgtype());
+    assert_eq!(text.body, "thread root");
+
+    let summary = root.thread_summary().unwrap();
+    assert_eq!(summary.num_replies(), 2);
+    assert_eq!(summary.latest_reply(), Some(event_id!("$reply2")));
+}
+
+#[async_test]
+async fn test_thread_send_attaches_thread_relation() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut ev_builder = SyncResponseBuilder::new();
+    ev_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, ev_builder.build_json_sync_response(), None).await;
+    let _response = client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let timeline = room
+        .timeline_builder()
+        .with_focus(TimelineFocus::Thread { root_event_id: owned_event_id!("$root") })
+        .build()
+        .await
+        .unwrap();
+    let (_, mut timeline_stream) =
+        timeline.subscribe_filter_map(|item| item.as_event().cloned()).await;
+
+    timeline.send(RoomMessageEventContent::text_plain("hi").into()).await;
+
+    let local_echo = assert_next_matches!(timeline_stream, VectorDiff::PushBack { value } => value);
+    assert_let!(TimelineItemContent::Message(msg) = local_echo.content());
+    assert_eq!(msg.thread_root(), Some(event_id!("$root")));
+}
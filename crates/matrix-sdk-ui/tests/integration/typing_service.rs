@@ -0,0 +1,202 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use futures_util::{pin_mut, StreamExt as _};
+use matrix_sdk::{config::SyncSettings, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{
+    async_test, sync_state_event, EphemeralTestEvent, GlobalAccountDataTestEvent,
+    JoinedRoomBuilder, SyncResponseBuilder,
+};
+use matrix_sdk_ui::typing_service::{Typer, TypingService};
+use ruma::{mxc_uri, room_id, user_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use crate::mock_sync;
+
+#[async_test]
+async fn test_typers() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_state_bulk([
+        sync_state_event!({
+            "content": {
+                "avatar_url": "mxc://example.org/alice",
+                "displayname": "Alice",
+                "membership": "join",
+            },
+            "event_id": "$alice_join",
+            "origin_server_ts": 151800140,
+            "sender": "@alice:example.org",
+            "state_key": "@alice:example.org",
+            "type": "m.room.member",
+        }),
+    ]));
+    sync_builder.add_global_account_data_event(GlobalAccountDataTestEvent::Custom(json!({
+        "content": {
+            "ignored_users": {
+                "@carol:example.org": {}
+            }
+        },
+        "type": "m.ignored_user_list",
+    })));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let typing_service = TypingService::new(room);
+
+    let typers = typing_service.subscribe();
+    pin_mut!(typers);
+
+    // Alice, Bob, Carol and ourselves are typing.
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_ephemeral_event(
+        EphemeralTestEvent::Custom(json!({
+            "content": {
+                "user_ids": [
+                    "@alice:example.org",
+                    "@bob:example.org",
+                    "@carol:example.org",
+                    "@example:localhost",
+                ]
+            },
+            "type": "m.typing",
+        })),
+    ));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    // Ourselves and the ignored user Carol are filtered out, and the known member
+    // is resolved.
+    let typers = tokio::time::timeout(Duration::from_secs(1), typers.next())
+        .await
+        .expect("the typers should have been updated")
+        .unwrap();
+
+    assert_eq!(
+        typers,
+        vec![
+            Typer {
+                user_id: user_id!("@alice:example.org").to_owned(),
+                display_name: Some("Alice".to_owned()),
+                avatar_url: Some(mxc_uri!("mxc://example.org/alice").to_owned()),
+            },
+            Typer {
+                user_id: user_id!("@bob:example.org").to_owned(),
+                display_name: None,
+                avatar_url: None,
+            },
+        ]
+    );
+}
+
+#[async_test]
+async fn test_keystrokes_send_a_single_typing_notice() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/typing/.*"))
+        .and(body_partial_json(json!({ "typing": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("typing start")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/typing/.*"))
+        .and(body_partial_json(json!({ "typing": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("typing stop")
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let typing_service = TypingService::new(room);
+
+    // Several keystrokes in a row only send one typing notice.
+    for _ in 0..5 {
+        typing_service.on_keystroke();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Stopping sends the typing stop notice right away.
+    typing_service.stop_typing().await.unwrap();
+}
+
+#[async_test]
+async fn test_typing_notice_is_renewed_while_typing() {
+    let room_id = room_id!("!a98sd12bjh:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let sync_settings = SyncSettings::new().timeout(Duration::from_millis(3000));
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(sync_settings.clone()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/typing/.*"))
+        .and(body_partial_json(json!({ "typing": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(2)
+        .named("typing start")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/typing/.*"))
+        .and(body_partial_json(json!({ "typing": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("typing stop")
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(room_id).unwrap();
+    let typing_service = TypingService::new(room);
+
+    // The user keeps typing for a bit less than 4 seconds, so the first typing
+    // notice is renewed once, before it expires.
+    for _ in 0..4 {
+        typing_service.on_keystroke();
+        tokio::time::sleep(Duration::from_millis(950)).await;
+    }
+
+    typing_service.stop_typing().await.unwrap();
+}
@@ -601,9 +601,14 @@ async fn build_store_config(
     let store_config = match builder_config {
         #[cfg(feature = "sqlite")]
         BuilderStoreConfig::Sqlite { path, passphrase } => {
-            let store_config = StoreConfig::new().state_store(
-                matrix_sdk_sqlite::SqliteStateStore::open(&path, passphrase.as_deref()).await?,
-            );
+            let store_config = StoreConfig::new()
+                .state_store(
+                    matrix_sdk_sqlite::SqliteStateStore::open(&path, passphrase.as_deref()).await?,
+                )
+                .event_cache_store(
+                    matrix_sdk_sqlite::SqliteEventCacheStore::open(&path, passphrase.as_deref())
+                        .await?,
+                );
 
             #[cfg(feature = "e2e-encryption")]
             let store_config = store_config.crypto_store(
@@ -623,8 +628,8 @@ async fn build_store_config(
     Ok(store_config)
 }
 
-// The indexeddb stores only implement `IntoStateStore` and `IntoCryptoStore` on
-// wasm32, so this only compiles there.
+// The indexeddb stores only implement `IntoStateStore`, `IntoCryptoStore` and
+// `IntoEventCacheStore` on wasm32, so this only compiles there.
 #[cfg(all(target_arch = "wasm32", feature = "indexeddb"))]
 async fn build_indexeddb_store_config(
     name: &str,
@@ -634,13 +639,18 @@ async fn build_indexeddb_store_config(
     {
         let (state_store, crypto_store) =
             matrix_sdk_indexeddb::open_stores_with_name(name, passphrase).await?;
-        Ok(StoreConfig::new().state_store(state_store).crypto_store(crypto_store))
+        let event_cache_store = matrix_sdk_indexeddb::open_event_cache_store(&state_store).await?;
+        Ok(StoreConfig::new()
+            .state_store(state_store)
+            .crypto_store(crypto_store)
+            .event_cache_store(event_cache_store))
     }
 
     #[cfg(not(feature = "e2e-encryption"))]
     {
         let state_store = matrix_sdk_indexeddb::open_state_store(name, passphrase).await?;
-        Ok(StoreConfig::new().state_store(state_store))
+        let event_cache_store = matrix_sdk_indexeddb::open_event_cache_store(&state_store).await?;
+        Ok(StoreConfig::new().state_store(state_store).event_cache_store(event_cache_store))
     }
 }
 
@@ -75,13 +75,15 @@ use crate::{
     config::RequestConfig,
     deduplicating_handler::DeduplicatingHandler,
     error::{HttpError, HttpResult},
-    event_cache::EventCache,
+    event_cache::{EventCache, SearchHit},
     event_handler::{
         EventHandler, EventHandlerDropGuard, EventHandlerHandle, EventHandlerStore, SyncEvent,
     },
     http_client::HttpClient,
     matrix_auth::MatrixAuth,
     notification_settings::NotificationSettings,
+    search::SearchBuilder,
+    send_queue::{SendQueue, SendQueueData},
     sync::{RoomUpdate, SyncResponse},
     Account, AuthApi, AuthSession, Error, Media, Pusher, RefreshTokenError, Result, Room,
     TransmissionProgress,
@@ -274,6 +276,9 @@ pub(crate) struct ClientInner {
     /// It becomes active when [`EventCache::subscribe`] is called.
     pub(crate) event_cache: OnceCell<EventCache>,
 
+    /// The send queues of all the rooms, and their shared state.
+    pub(crate) send_queue_data: SendQueueData,
+
     /// End-to-end encryption related state.
     #[cfg(feature = "e2e-encryption")]
     pub(crate) e2ee: EncryptionData,
@@ -322,6 +327,7 @@ impl ClientInner {
             respect_login_well_known,
             sync_beat: event_listener::Event::new(),
             event_cache,
+            send_queue_data: SendQueueData::new(),
             #[cfg(feature = "e2e-encryption")]
             e2ee: EncryptionData::new(encryption_settings),
             #[cfg(feature = "e2e-encryption")]
@@ -2093,6 +2099,32 @@ impl Client {
         // SAFETY: always initialized in the `Client` ctor.
         self.inner.event_cache.get().unwrap()
     }
+
+    /// The [`SendQueue`] of this [`Client`], giving access to the send queues
+    /// of all the rooms.
+    pub fn send_queue(&self) -> SendQueue {
+        SendQueue::new(self.clone())
+    }
+
+    /// Search the messages of the rooms on the homeserver, with the given
+    /// search term.
+    ///
+    /// This returns a [`SearchBuilder`] to configure the search, before
+    /// fetching the pages of results with [`SearchBuilder::into_stream`].
+    ///
+    /// Note that the homeserver can't search the messages of encrypted rooms,
+    /// see [`Client::search_messages`] for this.
+    pub fn search(&self, search_term: impl Into<String>) -> SearchBuilder {
+        SearchBuilder::new(self.clone(), search_term.into())
+    }
+
+    /// Search the messages of all the rooms in the local search index, the
+    /// best matches first.
+    ///
+    /// See [`Room::search_messages`] for the details.
+    pub async fn search_messages(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        Ok(self.base_client().event_cache_store().search_messages(None, query, limit).await?)
+    }
 }
 
 // The http mocking library is not supported for wasm32
@@ -510,7 +510,7 @@ impl Backups {
         Ok(())
     }
 
-    fn room_keys_stream(
+    pub(crate) fn room_keys_stream(
         &self,
     ) -> impl Stream<Item = Result<RoomKeyImportResult, BroadcastStreamRecvError>> {
         BroadcastStream::new(self.client.inner.e2ee.backup_state.room_keys_broadcaster.subscribe())
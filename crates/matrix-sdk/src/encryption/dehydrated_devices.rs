@@ -0,0 +1,482 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The dehydrated devices module.
+//!
+//! A dehydrated device is a device that lives on the homeserver, and that
+//! receives the room keys sent to the user while they don't have any other
+//! device, e.g. because they have logged out of all their devices. The private
+//! keys of the dehydrated device are encrypted with a pickle key, which is
+//! stored in the secret storage of the user, alongside the other secrets.
+//!
+//! When the user logs in again and recovers their secrets, the dehydrated
+//! device is rehydrated: the room keys it has received are imported in the
+//! new device, and the dehydrated device is replaced by a new one.
+//!
+//! See [MSC3814] for more details.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use matrix_sdk::Client;
+//! # use url::Url;
+//! # async {
+//! # let homeserver = Url::parse("http://example.com")?;
+//! # let client = Client::new(homeserver).await?;
+//! let secret_store = client
+//!     .encryption()
+//!     .secret_storage()
+//!     .open_secret_store("my recovery key or passphrase")
+//!     .await?;
+//!
+//! // Create a dehydrated device, and rotate it periodically from now on.
+//! client.encryption().dehydrated_devices().create(&secret_store).await?;
+//! # anyhow::Ok(()) };
+//! ```
+//!
+//! [MSC3814]: https://github.com/matrix-org/matrix-spec-proposals/pull/3814
+
+use std::{sync::Arc, time::Duration};
+
+use matrix_sdk_base::crypto::{
+    dehydrated_devices::DehydrationError,
+    vodozemac::{base64_decode, base64_encode},
+};
+use rand::RngCore;
+use ruma::{
+    api::client::{
+        dehydrated_device::{delete_dehydrated_device, get_dehydrated_device, get_events},
+        error::ErrorKind,
+    },
+    assign,
+};
+use thiserror::Error;
+use tracing::{info, instrument, warn};
+use zeroize::{Zeroize, Zeroizing};
+
+use super::{
+    secret_storage::{SecretStorageError, SecretStore},
+    tasks::DehydratedDeviceRotationTask,
+};
+use crate::{Client, Error, HttpError};
+
+/// The name of the secret containing the pickle key of the dehydrated device,
+/// in the secret storage.
+pub(crate) const PICKLE_KEY_SECRET_NAME: &str = "org.matrix.msc3814";
+
+/// The key of the pickle key of the dehydrated device, in the crypto store.
+pub(crate) const PICKLE_KEY_STORE_KEY: &str = "dehydrated_device_pickle_key";
+
+/// The period after which the dehydrated device is replaced by a new one.
+///
+/// The dehydrated device uses its one-time keys when other devices create
+/// Olm sessions with it, and it can't upload new ones, so it must be rotated
+/// regularly.
+const ROTATION_PERIOD: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// The display name of the dehydrated devices.
+const DEVICE_DISPLAY_NAME: &str = "Dehydrated device";
+
+/// Result type alias for the [`DehydratedDevices`] subsystem.
+pub type Result<T, E = DehydratedDeviceError> = std::result::Result<T, E>;
+
+/// Error type for the [`DehydratedDevices`] subsystem.
+#[derive(Debug, Error)]
+pub enum DehydratedDeviceError {
+    /// A typical SDK error.
+    #[error(transparent)]
+    Sdk(#[from] Error),
+
+    /// Error in the secret storage subsystem.
+    #[error(transparent)]
+    SecretStorage(#[from] SecretStorageError),
+
+    /// The dehydrated device couldn't be created or rehydrated.
+    #[error(transparent)]
+    Dehydration(#[from] DehydrationError),
+
+    /// The pickle key of the dehydrated device couldn't be found, either in
+    /// the secret storage or locally.
+    #[error("The pickle key of the dehydrated device is missing")]
+    MissingPickleKey,
+
+    /// The pickle key of the dehydrated device isn't a valid 32 bytes key.
+    #[error("The pickle key of the dehydrated device is invalid")]
+    InvalidPickleKey,
+}
+
+impl From<HttpError> for DehydratedDeviceError {
+    fn from(error: HttpError) -> Self {
+        Self::Sdk(error.into())
+    }
+}
+
+/// The dehydrated devices manager for the [`Client`].
+#[derive(Debug)]
+pub struct DehydratedDevices {
+    pub(super) client: Client,
+}
+
+impl DehydratedDevices {
+    /// Create a new dehydrated device and upload it to the homeserver,
+    /// replacing the existing one, if any.
+    ///
+    /// The pickle key of the dehydrated device is taken from the given secret
+    /// store, or generated and stored in it if there's none yet. The dehydrated
+    /// device is then rotated periodically, as long as the [`Client`] is
+    /// alive.
+    ///
+    /// Our private cross-signing keys must be known, to sign the dehydrated
+    /// device.
+    #[instrument(skip_all)]
+    pub async fn create(&self, secret_store: &SecretStore) -> Result<()> {
+        let pickle_key = match self.pickle_key_from_secret_store(secret_store).await? {
+            Some(pickle_key) => pickle_key,
+            None => {
+                info!("Generating a new pickle key for the dehydrated device");
+
+                let mut pickle_key = Zeroizing::new([0u8; 32]);
+                rand::thread_rng().fill_bytes(pickle_key.as_mut());
+
+                let mut secret = base64_encode(pickle_key.as_ref());
+                let ret = secret_store.put_secret(PICKLE_KEY_SECRET_NAME, &secret).await;
+                secret.zeroize();
+                ret?;
+
+                pickle_key
+            }
+        };
+
+        self.save_pickle_key(&pickle_key).await?;
+        self.upload_new_device(&pickle_key).await?;
+        self.start_rotation_task();
+
+        Ok(())
+    }
+
+    /// Is there a dehydrated device managed by this [`Client`]?
+    ///
+    /// This is the case after a call to [`DehydratedDevices::create()`] or
+    /// [`DehydratedDevices::rehydrate()`], until
+    /// [`DehydratedDevices::disable()`] is called.
+    pub async fn is_enabled(&self) -> Result<bool> {
+        Ok(self.load_pickle_key().await?.is_some())
+    }
+
+    /// Replace the dehydrated device with a new one.
+    ///
+    /// This is done automatically every week, but can be done earlier if
+    /// needed, e.g. if many Olm sessions have been created with the dehydrated
+    /// device.
+    #[instrument(skip_all)]
+    pub async fn rotate(&self) -> Result<()> {
+        let pickle_key =
+            self.load_pickle_key().await?.ok_or(DehydratedDeviceError::MissingPickleKey)?;
+        self.upload_new_device(&pickle_key).await
+    }
+
+    /// Rehydrate the dehydrated device of the user, if any, to import the room
+    /// keys it has received while the user didn't have any other device.
+    ///
+    /// The pickle key of the dehydrated device is taken from the given secret
+    /// store. Once the room keys have been imported, the dehydrated device is
+    /// replaced by a new one, which is then rotated periodically.
+    ///
+    /// This is done automatically by [`SecretStore::import_secrets()`], if the
+    /// secret store contains the pickle key of a dehydrated device.
+    ///
+    /// Returns the number of room keys that have been imported.
+    #[instrument(skip_all)]
+    pub async fn rehydrate(&self, secret_store: &SecretStore) -> Result<usize> {
+        let pickle_key = self
+            .pickle_key_from_secret_store(secret_store)
+            .await?
+            .ok_or(DehydratedDeviceError::MissingPickleKey)?;
+
+        self.rehydrate_with_pickle_key(&pickle_key).await
+    }
+
+    /// Delete the dehydrated device from the homeserver, and stop rotating it.
+    ///
+    /// The pickle key is left in the secret storage, so a dehydrated device
+    /// can be created again with the same key. If the dehydrated device
+    /// couldn't be deleted from the homeserver, it is still rotated.
+    #[instrument(skip_all)]
+    pub async fn disable(&self) -> Result<()> {
+        // Stop the rotation first, so a new dehydrated device isn't uploaded while we
+        // delete the current one.
+        let was_rotating =
+            self.client.inner.e2ee.tasks.lock().unwrap().rotate_dehydrated_device.take().is_some();
+
+        match self.client.send(delete_dehydrated_device::unstable::Request::new(), None).await {
+            Ok(_) => {}
+            Err(err) if err.client_api_error_kind() == Some(&ErrorKind::NotFound) => {}
+            Err(err) => {
+                // We still manage the dehydrated device, keep rotating it.
+                if was_rotating {
+                    self.start_rotation_task();
+                }
+
+                return Err(err.into());
+            }
+        }
+
+        // Only forget the pickle key once the dehydrated device is gone, otherwise we
+        // couldn't rotate nor delete it anymore.
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+        olm_machine.store().remove_custom_value(PICKLE_KEY_STORE_KEY).await.map_err(Error::from)?;
+
+        Ok(())
+    }
+
+    /// Rehydrate the dehydrated device if the given secret store contains its
+    /// pickle key, logging the errors.
+    pub(crate) async fn rehydrate_if_enabled(&self, secret_store: &SecretStore) {
+        match self.pickle_key_from_secret_store(secret_store).await {
+            Ok(Some(pickle_key)) => match self.rehydrate_with_pickle_key(&pickle_key).await {
+                Ok(num_room_keys) => {
+                    info!(num_room_keys, "Rehydrated the dehydrated device");
+                }
+                Err(e) => warn!("Couldn't rehydrate the dehydrated device: {e:?}"),
+            },
+            Ok(None) => {}
+            Err(e) => warn!("Couldn't get the pickle key of the dehydrated device: {e:?}"),
+        }
+    }
+
+    async fn rehydrate_with_pickle_key(&self, pickle_key: &[u8; 32]) -> Result<usize> {
+        self.save_pickle_key(pickle_key).await?;
+
+        let num_room_keys = self.import_room_keys(pickle_key).await?;
+
+        self.upload_new_device(pickle_key).await?;
+        self.start_rotation_task();
+
+        Ok(num_room_keys)
+    }
+
+    /// Start rotating the dehydrated device periodically, if we manage one.
+    pub(crate) async fn resume_rotation(&self) -> Result<()> {
+        if self.is_enabled().await? {
+            self.start_rotation_task();
+        }
+
+        Ok(())
+    }
+
+    fn start_rotation_task(&self) {
+        let task =
+            DehydratedDeviceRotationTask::new(Arc::downgrade(&self.client.inner), ROTATION_PERIOD);
+        self.client.inner.e2ee.tasks.lock().unwrap().rotate_dehydrated_device = Some(task);
+    }
+
+    /// Create a new dehydrated device and upload it, replacing the existing
+    /// one, if any.
+    async fn upload_new_device(&self, pickle_key: &[u8; 32]) -> Result<()> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let device = olm_machine.dehydrated_devices().create().await?;
+        let request = device.keys_for_upload(DEVICE_DISPLAY_NAME.to_owned(), pickle_key).await?;
+        let device_id = request.device_id.clone();
+
+        self.client.send(request, None).await?;
+
+        info!(%device_id, "Uploaded a new dehydrated device");
+
+        Ok(())
+    }
+
+    /// Rehydrate the dehydrated device stored on the homeserver, if any, and
+    /// import the room keys it has received.
+    async fn import_room_keys(&self, pickle_key: &[u8; 32]) -> Result<usize> {
+        let response =
+            match self.client.send(get_dehydrated_device::unstable::Request::new(), None).await {
+                Ok(response) => response,
+                Err(err) if err.client_api_error_kind() == Some(&ErrorKind::NotFound) => {
+                    info!("There is no dehydrated device to rehydrate");
+                    return Ok(0);
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let device_id = response.device_id;
+        let rehydrated = olm_machine
+            .dehydrated_devices()
+            .rehydrate(pickle_key, &device_id, response.device_data)
+            .await?;
+
+        let mut next_batch = None;
+        let mut num_room_keys = 0;
+
+        loop {
+            let request = assign!(get_events::unstable::Request::new(device_id.clone()), {
+                next_batch,
+            });
+            let response = self.client.send(request, None).await?;
+
+            if response.events.is_empty() {
+                break;
+            }
+
+            next_batch = response.next_batch;
+            num_room_keys +=
+                rehydrated.receive_events(response.events).await.map_err(Error::from)?.len();
+        }
+
+        Ok(num_room_keys)
+    }
+
+    async fn pickle_key_from_secret_store(
+        &self,
+        secret_store: &SecretStore,
+    ) -> Result<Option<Zeroizing<[u8; 32]>>> {
+        let Some(secret) = secret_store.get_secret(PICKLE_KEY_SECRET_NAME).await? else {
+            return Ok(None);
+        };
+        let secret = Zeroizing::new(secret);
+
+        let bytes = Zeroizing::new(
+            base64_decode(secret.as_str()).map_err(|_| DehydratedDeviceError::InvalidPickleKey)?,
+        );
+        Self::pickle_key_from_bytes(&bytes).map(Some)
+    }
+
+    async fn load_pickle_key(&self) -> Result<Option<Zeroizing<[u8; 32]>>> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        let Some(bytes) = olm_machine
+            .store()
+            .get_custom_value(PICKLE_KEY_STORE_KEY)
+            .await
+            .map_err(Error::from)?
+        else {
+            return Ok(None);
+        };
+
+        Self::pickle_key_from_bytes(&Zeroizing::new(bytes)).map(Some)
+    }
+
+    async fn save_pickle_key(&self, pickle_key: &[u8; 32]) -> Result<()> {
+        let olm_machine = self.client.olm_machine().await;
+        let olm_machine = olm_machine.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        olm_machine
+            .store()
+            .set_custom_value(PICKLE_KEY_STORE_KEY, pickle_key.to_vec())
+            .await
+            .map_err(Error::from)?;
+
+        Ok(())
+    }
+
+    fn pickle_key_from_bytes(bytes: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let mut pickle_key = Zeroizing::new([0u8; 32]);
+
+        if bytes.len() != pickle_key.len() {
+            return Err(DehydratedDeviceError::InvalidPickleKey);
+        }
+
+        pickle_key.copy_from_slice(bytes);
+        Ok(pickle_key)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use matrix_sdk_test::async_test;
+    use serde_json::json;
+    use wiremock::{
+        matchers::{method, path_regex},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    use crate::{
+        encryption::tasks::DehydratedDeviceRotationTask, test_utils::logged_in_client_with_server,
+    };
+
+    async fn num_uploaded_devices(server: &MockServer) -> usize {
+        server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| {
+                request.method == wiremock::http::Method::Put
+                    && request.url.path().ends_with("/dehydrated_device")
+            })
+            .count()
+    }
+
+    #[async_test]
+    async fn test_rotation_task_rotates_until_dropped() {
+        let (client, server) = logged_in_client_with_server().await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/r0/keys/upload$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "one_time_key_counts": { "signed_curve25519": 50 },
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("POST"))
+            .and(path_regex(r"^/_matrix/client/unstable/keys/(device_signing|signatures)/upload$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "failures": {} })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("PUT"))
+            .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device$"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "device_id": "DEHYDRATED",
+            })))
+            .mount(&server)
+            .await;
+
+        // The dehydrated device is signed with our self-signing key.
+        client.encryption().bootstrap_cross_signing(None).await.unwrap();
+
+        let dehydrated_devices = client.encryption().dehydrated_devices();
+        dehydrated_devices.save_pickle_key(&[0u8; 32]).await.unwrap();
+
+        let task = DehydratedDeviceRotationTask::new(
+            Arc::downgrade(&client.inner),
+            Duration::from_millis(100),
+        );
+
+        // The dehydrated device is replaced every period.
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while num_uploaded_devices(&server).await < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("The dehydrated device should have been rotated twice");
+
+        // Once the task is dropped, the dehydrated device isn't rotated anymore.
+        drop(task);
+        let num_rotations = num_uploaded_devices(&server).await;
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(num_uploaded_devices(&server).await, num_rotations);
+    }
+}
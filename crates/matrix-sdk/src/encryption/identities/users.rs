@@ -15,7 +15,9 @@
 use std::collections::BTreeMap;
 
 use matrix_sdk_base::{
-    crypto::{types::MasterPubkey, UserIdentities as CryptoUserIdentities},
+    crypto::{
+        store::CryptoStoreError, types::MasterPubkey, UserIdentities as CryptoUserIdentities,
+    },
     RoomMemberships,
 };
 use ruma::{
@@ -356,6 +358,79 @@ impl UserIdentity {
         }
     }
 
+    /// Has the identity of this user changed since we first saw it, or since
+    /// we last acknowledged it, without us having verified the new identity?
+    ///
+    /// Apps should warn the user about such a change, which might mean that
+    /// someone is impersonating the user. The change can be acknowledged with
+    /// [`UserIdentity::pin_current_identity()`] or, if the user was
+    /// verified before, with [`UserIdentity::withdraw_verification()`].
+    ///
+    /// This is always `false` for our own user identity.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::{Client, ruma::user_id};
+    /// # use url::Url;
+    /// # let alice = user_id!("@alice:example.org");
+    /// # let homeserver = Url::parse("http://example.com").unwrap();
+    /// # async {
+    /// # let client = Client::new(homeserver).await.unwrap();
+    /// let user = client.encryption().get_user_identity(alice).await?;
+    ///
+    /// if let Some(user) = user {
+    ///     if user.identity_needs_user_approval() {
+    ///         println!("The identity of {} changed", user.user_id());
+    ///     }
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub fn identity_needs_user_approval(&self) -> bool {
+        match &self.inner.identity {
+            CryptoUserIdentities::Own(_) => false,
+            CryptoUserIdentities::Other(identity) => identity.identity_needs_user_approval(),
+        }
+    }
+
+    /// Was this user verified in the past, but isn't verified anymore, most
+    /// likely because their identity changed?
+    ///
+    /// This is always `false` for our own user identity.
+    pub fn has_verification_violation(&self) -> bool {
+        match &self.inner.identity {
+            CryptoUserIdentities::Own(_) => false,
+            CryptoUserIdentities::Other(identity) => identity.has_verification_violation(),
+        }
+    }
+
+    /// Acknowledge a change of the identity of this user, by pinning its
+    /// current master key.
+    ///
+    /// If the user was verified before, the change needs to be acknowledged
+    /// with [`UserIdentity::withdraw_verification()`] instead.
+    ///
+    /// This does nothing for our own user identity.
+    pub async fn pin_current_identity(&self) -> Result<(), CryptoStoreError> {
+        match &self.inner.identity {
+            CryptoUserIdentities::Own(_) => Ok(()),
+            CryptoUserIdentities::Other(identity) => identity.pin_current_identity().await,
+        }
+    }
+
+    /// Acknowledge that this user, who was verified in the past, isn't
+    /// verified anymore, and pin their current master key.
+    ///
+    /// The user needs to be verified again to be considered as verified.
+    ///
+    /// This does nothing for our own user identity.
+    pub async fn withdraw_verification(&self) -> Result<(), CryptoStoreError> {
+        match &self.inner.identity {
+            CryptoUserIdentities::Own(_) => Ok(()),
+            CryptoUserIdentities::Other(identity) => identity.withdraw_verification().await,
+        }
+    }
+
     /// Get the public part of the Master key of this user identity.
     ///
     /// The public part of the Master key is usually used to uniquely identify
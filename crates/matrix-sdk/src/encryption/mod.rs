@@ -60,8 +60,9 @@ use tracing::{debug, error, instrument, trace, warn};
 
 use self::{
     backups::{types::BackupClientState, Backups},
+    dehydrated_devices::DehydratedDevices,
     futures::PrepareEncryptedFile,
-    identities::{DeviceUpdates, IdentityUpdates},
+    identities::{DeviceUpdates, IdentityUpdates, UserIdentity},
     recovery::{Recovery, RecoveryState},
     secret_storage::SecretStorage,
     tasks::{BackupDownloadTask, BackupUploadingTask, ClientTasks},
@@ -79,6 +80,7 @@ use crate::{
 };
 
 pub mod backups;
+pub mod dehydrated_devices;
 pub mod futures;
 pub mod identities;
 pub mod recovery;
@@ -91,9 +93,9 @@ pub use matrix_sdk_base::crypto::{
         SessionCreationError as MegolmSessionCreationError,
         SessionExportError as OlmSessionExportError,
     },
-    vodozemac, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError, KeyExportError,
-    LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult, SecretImportError,
-    SessionCreationError, SignatureError, VERSION,
+    vodozemac, CollectStrategy, CrossSigningStatus, CryptoStoreError, DecryptorError, EventError,
+    KeyExportError, LocalTrust, MediaEncryptionInfo, MegolmError, OlmError, RoomKeyImportResult,
+    SecretImportError, SessionCreationError, SignatureError, VERSION,
 };
 
 pub use crate::error::RoomKeyImportError;
@@ -907,6 +909,95 @@ impl Encryption {
             .map(move |updates| IdentityUpdates::new(client.to_owned(), updates)))
     }
 
+    /// Returns a stream of the user identities that changed without the
+    /// change being acknowledged, see
+    /// [`UserIdentity::identity_needs_user_approval()`].
+    ///
+    /// An identity is yielded every time it's updated while it needs to be
+    /// approved, e.g. when the user's master key changes after we first saw
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use matrix_sdk::Client;
+    /// # use futures_util::{pin_mut, StreamExt};
+    /// # let client: Client = unimplemented!();
+    /// # async {
+    /// let violations = client.encryption().identity_violations_stream().await?;
+    /// pin_mut!(violations);
+    ///
+    /// while let Some(identity) = violations.next().await {
+    ///     println!("The identity of {} changed", identity.user_id());
+    ///     identity.pin_current_identity().await?;
+    /// }
+    /// # anyhow::Ok(()) };
+    /// ```
+    pub async fn identity_violations_stream(&self) -> Result<impl Stream<Item = UserIdentity>> {
+        let updates = self.user_identities_stream().await?;
+
+        Ok(updates.flat_map(|updates| {
+            stream::iter(
+                updates
+                    .new
+                    .into_values()
+                    .chain(updates.changed.into_values())
+                    .filter(|identity| identity.identity_needs_user_approval()),
+            )
+        }))
+    }
+
+    /// Set whether sending a message should fail in rooms where the identity
+    /// of a member changed without the change being acknowledged.
+    ///
+    /// When enabled, sending a message in such a room fails with an
+    /// [`OlmError::IdentityViolation`], until the changes are acknowledged
+    /// with [`UserIdentity::pin_current_identity()`] or
+    /// [`UserIdentity::withdraw_verification()`]. This is disabled by default.
+    ///
+    /// [`OlmError::IdentityViolation`]: matrix_sdk_base::crypto::OlmError::IdentityViolation
+    pub async fn set_error_on_identity_violation(&self, enabled: bool) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().set_error_on_identity_violation(enabled).await?)
+    }
+
+    /// Set the strategy to collect the devices that should receive the room
+    /// keys of the encrypted rooms, for example to only share them with the
+    /// devices that were cross-signed by their owner.
+    ///
+    /// The devices that don't satisfy the strategy are sent a withheld code
+    /// instead. Rooms can override this strategy in their room settings. The
+    /// default is [`CollectStrategy::AllDevices`].
+    pub async fn set_room_key_sharing_strategy(&self, strategy: CollectStrategy) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().set_room_key_sharing_strategy(strategy).await?)
+    }
+
+    /// Set whether the room keys of encrypted rooms should be forwarded to the
+    /// users we invite, so they can read the existing history, as defined in
+    /// [MSC3061].
+    ///
+    /// Only the room keys that were created while the history of the room was
+    /// visible to new members are forwarded, when inviting users with
+    /// [`Room::invite_user_by_id()`] to rooms with a `shared` or
+    /// `world_readable` history visibility. This is disabled by default.
+    ///
+    /// The invited users only import the room keys once they accept the
+    /// invite.
+    ///
+    /// [MSC3061]: https://github.com/matrix-org/matrix-spec-proposals/pull/3061
+    /// [`Room::invite_user_by_id()`]: crate::Room::invite_user_by_id
+    pub async fn set_share_history_on_invite(&self, enabled: bool) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let olm = olm.as_ref().ok_or(Error::NoOlmMachine)?;
+
+        Ok(olm.store().set_share_history_on_invite(enabled).await?)
+    }
+
     /// Create and upload a new cross signing identity.
     ///
     /// # Arguments
@@ -1204,6 +1295,11 @@ impl Encryption {
         Recovery { client: self.client.to_owned() }
     }
 
+    /// Get the dehydrated devices manager of the client.
+    pub fn dehydrated_devices(&self) -> DehydratedDevices {
+        DehydratedDevices { client: self.client.to_owned() }
+    }
+
     /// Enables the crypto-store cross-process lock.
     ///
     /// This may be required if there are multiple processes that may do writes
@@ -1355,8 +1451,26 @@ impl Encryption {
     /// the initial upload of cross-signing keys without authentication,
     /// rendering this parameter obsolete.
     pub(crate) async fn run_initialization_tasks(&self, auth_data: Option<AuthData>) -> Result<()> {
+        // Update the latest events of the rooms when room keys are received, including
+        // outside of a sync, e.g. from the key backup.
+        #[cfg(feature = "experimental-sliding-sync")]
+        let room_keys_stream = self
+            .client
+            .olm_machine()
+            .await
+            .as_ref()
+            .map(|olm_machine| olm_machine.store().room_keys_received_stream());
+
         let mut tasks = self.client.inner.e2ee.tasks.lock().unwrap();
 
+        #[cfg(feature = "experimental-sliding-sync")]
+        if let Some(room_keys_stream) = room_keys_stream {
+            tasks.decrypt_latest_events = Some(tasks::LatestEventsDecryptionTask::new(
+                Arc::downgrade(&self.client.inner),
+                room_keys_stream,
+            ));
+        }
+
         let this = self.clone();
         tasks.setup_e2ee = Some(spawn(async move {
             if this.settings().auto_enable_cross_signing {
@@ -1371,6 +1485,9 @@ impl Encryption {
             if let Err(e) = this.recovery().setup().await {
                 error!("Couldn't setup and resume recovery {e:?}");
             }
+            if let Err(e) = this.dehydrated_devices().resume_rotation().await {
+                error!("Couldn't resume the rotation of the dehydrated device {e:?}");
+            }
 
             this.update_verification_state().await;
         }));
@@ -14,7 +14,9 @@
 
 use std::fmt;
 
-use matrix_sdk_base::crypto::{secret_storage::SecretStorageKey, CrossSigningKeyExport};
+use matrix_sdk_base::crypto::{
+    secret_storage::SecretStorageKey, vodozemac::base64_encode, CrossSigningKeyExport,
+};
 use ruma::{
     events::{
         secret::request::SecretName, secret_storage::secret::SecretEventContent,
@@ -31,7 +33,7 @@ use tracing::{
 use zeroize::Zeroize;
 
 use super::{DecryptionError, Result};
-use crate::Client;
+use crate::{encryption::dehydrated_devices, Client};
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// Secure key/value storage for Matrix users.
@@ -416,6 +418,9 @@ impl SecretStore {
 
         self.maybe_enable_backups().await?;
 
+        // Import the room keys received by our dehydrated device, if we have one.
+        self.client.encryption().dehydrated_devices().rehydrate_if_enabled(self).await;
+
         Ok(())
     }
 
@@ -436,6 +441,16 @@ impl SecretStore {
             key.zeroize();
         }
 
+        if let Some(mut pickle_key) =
+            olm_machine.store().get_custom_value(dehydrated_devices::PICKLE_KEY_STORE_KEY).await?
+        {
+            let mut key = base64_encode(&pickle_key);
+            self.put_secret(dehydrated_devices::PICKLE_KEY_SECRET_NAME, &key).await?;
+
+            key.zeroize();
+            pickle_key.zeroize();
+        }
+
         Ok(())
     }
 }
@@ -12,9 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+#[cfg(feature = "experimental-sliding-sync")]
+use std::collections::BTreeSet;
 use std::{collections::BTreeMap, sync::Weak, time::Duration};
 
+#[cfg(feature = "experimental-sliding-sync")]
+use futures_core::Stream;
 use futures_util::future::join_all;
+#[cfg(feature = "experimental-sliding-sync")]
+use futures_util::{pin_mut, StreamExt};
 use matrix_sdk_common::failures_cache::FailuresCache;
 use ruma::OwnedRoomId;
 use tokio::sync::mpsc::{self, UnboundedReceiver};
@@ -34,6 +40,10 @@ pub(crate) struct ClientTasks {
     #[cfg(feature = "e2e-encryption")]
     pub(crate) download_room_keys: Option<BackupDownloadTask>,
     pub(crate) setup_e2ee: Option<JoinHandle<()>>,
+    #[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
+    pub(crate) decrypt_latest_events: Option<LatestEventsDecryptionTask>,
+    #[cfg(feature = "e2e-encryption")]
+    pub(crate) rotate_dehydrated_device: Option<DehydratedDeviceRotationTask>,
 }
 
 #[cfg(feature = "e2e-encryption")]
@@ -196,3 +206,113 @@ impl BackupDownloadTask {
         }
     }
 }
+
+/// A task decrypting the latest encrypted events of the rooms for which room
+/// keys have been received, to update their latest event.
+#[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
+pub(crate) struct LatestEventsDecryptionTask {
+    #[allow(dead_code)]
+    join_handle: JoinHandle<()>,
+}
+
+#[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
+impl Drop for LatestEventsDecryptionTask {
+    fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.join_handle.abort();
+    }
+}
+
+#[cfg(all(feature = "e2e-encryption", feature = "experimental-sliding-sync"))]
+impl LatestEventsDecryptionTask {
+    pub(crate) fn new(
+        client: Weak<ClientInner>,
+        room_keys_stream: impl Stream<Item = Vec<matrix_sdk_base::crypto::store::RoomKeyInfo>>
+            + Send
+            + 'static,
+    ) -> Self {
+        let join_handle = spawn(async move {
+            Self::listen(client, room_keys_stream).await;
+        });
+
+        Self { join_handle }
+    }
+
+    pub(crate) async fn listen(
+        client: Weak<ClientInner>,
+        room_keys_stream: impl Stream<Item = Vec<matrix_sdk_base::crypto::store::RoomKeyInfo>>,
+    ) {
+        pin_mut!(room_keys_stream);
+
+        while let Some(room_keys) = room_keys_stream.next().await {
+            let Some(client) = client.upgrade() else {
+                trace!("Client got dropped, shutting down the task");
+                break;
+            };
+
+            let client = Client { inner: client };
+            let room_ids =
+                room_keys.into_iter().map(|room_key| room_key.room_id).collect::<BTreeSet<_>>();
+
+            trace!(?room_ids, "Got room keys, decrypting the latest events of the rooms");
+
+            if let Err(e) = client
+                .base_client()
+                .decrypt_latest_events_for_rooms(room_ids.iter().map(|room_id| &**room_id))
+                .await
+            {
+                warn!("Error when decrypting the latest events {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(feature = "e2e-encryption")]
+pub(crate) struct DehydratedDeviceRotationTask {
+    #[allow(dead_code)]
+    join_handle: JoinHandle<()>,
+}
+
+#[cfg(feature = "e2e-encryption")]
+impl Drop for DehydratedDeviceRotationTask {
+    fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.join_handle.abort();
+    }
+}
+
+#[cfg(feature = "e2e-encryption")]
+impl DehydratedDeviceRotationTask {
+    /// Rotate the dehydrated device of the client every `period`, as long as
+    /// the task is alive.
+    pub(crate) fn new(client: Weak<ClientInner>, period: Duration) -> Self {
+        let join_handle = spawn(async move {
+            Self::rotate_periodically(client, period).await;
+        });
+
+        Self { join_handle }
+    }
+
+    async fn rotate_periodically(client: Weak<ClientInner>, period: Duration) {
+        loop {
+            #[cfg(target_arch = "wasm32")]
+            gloo_timers::future::TimeoutFuture::new(period.as_millis() as u32).await;
+
+            #[cfg(not(target_arch = "wasm32"))]
+            tokio::time::sleep(period).await;
+
+            let Some(client) = client.upgrade() else {
+                trace!("Client got dropped, shutting down the task");
+                break;
+            };
+
+            let client = Client { inner: client };
+
+            trace!("Rotating the dehydrated device");
+
+            if let Err(e) = client.encryption().dehydrated_devices().rotate().await {
+                warn!("Error when rotating the dehydrated device {e:?}");
+            }
+        }
+    }
+}
@@ -23,7 +23,9 @@ use matrix_sdk_base::crypto::ScanError;
 use matrix_sdk_base::crypto::{
     CryptoStoreError, DecryptorError, KeyExportError, MegolmError, OlmError,
 };
-use matrix_sdk_base::{Error as SdkBaseError, RoomState, StoreError};
+use matrix_sdk_base::{
+    event_cache_store::EventCacheStoreError, Error as SdkBaseError, RoomState, StoreError,
+};
 use reqwest::Error as ReqwestError;
 use ruma::{
     api::{
@@ -219,6 +221,10 @@ pub enum Error {
     #[error(transparent)]
     StateStore(#[from] StoreError),
 
+    /// An error occurred in the event cache store.
+    #[error(transparent)]
+    EventCacheStore(#[from] EventCacheStoreError),
+
     /// An error encountered when trying to parse an identifier.
     #[error(transparent)]
     Identifier(#[from] IdParseError),
@@ -270,6 +276,10 @@ pub enum Error {
     #[error("a concurrent request failed; see logs for details")]
     ConcurrentRequestFailed,
 
+    /// The send queue of a room can't send its requests.
+    #[error(transparent)]
+    SendQueue(#[from] crate::send_queue::RoomSendQueueError),
+
     /// An other error was raised
     /// this might happen because encryption was enabled on the base-crate
     /// but not here and that raised.
@@ -471,6 +481,34 @@ impl From<RemovePushRuleError> for NotificationSettingsError {
     }
 }
 
+/// Errors that can occur when sharing a live location.
+#[derive(Debug, Error)]
+pub enum BeaconError {
+    /// There is no live location share of the user in the room.
+    #[error("the beacon information of the user wasn't found")]
+    NotFound,
+
+    /// The live location share of the user has been redacted.
+    #[error("the beacon information of the user has been redacted")]
+    Redacted,
+
+    /// The room must be joined to access the live location share.
+    #[error("the room must be joined to access the beacon information")]
+    Stripped,
+
+    /// The live location share has been stopped or has expired.
+    #[error("the live location share isn't live anymore")]
+    NotLive,
+
+    /// The beacon information couldn't be deserialized.
+    #[error(transparent)]
+    Deserialization(#[from] JsonError),
+
+    /// Another error occurred, e.g. when sending the request.
+    #[error(transparent)]
+    Sdk(#[from] Error),
+}
+
 #[derive(Debug, Error)]
 #[error("expected: {expected}, got: {got:?}")]
 pub struct WrongRoomState {
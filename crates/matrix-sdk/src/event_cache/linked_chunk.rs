@@ -66,6 +66,55 @@ impl<Item, Gap, const CAP: usize> LinkedChunk<Item, Gap, CAP> {
         }
     }
 
+    /// Create a new [`Self`] from existing chunks, e.g. to restore it from a
+    /// storage.
+    ///
+    /// The chunks are given in order, with their identifiers. The first chunk
+    /// must be an items chunk with the first identifier, like the first chunk
+    /// of any [`LinkedChunk`], and the identifiers must be unique.
+    pub fn from_chunks<I>(chunks: I) -> Result<Self, LinkedChunkError>
+    where
+        I: IntoIterator<Item = (ChunkIdentifier, ChunkContent<Item, Gap>)>,
+    {
+        let mut linked_chunk = Self::new();
+        let mut chunks = chunks.into_iter();
+
+        match chunks.next() {
+            Some((ChunkIdentifierGenerator::FIRST_IDENTIFIER, ChunkContent::Items(items))) => {
+                linked_chunk.length = items.len();
+                linked_chunk.latest_chunk_mut().content = ChunkContent::Items(items);
+            }
+            Some((identifier, _)) => {
+                return Err(LinkedChunkError::InvalidChunkIdentifier { identifier });
+            }
+            None => return Ok(linked_chunk),
+        }
+
+        let mut last_identifier = ChunkIdentifierGenerator::FIRST_IDENTIFIER;
+
+        for (identifier, content) in chunks {
+            if identifier == ChunkIdentifierGenerator::FIRST_IDENTIFIER {
+                return Err(LinkedChunkError::InvalidChunkIdentifier { identifier });
+            }
+
+            if let ChunkContent::Items(items) = &content {
+                linked_chunk.length += items.len();
+            }
+
+            let chunk = Box::new(Chunk::new(identifier, content));
+            let last_chunk = linked_chunk.latest_chunk_mut();
+            last_chunk.insert_next(NonNull::from(Box::leak(chunk)));
+            linked_chunk.last = last_chunk.next;
+
+            last_identifier = last_identifier.max(identifier);
+        }
+
+        linked_chunk.chunk_identifier_generator =
+            ChunkIdentifierGenerator::new_from_previous_chunk_identifier(last_identifier);
+
+        Ok(linked_chunk)
+    }
+
     /// Get the number of items in this linked chunk.
     pub fn len(&self) -> usize {
         self.length
@@ -372,6 +421,37 @@ impl<Item, Gap, const CAP: usize> LinkedChunk<Item, Gap, CAP> {
         }
     }
 
+    /// Replace the item at a specified position in the [`LinkedChunk`], and
+    /// return the replaced item.
+    ///
+    /// Because the `position` can be invalid, this method returns a
+    /// `Result`.
+    pub fn replace_item_at(
+        &mut self,
+        position: Position,
+        item: Item,
+    ) -> Result<Item, LinkedChunkError> {
+        let chunk_identifier = position.chunk_identifier();
+        let item_index = position.index();
+
+        let chunk = self
+            .chunk_mut(chunk_identifier)
+            .ok_or(LinkedChunkError::InvalidChunkIdentifier { identifier: chunk_identifier })?;
+
+        match &mut chunk.content {
+            ChunkContent::Gap(..) => {
+                Err(LinkedChunkError::ChunkIsAGap { identifier: chunk_identifier })
+            }
+            ChunkContent::Items(current_items) => {
+                let current_item = current_items
+                    .get_mut(item_index)
+                    .ok_or(LinkedChunkError::InvalidItemIndex { index: item_index })?;
+
+                Ok(std::mem::replace(current_item, item))
+            }
+        }
+    }
+
     /// Search backwards for a chunk, and return its identifier.
     pub fn chunk_identifier<'a, P>(&'a self, mut predicate: P) -> Option<ChunkIdentifier>
     where
@@ -610,10 +690,23 @@ impl ChunkIdentifierGenerator {
 /// It is not the position of the chunk, just its unique identifier.
 ///
 /// Learn more with [`ChunkIdentifierGenerator`].
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct ChunkIdentifier(u64);
 
+impl ChunkIdentifier {
+    /// Create an identifier from its value, e.g. when restoring a chunk from a
+    /// storage.
+    pub fn from_value(value: u64) -> Self {
+        Self(value)
+    }
+
+    /// Get the value of this identifier, e.g. to persist it.
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+}
+
 /// The position of something inside a [`Chunk`].
 ///
 /// It's a pair of a chunk position and an item index.
@@ -765,10 +858,15 @@ impl<Item, Gap, const CAPACITY: usize> Chunk<Item, Gap, CAPACITY> {
     }
 
     /// Get the unique identifier of the chunk.
-    fn identifier(&self) -> ChunkIdentifier {
+    pub fn identifier(&self) -> ChunkIdentifier {
         self.identifier
     }
 
+    /// Get the unique identifier of the previous chunk, if any.
+    pub fn previous_identifier(&self) -> Option<ChunkIdentifier> {
+        self.previous().map(Chunk::identifier)
+    }
+
     /// Get the content of the chunk.
     pub fn content(&self) -> &ChunkContent<Item, Gap> {
         &self.content
@@ -1102,6 +1200,32 @@ mod tests {
         assert_eq!(linked_chunk.len(), 9);
     }
 
+    #[test]
+    fn test_from_chunks() {
+        let linked_chunk = LinkedChunk::<char, (), 3>::from_chunks([
+            (ChunkIdentifier(0), ChunkContent::Items(vec!['a', 'b'])),
+            (ChunkIdentifier(4), ChunkContent::Gap(())),
+            (ChunkIdentifier(2), ChunkContent::Items(vec!['c'])),
+        ])
+        .unwrap();
+        assert_items_eq!(linked_chunk, ['a', 'b'] [-] ['c']);
+        assert_eq!(linked_chunk.len(), 3);
+
+        let identifiers = linked_chunk.chunks().map(Chunk::identifier).collect::<Vec<_>>();
+        assert_eq!(identifiers, [ChunkIdentifier(0), ChunkIdentifier(4), ChunkIdentifier(2)]);
+
+        // New chunks get identifiers that aren't used yet.
+        let mut linked_chunk = linked_chunk;
+        linked_chunk.push_gap_back(());
+        assert_eq!(linked_chunk.chunks().last().unwrap().identifier(), ChunkIdentifier(5));
+
+        // The first chunk must be the first items chunk.
+        assert_matches!(
+            LinkedChunk::<char, (), 3>::from_chunks([(ChunkIdentifier(1), ChunkContent::Gap(()))]),
+            Err(LinkedChunkError::InvalidChunkIdentifier { identifier: ChunkIdentifier(1) })
+        );
+    }
+
     #[test]
     fn test_identifiers_and_positions() {
         let mut linked_chunk = LinkedChunk::<char, (), 3>::new();
@@ -1544,6 +1668,39 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_replace_item_at() -> Result<(), LinkedChunkError> {
+        let mut linked_chunk = LinkedChunk::<char, (), 3>::new();
+        linked_chunk.push_items_back(['a', 'b', 'c', 'd']);
+        linked_chunk.push_gap_back(());
+        assert_items_eq!(linked_chunk, ['a', 'b', 'c'] ['d'] [-]);
+
+        // Replace an item in the first chunk.
+        assert_eq!(linked_chunk.replace_item_at(Position(ChunkIdentifier(0), 1), 'x')?, 'b');
+        assert_items_eq!(linked_chunk, ['a', 'x', 'c'] ['d'] [-]);
+
+        // Replace an item in another chunk.
+        assert_eq!(linked_chunk.replace_item_at(Position(ChunkIdentifier(1), 0), 'y')?, 'd');
+        assert_items_eq!(linked_chunk, ['a', 'x', 'c'] ['y'] [-]);
+        assert_eq!(linked_chunk.len(), 4);
+
+        // Invalid positions are rejected.
+        assert_matches!(
+            linked_chunk.replace_item_at(Position(ChunkIdentifier(1), 1), 'z'),
+            Err(LinkedChunkError::InvalidItemIndex { index: 1 })
+        );
+        assert_matches!(
+            linked_chunk.replace_item_at(Position(ChunkIdentifier(2), 0), 'z'),
+            Err(LinkedChunkError::ChunkIsAGap { identifier: ChunkIdentifier(2) })
+        );
+        assert_matches!(
+            linked_chunk.replace_item_at(Position(ChunkIdentifier(42), 0), 'z'),
+            Err(LinkedChunkError::InvalidChunkIdentifier { identifier: ChunkIdentifier(42) })
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_chunk_item_positions() {
         let mut linked_chunk = LinkedChunk::<char, (), 3>::new();
@@ -34,31 +34,53 @@
 //! - [x] backwards pagination
 //! - [ ] forward pagination
 //! - [ ] reconcile results with cached timelines.
-//! - [ ] retry decryption upon receiving new keys (from an encryption sync
+//! - [x] retry decryption upon receiving new keys (from an encryption sync
 //!   service or from a key backup).
 //! - [ ] expose the latest event for a given room.
-//! - [ ] caching of events on-disk.
+//! - [x] caching of events on-disk.
+//! - [x] local full-text search of the messages, see
+//!   [`EventCache::enable_search_index`].
 
 #![forbid(missing_docs)]
 
+#[cfg(feature = "e2e-encryption")]
+use std::collections::BTreeSet;
 use std::{
     collections::BTreeMap,
     fmt::Debug,
-    sync::{Arc, OnceLock, Weak},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, OnceLock, Weak,
+    },
     time::Duration,
 };
 
+#[cfg(feature = "e2e-encryption")]
+use futures_util::{pin_mut, stream, StreamExt};
+pub use matrix_sdk_base::event_cache_store::SearchHit;
 use matrix_sdk_base::{
     deserialized_responses::{AmbiguityChange, SyncTimelineEvent, TimelineEvent},
+    event_cache_store::IndexedBody,
     sync::{JoinedRoomUpdate, LeftRoomUpdate, RoomUpdates, Timeline},
 };
 use matrix_sdk_common::executor::{spawn, JoinHandle};
+#[cfg(feature = "e2e-encryption")]
+use ruma::events::{
+    room::encrypted::{EncryptedEventScheme, OriginalSyncRoomEncryptedEvent},
+    SyncMessageLikeEvent,
+};
 use ruma::{
     assign,
-    events::{AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent},
+    events::{
+        room::message::{Relation, SyncRoomMessageEvent},
+        AnyRoomAccountDataEvent, AnySyncEphemeralRoomEvent, AnySyncMessageLikeEvent,
+        AnySyncTimelineEvent,
+    },
     serde::Raw,
-    OwnedEventId, OwnedRoomId, RoomId,
+    OwnedEventId, OwnedRoomId, RoomId, RoomVersionId,
 };
+#[cfg(feature = "e2e-encryption")]
+use tokio::select;
 use tokio::{
     sync::{
         broadcast::{error::RecvError, Receiver, Sender},
@@ -116,6 +138,8 @@ pub type Result<T> = std::result::Result<T, EventCacheError>;
 /// Hold handles to the tasks spawn by a [`RoomEventCache`].
 pub struct EventCacheDropHandles {
     listen_updates_task: JoinHandle<()>,
+    #[cfg(feature = "e2e-encryption")]
+    retry_decryption_task: JoinHandle<()>,
 }
 
 impl Debug for EventCacheDropHandles {
@@ -127,6 +151,8 @@ impl Debug for EventCacheDropHandles {
 impl Drop for EventCacheDropHandles {
     fn drop(&mut self) {
         self.listen_updates_task.abort();
+        #[cfg(feature = "e2e-encryption")]
+        self.retry_decryption_task.abort();
     }
 }
 
@@ -156,10 +182,42 @@ impl EventCache {
                 multiple_room_updates_lock: Default::default(),
                 by_room: Default::default(),
                 drop_handles: Default::default(),
+                search_index_enabled: Default::default(),
+                search_index_in_encrypted_rooms: Default::default(),
             }),
         }
     }
 
+    /// Enable the local full-text search index of the messages.
+    ///
+    /// From now on, the bodies of the unencrypted messages received by the
+    /// event cache are indexed in the event cache store. Edits update the
+    /// indexed body of the original message, and redacted messages are removed
+    /// from the index.
+    ///
+    /// The messages can then be searched with
+    /// [`Room::search_messages`] or [`Client::search_messages`].
+    ///
+    /// The decrypted messages of encrypted rooms are only indexed after
+    /// [`EventCache::enable_search_index_in_encrypted_rooms`] has been called.
+    pub fn enable_search_index(&self) {
+        self.inner.search_index_enabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Enable the local full-text search index of the messages, including the
+    /// decrypted messages of encrypted rooms.
+    ///
+    /// See [`EventCache::enable_search_index`].
+    ///
+    /// **Warning:** some stores can't encrypt the index, even when they're
+    /// configured with a passphrase, so the decrypted messages are then
+    /// persisted in plaintext; see the documentation of the event cache store
+    /// in use.
+    pub fn enable_search_index_in_encrypted_rooms(&self) {
+        self.inner.search_index_in_encrypted_rooms.store(true, Ordering::SeqCst);
+        self.inner.search_index_enabled.store(true, Ordering::SeqCst);
+    }
+
     /// Starts subscribing the [`EventCache`] to sync responses, if not done
     /// before.
     ///
@@ -174,7 +232,16 @@ impl EventCache {
             let listen_updates_task =
                 spawn(Self::listen_task(self.inner.clone(), room_updates_feed));
 
-            Arc::new(EventCacheDropHandles { listen_updates_task })
+            // Spawn the task that will retry to decrypt the events when room keys are
+            // received.
+            #[cfg(feature = "e2e-encryption")]
+            let retry_decryption_task = spawn(Self::retry_decryption_task(self.inner.clone()));
+
+            Arc::new(EventCacheDropHandles {
+                listen_updates_task,
+                #[cfg(feature = "e2e-encryption")]
+                retry_decryption_task,
+            })
         });
 
         Ok(())
@@ -202,10 +269,14 @@ impl EventCache {
                 }
 
                 Err(RecvError::Lagged(_)) => {
-                    // Forget everything we know; we could have missed events, and we have
-                    // no way to reconcile at the moment!
+                    // We could have missed events, and we have no way to reconcile at the
+                    // moment! The events that were persisted are still valid though, and the
+                    // next sync response adds a gap before its events, so start over from the
+                    // persisted events.
                     // TODO: implement Smart Matching™,
-                    inner.by_room.write().await.clear();
+                    if let Err(err) = inner.reload_all_rooms().await {
+                        error!("Error when reloading all the rooms: {err}");
+                    }
                 }
 
                 Err(RecvError::Closed) => {
@@ -216,6 +287,101 @@ impl EventCache {
         }
     }
 
+    /// Retry to decrypt the events of the rooms that couldn't be decrypted,
+    /// every time room keys are received, be it via the crypto store or by
+    /// downloading them from the key backup.
+    #[cfg(feature = "e2e-encryption")]
+    async fn retry_decryption_task(inner: Arc<EventCacheInner>) {
+        trace!("Spawning the retry decryption task");
+
+        // Don't keep the client alive for the lifetime of the task.
+        let (mut olm_machine_changes, backup_room_keys_stream) = {
+            let Ok(client) = inner.client() else {
+                return;
+            };
+
+            let olm_machine_changes = client.base_client().subscribe_to_olm_machine_changes();
+
+            let backup_room_keys_stream =
+                client.encryption().backups().room_keys_stream().map(|import_result| {
+                    match import_result {
+                        Ok(import_result) => import_result
+                            .keys
+                            .into_iter()
+                            .flat_map(|(room_id, session_ids_by_sender_key)| {
+                                session_ids_by_sender_key
+                                    .into_values()
+                                    .flatten()
+                                    .map(move |session_id| (room_id.clone(), session_id))
+                            })
+                            .collect(),
+                        Err(err) => {
+                            warn!("Missed room keys downloaded from the backup: {err}");
+                            Vec::new()
+                        }
+                    }
+                });
+
+            (olm_machine_changes, backup_room_keys_stream)
+        };
+        pin_mut!(backup_room_keys_stream);
+
+        loop {
+            // Subscribe to the room keys received by the current `OlmMachine`. It may not
+            // exist yet, in which case we only listen to the key backup until it's
+            // created.
+            let room_keys_stream = {
+                let Ok(client) = inner.client() else {
+                    return;
+                };
+
+                let room_keys_stream = client
+                    .olm_machine()
+                    .await
+                    .as_ref()
+                    .map(|olm_machine| olm_machine.store().room_keys_received_stream());
+
+                stream::iter(room_keys_stream).flatten().map(|room_keys| {
+                    room_keys
+                        .into_iter()
+                        .map(|room_key| (room_key.room_id, room_key.session_id))
+                        .collect::<Vec<_>>()
+                })
+            };
+            pin_mut!(room_keys_stream);
+
+            loop {
+                let room_keys = select! {
+                    Some(room_keys) = room_keys_stream.next() => room_keys,
+                    Some(room_keys) = backup_room_keys_stream.next() => room_keys,
+                    Some(()) = olm_machine_changes.next() => {
+                        trace!("The OlmMachine has changed, resubscribing to its room keys");
+                        break;
+                    }
+                    else => return,
+                };
+
+                let mut session_ids_by_room = BTreeMap::<_, BTreeSet<_>>::new();
+
+                for (room_id, session_id) in room_keys {
+                    session_ids_by_room.entry(room_id).or_default().insert(session_id);
+                }
+
+                if let Err(err) = inner.retry_decryption(session_ids_by_room).await {
+                    match err {
+                        EventCacheError::ClientDropped => {
+                            // The client has dropped, exit the task.
+                            return;
+                        }
+                        err => {
+                            error!("Error when retrying to decrypt events: {err}");
+                        }
+                    }
+                }
+            }
+        }
+    }
+
     /// Return a room-specific view over the [`EventCache`].
     pub(crate) async fn for_room(
         &self,
@@ -277,6 +443,12 @@ struct EventCacheInner {
 
     /// Handles to keep alive the task listening to updates.
     drop_handles: OnceLock<Arc<EventCacheDropHandles>>,
+
+    /// Whether the messages must be indexed for the local full-text search.
+    search_index_enabled: AtomicBool,
+
+    /// Whether the decrypted messages must be indexed too.
+    search_index_in_encrypted_rooms: AtomicBool,
 }
 
 impl EventCacheInner {
@@ -323,6 +495,66 @@ impl EventCacheInner {
         Ok(())
     }
 
+    /// Retry to decrypt the events encrypted with the given sessions, for each
+    /// room.
+    ///
+    /// Only the rooms whose events are loaded in memory are considered; the
+    /// events of the other rooms are retried when they're loaded from the
+    /// event cache store.
+    #[cfg(feature = "e2e-encryption")]
+    async fn retry_decryption(
+        &self,
+        session_ids_by_room: BTreeMap<OwnedRoomId, BTreeSet<String>>,
+    ) -> Result<()> {
+        // Make sure the client is still alive.
+        self.client()?;
+
+        for (room_id, session_ids) in session_ids_by_room {
+            let room = self.by_room.read().await.get(&room_id).cloned();
+
+            if let Some(room) = room {
+                room.inner.retry_decryption(Some(&session_ids)).await;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replace the events of all the rooms in memory by the ones persisted in
+    /// the event cache store, and notify the observers that the events have
+    /// been cleared.
+    async fn reload_all_rooms(&self) -> Result<()> {
+        let client = self.client()?;
+        let store = client.base_client().event_cache_store();
+        let rooms = self.by_room.read().await.values().cloned().collect::<Vec<_>>();
+
+        for room in &rooms {
+            let room_id = room.inner.room.room_id();
+            let mut room_events = room.inner.events.write().await;
+
+            *room_events = match store.load_chunks(room_id).await {
+                Ok(chunks) => RoomEvents::from_persisted_chunks(chunks),
+                Err(err) => {
+                    error!(%room_id, "Couldn't load the stored events: {err}");
+                    RoomEvents::default()
+                }
+            };
+
+            // Notify all the observers that we've lost track of state. (We ignore the
+            // error if there aren't any.)
+            let _ = room.inner.sender.send(RoomEventCacheUpdate::Clear);
+        }
+
+        // The room keys of the persisted events may have been received since they
+        // were saved.
+        #[cfg(feature = "e2e-encryption")]
+        for room in rooms {
+            room.inner.retry_decryption(None).await;
+        }
+
+        Ok(())
+    }
+
     /// Return a room-specific view over the [`EventCache`].
     ///
     /// It may not be found, if the room isn't known to the client, in which
@@ -346,13 +578,30 @@ impl EventCacheInner {
                     return Ok(Some(room.clone()));
                 }
 
-                let Some(room) = self.client()?.get_room(room_id) else {
+                let client = self.client()?;
+                let Some(room) = client.get_room(room_id) else {
                     return Ok(None);
                 };
 
-                let room_event_cache = RoomEventCache::new(room);
+                // Start from the events that have been persisted for this room, if any.
+                let room_events =
+                    match client.base_client().event_cache_store().load_chunks(room_id).await {
+                        Ok(chunks) => RoomEvents::from_persisted_chunks(chunks),
+                        Err(err) => {
+                            error!(%room_id, "Couldn't load the stored events: {err}");
+                            RoomEvents::default()
+                        }
+                    };
+
+                let room_event_cache = RoomEventCache::new(room, room_events);
 
                 by_room_guard.insert(room_id.to_owned(), room_event_cache.clone());
+                drop(by_room_guard);
+
+                // The room keys of the persisted events may have been received since they
+                // were saved, while the room wasn't loaded.
+                #[cfg(feature = "e2e-encryption")]
+                room_event_cache.inner.retry_decryption(None).await;
 
                 Ok(Some(room_event_cache))
             }
@@ -375,9 +624,10 @@ impl Debug for RoomEventCache {
 }
 
 impl RoomEventCache {
-    /// Create a new [`RoomEventCache`] using the given room and store.
-    fn new(room: Room) -> Self {
-        Self { inner: Arc::new(RoomEventCacheInner::new(room)) }
+    /// Create a new [`RoomEventCache`] using the given room and initial
+    /// events.
+    fn new(room: Room, events: RoomEvents) -> Self {
+        Self { inner: Arc::new(RoomEventCacheInner::new(room, events)) }
     }
 
     /// Subscribe to room updates for this room, after getting the initial list
@@ -444,12 +694,12 @@ struct RoomEventCacheInner {
 impl RoomEventCacheInner {
     /// Creates a new cache for a room, and subscribes to room updates, so as
     /// to handle new timeline events.
-    fn new(room: Room) -> Self {
+    fn new(room: Room, events: RoomEvents) -> Self {
         let sender = Sender::new(32);
 
         Self {
             room,
-            events: RwLock::new(RoomEvents::default()),
+            events: RwLock::new(events),
             sender,
             pagination_lock: Default::default(),
             pagination_token_notifier: Default::default(),
@@ -547,6 +797,7 @@ impl RoomEventCacheInner {
 
         // Reset the events.
         room_events.reset();
+        self.save_events(&mut room_events).await;
 
         // Propagate to observers.
         let _ = self.sender.send(RoomEventCacheUpdate::Clear);
@@ -612,6 +863,12 @@ impl RoomEventCacheInner {
             room_events.push_events(events.clone().into_iter());
         }
 
+        if !events.is_empty() || prev_batch.is_some() {
+            self.save_events(&mut room_events).await;
+        }
+
+        self.update_search_index(&events).await;
+
         // Now that all events have been added, we can trigger the
         // `pagination_token_notifier`.
         if prev_batch.is_some() {
@@ -691,7 +948,10 @@ impl RoomEventCacheInner {
             // (backward). The `RoomEvents` API expects the first event to be the oldest.
             .rev()
             .cloned()
-            .map(SyncTimelineEvent::from);
+            .map(SyncTimelineEvent::from)
+            .collect::<Vec<_>>();
+
+        self.update_search_index(&sync_events).await;
 
         // There is a `token`/gap, let's replace it by new events!
         if let Some(gap_identifier) = gap_identifier {
@@ -717,6 +977,8 @@ impl RoomEventCacheInner {
 
             trace!("replaced gap with new events from backpagination");
 
+            self.save_events(&mut room_events).await;
+
             // TODO: implement smarter reconciliation later
             //let _ = self.sender.send(RoomEventCacheUpdate::Prepend { events });
 
@@ -757,10 +1019,147 @@ impl RoomEventCacheInner {
                 }
             }
 
+            self.save_events(&mut room_events).await;
+
             Ok(BackPaginationOutcome::Success { events, reached_start })
         }
     }
 
+    /// Retry to decrypt the events of the room that couldn't be decrypted,
+    /// and were encrypted with one of the given sessions, or with any session
+    /// if `session_ids` is `None`.
+    ///
+    /// The decrypted events replace the encrypted ones in place, and observers
+    /// are notified with a [`RoomEventCacheUpdate::ReplacedEvents`].
+    #[cfg(feature = "e2e-encryption")]
+    #[instrument(skip_all, fields(room_id = %self.room.room_id()))]
+    async fn retry_decryption(&self, session_ids: Option<&BTreeSet<String>>) {
+        let utds = self
+            .events
+            .read()
+            .await
+            .events()
+            .filter_map(|(_, event)| {
+                let (session_id, raw) = utd_session_id(event)?;
+                session_ids
+                    .map_or(true, |session_ids| session_ids.contains(&session_id))
+                    .then_some((event.event_id()?, raw))
+            })
+            .collect::<Vec<_>>();
+
+        if utds.is_empty() {
+            return;
+        }
+
+        trace!(num_events = utds.len(), "Retrying to decrypt events");
+
+        // Decrypt the events without holding the lock, so the events of the room can
+        // still be updated in the meantime.
+        let mut decrypted_events = Vec::with_capacity(utds.len());
+
+        for (event_id, raw) in utds {
+            match self.room.decrypt_event(&raw).await {
+                Ok(event) => decrypted_events.push((event_id, SyncTimelineEvent::from(event))),
+                Err(err) => {
+                    warn!("Couldn't decrypt the event with the new room keys: {err}");
+                }
+            }
+        }
+
+        if decrypted_events.is_empty() {
+            return;
+        }
+
+        let mut room_events = self.events.write().await;
+        let mut replaced_events = Vec::with_capacity(decrypted_events.len());
+
+        for (event_id, event) in decrypted_events {
+            // The events may have moved, been removed or been decrypted already while we
+            // were decrypting them.
+            let Some(position) = room_events.event_position(|event| {
+                event.encryption_info.is_none() && event.event_id().as_ref() == Some(&event_id)
+            }) else {
+                continue;
+            };
+
+            match room_events.replace_event_at(position, event.clone()) {
+                Ok(_) => replaced_events.push(event),
+                Err(err) => error!("Couldn't replace the decrypted event: {err:?}"),
+            }
+        }
+
+        if replaced_events.is_empty() {
+            return;
+        }
+
+        self.save_events(&mut room_events).await;
+        self.update_search_index(&replaced_events).await;
+
+        let _ = self.sender.send(RoomEventCacheUpdate::ReplacedEvents { events: replaced_events });
+    }
+
+    /// Persist the events of the room in the event cache store.
+    ///
+    /// Only the chunks that changed since the last time are saved. Failing to
+    /// do so isn't fatal, as the events are still available in memory; the
+    /// error is only logged.
+    async fn save_events(&self, room_events: &mut RoomEvents) {
+        let updates = room_events.persisted_chunk_updates();
+
+        if updates.is_empty() {
+            return;
+        }
+
+        let client = self.room.client();
+        let room_id = self.room.room_id();
+        let store = client.base_client().event_cache_store();
+
+        match store.update_chunks(room_id, updates).await {
+            Ok(()) => room_events.mark_chunks_as_persisted(),
+            Err(err) => error!(%room_id, "Couldn't save the events: {err}"),
+        }
+    }
+
+    /// Update the search index with the given events, if it's enabled.
+    ///
+    /// As for [`Self::save_events`], failing to do so isn't fatal; the error
+    /// is only logged.
+    async fn update_search_index(&self, events: &[SyncTimelineEvent]) {
+        let client = self.room.client();
+        let event_cache = client.event_cache();
+        if !event_cache.inner.search_index_enabled.load(Ordering::SeqCst) {
+            return;
+        }
+        let include_decrypted =
+            event_cache.inner.search_index_in_encrypted_rooms.load(Ordering::SeqCst);
+
+        let room_id = self.room.room_id();
+        let room_version = self.room.clone_info().room_version().cloned();
+        let room_version = room_version.unwrap_or(RoomVersionId::V1);
+        let store = client.base_client().event_cache_store();
+
+        for event in events {
+            if event.encryption_info.is_some() && !include_decrypted {
+                continue;
+            }
+
+            for update in SearchIndexUpdate::for_event(event, &room_version) {
+                let result = match update {
+                    SearchIndexUpdate::Index { event_id, body } => {
+                        store.index_message(room_id, &event_id, body).await
+                    }
+                    SearchIndexUpdate::Redact(event_id) => {
+                        store.redact_indexed_message(room_id, &event_id).await
+                    }
+                };
+
+                if let Err(err) = result {
+                    error!(%room_id, "Couldn't update the search index: {err}");
+                }
+            }
+        }
+    }
+
     /// Returns the oldest back-pagination token, that is, the one closest to
     /// the start of the timeline as we know it.
     ///
@@ -819,6 +1218,123 @@ pub enum BackPaginationOutcome {
     UnknownBackpaginationToken,
 }
 
+/// How the search index must be updated for a single event.
+#[derive(Debug, PartialEq)]
+enum SearchIndexUpdate {
+    /// Index the given body for the given message; the store merges it with
+    /// the previously indexed body, if any.
+    Index { event_id: OwnedEventId, body: IndexedBody },
+
+    /// The given event has been redacted: remove it from the index if it's a
+    /// message, or index its original body again if it's the indexed edit of
+    /// a message.
+    Redact(OwnedEventId),
+}
+
+impl SearchIndexUpdate {
+    /// Compute how the search index must be updated for the given event.
+    fn for_event(event: &SyncTimelineEvent, room_version: &RoomVersionId) -> Vec<Self> {
+        let Ok(AnySyncTimelineEvent::MessageLike(event)) = event.event.deserialize() else {
+            return Vec::new();
+        };
+
+        match &event {
+            AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Original(ev)) => {
+                match &ev.content.relates_to {
+                    // An edit replaces the indexed body of the original message.
+                    Some(Relation::Replacement(replacement)) => vec![Self::Index {
+                        event_id: replacement.event_id.clone(),
+                        body: IndexedBody {
+                            body: replacement.new_content.msgtype.body().to_owned(),
+                            sender: ev.sender.clone(),
+                            origin_server_ts: ev.origin_server_ts,
+                            edit_id: Some(ev.event_id.clone()),
+                        },
+                    }],
+
+                    _ => {
+                        let mut updates = vec![Self::Index {
+                            event_id: ev.event_id.clone(),
+                            body: IndexedBody {
+                                body: ev.content.body().to_owned(),
+                                sender: ev.sender.clone(),
+                                origin_server_ts: ev.origin_server_ts,
+                                edit_id: None,
+                            },
+                        }];
+
+                        // Also index the latest edit bundled by the server, if any, since the
+                        // edit itself might never be received, e.g. when back-paginating.
+                        updates.extend(
+                            bundled_edit_body(&event)
+                                .map(|body| Self::Index { event_id: ev.event_id.clone(), body }),
+                        );
+
+                        updates
+                    }
+                }
+            }
+
+            // A redacted message can't be told apart from a redacted edit.
+            AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Redacted(ev)) => {
+                vec![Self::Redact(ev.event_id.clone())]
+            }
+
+            AnySyncMessageLikeEvent::RoomRedaction(ev) => ev
+                .redacts(room_version)
+                .map(|redacts| Self::Redact(redacts.to_owned()))
+                .into_iter()
+                .collect(),
+
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Get the session ID of the given event, along with the event itself, if it's
+/// a Megolm event that couldn't be decrypted.
+#[cfg(feature = "e2e-encryption")]
+fn utd_session_id(
+    event: &SyncTimelineEvent,
+) -> Option<(String, Raw<OriginalSyncRoomEncryptedEvent>)> {
+    if event.encryption_info.is_some() {
+        // The event has been decrypted already.
+        return None;
+    }
+
+    let AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+        SyncMessageLikeEvent::Original(encrypted),
+    )) = event.event.deserialize().ok()?
+    else {
+        return None;
+    };
+
+    match encrypted.content.scheme {
+        EncryptedEventScheme::MegolmV1AesSha2(content) => {
+            Some((content.session_id, event.event.clone().cast()))
+        }
+        _ => None,
+    }
+}
+
+/// Get the new body of the latest edit bundled with the given message, if any.
+fn bundled_edit_body(event: &AnySyncMessageLikeEvent) -> Option<IndexedBody> {
+    match *event.relations().replace? {
+        AnySyncMessageLikeEvent::RoomMessage(SyncRoomMessageEvent::Original(edit)) => {
+            match edit.content.relates_to? {
+                Relation::Replacement(replacement) => Some(IndexedBody {
+                    body: replacement.new_content.msgtype.body().to_owned(),
+                    sender: edit.sender,
+                    origin_server_ts: edit.origin_server_ts,
+                    edit_id: Some(edit.event_id),
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 /// An update related to events happened in a room.
 #[derive(Debug, Clone)]
 pub enum RoomEventCacheUpdate {
@@ -844,14 +1360,27 @@ pub enum RoomEventCacheUpdate {
         /// details of the ambiguity change.
         ambiguity_changes: BTreeMap<OwnedEventId, AmbiguityChange>,
     },
+
+    /// Some events of the room have been replaced in place, e.g. because they
+    /// could be decrypted after the room keys have been received.
+    ///
+    /// The new events have the same event IDs as the ones they replace.
+    ReplacedEvents {
+        /// The new version of the events.
+        events: Vec<SyncTimelineEvent>,
+    },
 }
 
 #[cfg(test)]
 mod tests {
-    use assert_matches2::assert_matches;
+    use assert_matches2::{assert_let, assert_matches};
+    use matrix_sdk_base::{
+        event_cache_store::{ChunkUpdate, EventCacheChunk, StoredChunk},
+        RoomState,
+    };
     use matrix_sdk_common::executor::spawn;
     use matrix_sdk_test::{async_test, sync_timeline_event};
-    use ruma::room_id;
+    use ruma::{event_id, room_id};
 
     use super::{BackPaginationOutcome, EventCacheError};
     use crate::{event_cache::store::PaginationToken, test_utils::logged_in_client};
@@ -872,6 +1401,364 @@ mod tests {
         assert_matches!(result, Err(EventCacheError::NotSubscribedYet));
     }
 
+    #[async_test]
+    async fn test_events_are_loaded_from_the_store() {
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!galette:saucisse.bzh");
+        client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+        // Some events and a gap have been persisted in a previous session.
+        client
+            .base_client()
+            .event_cache_store()
+            .update_chunks(
+                room_id,
+                vec![
+                    ChunkUpdate::Upsert(StoredChunk {
+                        identifier: 2,
+                        previous: Some(1),
+                        content: EventCacheChunk::Events(vec![sync_timeline_event!({
+                            "sender": "b@z.h",
+                            "type": "m.room.message",
+                            "event_id": "$ida",
+                            "origin_server_ts": 12344446,
+                            "content": { "body":"yolo", "msgtype": "m.text" },
+                        })
+                        .into()]),
+                    }),
+                    ChunkUpdate::Upsert(StoredChunk {
+                        identifier: 0,
+                        previous: None,
+                        content: EventCacheChunk::Events(Vec::new()),
+                    }),
+                    ChunkUpdate::Upsert(StoredChunk {
+                        identifier: 1,
+                        previous: Some(0),
+                        content: EventCacheChunk::Gap { prev_token: "old".to_owned() },
+                    }),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        let (room_event_cache, _drop_handles) = event_cache.for_room(room_id).await.unwrap();
+        let room_event_cache = room_event_cache.unwrap();
+
+        // The persisted events are returned immediately,
+        let (events, _stream) = room_event_cache.subscribe().await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().as_deref(), Some(event_id!("$ida")));
+
+        // And back-pagination resumes from the persisted gap.
+        let token = room_event_cache.oldest_backpagination_token(None).await.unwrap();
+        assert_eq!(token, Some(PaginationToken("old".to_owned())));
+    }
+
+    #[cfg(feature = "e2e-encryption")]
+    #[async_test]
+    async fn test_persisted_events_are_decrypted_when_loaded_from_the_store() {
+        use std::io::Cursor;
+
+        use crate::crypto::decrypt_room_key_export;
+
+        const SESSION_KEY: &[u8] = b"\
+            -----BEGIN MEGOLM SESSION DATA-----\n\
+            ASKcWoiAVUM97482UAi83Avce62hSLce7i5JhsqoF6xeAAAACqt2Cg3nyJPRWTTMXxXH7TXnkfdlmBXbQtq5\
+            bpHo3LRijcq2Gc6TXilESCmJN14pIsfKRJrWjZ0squ/XsoTFytuVLWwkNaW3QF6obeg2IoVtJXLMPdw3b2vO\
+            vgwGY3OMP0XafH13j1vcb6YLzvgLkZQLnYvd47hv3yK/9GmKS9tokuaQ7dCVYckYcIOS09EDTs70YdxUd5WG\
+            rQynATCLFP1p/NAGv70r9MK7Cy/mNpjD0r4qC7UEDIoi1kOWzHgnLo19wtvwsb8Fg8ATxcs3Wmtj8hIUYpDx\
+            ia4sM10zbytUuaPUAfCDf42IyxdmOnGe1CueXhgI71y+RW0s0argNqUt7jB70JT0o9CyX6UBGRaqLk2MPY9T\
+            hUu5J8X3UgIa6rcbWigzohzWm9rdbEHFrSWqjpfQYMaAKQQgETrjSy4XTrp2RhC2oNqG/hylI4ab+F4X6fpH\
+            DYP1NqNMP5g36xNu7LhDnrUB5qsPjYOmWORxGLfudpF3oLYCSlr3DgHqEIB6HjQblLZ3KQuPBse3zxyROTnS\
+            AhdPH4a/z1wioFtKNVph3hecsiKEdqnz4Y2coSIdhz58mJ9JWNQoFAENE5CSsoEZAGvafYZVpW4C75YY2zq1\
+            wIeiFi1dT43/jLAUGkslsi1VvnyfUu8qO404RxYO3XHoGLMFoFLOO+lZ+VGci2Vz10AhxJhEBHxRKxw4k2uB\
+            HztoSJUr/2Y\n\
+            -----END MEGOLM SESSION DATA-----";
+
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+        client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+        // An event that couldn't be decrypted has been persisted in a previous session,
+        client
+            .base_client()
+            .event_cache_store()
+            .update_chunks(
+                room_id,
+                vec![ChunkUpdate::Upsert(StoredChunk {
+                    identifier: 0,
+                    previous: None,
+                    content: EventCacheChunk::Events(vec![sync_timeline_event!({
+                        "sender": "@bob:example.com",
+                        "type": "m.room.encrypted",
+                        "event_id": "$utd",
+                        "origin_server_ts": 12344446,
+                        "content": {
+                            "algorithm": "m.megolm.v1.aes-sha2",
+                            "ciphertext": "AwgAEtABPRMavuZMDJrPo6pGQP4qVmpcuapuXtzKXJyi3YpEsjSWdzuRKIgJzD4P\
+                                cSqJM1A8kzxecTQNJsC5q22+KSFEPxPnI4ltpm7GFowSoPSW9+bFdnlfUzEP1jPq\
+                                YevHAsMJp2fRKkzQQbPordrUk1gNqEpGl4BYFeRqKl9GPdKFwy45huvQCLNNueql\
+                                CFZVoYMuhxrfyMiJJAVNTofkr2um2mKjDTlajHtr39pTG8k0eOjSXkLOSdZvNOMz\
+                                hGhSaFNeERSA2G2YbeknOvU7MvjiO0AKuxaAe1CaVhAI14FCgzrJ8g0y5nly+n7x\
+                                QzL2G2Dn8EoXM5Iqj8W99iokQoVsSrUEnaQ1WnSIfewvDDt4LCaD/w7PGETMCQ",
+                            "sender_key": "DeHIg4gwhClxzFYcmNntPNF9YtsdZbmMy8+3kzCMXHA",
+                            "device_id": "NLAZCWIOCO",
+                            "session_id": "gM8i47Xhu0q52xLfgUXzanCMpLinoyVyH7R58cBuVBU",
+                        },
+                    })
+                    .into()]),
+                })],
+            )
+            .await
+            .unwrap();
+
+        // And its room key has been received since then.
+        let exported_keys = decrypt_room_key_export(Cursor::new(SESSION_KEY), "1234").unwrap();
+        client
+            .olm_machine_for_testing()
+            .await
+            .as_ref()
+            .unwrap()
+            .store()
+            .import_exported_room_keys(exported_keys, |_, _| {})
+            .await
+            .unwrap();
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        let (room_event_cache, _drop_handles) = event_cache.for_room(room_id).await.unwrap();
+        let room_event_cache = room_event_cache.unwrap();
+
+        // The event is decrypted when the room's events are loaded from the store,
+        let (events, _stream) = room_event_cache.subscribe().await.unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().as_deref(), Some(event_id!("$utd")));
+        assert!(events[0].encryption_info.is_some());
+
+        // And the decrypted event is persisted.
+        let chunks = client.base_client().event_cache_store().load_chunks(room_id).await.unwrap();
+        assert_let!(EventCacheChunk::Events(events) = &chunks[0].content);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].encryption_info.is_some());
+    }
+
+    #[async_test]
+    async fn test_events_are_saved_in_the_store() {
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!galette:saucisse.bzh");
+        client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+
+        event_cache
+            .add_initial_events(
+                room_id,
+                vec![sync_timeline_event!({
+                    "sender": "b@z.h",
+                    "type": "m.room.message",
+                    "event_id": "$ida",
+                    "origin_server_ts": 12344446,
+                    "content": { "body":"yolo", "msgtype": "m.text" },
+                })
+                .into()],
+                Some("old".to_owned()),
+            )
+            .await
+            .unwrap();
+
+        let mut chunks =
+            client.base_client().event_cache_store().load_chunks(room_id).await.unwrap();
+        chunks.sort_by_key(|chunk| chunk.identifier);
+        assert_eq!(chunks.len(), 3);
+
+        // The first chunk is always an empty chunk of events.
+        assert_eq!(chunks[0].previous, None);
+        assert_let!(EventCacheChunk::Events(events) = &chunks[0].content);
+        assert!(events.is_empty());
+
+        assert_eq!(chunks[1].previous, Some(0));
+        assert_let!(EventCacheChunk::Gap { prev_token } = &chunks[1].content);
+        assert_eq!(prev_token, "old");
+
+        assert_eq!(chunks[2].previous, Some(1));
+        assert_let!(EventCacheChunk::Events(events) = &chunks[2].content);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_id().as_deref(), Some(event_id!("$ida")));
+    }
+
+    #[async_test]
+    async fn test_messages_are_indexed_for_search() {
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!galette:saucisse.bzh");
+        client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.enable_search_index();
+
+        event_cache
+            .add_initial_events(
+                room_id,
+                vec![
+                    sync_timeline_event!({
+                        "sender": "@b:z.h",
+                        "type": "m.room.message",
+                        "event_id": "$ida",
+                        "origin_server_ts": 12344446,
+                        "content": { "body": "I love pancakes", "msgtype": "m.text" },
+                    })
+                    .into(),
+                    sync_timeline_event!({
+                        "sender": "@b:z.h",
+                        "type": "m.room.message",
+                        "event_id": "$idb",
+                        "origin_server_ts": 12344447,
+                        "content": { "body": "Pancakes are great", "msgtype": "m.text" },
+                    })
+                    .into(),
+                    // An edit of the first message.
+                    sync_timeline_event!({
+                        "sender": "@b:z.h",
+                        "type": "m.room.message",
+                        "event_id": "$idc",
+                        "origin_server_ts": 12344448,
+                        "content": {
+                            "body": "* I love waffles",
+                            "msgtype": "m.text",
+                            "m.new_content": { "body": "I love waffles", "msgtype": "m.text" },
+                            "m.relates_to": { "rel_type": "m.replace", "event_id": "$ida" },
+                        },
+                    })
+                    .into(),
+                    // An edit of the second message, by someone else.
+                    sync_timeline_event!({
+                        "sender": "@mallory:z.h",
+                        "type": "m.room.message",
+                        "event_id": "$idd",
+                        "origin_server_ts": 12344449,
+                        "content": {
+                            "body": "* Pancakes are terrible",
+                            "msgtype": "m.text",
+                            "m.new_content": { "body": "Pancakes are terrible", "msgtype": "m.text" },
+                            "m.relates_to": { "rel_type": "m.replace", "event_id": "$idb" },
+                        },
+                    })
+                    .into(),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let room = client.get_room(room_id).unwrap();
+
+        // The edit of another sender is ignored.
+        assert!(room.search_messages("terrible", 10).await.unwrap().is_empty());
+
+        let hits = room.search_messages("pancakes", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].event_id, event_id!("$idb"));
+
+        let hits = client.search_messages("waffles", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].room_id, room_id);
+        assert_eq!(hits[0].event_id, event_id!("$ida"));
+
+        // A redaction removes the message from the index.
+        event_cache
+            .add_initial_events(
+                room_id,
+                vec![sync_timeline_event!({
+                    "sender": "@b:z.h",
+                    "type": "m.room.redaction",
+                    "event_id": "$ide",
+                    "origin_server_ts": 12344450,
+                    "redacts": "$idb",
+                    "content": { "redacts": "$idb" },
+                })
+                .into()],
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert!(room.search_messages("pancakes", 10).await.unwrap().is_empty());
+    }
+
+    #[async_test]
+    async fn test_redacted_edit_is_removed_from_search_index() {
+        let client = logged_in_client(None).await;
+        let room_id = room_id!("!galette:saucisse.bzh");
+        client.base_client().get_or_create_room(room_id, RoomState::Joined);
+
+        let event_cache = client.event_cache();
+        event_cache.subscribe().unwrap();
+        event_cache.enable_search_index();
+
+        event_cache
+            .add_initial_events(
+                room_id,
+                vec![
+                    sync_timeline_event!({
+                        "sender": "@b:z.h",
+                        "type": "m.room.message",
+                        "event_id": "$ida",
+                        "origin_server_ts": 12344446,
+                        "content": { "body": "I love pancakes", "msgtype": "m.text" },
+                    })
+                    .into(),
+                    sync_timeline_event!({
+                        "sender": "@b:z.h",
+                        "type": "m.room.message",
+                        "event_id": "$idb",
+                        "origin_server_ts": 12344447,
+                        "content": {
+                            "body": "* I love waffles",
+                            "msgtype": "m.text",
+                            "m.new_content": { "body": "I love waffles", "msgtype": "m.text" },
+                            "m.relates_to": { "rel_type": "m.replace", "event_id": "$ida" },
+                        },
+                    })
+                    .into(),
+                ],
+                None,
+            )
+            .await
+            .unwrap();
+
+        let room = client.get_room(room_id).unwrap();
+        assert_eq!(room.search_messages("waffles", 10).await.unwrap().len(), 1);
+
+        // Redacting the edit indexes the original message again.
+        event_cache
+            .add_initial_events(
+                room_id,
+                vec![sync_timeline_event!({
+                    "sender": "@b:z.h",
+                    "type": "m.room.redaction",
+                    "event_id": "$idc",
+                    "origin_server_ts": 12344448,
+                    "redacts": "$idb",
+                    "content": { "redacts": "$idb" },
+                })
+                .into()],
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert!(room.search_messages("waffles", 10).await.unwrap().is_empty());
+
+        let hits = room.search_messages("pancakes", 10).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].event_id, event_id!("$ida"));
+    }
+
     // Those tests require time to work, and it does not on wasm32.
     #[cfg(not(target_arch = "wasm32"))]
     mod time_tests {
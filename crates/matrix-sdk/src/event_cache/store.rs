@@ -12,12 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{fmt, iter::once};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    iter::once,
+};
 
+use matrix_sdk_base::event_cache_store::{ChunkUpdate, EventCacheChunk, StoredChunk};
 use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+use tracing::warn;
 
 use super::linked_chunk::{
-    Chunk, ChunkIdentifier, LinkedChunk, LinkedChunkError, LinkedChunkIter,
+    Chunk, ChunkContent, ChunkIdentifier, LinkedChunk, LinkedChunkError, LinkedChunkIter,
     LinkedChunkIterBackward, Position,
 };
 
@@ -34,8 +40,20 @@ pub struct Gap {
 
 const DEFAULT_CHUNK_CAPACITY: usize = 128;
 
+/// The state of a chunk, as last persisted in an event cache store: the
+/// identifier of the previous chunk, and the number of events.
+type PersistedChunkState = (Option<ChunkIdentifier>, usize);
+
 pub struct RoomEvents {
     chunks: LinkedChunk<SyncTimelineEvent, Gap, DEFAULT_CHUNK_CAPACITY>,
+
+    /// The state of the chunks persisted in the event cache store, to know
+    /// which ones need to be saved again.
+    persisted: BTreeMap<ChunkIdentifier, PersistedChunkState>,
+
+    /// The chunks that changed without their state changing, e.g. because an
+    /// event was replaced.
+    modified: BTreeSet<ChunkIdentifier>,
 }
 
 impl Default for RoomEvents {
@@ -47,12 +65,126 @@ impl Default for RoomEvents {
 #[allow(dead_code)]
 impl RoomEvents {
     pub fn new() -> Self {
-        Self { chunks: LinkedChunk::new() }
+        Self { chunks: LinkedChunk::new(), persisted: BTreeMap::new(), modified: BTreeSet::new() }
+    }
+
+    /// Create a new `RoomEvents` from chunks that have been persisted in an
+    /// event cache store.
+    pub fn from_persisted_chunks(chunks: Vec<StoredChunk>) -> Self {
+        let persisted = chunks
+            .iter()
+            .map(|chunk| {
+                let len = match &chunk.content {
+                    EventCacheChunk::Events(events) => events.len(),
+                    EventCacheChunk::Gap { .. } => 0,
+                };
+
+                (
+                    ChunkIdentifier::from_value(chunk.identifier),
+                    (chunk.previous.map(ChunkIdentifier::from_value), len),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        // Put the chunks in order by following the links to the previous chunks.
+        let mut chunks_by_previous =
+            chunks.into_iter().map(|chunk| (chunk.previous, chunk)).collect::<HashMap<_, _>>();
+        let mut ordered_chunks = Vec::with_capacity(chunks_by_previous.len());
+        let mut previous = None;
+
+        while let Some(chunk) = chunks_by_previous.remove(&previous) {
+            previous = Some(chunk.identifier);
+            ordered_chunks.push(chunk);
+        }
+
+        if !chunks_by_previous.is_empty() {
+            warn!(
+                num_chunks = chunks_by_previous.len(),
+                "Some persisted chunks aren't linked to the other ones, ignoring them"
+            );
+        }
+
+        let linked_chunk = LinkedChunk::from_chunks(ordered_chunks.into_iter().map(|chunk| {
+            let content = match chunk.content {
+                EventCacheChunk::Events(events) => ChunkContent::Items(events),
+                EventCacheChunk::Gap { prev_token } => {
+                    ChunkContent::Gap(Gap { prev_token: PaginationToken(prev_token) })
+                }
+            };
+
+            (ChunkIdentifier::from_value(chunk.identifier), content)
+        }));
+
+        match linked_chunk {
+            // The chunks that couldn't be restored, if any, will be removed from the store with
+            // the next changes.
+            Ok(chunks) => Self { chunks, persisted, modified: BTreeSet::new() },
+            Err(err) => {
+                warn!("Couldn't restore the persisted chunks, ignoring them: {err:?}");
+
+                // The new chunks could reuse the identifiers of persisted chunks, make sure
+                // they are saved again.
+                let modified = persisted.keys().copied().collect();
+                Self { chunks: LinkedChunk::new(), persisted, modified }
+            }
+        }
+    }
+
+    /// Return the changes to apply to the chunks persisted in an event cache
+    /// store, so they match the current chunks.
+    ///
+    /// Once the changes have been saved, [`Self::mark_chunks_as_persisted`]
+    /// must be called.
+    pub fn persisted_chunk_updates(&self) -> Vec<ChunkUpdate> {
+        let mut removed = self.persisted.clone();
+        let mut updates = Vec::new();
+
+        for chunk in self.chunks() {
+            let identifier = chunk.identifier();
+            let state = chunk_state(chunk);
+
+            if removed.remove(&identifier) == Some(state) && !self.modified.contains(&identifier) {
+                continue;
+            }
+
+            let content = match chunk.content() {
+                ChunkContent::Items(events) => EventCacheChunk::Events(events.clone()),
+                ChunkContent::Gap(gap) => {
+                    EventCacheChunk::Gap { prev_token: gap.prev_token.0.clone() }
+                }
+            };
+
+            updates.push(ChunkUpdate::Upsert(StoredChunk {
+                identifier: identifier.value(),
+                previous: state.0.map(|previous| previous.value()),
+                content,
+            }));
+        }
+
+        // The chunks that don't exist anymore.
+        updates.extend(
+            removed
+                .into_keys()
+                .map(|identifier| ChunkUpdate::Remove { identifier: identifier.value() }),
+        );
+
+        updates
+    }
+
+    /// Remember that the current chunks have been persisted, i.e. that the
+    /// changes from [`Self::persisted_chunk_updates`] have been saved.
+    pub fn mark_chunks_as_persisted(&mut self) {
+        self.persisted =
+            self.chunks().map(|chunk| (chunk.identifier(), chunk_state(chunk))).collect();
+        self.modified.clear();
     }
 
     /// Clear all events.
     pub fn reset(&mut self) {
         self.chunks = LinkedChunk::new();
+
+        // The identifiers of the persisted chunks will be reused.
+        self.modified.extend(self.persisted.keys().copied());
     }
 
     /// Return the number of events.
@@ -91,12 +223,28 @@ impl RoomEvents {
         I: IntoIterator<Item = SyncTimelineEvent>,
         I::IntoIter: ExactSizeIterator,
     {
-        self.chunks.insert_items_at(events, position)
+        self.chunks.insert_items_at(events, position)?;
+        self.modified.insert(position.chunk_identifier());
+        Ok(())
     }
 
     /// Insert a gap at a specified position.
     pub fn insert_gap_at(&mut self, gap: Gap, position: Position) -> Result<(), LinkedChunkError> {
-        self.chunks.insert_gap_at(gap, position)
+        self.chunks.insert_gap_at(gap, position)?;
+        self.modified.insert(position.chunk_identifier());
+        Ok(())
+    }
+
+    /// Replace the event at a specified position, and return the replaced
+    /// event.
+    pub fn replace_event_at(
+        &mut self,
+        position: Position,
+        event: SyncTimelineEvent,
+    ) -> Result<SyncTimelineEvent, LinkedChunkError> {
+        let replaced_event = self.chunks.replace_item_at(position, event)?;
+        self.modified.insert(position.chunk_identifier());
+        Ok(replaced_event)
     }
 
     /// Replace the gap identified by `gap_identifier`, by events.
@@ -203,8 +351,135 @@ impl RoomEvents {
     }
 }
 
+/// The state of the chunk to compare with its persisted state.
+fn chunk_state(
+    chunk: &Chunk<SyncTimelineEvent, Gap, DEFAULT_CHUNK_CAPACITY>,
+) -> PersistedChunkState {
+    let len = match chunk.content() {
+        ChunkContent::Items(events) => events.len(),
+        ChunkContent::Gap(..) => 0,
+    };
+
+    (chunk.previous_identifier(), len)
+}
+
 impl fmt::Debug for RoomEvents {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         formatter.debug_struct("RoomEvents").field("chunk", &self.chunks).finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_matches2::assert_let;
+    use matrix_sdk_base::event_cache_store::{ChunkUpdate, EventCacheChunk, StoredChunk};
+    use matrix_sdk_common::deserialized_responses::SyncTimelineEvent;
+    use matrix_sdk_test::sync_timeline_event;
+
+    use super::{Chunk, Gap, PaginationToken, RoomEvents};
+
+    fn event(event_id: &str) -> SyncTimelineEvent {
+        sync_timeline_event!({
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+            "event_id": event_id,
+            "origin_server_ts": 152037280,
+            "content": { "body": "hello", "msgtype": "m.text" },
+        })
+        .into()
+    }
+
+    fn room_events() -> RoomEvents {
+        let mut room_events = RoomEvents::new();
+        room_events.push_events([event("$ev0")]);
+        room_events.push_gap(Gap { prev_token: PaginationToken("token".to_owned()) });
+        room_events.push_events([event("$ev1")]);
+        room_events
+    }
+
+    #[test]
+    fn test_only_changed_chunks_are_persisted() {
+        let mut room_events = room_events();
+
+        // All the chunks are new.
+        assert_eq!(room_events.persisted_chunk_updates().len(), 3);
+        room_events.mark_chunks_as_persisted();
+        assert!(room_events.persisted_chunk_updates().is_empty());
+
+        // Pushing events only changes the last chunk.
+        room_events.push_events([event("$ev2")]);
+        let updates = room_events.persisted_chunk_updates();
+        assert_eq!(updates.len(), 1);
+        assert_let!(ChunkUpdate::Upsert(chunk) = &updates[0]);
+        assert_eq!(chunk.identifier, 2);
+        assert_let!(EventCacheChunk::Events(events) = &chunk.content);
+        assert_eq!(events.len(), 2);
+        room_events.mark_chunks_as_persisted();
+
+        // Replacing an event changes its chunk, even if its length is the same.
+        let position =
+            room_events.event_position(|event| event.event_id().unwrap() == "$ev0").unwrap();
+        room_events.replace_event_at(position, event("$ev0")).unwrap();
+        let updates = room_events.persisted_chunk_updates();
+        assert_eq!(updates.len(), 1);
+        assert_let!(ChunkUpdate::Upsert(chunk) = &updates[0]);
+        assert_eq!(chunk.identifier, 0);
+        room_events.mark_chunks_as_persisted();
+
+        // Replacing the gap adds a chunk, links the next chunk to it, and removes the
+        // gap.
+        let gap_identifier = room_events.chunk_identifier(Chunk::is_gap).unwrap();
+        room_events.replace_gap_at([event("$ev3")], gap_identifier).unwrap();
+        let updates = room_events.persisted_chunk_updates();
+        assert_eq!(updates.len(), 3);
+        assert_let!(ChunkUpdate::Upsert(chunk) = &updates[0]);
+        assert_eq!((chunk.identifier, chunk.previous), (3, Some(0)));
+        assert_let!(ChunkUpdate::Upsert(chunk) = &updates[1]);
+        assert_eq!((chunk.identifier, chunk.previous), (2, Some(3)));
+        assert_let!(ChunkUpdate::Remove { identifier: 1 } = &updates[2]);
+        room_events.mark_chunks_as_persisted();
+
+        // Resetting the events saves the first chunk again, and removes the others.
+        room_events.reset();
+        let updates = room_events.persisted_chunk_updates();
+        assert_eq!(updates.len(), 3);
+        assert_let!(ChunkUpdate::Upsert(chunk) = &updates[0]);
+        assert_eq!(chunk.identifier, 0);
+        assert_let!(EventCacheChunk::Events(events) = &chunk.content);
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn test_persisted_chunks_are_restored() {
+        let room_events = room_events();
+
+        // The chunks are loaded in any order.
+        let chunks = room_events
+            .persisted_chunk_updates()
+            .into_iter()
+            .rev()
+            .map(|update| {
+                assert_let!(ChunkUpdate::Upsert(chunk) = update);
+                chunk
+            })
+            .collect::<Vec<StoredChunk>>();
+
+        let mut restored = RoomEvents::from_persisted_chunks(chunks);
+        assert!(restored.persisted_chunk_updates().is_empty());
+
+        let event_ids =
+            restored.events().map(|(_, event)| event.event_id().unwrap()).collect::<Vec<_>>();
+        assert_eq!(event_ids, ["$ev0", "$ev1"]);
+        assert_eq!(
+            restored.chunk_identifier(Chunk::is_gap),
+            room_events.chunk_identifier(Chunk::is_gap)
+        );
+
+        // New chunks don't reuse the identifiers of the restored chunks.
+        restored.push_gap(Gap { prev_token: PaginationToken("other_token".to_owned()) });
+        let updates = restored.persisted_chunk_updates();
+        assert_eq!(updates.len(), 1);
+        assert_let!(ChunkUpdate::Upsert(chunk) = &updates[0]);
+        assert_eq!((chunk.identifier, chunk.previous), (3, Some(2)));
+    }
+}
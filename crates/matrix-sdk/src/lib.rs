@@ -50,6 +50,8 @@ pub mod oidc;
 pub mod pusher;
 pub mod room;
 pub mod room_directory_search;
+pub mod search;
+pub mod send_queue;
 pub mod utils;
 pub mod futures {
     //! Named futures returned from methods on types in [the crate root][crate].
@@ -70,8 +72,8 @@ pub use client::{
 #[cfg(feature = "image-proc")]
 pub use error::ImageError;
 pub use error::{
-    Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError, Result,
-    RumaApiError,
+    BeaconError, Error, HttpError, HttpResult, NotificationSettingsError, RefreshTokenError,
+    Result, RumaApiError,
 };
 pub use http_client::TransmissionProgress;
 #[cfg(all(feature = "e2e-encryption", feature = "sqlite"))]
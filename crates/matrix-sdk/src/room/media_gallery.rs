@@ -0,0 +1,373 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for browsing the media shared in a room, e.g. for a "files and
+//! media" screen.
+//!
+//! See [`Room::media_gallery`] to get started.
+
+use async_stream::stream;
+use futures_core::Stream;
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+use ruma::{
+    api::client::filter::{RoomEventFilter, UrlFilter},
+    assign,
+    events::{
+        room::{
+            message::{AudioInfo, FileInfo, MessageType, Relation, VideoInfo},
+            ImageInfo, MediaSource,
+        },
+        AnyMessageLikeEvent, AnyTimelineEvent, MessageLikeEvent,
+    },
+    uint, MilliSecondsSinceUnixEpoch, OwnedEventId, OwnedUserId, UInt,
+};
+use tracing::{instrument, warn};
+
+use super::{MessagesOptions, Room};
+use crate::Result;
+
+/// The default number of events requested to the server per page.
+const DEFAULT_BATCH_SIZE: UInt = uint!(50);
+
+/// A builder for browsing the media shared in a room, from the most recent to
+/// the oldest.
+///
+/// It's created with [`Room::media_gallery`], and the media are fetched with
+/// [`MediaGallery::into_stream`].
+///
+/// # Example
+///
+/// ```no_run
+/// use futures_util::{pin_mut, StreamExt};
+/// use matrix_sdk::{ruma::room_id, Client};
+/// use url::Url;
+///
+/// async {
+///     let homeserver = Url::parse("http://localhost:8080")?;
+///     let client = Client::new(homeserver).await?;
+///     let room = client.get_room(room_id!("!crepe:saucisse.bzh")).unwrap();
+///
+///     let months = room.media_gallery().into_stream();
+///     pin_mut!(months);
+///
+///     while let Some(month) = months.next().await {
+///         let month = month?;
+///         println!(
+///             "{}-{:02}: {} media",
+///             month.year,
+///             month.month,
+///             month.items.len()
+///         );
+///     }
+///     anyhow::Ok(())
+/// };
+/// ```
+#[derive(Debug)]
+pub struct MediaGallery {
+    room: Room,
+    batch_size: UInt,
+    from: Option<String>,
+}
+
+impl MediaGallery {
+    pub(crate) fn new(room: Room) -> Self {
+        Self { room, batch_size: DEFAULT_BATCH_SIZE, from: None }
+    }
+
+    /// Set the maximum number of events requested to the server per page.
+    ///
+    /// Since the server can't filter encrypted events by message type, some
+    /// of them may not be media. Default: 50.
+    pub fn batch_size(mut self, batch_size: UInt) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Start browsing from the given pagination token, instead of the most
+    /// recent event of the room.
+    pub fn from(mut self, from: String) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Browse the media of the room, returning a stream of the months that
+    /// contain media, from the most recent to the oldest.
+    ///
+    /// A month is only yielded once all its media have been fetched, i.e.
+    /// once the room history has been paginated past the start of the month,
+    /// or the start of the room is reached. The stream ends at the start of
+    /// the room, or when an error occurs, which is the last item of the stream.
+    pub fn into_stream(mut self) -> impl Stream<Item = Result<MediaGalleryMonth>> {
+        stream! {
+            let mut current_month: Option<MediaGalleryMonth> = None;
+
+            loop {
+                let from = self.from.take();
+                let page = match self.fetch_page(from).await {
+                    Ok(page) => page,
+                    Err(err) => {
+                        yield Err(err);
+                        break;
+                    }
+                };
+
+                for item in page.items {
+                    let (year, month) = year_and_month(item.timestamp);
+
+                    match &mut current_month {
+                        Some(current) if current.year == year && current.month == month => {
+                            current.items.push(item);
+                        }
+                        _ => {
+                            let new_month =
+                                MediaGalleryMonth { year, month, items: vec![item] };
+
+                            if let Some(previous) = current_month.replace(new_month) {
+                                yield Ok(previous);
+                            }
+                        }
+                    }
+                }
+
+                // The events are in reverse chronological order, so if the oldest event of the
+                // page is in an older month, the current month is complete.
+                let is_current_month_complete = page
+                    .oldest_timestamp
+                    .map(year_and_month)
+                    .zip(current_month.as_ref())
+                    .is_some_and(|(oldest, current)| oldest != (current.year, current.month));
+
+                if is_current_month_complete {
+                    if let Some(month) = current_month.take() {
+                        yield Ok(month);
+                    }
+                }
+
+                self.from = page.end;
+                if self.from.is_none() {
+                    break;
+                }
+            }
+
+            if let Some(month) = current_month {
+                yield Ok(month);
+            }
+        }
+    }
+
+    /// Fetch a single page of events, starting at the given pagination token,
+    /// and return the media among them.
+    #[instrument(skip(self), fields(room_id = ?self.room.room_id()))]
+    async fn fetch_page(&self, from: Option<String>) -> Result<MediaGalleryPage> {
+        let filter = if self.room.is_encrypted().await? {
+            // The message type and the URL are in the encrypted content of encrypted
+            // events, so the server can only filter on the event type.
+            assign!(RoomEventFilter::default(), {
+                types: Some(vec!["m.room.message".to_owned(), "m.room.encrypted".to_owned()]),
+            })
+        } else {
+            assign!(RoomEventFilter::default(), {
+                types: Some(vec!["m.room.message".to_owned()]),
+                url_filter: Some(UrlFilter::EventsWithUrl),
+            })
+        };
+
+        let options =
+            assign!(MessagesOptions::backward(), { from, limit: self.batch_size, filter });
+
+        let messages = self.room.messages(options).await?;
+        let items = messages.chunk.iter().filter_map(MediaGalleryItem::from_event).collect();
+        let oldest_timestamp = messages
+            .chunk
+            .iter()
+            .filter_map(|event| event.event.get_field("origin_server_ts").ok().flatten())
+            .min();
+
+        Ok(MediaGalleryPage { items, oldest_timestamp, end: messages.end })
+    }
+}
+
+/// A page of events fetched by a [`MediaGallery`].
+struct MediaGalleryPage {
+    /// The media of the page, from the most recent to the oldest.
+    items: Vec<MediaGalleryItem>,
+
+    /// The timestamp of the oldest event of the page, media or not.
+    oldest_timestamp: Option<MilliSecondsSinceUnixEpoch>,
+
+    /// The token to fetch the next page, if any.
+    end: Option<String>,
+}
+
+/// The media of a room that were sent during a given month.
+#[derive(Clone, Debug)]
+pub struct MediaGalleryMonth {
+    /// The year, in the UTC timezone.
+    pub year: i32,
+
+    /// The month of the year, from 1 to 12, in the UTC timezone.
+    pub month: u8,
+
+    /// The media of this month, from the most recent to the oldest.
+    pub items: Vec<MediaGalleryItem>,
+}
+
+/// A media shared in a room.
+#[derive(Clone, Debug)]
+pub struct MediaGalleryItem {
+    /// The ID of the event of the media.
+    pub event_id: OwnedEventId,
+
+    /// The user who sent the media.
+    pub sender: OwnedUserId,
+
+    /// The time when the media was sent, according to the server of the
+    /// sender.
+    pub timestamp: MilliSecondsSinceUnixEpoch,
+
+    /// The description of the media, or its file name if there is none.
+    pub body: String,
+
+    /// The source of the media.
+    pub source: MediaSource,
+
+    /// The source of the thumbnail of the media, if any.
+    pub thumbnail_source: Option<MediaSource>,
+
+    /// The kind of the media, with its metadata.
+    pub info: MediaGalleryItemInfo,
+}
+
+impl MediaGalleryItem {
+    /// Get the media of the given event, if it's a media message.
+    ///
+    /// Edits are ignored, the original event of a media being enough to
+    /// display it in a gallery.
+    fn from_event(event: &TimelineEvent) -> Option<Self> {
+        let event = match event.event.deserialize() {
+            Ok(AnyTimelineEvent::MessageLike(AnyMessageLikeEvent::RoomMessage(
+                MessageLikeEvent::Original(event),
+            ))) => event,
+            Ok(_) => return None,
+            Err(err) => {
+                warn!("Couldn't deserialize event: {err}");
+                return None;
+            }
+        };
+
+        if matches!(event.content.relates_to, Some(Relation::Replacement(_))) {
+            return None;
+        }
+
+        let (body, source, thumbnail_source, info) = match event.content.msgtype {
+            MessageType::Image(content) => {
+                let thumbnail_source =
+                    content.info.as_ref().and_then(|info| info.thumbnail_source.clone());
+                (
+                    content.body,
+                    content.source,
+                    thumbnail_source,
+                    MediaGalleryItemInfo::Image(content.info),
+                )
+            }
+            MessageType::Video(content) => {
+                let thumbnail_source =
+                    content.info.as_ref().and_then(|info| info.thumbnail_source.clone());
+                (
+                    content.body,
+                    content.source,
+                    thumbnail_source,
+                    MediaGalleryItemInfo::Video(content.info),
+                )
+            }
+            MessageType::File(content) => {
+                let thumbnail_source =
+                    content.info.as_ref().and_then(|info| info.thumbnail_source.clone());
+                (
+                    content.body,
+                    content.source,
+                    thumbnail_source,
+                    MediaGalleryItemInfo::File(content.info),
+                )
+            }
+            MessageType::Audio(content) => {
+                (content.body, content.source, None, MediaGalleryItemInfo::Audio(content.info))
+            }
+            _ => return None,
+        };
+
+        Some(Self {
+            event_id: event.event_id,
+            sender: event.sender,
+            timestamp: event.origin_server_ts,
+            body,
+            source,
+            thumbnail_source,
+            info,
+        })
+    }
+}
+
+/// The kind of a [`MediaGalleryItem`], with the metadata of the media.
+#[derive(Clone, Debug)]
+pub enum MediaGalleryItemInfo {
+    /// An image.
+    Image(Option<Box<ImageInfo>>),
+
+    /// A video.
+    Video(Option<Box<VideoInfo>>),
+
+    /// A generic file.
+    File(Option<Box<FileInfo>>),
+
+    /// An audio file.
+    Audio(Option<Box<AudioInfo>>),
+}
+
+/// Get the year and month of the given timestamp, in the UTC timezone.
+fn year_and_month(timestamp: MilliSecondsSinceUnixEpoch) -> (i32, u8) {
+    // Algorithm from Howard Hinnant's `civil_from_days`, see
+    // <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+    let days = i64::from(timestamp.0).div_euclid(86_400_000) + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year as i32, month as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use ruma::{uint, MilliSecondsSinceUnixEpoch};
+
+    use super::year_and_month;
+
+    #[test]
+    fn test_year_and_month() {
+        let ts = MilliSecondsSinceUnixEpoch;
+
+        assert_eq!(year_and_month(ts(uint!(0))), (1970, 1));
+        // 2024-02-29T23:59:59.999Z
+        assert_eq!(year_and_month(ts(uint!(1_709_251_199_999))), (2024, 2));
+        // 2024-03-01T00:00:00Z
+        assert_eq!(year_and_month(ts(uint!(1_709_251_200_000))), (2024, 3));
+        // 2023-12-31T12:00:00Z
+        assert_eq!(year_and_month(ts(uint!(1_704_024_000_000))), (2023, 12));
+    }
+}
@@ -17,13 +17,17 @@ use std::fmt;
 use matrix_sdk_common::{debug::DebugStructExt as _, deserialized_responses::TimelineEvent};
 use ruma::{
     api::{
-        client::{filter::RoomEventFilter, message::get_message_events},
+        client::{
+            filter::RoomEventFilter,
+            message::get_message_events,
+            relations::{get_relating_events, get_relating_events_with_rel_type},
+        },
         Direction,
     },
     assign,
-    events::AnyStateEvent,
+    events::{relation::RelationType, AnyStateEvent},
     serde::Raw,
-    uint, RoomId, UInt,
+    uint, EventId, RoomId, UInt,
 };
 
 /// Options for [`messages`][super::Room::messages].
@@ -139,3 +143,162 @@ pub struct Messages {
     /// A list of state events relevant to showing the `chunk`.
     pub state: Vec<Raw<AnyStateEvent>>,
 }
+
+/// Options for [`relations`][super::Room::relations].
+///
+/// See that method and
+/// <https://spec.matrix.org/v1.9/client-server-api/#get_matrixclientv1roomsroomidrelationseventid>
+/// for details.
+#[non_exhaustive]
+pub struct RelationsOptions {
+    /// The token to start returning events from.
+    ///
+    /// This token can be obtained from a `next_batch` or `prev_batch` token
+    /// returned by a previous `relations` call.
+    ///
+    /// If `from` isn't provided the homeserver shall return a list of related
+    /// events starting from the first or last (per the value of the dir
+    /// parameter) visible relation.
+    pub from: Option<String>,
+
+    /// The token to stop returning events at.
+    pub to: Option<String>,
+
+    /// The direction to return events in.
+    ///
+    /// Default: `Backward`.
+    pub dir: Direction,
+
+    /// The maximum number of events to return.
+    ///
+    /// The server chooses the default value if it's not set.
+    pub limit: Option<UInt>,
+
+    /// Only return events that relate to the original event with this
+    /// relation type.
+    pub rel_type: Option<RelationType>,
+}
+
+impl RelationsOptions {
+    /// Creates `RelationsOptions` returning relations of any type, from the
+    /// most recent one.
+    pub fn new() -> Self {
+        Self { from: None, to: None, dir: Direction::Backward, limit: None, rel_type: None }
+    }
+
+    /// Creates `RelationsOptions` returning only relations of the given type,
+    /// from the most recent one.
+    pub fn with_rel_type(rel_type: RelationType) -> Self {
+        Self { rel_type: Some(rel_type), ..Self::new() }
+    }
+
+    /// Creates a new `RelationsOptions` from `self` with the `from` field set
+    /// to the given value.
+    ///
+    /// Since the field is public, you can also assign to it directly. This
+    /// method merely acts as a shorthand for that, because it is very
+    /// common to set this field.
+    pub fn from<'a>(self, from: impl Into<Option<&'a str>>) -> Self {
+        Self { from: from.into().map(ToOwned::to_owned), ..self }
+    }
+
+    pub(super) fn into_request(self, room_id: &RoomId, event_id: &EventId) -> RelationsRequest {
+        match self.rel_type {
+            Some(rel_type) => RelationsRequest::WithRelType(assign!(
+                get_relating_events_with_rel_type::v1::Request::new(
+                    room_id.to_owned(),
+                    event_id.to_owned(),
+                    rel_type,
+                ),
+                { from: self.from, to: self.to, dir: self.dir, limit: self.limit }
+            )),
+            None => RelationsRequest::Any(assign!(
+                get_relating_events::v1::Request::new(room_id.to_owned(), event_id.to_owned()),
+                { from: self.from, to: self.to, dir: self.dir, limit: self.limit }
+            )),
+        }
+    }
+}
+
+impl Default for RelationsOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RelationsOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { from, to, dir, limit, rel_type } = self;
+
+        f.debug_struct("RelationsOptions")
+            .maybe_field("from", from)
+            .maybe_field("to", to)
+            .field("dir", dir)
+            .maybe_field("limit", limit)
+            .maybe_field("rel_type", rel_type)
+            .finish()
+    }
+}
+
+/// The request to send for a [`RelationsOptions`], depending on whether the
+/// relation type is restricted or not.
+pub(super) enum RelationsRequest {
+    Any(get_relating_events::v1::Request),
+    WithRelType(get_relating_events_with_rel_type::v1::Request),
+}
+
+/// The result of a `Room::relations` call.
+///
+/// In short, this is a possibly decrypted version of the response of a
+/// `/relations` api call.
+#[derive(Debug)]
+pub struct Relations {
+    /// The events relating to the original event.
+    pub chunk: Vec<TimelineEvent>,
+
+    /// An opaque string representing a pagination token, to be passed as
+    /// `from` to get the next batch of results.
+    ///
+    /// If this is `None`, there are no more results to fetch.
+    pub next_batch: Option<String>,
+
+    /// An opaque string representing a pagination token, to get the previous
+    /// batch of results.
+    pub prev_batch: Option<String>,
+}
+
+/// The result of a `Room::event_with_context` call.
+///
+/// In short, this is a possibly decrypted version of the response of a
+/// `/context` api call.
+#[derive(Debug, Default)]
+pub struct EventWithContextResponse {
+    /// The event targeted by the `/context` query.
+    pub event: Option<TimelineEvent>,
+
+    /// Events before the target event, if a non-zero context size was
+    /// requested.
+    ///
+    /// Like the corresponding Matrix response, these are in reverse
+    /// chronological order.
+    pub events_before: Vec<TimelineEvent>,
+
+    /// Events after the target event, if a non-zero context size was
+    /// requested, in chronological order.
+    pub events_after: Vec<TimelineEvent>,
+
+    /// A token to paginate backwards from the earliest event in
+    /// `events_before`, if any.
+    pub prev_batch_token: Option<String>,
+
+    /// A token to paginate forwards from the latest event in `events_after`,
+    /// if any.
+    pub next_batch_token: Option<String>,
+
+    /// State events related to the request.
+    ///
+    /// If lazy-loading of members was requested, this may contain room
+    /// membership events.
+    pub state: Vec<Raw<AnyStateEvent>>,
+}
@@ -48,6 +48,8 @@ use ruma::{
     },
     assign,
     events::{
+        beacon::BeaconEventContent,
+        beacon_info::BeaconInfoEventContent,
         direct::DirectEventContent,
         marked_unread::MarkedUnreadEventContent,
         receipt::{Receipt, ReceiptThread, ReceiptType},
@@ -57,6 +59,7 @@ use ruma::{
             history_visibility::HistoryVisibility,
             message::RoomMessageEventContent,
             name::RoomNameEventContent,
+            pinned_events::RoomPinnedEventsEventContent,
             power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
             server_acl::RoomServerAclEventContent,
             topic::RoomTopicEventContent,
@@ -65,14 +68,15 @@ use ruma::{
         space::{child::SpaceChildEventContent, parent::SpaceParentEventContent},
         tag::{TagInfo, TagName},
         typing::SyncTypingEvent,
-        AnyRoomAccountDataEvent, AnyStateEvent, EmptyStateKey, MessageLikeEventContent,
-        MessageLikeEventType, RedactContent, RedactedStateEventContent, RoomAccountDataEvent,
-        RoomAccountDataEventContent, RoomAccountDataEventType, StateEventContent, StateEventType,
-        StaticEventContent, StaticStateEventContent, SyncStateEvent,
+        AnyRoomAccountDataEvent, AnyStateEvent, AnyTimelineEvent, EmptyStateKey,
+        MessageLikeEventContent, MessageLikeEventType, OriginalSyncStateEvent, RedactContent,
+        RedactedStateEventContent, RoomAccountDataEvent, RoomAccountDataEventContent,
+        RoomAccountDataEventType, StateEventContent, StateEventType, StaticEventContent,
+        StaticStateEventContent, SyncStateEvent,
     },
     push::{Action, PushConditionRoomCtx},
     serde::Raw,
-    uint, EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
+    EventId, Int, MatrixToUri, MatrixUri, MxcUri, OwnedEventId, OwnedRoomId, OwnedServerName,
     OwnedTransactionId, OwnedUserId, TransactionId, UInt, UserId,
 };
 use serde::de::DeserializeOwned;
@@ -80,28 +84,34 @@ use thiserror::Error;
 use tokio::sync::broadcast;
 use tracing::{debug, info, instrument, warn};
 
-use self::futures::{SendAttachment, SendMessageLikeEvent, SendRawMessageLikeEvent};
+use self::{
+    futures::{SendAttachment, SendMessageLikeEvent, SendRawMessageLikeEvent},
+    media_gallery::MediaGallery,
+    messages::RelationsRequest,
+};
 pub use self::{
     member::{RoomMember, RoomMemberRole},
-    messages::{Messages, MessagesOptions},
+    messages::{EventWithContextResponse, Messages, MessagesOptions, Relations, RelationsOptions},
 };
 #[cfg(doc)]
 use crate::event_cache::EventCache;
 use crate::{
     attachment::AttachmentConfig,
     config::RequestConfig,
-    error::WrongRoomState,
-    event_cache::{self, EventCacheDropHandles, RoomEventCache},
+    error::{BeaconError, WrongRoomState},
+    event_cache::{self, EventCacheDropHandles, RoomEventCache, SearchHit},
     event_handler::{EventHandler, EventHandlerDropGuard, EventHandlerHandle, SyncEvent},
     media::{MediaFormat, MediaRequest},
     notification_settings::{IsEncrypted, IsOneToOne, RoomNotificationMode},
     room::power_levels::{RoomPowerLevelChanges, RoomPowerLevelsExt},
+    send_queue::RoomSendQueue,
     sync::RoomUpdate,
     utils::{IntoRawMessageLikeEventContent, IntoRawStateEventContent},
     BaseRoom, Client, Error, HttpError, HttpResult, Result, RoomState, TransmissionProgress,
 };
 
 pub mod futures;
+pub mod media_gallery;
 mod member;
 mod messages;
 pub mod power_levels;
@@ -235,7 +245,7 @@ impl Room {
     ///
     /// With the encryption feature, messages are decrypted if possible. If
     /// decryption fails for an individual message, that message is returned
-    /// undecrypted.
+    /// undecrypted, with the reason of the failure in its `utd_cause`.
     ///
     /// # Examples
     ///
@@ -263,44 +273,86 @@ impl Room {
         let request = options.into_request(room_id);
         let http_response = self.client.send(request, None).await?;
 
-        #[allow(unused_mut)]
-        let mut response = Messages {
+        let mut chunk = Vec::with_capacity(http_response.chunk.len());
+        for event in http_response.chunk {
+            chunk.push(self.try_decrypt_room_event(event).await?);
+        }
+
+        Ok(Messages {
             start: http_response.start,
             end: http_response.end,
-            #[cfg(not(feature = "e2e-encryption"))]
-            chunk: http_response.chunk.into_iter().map(TimelineEvent::new).collect(),
-            #[cfg(feature = "e2e-encryption")]
-            chunk: Vec::with_capacity(http_response.chunk.len()),
+            chunk,
             state: http_response.state,
-        };
+        })
+    }
 
-        #[cfg(feature = "e2e-encryption")]
-        for event in http_response.chunk {
-            let decrypted_event = if let Ok(AnySyncTimelineEvent::MessageLike(
-                AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(_)),
-            )) = event.deserialize_as::<AnySyncTimelineEvent>()
-            {
-                if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
-                    event
-                } else {
-                    TimelineEvent::new(event)
+    /// Browse the media shared in this room, e.g. for a "files and media"
+    /// screen.
+    ///
+    /// The images, videos, files and audio messages of the room are fetched
+    /// from the most recent to the oldest, decrypted if possible, and grouped
+    /// by month. See [`MediaGallery`] for the available options.
+    pub fn media_gallery(&self) -> MediaGallery {
+        MediaGallery::new(self.clone())
+    }
+
+    /// Fetch the events relating to the event with the given `EventId` in this
+    /// room, using the `/relations` endpoint.
+    ///
+    /// If the encryption feature is enabled, encrypted events are
+    /// transparently decrypted when possible.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the event whose relations should be fetched.
+    ///
+    /// * `options` - Options for the request, such as the relation type to
+    ///   restrict the results to, and the pagination token to start from.
+    #[instrument(skip(self), fields(room_id = ?self.inner.room_id()))]
+    pub async fn relations(
+        &self,
+        event_id: &EventId,
+        options: RelationsOptions,
+    ) -> Result<Relations> {
+        let (chunk, next_batch, prev_batch) =
+            match options.into_request(self.inner.room_id(), event_id) {
+                RelationsRequest::Any(request) => {
+                    let response = self.client.send(request, None).await?;
+                    (response.chunk, response.next_batch, response.prev_batch)
+                }
+                RelationsRequest::WithRelType(request) => {
+                    let response = self.client.send(request, None).await?;
+                    (response.chunk, response.next_batch, response.prev_batch)
                 }
-            } else {
-                TimelineEvent::new(event)
             };
-            response.chunk.push(decrypted_event);
-        }
 
-        if let Some(push_context) = self.push_context().await? {
-            let push_rules = self.client().account().push_rules().await?;
+        let mut relations =
+            Relations { chunk: Vec::with_capacity(chunk.len()), next_batch, prev_batch };
+
+        for event in chunk {
+            let event = event.cast::<AnyTimelineEvent>();
 
-            for event in &mut response.chunk {
-                event.push_actions =
-                    Some(push_rules.get_actions(&event.event, &push_context).to_owned());
+            #[cfg(feature = "e2e-encryption")]
+            if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
+                SyncMessageLikeEvent::Original(_),
+            ))) = event.deserialize_as::<AnySyncTimelineEvent>()
+            {
+                if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
+                    relations.chunk.push(event);
+                    continue;
+                }
             }
+
+            let push_actions = self.event_push_actions(&event).await?;
+            relations.chunk.push(TimelineEvent {
+                event,
+                encryption_info: None,
+                push_actions,
+                utd_cause: None,
+            });
         }
 
-        Ok(response)
+        Ok(relations)
     }
 
     /// Register a handler for events of a specific type, within this room.
@@ -361,32 +413,32 @@ impl Room {
             get_room_event::v3::Request::new(self.room_id().to_owned(), event_id.to_owned());
         let event = self.client.send(request, None).await?.event;
 
-        #[cfg(feature = "e2e-encryption")]
-        if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
-            SyncMessageLikeEvent::Original(_),
-        ))) = event.deserialize_as::<AnySyncTimelineEvent>()
-        {
-            if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
-                return Ok(event);
-            }
-        }
-
-        let push_actions = self.event_push_actions(&event).await?;
-
-        Ok(TimelineEvent { event, encryption_info: None, push_actions })
+        self.try_decrypt_room_event(event).await
     }
 
     /// Fetch the event with the given `EventId` in this room, using the
     /// `/context` endpoint to get more information.
+    ///
+    /// # Arguments
+    ///
+    /// * `event_id` - The ID of the event to fetch.
+    ///
+    /// * `lazy_load_members` - Whether to ask the server for the membership
+    ///   events of the senders of the returned events.
+    ///
+    /// * `context_size` - The maximum number of events to return around the
+    ///   target event, split between the events before and after it. Use `0` to
+    ///   only fetch the target event.
     pub async fn event_with_context(
         &self,
         event_id: &EventId,
         lazy_load_members: bool,
-    ) -> Result<Option<(TimelineEvent, Vec<Raw<AnyStateEvent>>)>> {
+        context_size: UInt,
+    ) -> Result<EventWithContextResponse> {
         let mut request =
             context::get_context::v3::Request::new(self.room_id().to_owned(), event_id.to_owned());
 
-        request.limit = uint!(0);
+        request.limit = context_size;
 
         if lazy_load_members {
             request.filter.lazy_load_options =
@@ -395,23 +447,61 @@ impl Room {
 
         let response = self.client.send(request, None).await?;
 
-        let Some(event) = response.event else {
-            return Ok(None);
+        let event = match response.event {
+            Some(event) => Some(self.try_decrypt_room_event(event).await?),
+            None => None,
         };
 
+        let mut events_before = Vec::with_capacity(response.events_before.len());
+        for event in response.events_before {
+            events_before.push(self.try_decrypt_room_event(event).await?);
+        }
+
+        let mut events_after = Vec::with_capacity(response.events_after.len());
+        for event in response.events_after {
+            events_after.push(self.try_decrypt_room_event(event).await?);
+        }
+
+        Ok(EventWithContextResponse {
+            event,
+            events_before,
+            events_after,
+            prev_batch_token: response.start,
+            next_batch_token: response.end,
+            state: response.state,
+        })
+    }
+
+    /// Try to decrypt the given event if it's encrypted, and compute its push
+    /// actions otherwise.
+    pub(crate) async fn try_decrypt_room_event(
+        &self,
+        event: Raw<AnyTimelineEvent>,
+    ) -> Result<TimelineEvent> {
         #[cfg(feature = "e2e-encryption")]
-        if let Ok(AnySyncTimelineEvent::MessageLike(AnySyncMessageLikeEvent::RoomEncrypted(
-            SyncMessageLikeEvent::Original(_),
-        ))) = event.deserialize_as::<AnySyncTimelineEvent>()
+        let utd_cause = if let Ok(AnySyncTimelineEvent::MessageLike(
+            AnySyncMessageLikeEvent::RoomEncrypted(SyncMessageLikeEvent::Original(_)),
+        )) = event.deserialize_as::<AnySyncTimelineEvent>()
         {
-            if let Ok(event) = self.decrypt_event(event.cast_ref()).await {
-                return Ok(Some((event, response.state)));
+            match self.decrypt_event(event.cast_ref()).await {
+                Ok(event) => return Ok(event),
+                Err(Error::MegolmError(error)) => self
+                    .client
+                    .olm_machine()
+                    .await
+                    .as_ref()
+                    .map(|olm| olm.utd_cause(event.cast_ref(), &error)),
+                Err(_) => None,
             }
-        }
+        } else {
+            None
+        };
+        #[cfg(not(feature = "e2e-encryption"))]
+        let utd_cause = None;
 
         let push_actions = self.event_push_actions(&event).await?;
 
-        Ok(Some((TimelineEvent { event, encryption_info: None, push_actions }, response.state)))
+        Ok(TimelineEvent { event, encryption_info: None, push_actions, utd_cause })
     }
 
     pub(crate) async fn request_members(&self) -> Result<()> {
@@ -1214,6 +1304,46 @@ impl Room {
         let request = invite_user::v3::Request::new(self.room_id().to_owned(), recipient);
         self.client.send(request, None).await?;
 
+        // The invite was sent, failing to share the room history shouldn't
+        // make it look like it wasn't.
+        #[cfg(feature = "e2e-encryption")]
+        if let Err(error) = self.share_history_room_keys(user_id).await {
+            warn!(?error, "Couldn't share the room history with the invited user");
+        }
+
+        Ok(())
+    }
+
+    /// Forward the room keys with a shared history of this room to a user we
+    /// invited, if this was enabled with
+    /// [`Encryption::set_share_history_on_invite()`].
+    ///
+    /// [`Encryption::set_share_history_on_invite()`]: crate::encryption::Encryption::set_share_history_on_invite
+    #[cfg(feature = "e2e-encryption")]
+    async fn share_history_room_keys(&self, user_id: &UserId) -> Result<()> {
+        let olm = self.client.olm_machine().await;
+        let Some(olm) = olm.as_ref() else {
+            return Ok(());
+        };
+
+        if !olm.store().get_share_history_on_invite().await? || !self.is_encrypted().await? {
+            return Ok(());
+        }
+
+        // The invited user isn't necessarily tracked yet, make sure we know
+        // about all of their devices and have an Olm session with each of them.
+        let (request_id, request) = olm.query_keys_for_users([user_id]);
+        self.client.keys_query(&request_id, request.device_keys).await?;
+        self.client.claim_one_time_keys([user_id].into_iter()).await?;
+
+        let requests =
+            olm.share_room_history_keys(self.room_id(), user_id, self.history_visibility()).await?;
+
+        for request in requests {
+            let response = self.client.send_to_device(&request).await?;
+            self.client.mark_request_as_sent(&request.txn_id, &response).await?;
+        }
+
         Ok(())
     }
 
@@ -1900,6 +2030,50 @@ impl Room {
         self.send_state_event(RoomTopicEventContent::new(topic.into())).await
     }
 
+    /// Pins an event in this room, by adding it to the `m.room.pinned_events`
+    /// state event.
+    ///
+    /// Returns `Ok(false)` if the event was already pinned, in which case no
+    /// request is sent.
+    ///
+    /// See [`Self::can_user_pin_unpin`] to check whether the user is allowed
+    /// to do so.
+    pub async fn pin_event(&self, event_id: &EventId) -> Result<bool> {
+        let mut pinned_event_ids = self.pinned_event_ids();
+
+        if pinned_event_ids.iter().any(|id| id == event_id) {
+            return Ok(false);
+        }
+
+        pinned_event_ids.push(event_id.to_owned());
+        self.send_state_event(RoomPinnedEventsEventContent::new(pinned_event_ids)).await?;
+
+        Ok(true)
+    }
+
+    /// Unpins an event in this room, by removing it from the
+    /// `m.room.pinned_events` state event.
+    ///
+    /// Returns `Ok(false)` if the event wasn't pinned, in which case no request
+    /// is sent.
+    ///
+    /// See [`Self::can_user_pin_unpin`] to check whether the user is allowed
+    /// to do so.
+    pub async fn unpin_event(&self, event_id: &EventId) -> Result<bool> {
+        let mut pinned_event_ids = self.pinned_event_ids();
+        let num_pinned_events = pinned_event_ids.len();
+
+        pinned_event_ids.retain(|id| id != event_id);
+
+        if pinned_event_ids.len() == num_pinned_events {
+            return Ok(false);
+        }
+
+        self.send_state_event(RoomPinnedEventsEventContent::new(pinned_event_ids)).await?;
+
+        Ok(true)
+    }
+
     /// Sets the new avatar url for this room.
     ///
     /// # Arguments
@@ -2108,6 +2282,94 @@ impl Room {
         Ok(self.client.send(request, None).await?)
     }
 
+    /// Start sharing the live location of the current user in this room
+    /// ([MSC3489]).
+    ///
+    /// The share lasts for the given `duration`, unless it's stopped before
+    /// with [`Room::stop_live_location_share()`]. The locations themselves are
+    /// shared with [`Room::send_location_beacon()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `duration` - How long the location will be shared.
+    ///
+    /// * `description` - An optional description of the share.
+    ///
+    /// [MSC3489]: https://github.com/matrix-org/matrix-spec-proposals/pull/3489
+    #[instrument(skip_all)]
+    pub async fn start_live_location_share(
+        &self,
+        duration: Duration,
+        description: Option<String>,
+    ) -> Result<send_state_event::v3::Response> {
+        self.ensure_room_joined()?;
+
+        let content = BeaconInfoEventContent::new(description, duration, true, None);
+        self.send_state_event_for_key(self.own_user_id(), content).await
+    }
+
+    /// Stop sharing the live location of the current user in this room.
+    ///
+    /// Returns an error if the current user hasn't started a live location
+    /// share in this room.
+    #[instrument(skip_all)]
+    pub async fn stop_live_location_share(
+        &self,
+    ) -> Result<send_state_event::v3::Response, BeaconError> {
+        self.ensure_room_joined()?;
+
+        let mut beacon_info = self.get_user_beacon_info(self.own_user_id()).await?;
+        beacon_info.content.stop();
+
+        Ok(self.send_state_event_for_key(self.own_user_id(), beacon_info.content).await?)
+    }
+
+    /// Share the current location of the current user in this room, as part of
+    /// the live location share started with
+    /// [`Room::start_live_location_share()`].
+    ///
+    /// # Arguments
+    ///
+    /// * `geo_uri` - The location, as a `geo:` URI as defined in [RFC 5870].
+    ///
+    /// Returns an error if there is no live location share of the current user
+    /// in this room, or if it has been stopped or has expired.
+    ///
+    /// [RFC 5870]: https://datatracker.ietf.org/doc/html/rfc5870
+    #[instrument(skip_all)]
+    pub async fn send_location_beacon(
+        &self,
+        geo_uri: String,
+    ) -> Result<send_message_event::v3::Response, BeaconError> {
+        self.ensure_room_joined()?;
+
+        let beacon_info = self.get_user_beacon_info(self.own_user_id()).await?;
+        if !beacon_info.content.is_live() {
+            return Err(BeaconError::NotLive);
+        }
+
+        let content = BeaconEventContent::new(beacon_info.event_id, geo_uri, None);
+        Ok(self.send(content).await?)
+    }
+
+    /// Get the latest `beacon_info` state event of the given user in this room,
+    /// which describes their live location share.
+    pub async fn get_user_beacon_info(
+        &self,
+        user_id: &UserId,
+    ) -> Result<OriginalSyncStateEvent<BeaconInfoEventContent>, BeaconError> {
+        let raw_event = self
+            .get_state_event_static_for_key::<BeaconInfoEventContent, _>(user_id)
+            .await?
+            .ok_or(BeaconError::NotFound)?;
+
+        match raw_event.deserialize()? {
+            SyncOrStrippedState::Sync(SyncStateEvent::Original(beacon_info)) => Ok(beacon_info),
+            SyncOrStrippedState::Sync(SyncStateEvent::Redacted(_)) => Err(BeaconError::Redacted),
+            SyncOrStrippedState::Stripped(_) => Err(BeaconError::Stripped),
+        }
+    }
+
     /// Strips all information out of an event of the room.
     ///
     /// Returns the [`redact_event::v3::Response`] from the server.
@@ -2210,6 +2472,14 @@ impl Room {
         Ok(self.room_power_levels().await?.user_can_send_state(user_id, state_event))
     }
 
+    /// Returns true if the user with the given user_id is able to pin or unpin
+    /// events in the room.
+    ///
+    /// The call may fail if there is an error in getting the power levels.
+    pub async fn can_user_pin_unpin(&self, user_id: &UserId) -> Result<bool> {
+        self.can_user_send_state(user_id, StateEventType::RoomPinnedEvents).await
+    }
+
     /// Returns true if the user with the given user_id is able to send a
     /// specific message type in the room.
     ///
@@ -2603,6 +2873,28 @@ impl Room {
             (maybe_room.unwrap(), drop_handles)
         })
     }
+
+    /// Returns the [`RoomSendQueue`] of this room, which persists and sends
+    /// requests in order, and survives restarts of the application.
+    pub fn send_queue(&self) -> RoomSendQueue {
+        self.client.send_queue().for_room(self)
+    }
+
+    /// Search the messages of this room in the local search index, the best
+    /// matches first.
+    ///
+    /// Each word of the query must match the beginning of a word of a message
+    /// for it to be returned, ignoring case. Only the messages seen by the
+    /// event cache since [`EventCache::enable_search_index`] has been called
+    /// are indexed.
+    pub async fn search_messages(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>> {
+        Ok(self
+            .client
+            .base_client()
+            .event_cache_store()
+            .search_messages(Some(self.room_id()), query, limit)
+            .await?)
+    }
 }
 
 /// Details of the (latest) invite.
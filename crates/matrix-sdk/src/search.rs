@@ -0,0 +1,336 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Types for searching the messages of the rooms on the homeserver, with the
+//! `/search` endpoint.
+//!
+//! The server can only search the messages of unencrypted rooms; see
+//! [`Client::search_messages`] to search the messages of encrypted rooms
+//! locally.
+
+use std::collections::BTreeMap;
+
+use async_stream::stream;
+use futures_core::Stream;
+use matrix_sdk_base::deserialized_responses::TimelineEvent;
+pub use ruma::api::client::search::search_events::v3::{OrderBy, UserProfile};
+use ruma::{
+    api::client::{
+        filter::RoomEventFilter,
+        search::search_events::v3::{
+            Categories, Criteria, EventContext, Grouping, GroupingKey, Request, ResultGroup,
+            RoomIdOrUserId,
+        },
+    },
+    assign,
+    events::AnyTimelineEvent,
+    serde::Raw,
+    OwnedEventId, OwnedRoomId, OwnedUserId, UInt,
+};
+use tracing::{instrument, warn};
+
+use crate::{Client, Result};
+
+/// A builder for a search of messages on the homeserver.
+///
+/// It's created with [`Client::search`], and the results are fetched with
+/// [`SearchBuilder::into_stream`].
+///
+/// # Example
+///
+/// ```no_run
+/// use futures_util::{pin_mut, StreamExt};
+/// use matrix_sdk::{search::OrderBy, Client};
+/// use url::Url;
+///
+/// async {
+///     let homeserver = Url::parse("http://localhost:8080")?;
+///     let client = Client::new(homeserver).await?;
+///
+///     let pages =
+///         client.search("pancakes").order_by(OrderBy::Recent).into_stream();
+///     pin_mut!(pages);
+///
+///     while let Some(page) = pages.next().await {
+///         for result in page?.results {
+///             println!("Found {:?}", result.event.event_id());
+///         }
+///     }
+///     anyhow::Ok(())
+/// };
+/// ```
+#[derive(Debug)]
+pub struct SearchBuilder {
+    client: Client,
+    search_term: String,
+    rooms: Option<Vec<OwnedRoomId>>,
+    order_by: Option<OrderBy>,
+    event_context: Option<EventContext>,
+    group_by_room: bool,
+    next_batch: Option<String>,
+}
+
+impl SearchBuilder {
+    pub(crate) fn new(client: Client, search_term: String) -> Self {
+        Self {
+            client,
+            search_term,
+            rooms: None,
+            order_by: None,
+            event_context: None,
+            group_by_room: false,
+            next_batch: None,
+        }
+    }
+
+    /// Only search the messages of the given rooms.
+    ///
+    /// By default, the messages of all the rooms the user is in are searched.
+    pub fn rooms(mut self, rooms: Vec<OwnedRoomId>) -> Self {
+        self.rooms = Some(rooms);
+        self
+    }
+
+    /// Set the order of the results, either by rank or by recency.
+    ///
+    /// By default, the server orders the results by rank.
+    pub fn order_by(mut self, order_by: OrderBy) -> Self {
+        self.order_by = Some(order_by);
+        self
+    }
+
+    /// Return the given number of events before and after each result.
+    ///
+    /// If `include_profile` is true, the profiles of the senders of the events
+    /// are returned too, as they were at the time of the events.
+    pub fn event_context(
+        mut self,
+        before_limit: UInt,
+        after_limit: UInt,
+        include_profile: bool,
+    ) -> Self {
+        self.event_context = Some(assign!(EventContext::new(), {
+            before_limit,
+            after_limit,
+            include_profile,
+        }));
+        self
+    }
+
+    /// Group the results by room, in [`SearchResultsPage::groups`].
+    pub fn group_by_room(mut self) -> Self {
+        self.group_by_room = true;
+        self
+    }
+
+    /// Resume a previous search from the given `next_batch` token.
+    pub fn from(mut self, next_batch: String) -> Self {
+        self.next_batch = Some(next_batch);
+        self
+    }
+
+    /// Run the search, returning a stream of the pages of results.
+    ///
+    /// The pages are fetched lazily, one after the other, until the last one,
+    /// or until an error occurs, which is the last item of the stream.
+    pub fn into_stream(mut self) -> impl Stream<Item = Result<SearchResultsPage>> {
+        stream! {
+            loop {
+                let next_batch = self.next_batch.take();
+                match self.fetch_page(next_batch).await {
+                    Ok(page) => {
+                        self.next_batch = page.next_batch.clone();
+                        let is_last_page = self.next_batch.is_none();
+
+                        yield Ok(page);
+
+                        if is_last_page {
+                            break;
+                        }
+                    }
+
+                    Err(err) => {
+                        yield Err(err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Fetch a single page of results, starting at the given `next_batch`
+    /// token.
+    #[instrument(skip(self))]
+    async fn fetch_page(&self, next_batch: Option<String>) -> Result<SearchResultsPage> {
+        let mut criteria = assign!(Criteria::new(self.search_term.clone()), {
+            filter: assign!(RoomEventFilter::default(), { rooms: self.rooms.clone() }),
+            order_by: self.order_by.clone(),
+        });
+
+        if let Some(event_context) = &self.event_context {
+            criteria.event_context = event_context.clone();
+        }
+
+        if self.group_by_room {
+            criteria.groupings.group_by = vec![Grouping::new(GroupingKey::RoomId)];
+        }
+
+        let request = assign!(Request::new(), {
+            next_batch,
+            search_categories: assign!(Categories::new(), { room_events: Some(criteria) }),
+        });
+
+        let mut room_events = self.client.send(request, None).await?.search_categories.room_events;
+
+        let mut results = Vec::with_capacity(room_events.results.len());
+        for result in room_events.results {
+            let Some(event) = result.result else {
+                continue;
+            };
+
+            let context = result.context;
+
+            let Some(event) = self.to_timeline_event(event).await else {
+                continue;
+            };
+
+            let mut events_before = Vec::with_capacity(context.events_before.len());
+            for event in context.events_before {
+                events_before.extend(self.to_timeline_event(event).await);
+            }
+
+            let mut events_after = Vec::with_capacity(context.events_after.len());
+            for event in context.events_after {
+                events_after.extend(self.to_timeline_event(event).await);
+            }
+
+            results.push(SearchResult {
+                event,
+                rank: result.rank,
+                events_before,
+                events_after,
+                profile_info: context.profile_info,
+            });
+        }
+
+        let groups = room_events
+            .groups
+            .remove(&GroupingKey::RoomId)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(id, group)| match id {
+                RoomIdOrUserId::RoomId(room_id) => Some((room_id, group.into())),
+                _ => None,
+            })
+            .collect();
+
+        Ok(SearchResultsPage {
+            count: room_events.count,
+            results,
+            highlights: room_events.highlights,
+            groups,
+            next_batch: room_events.next_batch,
+        })
+    }
+
+    /// Convert an event returned by the server into a [`TimelineEvent`],
+    /// decrypting it if possible.
+    ///
+    /// Returns `None` if the event couldn't be converted, so that a single bad
+    /// event doesn't fail the whole page; the error is logged.
+    async fn to_timeline_event(&self, event: Raw<AnyTimelineEvent>) -> Option<TimelineEvent> {
+        let room = event
+            .get_field::<OwnedRoomId>("room_id")
+            .ok()
+            .flatten()
+            .and_then(|room_id| self.client.get_room(&room_id));
+
+        let Some(room) = room else {
+            return Some(TimelineEvent::new(event));
+        };
+
+        match room.try_decrypt_room_event(event).await {
+            Ok(event) => Some(event),
+            Err(err) => {
+                warn!(room_id = %room.room_id(), "Skipping a search result event: {err}");
+                None
+            }
+        }
+    }
+}
+
+/// A page of results of a search of messages on the homeserver.
+#[derive(Debug)]
+pub struct SearchResultsPage {
+    /// An approximation of the total number of results, if the server
+    /// provided it.
+    pub count: Option<UInt>,
+
+    /// The results of this page, in the requested order.
+    pub results: Vec<SearchResult>,
+
+    /// The words that should be highlighted in the results, which may be
+    /// different from the words of the search term, e.g. because of stemming.
+    pub highlights: Vec<String>,
+
+    /// The results grouped by room, if [`SearchBuilder::group_by_room`] has
+    /// been used.
+    pub groups: BTreeMap<OwnedRoomId, RoomSearchResults>,
+
+    /// The token to fetch the next page of results, or `None` if this is the
+    /// last page.
+    pub next_batch: Option<String>,
+}
+
+/// A single result of a search of messages on the homeserver.
+#[derive(Debug)]
+pub struct SearchResult {
+    /// The event matching the search, decrypted if possible.
+    pub event: TimelineEvent,
+
+    /// A number describing how well the event matches the search; the higher,
+    /// the better.
+    pub rank: Option<f64>,
+
+    /// The events before the matching event, if
+    /// [`SearchBuilder::event_context`] has been used.
+    pub events_before: Vec<TimelineEvent>,
+
+    /// The events after the matching event, if
+    /// [`SearchBuilder::event_context`] has been used.
+    pub events_after: Vec<TimelineEvent>,
+
+    /// The profiles of the senders of the events, if they were requested with
+    /// [`SearchBuilder::event_context`].
+    pub profile_info: BTreeMap<OwnedUserId, UserProfile>,
+}
+
+/// The results of a search of messages in a single room.
+#[derive(Debug)]
+pub struct RoomSearchResults {
+    /// The IDs of the events of this room matching the search.
+    pub event_ids: Vec<OwnedEventId>,
+
+    /// The order of this room among the other rooms.
+    pub order: Option<UInt>,
+
+    /// The token to fetch more results for this room, if any.
+    pub next_batch: Option<String>,
+}
+
+impl From<ResultGroup> for RoomSearchResults {
+    fn from(group: ResultGroup) -> Self {
+        Self { event_ids: group.results, order: group.order, next_batch: group.next_batch }
+    }
+}
@@ -0,0 +1,694 @@
+// Copyright 2024 The Matrix.org Foundation C.I.C.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A send queue, persisting and serializing the sending of requests to rooms.
+//!
+//! Every [`Room`] has its own [`RoomSendQueue`], which sends the requests that
+//! have been pushed to it in order, one after the other. The requests are
+//! persisted in the state store before being sent, so that they survive a
+//! restart of the application: call
+//! [`SendQueue::respawn_tasks_for_rooms_with_unsent_requests`] after restoring
+//! a session to resume sending them.
+//!
+//! When a request fails because of a network error, or an error that's likely
+//! temporary (e.g. a server error or rate-limiting), it's retried with an
+//! exponential backoff, until it succeeds. Other errors are considered
+//! unrecoverable: the request is kept at the head of the queue, and the queue
+//! stops sending anything until the failed request is retried (by pushing it
+//! again with [`RoomSendQueue::push`]) or cancelled with
+//! [`RoomSendQueue::cancel`], so that requests are never sent out of order.
+//!
+//! Observers can subscribe to the updates of a room's queue with
+//! [`RoomSendQueue::subscribe`], to render the queued requests as local echoes.
+//!
+//! The content of the files of the queued attachments is kept in the media
+//! store until they've been sent, see
+//! [`QueuedRequest::attachment_media_request`].
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, RwLock as StdRwLock, Weak,
+    },
+    time::Duration,
+};
+
+use futures_util::future::{select, Either};
+pub use matrix_sdk_base::store::{QueuedRequest, QueuedRequestKind};
+use matrix_sdk_common::executor::{spawn, JoinHandle};
+use mime::Mime;
+use ruma::{
+    events::{
+        reaction::ReactionEventContent, relation::Annotation, AnyMessageLikeEventContent,
+        EventContent as _,
+    },
+    serde::Raw,
+    OwnedEventId, OwnedRoomId, OwnedTransactionId, RoomId, TransactionId,
+};
+use tokio::sync::{broadcast, Mutex, Notify};
+use tracing::{debug, info, instrument, trace, warn};
+
+use crate::{client::ClientInner, Client, Error, Result, Room, RoomState};
+
+/// The delay before the first retry of a request that failed with a
+/// recoverable error.
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// The maximum delay between two retries of a request.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A client-wide send queue, giving access to the send queues of all the
+/// rooms.
+#[derive(Clone)]
+pub struct SendQueue {
+    client: Client,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for SendQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendQueue").finish_non_exhaustive()
+    }
+}
+
+impl SendQueue {
+    pub(crate) fn new(client: Client) -> Self {
+        Self { client }
+    }
+
+    fn data(&self) -> &SendQueueData {
+        &self.client.inner.send_queue_data
+    }
+
+    /// Get the send queue of the given room, creating it if it didn't exist
+    /// yet.
+    pub fn for_room(&self, room: &Room) -> RoomSendQueue {
+        self.for_room_id(room.room_id())
+    }
+
+    fn for_room_id(&self, room_id: &RoomId) -> RoomSendQueue {
+        let data = self.data();
+
+        if let Some(queue) = data.rooms.read().unwrap().get(room_id) {
+            return queue.clone();
+        }
+
+        let mut rooms = data.rooms.write().unwrap();
+
+        // Another caller might have created the queue in the meanwhile.
+        rooms
+            .entry(room_id.to_owned())
+            .or_insert_with(|| {
+                RoomSendQueue::new(
+                    Arc::downgrade(&self.client.inner),
+                    room_id.to_owned(),
+                    data.globally_enabled.clone(),
+                )
+            })
+            .clone()
+    }
+
+    /// Spawn the sending tasks of all the rooms that have requests persisted
+    /// in the state store, that haven't been sent yet.
+    ///
+    /// This should be called after a session has been restored, to resume
+    /// sending the requests that were pending when the client was last
+    /// stopped.
+    pub async fn respawn_tasks_for_rooms_with_unsent_requests(&self) -> Result<()> {
+        let room_ids = self.client.store().load_rooms_with_unsent_requests().await?;
+
+        for room_id in room_ids {
+            debug!(?room_id, "respawning the send queue of a room with unsent requests");
+            self.for_room_id(&room_id).inner.notifier.notify_one();
+        }
+
+        Ok(())
+    }
+
+    /// Globally enable or disable the send queues of all the rooms.
+    ///
+    /// While disabled, the queues still accept new requests and persist them,
+    /// but don't send anything. Re-enabling the queues wakes them up, so that
+    /// requests waiting for a retry are sent immediately, e.g. when
+    /// connectivity has been restored.
+    pub fn set_enabled(&self, enabled: bool) {
+        let data = self.data();
+        data.globally_enabled.store(enabled, Ordering::SeqCst);
+
+        if enabled {
+            for queue in data.rooms.read().unwrap().values() {
+                queue.inner.notifier.notify_one();
+            }
+        }
+    }
+
+    /// Whether the send queues are globally enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.data().globally_enabled.load(Ordering::SeqCst)
+    }
+}
+
+/// The send queue data shared by all the clones of a [`Client`].
+pub(crate) struct SendQueueData {
+    /// The send queues of all the rooms, created lazily.
+    rooms: StdRwLock<BTreeMap<OwnedRoomId, RoomSendQueue>>,
+
+    /// Whether the send queues are enabled.
+    globally_enabled: Arc<AtomicBool>,
+}
+
+impl SendQueueData {
+    pub(crate) fn new() -> Self {
+        Self { rooms: Default::default(), globally_enabled: Arc::new(AtomicBool::new(true)) }
+    }
+}
+
+/// An update to the send queue of a room.
+#[derive(Clone, Debug)]
+pub enum RoomSendQueueUpdate {
+    /// A new request has been pushed to the queue.
+    NewLocalEvent(QueuedRequest),
+
+    /// A request has been cancelled, and removed from the queue.
+    CancelledLocalEvent {
+        /// The transaction ID of the cancelled request.
+        transaction_id: OwnedTransactionId,
+    },
+
+    /// Sending a request failed.
+    SendError {
+        /// The transaction ID of the request that failed to be sent.
+        transaction_id: OwnedTransactionId,
+
+        /// The error that happened when sending the request.
+        error: Arc<Error>,
+
+        /// Whether the error is considered recoverable.
+        ///
+        /// If true, the request will be retried later. Otherwise, the queue
+        /// won't send anything until the request is retried or cancelled. In
+        /// both cases, the request is still in the queue.
+        is_recoverable: bool,
+    },
+
+    /// A request has been sent successfully, and removed from the queue.
+    SentEvent {
+        /// The transaction ID of the sent request.
+        transaction_id: OwnedTransactionId,
+
+        /// The event ID returned by the server.
+        event_id: OwnedEventId,
+    },
+}
+
+/// The send queue of a single room.
+///
+/// Cloning a `RoomSendQueue` is cheap, and all the clones share the same
+/// queue.
+#[derive(Clone)]
+pub struct RoomSendQueue {
+    inner: Arc<RoomSendQueueInner>,
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for RoomSendQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoomSendQueue").field("room_id", &self.inner.room_id).finish()
+    }
+}
+
+struct RoomSendQueueInner {
+    /// The client this queue belongs to.
+    ///
+    /// This is a weak reference, since the queue is owned by the client.
+    client: Weak<ClientInner>,
+
+    /// The room this queue belongs to.
+    room_id: OwnedRoomId,
+
+    /// The sender of the updates of this queue.
+    updates: broadcast::Sender<RoomSendQueueUpdate>,
+
+    /// A notifier to wake up the sending task.
+    notifier: Arc<Notify>,
+
+    /// The transaction ID of the request that's being sent, if any.
+    being_sent: Arc<Mutex<Option<OwnedTransactionId>>>,
+
+    /// The transaction ID of the request that failed with an unrecoverable
+    /// error, if any.
+    ///
+    /// While it's set, the queue doesn't send anything.
+    failed: Arc<StdRwLock<Option<OwnedTransactionId>>>,
+
+    /// The handle of the sending task.
+    task: JoinHandle<()>,
+}
+
+impl Drop for RoomSendQueueInner {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl RoomSendQueue {
+    fn new(
+        client: Weak<ClientInner>,
+        room_id: OwnedRoomId,
+        globally_enabled: Arc<AtomicBool>,
+    ) -> Self {
+        let (updates, _) = broadcast::channel(32);
+        let notifier = Arc::new(Notify::new());
+        let being_sent = Arc::new(Mutex::new(None));
+        let failed = Arc::new(StdRwLock::new(None));
+
+        let task = spawn(Self::sending_task(
+            client.clone(),
+            room_id.clone(),
+            globally_enabled,
+            updates.clone(),
+            notifier.clone(),
+            being_sent.clone(),
+            failed.clone(),
+        ));
+
+        Self {
+            inner: Arc::new(RoomSendQueueInner {
+                client,
+                room_id,
+                updates,
+                notifier,
+                being_sent,
+                failed,
+                task,
+            }),
+        }
+    }
+
+    fn client(&self) -> Result<Client> {
+        self.inner.client.upgrade().map(|inner| Client { inner }).ok_or(Error::InconsistentState)
+    }
+
+    /// Queue a message-like event to be sent to the room.
+    ///
+    /// Returns the transaction ID of the queued request, which is also the
+    /// transaction ID of the event's local echo.
+    pub async fn send(&self, content: AnyMessageLikeEventContent) -> Result<OwnedTransactionId> {
+        let event_type = content.event_type().to_string();
+        let content = Raw::new(&content)?.cast();
+        self.push(QueuedRequest::new(QueuedRequestKind::Event { event_type, content })).await
+    }
+
+    /// Queue a reaction to the given event, with the given key.
+    pub async fn react(&self, event_id: OwnedEventId, key: String) -> Result<OwnedTransactionId> {
+        let content = ReactionEventContent::new(Annotation::new(event_id, key));
+        self.send(content.into()).await
+    }
+
+    /// Queue an attachment to be uploaded, then sent to the room.
+    ///
+    /// The whole content of the attachment is persisted in the media store
+    /// until it's been sent.
+    pub async fn send_attachment(
+        &self,
+        filename: String,
+        content_type: &Mime,
+        data: Vec<u8>,
+        caption: Option<String>,
+    ) -> Result<OwnedTransactionId> {
+        let request = QueuedRequest::new(QueuedRequestKind::Attachment {
+            filename,
+            content_type: content_type.to_string(),
+            caption,
+        });
+
+        let client = self.client()?;
+        let media_request = request.attachment_media_request();
+        client.store().add_media_content(&media_request, data).await?;
+
+        let result = self.push(request).await;
+
+        if result.is_err() {
+            if let Err(err) = client.store().remove_media_content(&media_request).await {
+                warn!("couldn't remove the file of an attachment that wasn't queued: {err}");
+            }
+        }
+
+        result
+    }
+
+    /// Queue the redaction of an event.
+    pub async fn redact(
+        &self,
+        redacts: OwnedEventId,
+        reason: Option<String>,
+    ) -> Result<OwnedTransactionId> {
+        self.push(QueuedRequest::new(QueuedRequestKind::Redaction { redacts, reason })).await
+    }
+
+    /// Push a request to the queue.
+    ///
+    /// If a request with the same transaction ID is already in the queue, it
+    /// is retried: it keeps its position in the queue, so that requests are
+    /// still sent in order, and the queue is woken up. This is how a request
+    /// that failed with an unrecoverable error is retried, which unblocks the
+    /// queue.
+    pub async fn push(&self, request: QueuedRequest) -> Result<OwnedTransactionId> {
+        let client = self.client()?;
+        let store = client.store();
+        let transaction_id = request.transaction_id.clone();
+
+        // Hold the lock, so that the request can't start being sent or be
+        // cancelled while it's being looked up.
+        let _being_sent = self.inner.being_sent.lock().await;
+
+        let already_queued =
+            store.load_send_queue_request(&self.inner.room_id, &transaction_id).await?.is_some();
+
+        if already_queued {
+            trace!(%transaction_id, "retrying request");
+            self.unblock_if_failed(&transaction_id);
+        } else {
+            store.save_send_queue_request(&self.inner.room_id, request.clone()).await?;
+            let _ = self.inner.updates.send(RoomSendQueueUpdate::NewLocalEvent(request));
+        }
+
+        self.inner.notifier.notify_one();
+
+        Ok(transaction_id)
+    }
+
+    /// Cancel a request that hasn't been sent yet.
+    ///
+    /// Returns whether the request was found in the queue and removed. A
+    /// request that is currently being sent can't be cancelled.
+    pub async fn cancel(&self, transaction_id: &TransactionId) -> Result<bool> {
+        let being_sent = self.inner.being_sent.lock().await;
+
+        if being_sent.as_deref() == Some(transaction_id) {
+            debug!(%transaction_id, "can't cancel a request that is being sent");
+            return Ok(false);
+        }
+
+        let client = self.client()?;
+        let request =
+            client.store().load_send_queue_request(&self.inner.room_id, transaction_id).await?;
+        let removed =
+            client.store().remove_send_queue_request(&self.inner.room_id, transaction_id).await?;
+
+        if let Some(request) = request.filter(|_| removed) {
+            Self::remove_attachment_media(&client, &request).await;
+
+            let _ = self.inner.updates.send(RoomSendQueueUpdate::CancelledLocalEvent {
+                transaction_id: transaction_id.to_owned(),
+            });
+
+            if self.unblock_if_failed(transaction_id) {
+                self.inner.notifier.notify_one();
+            }
+        }
+
+        Ok(removed)
+    }
+
+    /// Whether the queue is blocked by a request that failed with an
+    /// unrecoverable error, and that must be retried or cancelled before
+    /// anything else is sent.
+    pub fn is_blocked(&self) -> bool {
+        self.inner.failed.read().unwrap().is_some()
+    }
+
+    /// Unblock the queue if it was blocked by the given request.
+    ///
+    /// Returns whether the queue was unblocked.
+    fn unblock_if_failed(&self, transaction_id: &TransactionId) -> bool {
+        let mut failed = self.inner.failed.write().unwrap();
+
+        if failed.as_deref() == Some(transaction_id) {
+            debug!(%transaction_id, "unblocking the queue");
+            *failed = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Subscribe to the updates of this queue.
+    ///
+    /// Returns the requests that are currently queued, in order, and a
+    /// receiver for the updates that happen afterwards.
+    pub async fn subscribe(
+        &self,
+    ) -> Result<(Vec<QueuedRequest>, broadcast::Receiver<RoomSendQueueUpdate>)> {
+        // Subscribe first, so that no update is missed; observers must thus be
+        // ready to receive a `NewLocalEvent` for a request they already know.
+        let receiver = self.inner.updates.subscribe();
+        let requests = self.client()?.store().load_send_queue_requests(&self.inner.room_id).await?;
+        Ok((requests, receiver))
+    }
+
+    #[instrument(skip_all, fields(room_id = %room_id))]
+    async fn sending_task(
+        client: Weak<ClientInner>,
+        room_id: OwnedRoomId,
+        globally_enabled: Arc<AtomicBool>,
+        updates: broadcast::Sender<RoomSendQueueUpdate>,
+        notifier: Arc<Notify>,
+        being_sent: Arc<Mutex<Option<OwnedTransactionId>>>,
+        failed: Arc<StdRwLock<Option<OwnedTransactionId>>>,
+    ) {
+        info!("spawned the sending task");
+
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+
+        loop {
+            let Some(client) = client.upgrade().map(|inner| Client { inner }) else {
+                info!("client has been dropped, stopping");
+                break;
+            };
+
+            if !globally_enabled.load(Ordering::SeqCst) {
+                trace!("send queue is disabled, waiting");
+                drop(client);
+                notifier.notified().await;
+                continue;
+            }
+
+            if failed.read().unwrap().is_some() {
+                trace!("a request failed, waiting for it to be retried or cancelled");
+                drop(client);
+                notifier.notified().await;
+                continue;
+            }
+
+            let request = {
+                // Hold the lock while looking for the next request, so that it can't be
+                // cancelled before it's marked as being sent.
+                let mut being_sent = being_sent.lock().await;
+
+                let request = match client.store().load_next_send_queue_request(&room_id).await {
+                    Ok(request) => request,
+                    Err(err) => {
+                        warn!("couldn't load the queued requests: {err}");
+                        None
+                    }
+                };
+
+                *being_sent = request.as_ref().map(|request| request.transaction_id.clone());
+                request
+            };
+
+            let Some(request) = request else {
+                trace!("queue is empty, waiting");
+                drop(client);
+                notifier.notified().await;
+                continue;
+            };
+
+            let transaction_id = request.transaction_id.clone();
+            trace!(%transaction_id, "sending request");
+
+            let result = match client.get_room(&room_id) {
+                Some(room) if room.state() == RoomState::Joined => {
+                    Self::send_request(&room, request.clone()).await
+                }
+                Some(_) => {
+                    info!("refusing to send, the room is not joined");
+                    Err(RoomSendQueueError::RoomNotJoined.into())
+                }
+                None => {
+                    info!("refusing to send, the room is missing");
+                    Err(RoomSendQueueError::RoomDisappeared.into())
+                }
+            };
+
+            match result {
+                Ok(event_id) => {
+                    trace!(%transaction_id, %event_id, "request sent");
+                    retry_delay = INITIAL_RETRY_DELAY;
+
+                    Self::remove_sent_request(&client, &room_id, &request, &being_sent).await;
+                    let _ =
+                        updates.send(RoomSendQueueUpdate::SentEvent { transaction_id, event_id });
+                }
+
+                Err(error) if is_recoverable(&error) => {
+                    warn!(%transaction_id, "recoverable error when sending request: {error}");
+                    *being_sent.lock().await = None;
+
+                    let _ = updates.send(RoomSendQueueUpdate::SendError {
+                        transaction_id,
+                        error: Arc::new(error),
+                        is_recoverable: true,
+                    });
+
+                    // Wait before retrying, unless something wakes the queue up in the
+                    // meanwhile.
+                    drop(client);
+                    let sleep = std::pin::pin!(sleep(retry_delay));
+                    let notified = std::pin::pin!(notifier.notified());
+                    if let Either::Right(_) = select(sleep, notified).await {
+                        trace!("woken up before the end of the retry delay");
+                    }
+
+                    retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                }
+
+                Err(error) => {
+                    warn!(%transaction_id, "unrecoverable error when sending request: {error}");
+
+                    // Keep the request at the head of the queue, and don't send anything
+                    // until it's retried or cancelled, so that the next requests aren't
+                    // sent before it.
+                    {
+                        let mut being_sent = being_sent.lock().await;
+                        *failed.write().unwrap() = Some(transaction_id.clone());
+                        *being_sent = None;
+                    }
+
+                    let _ = updates.send(RoomSendQueueUpdate::SendError {
+                        transaction_id,
+                        error: Arc::new(error),
+                        is_recoverable: false,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Remove a request that's not going to be sent again from the store.
+    async fn remove_sent_request(
+        client: &Client,
+        room_id: &RoomId,
+        request: &QueuedRequest,
+        being_sent: &Mutex<Option<OwnedTransactionId>>,
+    ) {
+        let mut being_sent = being_sent.lock().await;
+        let transaction_id = &request.transaction_id;
+
+        if let Err(err) = client.store().remove_send_queue_request(room_id, transaction_id).await {
+            warn!(%transaction_id, "couldn't remove the request from the store: {err}");
+        }
+
+        *being_sent = None;
+        drop(being_sent);
+
+        Self::remove_attachment_media(client, request).await;
+    }
+
+    /// Remove the file of a request from the media store, if it's an
+    /// attachment.
+    async fn remove_attachment_media(client: &Client, request: &QueuedRequest) {
+        if !matches!(request.kind, QueuedRequestKind::Attachment { .. }) {
+            return;
+        }
+
+        let media_request = request.attachment_media_request();
+        if let Err(err) = client.store().remove_media_content(&media_request).await {
+            let transaction_id = &request.transaction_id;
+            warn!(%transaction_id, "couldn't remove the file of the attachment: {err}");
+        }
+    }
+
+    /// Actually send a request, returning the ID of the resulting event.
+    async fn send_request(room: &Room, request: QueuedRequest) -> Result<OwnedEventId> {
+        let media_request = request.attachment_media_request();
+        let QueuedRequest { transaction_id, kind } = request;
+
+        match kind {
+            QueuedRequestKind::Event { event_type, content } => Ok(room
+                .send_raw(&event_type, content)
+                .with_transaction_id(&transaction_id)
+                .await?
+                .event_id),
+
+            QueuedRequestKind::Attachment { filename, content_type, caption } => {
+                let data = room
+                    .client()
+                    .store()
+                    .get_media_content(&media_request)
+                    .await?
+                    .ok_or(RoomSendQueueError::MissingAttachment)?;
+                let content_type =
+                    content_type.parse::<Mime>().unwrap_or(mime::APPLICATION_OCTET_STREAM);
+                let config = crate::attachment::AttachmentConfig::new()
+                    .txn_id(&transaction_id)
+                    .caption(caption);
+
+                Ok(room.send_attachment(&filename, &content_type, data, config).await?.event_id)
+            }
+
+            QueuedRequestKind::Redaction { redacts, reason } => {
+                Ok(room.redact(&redacts, reason.as_deref(), Some(transaction_id)).await?.event_id)
+            }
+        }
+    }
+}
+
+/// An error that prevents the send queue of a room from sending its requests.
+#[derive(Debug, thiserror::Error)]
+pub enum RoomSendQueueError {
+    /// The room isn't in the joined state, so nothing can be sent to it.
+    #[error("the room isn't in the joined state")]
+    RoomNotJoined,
+
+    /// The room is missing from the client, e.g. because it's been forgotten.
+    #[error("the room is missing from the client")]
+    RoomDisappeared,
+
+    /// The file of an attachment is missing from the media store.
+    #[error("the file of the attachment is missing from the media store")]
+    MissingAttachment,
+}
+
+/// Whether an error that happened while sending a request is likely to be
+/// temporary, so that the request is worth retrying later.
+fn is_recoverable(error: &Error) -> bool {
+    match error {
+        Error::Http(crate::HttpError::Reqwest(_)) => true,
+        _ => error.as_client_api_error().is_some_and(|error| {
+            error.status_code.is_server_error() || error.status_code.as_u16() == 429
+        }),
+    }
+}
+
+async fn sleep(delay: Duration) {
+    #[cfg(target_arch = "wasm32")]
+    gloo_timers::future::TimeoutFuture::new(delay.as_millis() as u32).await;
+
+    #[cfg(not(target_arch = "wasm32"))]
+    tokio::time::sleep(delay).await;
+}
@@ -1,6 +1,9 @@
 mod backups;
+mod dehydrated_devices;
+mod identities;
 mod recovery;
 mod secret_storage;
+mod shared_history;
 mod verification;
 
 async fn mock_secret_store_with_backup_key(
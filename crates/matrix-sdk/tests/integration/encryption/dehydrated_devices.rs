@@ -0,0 +1,469 @@
+use std::{
+    collections::BTreeMap,
+    iter,
+    sync::{Arc, Mutex},
+};
+
+use assert_matches::assert_matches;
+use matrix_sdk::{
+    crypto::{EncryptionSettings, OlmMachine, OutgoingRequests},
+    encryption::{dehydrated_devices::DehydratedDeviceError, secret_storage::SecretStore},
+    Client,
+};
+use matrix_sdk_test::async_test;
+use ruma::{
+    api::client::keys::{claim_keys, get_keys},
+    device_id,
+    encryption::{DeviceKeys, OneTimeKey},
+    events::secret_storage::secret::SecretEventContent,
+    room_id,
+    serde::Raw,
+    user_id, OwnedDeviceId, OwnedDeviceKeyId, RoomId, UserId,
+};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{body_partial_json, header, method, path, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::secret_storage::{mock_secret_store_key, SECRET_STORE_KEY};
+use crate::logged_in_client_with_server;
+
+async fn mock_cross_signing_bootstrap(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/upload"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "one_time_key_counts": {
+                "signed_curve25519": 50
+            }
+        })))
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/unstable/keys/device_signing/upload"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/unstable/keys/signatures/upload"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "failures": {},
+        })))
+        .mount(server)
+        .await;
+}
+
+#[async_test]
+async fn test_dehydrated_device_creation_and_disabling() {
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap().to_owned();
+
+    mock_cross_signing_bootstrap(&server).await;
+    client.encryption().bootstrap_cross_signing(None).await.unwrap();
+
+    mock_secret_store_key(
+        &server,
+        &user_id,
+        "bmur2d9ypPUH1msSwCxQOJkuKRmJI55e",
+        "xv5b6/p3ExEw++wTyfSHEg==",
+        "ujBBbXahnTAMkmPUX2/0+VTfUh63pGyVRuBcDMgmJC8=",
+    )
+    .await;
+
+    let secret_store =
+        client.encryption().secret_storage().open_secret_store(SECRET_STORE_KEY).await.unwrap();
+
+    let dehydrated_devices = client.encryption().dehydrated_devices();
+    assert!(!dehydrated_devices.is_enabled().await.unwrap());
+
+    // Without a pickle key, the dehydrated device can't be rotated.
+    assert_matches!(
+        dehydrated_devices.rotate().await,
+        Err(DehydratedDeviceError::MissingPickleKey)
+    );
+
+    // There's no pickle key in the secret storage yet, so a new one is created.
+    Mock::given(method("GET"))
+        .and(path(format!("_matrix/client/r0/user/{user_id}/account_data/org.matrix.msc3814")))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found"
+        })))
+        .named("pickle key account data GET")
+        .mount(&server)
+        .await;
+
+    let uploaded_pickle_key: Arc<Mutex<Option<SecretEventContent>>> = Mutex::new(None).into();
+
+    Mock::given(method("PUT"))
+        .and(path(format!("_matrix/client/r0/user/{user_id}/account_data/org.matrix.msc3814")))
+        .and(header("authorization", "Bearer 1234"))
+        .and({
+            let uploaded_pickle_key = uploaded_pickle_key.clone();
+            move |request: &wiremock::Request| {
+                *uploaded_pickle_key.lock().unwrap() = Some(
+                    request.body_json().expect("The request body should be a SecretEventContent"),
+                );
+                true
+            }
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .named("pickle key account data PUT")
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "initial_device_display_name": "Dehydrated device",
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_id": "DEHYDRATED",
+        })))
+        .expect(1)
+        .named("dehydrated device PUT")
+        .mount(&server)
+        .await;
+
+    dehydrated_devices.create(&secret_store).await.unwrap();
+
+    assert!(dehydrated_devices.is_enabled().await.unwrap());
+    assert!(
+        uploaded_pickle_key
+            .lock()
+            .unwrap()
+            .as_ref()
+            .expect("The pickle key should have been stored in the secret storage")
+            .encrypted
+            .contains_key("bmur2d9ypPUH1msSwCxQOJkuKRmJI55e"),
+        "The pickle key should be encrypted with the secret storage key"
+    );
+
+    // If the dehydrated device can't be deleted, we keep managing it.
+    {
+        let _scope = Mock::given(method("DELETE"))
+            .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device$"))
+            .and(header("authorization", "Bearer 1234"))
+            .respond_with(ResponseTemplate::new(500).set_body_json(json!({
+                "errcode": "M_UNKNOWN",
+                "error": "Internal server error",
+            })))
+            .expect(1)
+            .named("failing dehydrated device DELETE")
+            .mount_as_scoped(&server)
+            .await;
+
+        dehydrated_devices.disable().await.unwrap_err();
+        assert!(dehydrated_devices.is_enabled().await.unwrap());
+    }
+
+    Mock::given(method("DELETE"))
+        .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_id": "DEHYDRATED",
+        })))
+        .expect(1)
+        .named("dehydrated device DELETE")
+        .mount(&server)
+        .await;
+
+    dehydrated_devices.disable().await.unwrap();
+    assert!(!dehydrated_devices.is_enabled().await.unwrap());
+
+    server.verify().await;
+}
+
+/// Create a dehydrated device for the client, with cross-signing and secret
+/// storage set up.
+///
+/// Returns the secret store, and the body of the request that uploaded the
+/// dehydrated device. The pickle key is available in the secret storage
+/// afterwards.
+async fn create_dehydrated_device(
+    client: &Client,
+    server: &MockServer,
+) -> (SecretStore, Arc<Mutex<Option<JsonValue>>>) {
+    let user_id = client.user_id().unwrap().to_owned();
+
+    mock_cross_signing_bootstrap(server).await;
+    client.encryption().bootstrap_cross_signing(None).await.unwrap();
+
+    mock_secret_store_key(
+        server,
+        &user_id,
+        "bmur2d9ypPUH1msSwCxQOJkuKRmJI55e",
+        "xv5b6/p3ExEw++wTyfSHEg==",
+        "ujBBbXahnTAMkmPUX2/0+VTfUh63pGyVRuBcDMgmJC8=",
+    )
+    .await;
+
+    let secret_store =
+        client.encryption().secret_storage().open_secret_store(SECRET_STORE_KEY).await.unwrap();
+
+    // The pickle key is stored in the secret storage when it's created, and
+    // returned from then on.
+    let pickle_key: Arc<Mutex<Option<JsonValue>>> = Mutex::new(None).into();
+
+    Mock::given(method("GET"))
+        .and(path(format!("_matrix/client/r0/user/{user_id}/account_data/org.matrix.msc3814")))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with({
+            let pickle_key = pickle_key.clone();
+            move |_: &wiremock::Request| match pickle_key.lock().unwrap().clone() {
+                Some(content) => ResponseTemplate::new(200).set_body_json(content),
+                None => ResponseTemplate::new(404).set_body_json(json!({
+                    "errcode": "M_NOT_FOUND",
+                    "error": "Account data not found"
+                })),
+            }
+        })
+        .named("pickle key account data GET")
+        .mount(server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path(format!("_matrix/client/r0/user/{user_id}/account_data/org.matrix.msc3814")))
+        .and(header("authorization", "Bearer 1234"))
+        .and({
+            let pickle_key = pickle_key.clone();
+            move |request: &wiremock::Request| {
+                *pickle_key.lock().unwrap() = Some(request.body_json().unwrap());
+                true
+            }
+        })
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .named("pickle key account data PUT")
+        .mount(server)
+        .await;
+
+    // Keep the body of the latest uploaded dehydrated device.
+    let uploaded_device: Arc<Mutex<Option<JsonValue>>> = Mutex::new(None).into();
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and({
+            let uploaded_device = uploaded_device.clone();
+            move |request: &wiremock::Request| {
+                *uploaded_device.lock().unwrap() = Some(request.body_json().unwrap());
+                true
+            }
+        })
+        .respond_with(move |request: &wiremock::Request| {
+            let body: JsonValue = request.body_json().unwrap();
+            ResponseTemplate::new(200).set_body_json(json!({ "device_id": body["device_id"] }))
+        })
+        .named("dehydrated device PUT")
+        .mount(server)
+        .await;
+
+    client.encryption().dehydrated_devices().create(&secret_store).await.unwrap();
+
+    (secret_store, uploaded_device)
+}
+
+/// Share a room key with the given dehydrated device, from another user.
+///
+/// Returns the encrypted to-device event that the dehydrated device receives.
+async fn share_room_key_with_dehydrated_device(
+    user_id: &UserId,
+    uploaded_device: &JsonValue,
+    room_id: &RoomId,
+) -> JsonValue {
+    let sender = OlmMachine::new(user_id!("@bob:example.org"), device_id!("BOBDEVICE")).await;
+
+    let device_id: OwnedDeviceId =
+        serde_json::from_value(uploaded_device["device_id"].clone()).unwrap();
+    let device_keys: Raw<DeviceKeys> =
+        serde_json::from_value(uploaded_device["device_keys"].clone()).unwrap();
+    let one_time_key: (OwnedDeviceKeyId, Raw<OneTimeKey>) =
+        serde_json::from_value::<BTreeMap<OwnedDeviceKeyId, Raw<OneTimeKey>>>(
+            uploaded_device["one_time_keys"].clone(),
+        )
+        .unwrap()
+        .pop_first()
+        .unwrap();
+
+    // The sender learns about the dehydrated device,
+    sender.update_tracked_users([user_id]).await.unwrap();
+
+    let keys_query = keys_query_response(user_id, &device_id, device_keys);
+    for request in sender.outgoing_requests().await.unwrap() {
+        if let OutgoingRequests::KeysQuery(_) = request.request() {
+            sender.mark_request_as_sent(request.request_id(), &keys_query).await.unwrap();
+        }
+    }
+
+    // Creates an Olm session with it,
+    let (request_id, _) = sender.get_missing_sessions(iter::once(user_id)).await.unwrap().unwrap();
+    let keys_claim_response = claim_keys::v3::Response::new(BTreeMap::from([(
+        user_id.to_owned(),
+        BTreeMap::from([(device_id, BTreeMap::from([one_time_key]))]),
+    )]));
+    sender.mark_request_as_sent(&request_id, &keys_claim_response).await.unwrap();
+
+    // And shares a room key with it.
+    let requests = sender
+        .share_room_key(room_id, iter::once(user_id), EncryptionSettings::default())
+        .await
+        .unwrap();
+    let content = requests[0].messages.values().next().unwrap().values().next().unwrap();
+
+    json!({
+        "sender": "@bob:example.org",
+        "type": "m.room.encrypted",
+        "content": content,
+    })
+}
+
+/// A `/keys/query` response containing only the given device.
+fn keys_query_response(
+    user_id: &UserId,
+    device_id: &OwnedDeviceId,
+    device_keys: Raw<DeviceKeys>,
+) -> get_keys::v3::Response {
+    let mut response = get_keys::v3::Response::new();
+    response.device_keys =
+        BTreeMap::from([(user_id.to_owned(), BTreeMap::from([(device_id.clone(), device_keys)]))]);
+    response
+}
+
+/// Serve the given dehydrated device, which received the given to-device
+/// events, in two batches.
+async fn mock_dehydrated_device(server: &MockServer, device: &JsonValue, events: Vec<JsonValue>) {
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_id": device["device_id"],
+            "device_data": device["device_data"],
+        })))
+        .expect(1)
+        .named("dehydrated device GET")
+        .mount(server)
+        .await;
+
+    // The second batch is empty, which means that all the events have been
+    // received.
+    Mock::given(method("POST"))
+        .and(path_regex(
+            r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device/.*/events$",
+        ))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "next_batch": "batch1" })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": [],
+            "next_batch": "batch2",
+        })))
+        .expect(1)
+        .named("dehydrated device events second batch POST")
+        .mount(server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(
+            r"^/_matrix/client/unstable/org.matrix.msc3814.v1/dehydrated_device/.*/events$",
+        ))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "events": events,
+            "next_batch": "batch1",
+        })))
+        .expect(1)
+        .named("dehydrated device events first batch POST")
+        .mount(server)
+        .await;
+}
+
+async fn num_room_keys(client: &Client, room_id: &RoomId) -> usize {
+    client
+        .olm_machine_for_testing()
+        .await
+        .as_ref()
+        .unwrap()
+        .store()
+        .export_room_keys(|session| session.room_id() == room_id)
+        .await
+        .unwrap()
+        .len()
+}
+
+#[async_test]
+async fn test_dehydrated_device_rehydration() {
+    let room_id = room_id!("!test:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap().to_owned();
+
+    let (secret_store, uploaded_device) = create_dehydrated_device(&client, &server).await;
+    let device = uploaded_device.lock().unwrap().clone().unwrap();
+
+    // While we're offline, someone shares a room key with our dehydrated device.
+    let event = share_room_key_with_dehydrated_device(&user_id, &device, room_id).await;
+    mock_dehydrated_device(&server, &device, vec![event]).await;
+
+    assert_eq!(num_room_keys(&client, room_id).await, 0);
+
+    // Rehydrating the device imports the room key,
+    let num_imported =
+        client.encryption().dehydrated_devices().rehydrate(&secret_store).await.unwrap();
+    assert_eq!(num_imported, 1);
+    assert_eq!(num_room_keys(&client, room_id).await, 1);
+
+    // And replaces the dehydrated device with a new one.
+    let new_device = uploaded_device.lock().unwrap().clone().unwrap();
+    assert_ne!(new_device["device_id"], device["device_id"]);
+    assert!(client.encryption().dehydrated_devices().is_enabled().await.unwrap());
+
+    server.verify().await;
+}
+
+#[async_test]
+async fn test_dehydrated_device_rehydration_when_importing_secrets() {
+    let room_id = room_id!("!test:example.org");
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap().to_owned();
+
+    let (secret_store, uploaded_device) = create_dehydrated_device(&client, &server).await;
+    let device = uploaded_device.lock().unwrap().clone().unwrap();
+
+    let event = share_room_key_with_dehydrated_device(&user_id, &device, room_id).await;
+    mock_dehydrated_device(&server, &device, vec![event]).await;
+
+    // The other secrets aren't in the secret storage.
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.*/account_data/m\.(cross_signing\..*|megolm_backup\.v1)$",
+        ))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "errcode": "M_NOT_FOUND",
+            "error": "Account data not found"
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("_matrix/client/r0/keys/query"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+
+    // Importing the secrets rehydrates the dehydrated device.
+    secret_store.import_secrets().await.unwrap();
+    assert_eq!(num_room_keys(&client, room_id).await, 1);
+
+    let new_device = uploaded_device.lock().unwrap().clone().unwrap();
+    assert_ne!(new_device["device_id"], device["device_id"]);
+
+    server.verify().await;
+}
@@ -0,0 +1,65 @@
+use futures_util::pin_mut;
+use matrix_sdk::{crypto::OlmMachine, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{async_test, response_from_file};
+use ruma::{
+    api::{client::keys::get_keys, IncomingResponse},
+    device_id, user_id, TransactionId, UserId,
+};
+use serde_json::json;
+use stream_assert::{assert_pending, assert_ready};
+
+/// A `/keys/query` response containing a new identity for the given user.
+async fn new_identity_key_query(user_id: &UserId) -> get_keys::v3::Response {
+    let machine = OlmMachine::new(user_id, device_id!("OTHERDEVICE")).await;
+    let identity = machine.bootstrap_cross_signing(false).await.unwrap().upload_signing_keys_req;
+
+    let response = json!({
+        "device_keys": {},
+        "failures": {},
+        "master_keys": {
+            user_id.as_str(): identity.master_key,
+        },
+        "self_signing_keys": {
+            user_id.as_str(): identity.self_signing_key,
+        },
+    });
+
+    get_keys::v3::Response::try_from_http_response(response_from_file(&response)).unwrap()
+}
+
+#[async_test]
+async fn test_identity_violations_stream() {
+    let (client, _server) = logged_in_client_with_server().await;
+    let bob = user_id!("@bob:example.org");
+
+    let violations = client.encryption().identity_violations_stream().await.unwrap();
+    pin_mut!(violations);
+
+    let olm_machine = client.olm_machine_for_testing().await;
+    let olm_machine = olm_machine.as_ref().unwrap();
+
+    // The first identity we see for Bob is pinned, so it's not a violation.
+    olm_machine
+        .mark_request_as_sent(&TransactionId::new(), &new_identity_key_query(bob).await)
+        .await
+        .unwrap();
+    assert_pending!(violations);
+
+    // When Bob's identity changes, it's reported.
+    olm_machine
+        .mark_request_as_sent(&TransactionId::new(), &new_identity_key_query(bob).await)
+        .await
+        .unwrap();
+
+    let identity = assert_ready!(violations);
+    assert_eq!(identity.user_id(), bob);
+    assert!(identity.identity_needs_user_approval());
+    assert!(!identity.has_verification_violation());
+
+    // Once the change is acknowledged, it's not a violation anymore.
+    identity.pin_current_identity().await.unwrap();
+
+    let identity = client.encryption().get_user_identity(bob).await.unwrap().unwrap();
+    assert!(!identity.identity_needs_user_approval());
+    assert_pending!(violations);
+}
@@ -0,0 +1,291 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    iter,
+};
+
+use matrix_sdk::{
+    config::SyncSettings,
+    crypto::{EncryptionSettings, EncryptionSyncChanges, OlmMachine, OutgoingRequests},
+    Client,
+};
+use matrix_sdk_test::{
+    async_test, InvitedRoomBuilder, JoinedRoomBuilder, StateTestEvent, StrippedStateTestEvent,
+    SyncResponseBuilder,
+};
+use ruma::{
+    api::client::keys::{claim_keys, get_keys},
+    device_id,
+    encryption::{DeviceKeys, OneTimeKey},
+    events::room::history_visibility::HistoryVisibility,
+    room_id,
+    serde::Raw,
+    user_id, DeviceId, OwnedDeviceKeyId, RoomId, UserId,
+};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{header, method, path, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use crate::{logged_in_client_with_server, mock_sync};
+
+/// Get the device keys and a one-time key of the given machine, as they would
+/// be uploaded.
+async fn device_keys_and_one_time_key(
+    machine: &OlmMachine,
+) -> (Raw<DeviceKeys>, (OwnedDeviceKeyId, Raw<OneTimeKey>)) {
+    for request in machine.outgoing_requests().await.unwrap() {
+        if let OutgoingRequests::KeysUpload(request) = request.request() {
+            let mut one_time_keys = request.one_time_keys.clone();
+            return (request.device_keys.clone().unwrap(), one_time_keys.pop_first().unwrap());
+        }
+    }
+
+    panic!("The machine should have keys to upload");
+}
+
+/// Let the given machine know about the given device.
+async fn receive_device_keys(
+    machine: &OlmMachine,
+    user_id: &UserId,
+    device_id: &DeviceId,
+    device_keys: Raw<DeviceKeys>,
+) {
+    machine.update_tracked_users([user_id]).await.unwrap();
+
+    let mut keys_query = get_keys::v3::Response::new();
+    keys_query.device_keys = BTreeMap::from([(
+        user_id.to_owned(),
+        BTreeMap::from([(device_id.to_owned(), device_keys)]),
+    )]);
+
+    for request in machine.outgoing_requests().await.unwrap() {
+        if let OutgoingRequests::KeysQuery(_) = request.request() {
+            machine.mark_request_as_sent(request.request_id(), &keys_query).await.unwrap();
+        }
+    }
+}
+
+/// Get the ID of the room key of the given room, if there's only one.
+async fn room_key_session_id(machine: &OlmMachine, room_id: &RoomId) -> Option<String> {
+    let mut room_keys =
+        machine.store().export_room_keys(|session| session.room_id() == room_id).await.unwrap();
+    assert!(room_keys.len() <= 1);
+
+    room_keys.pop().map(|room_key| room_key.session_id)
+}
+
+async fn client_room_key_session_id(client: &Client, room_id: &RoomId) -> Option<String> {
+    room_key_session_id(client.olm_machine_for_testing().await.as_ref().unwrap(), room_id).await
+}
+
+#[async_test]
+async fn test_invite_user_by_id_shares_history_room_keys() {
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap().to_owned();
+    let device_id = client.device_id().unwrap().to_owned();
+    let room_id = room_id!("!test:localhost");
+    let bob_id = user_id!("@bob:example.org");
+    let bob_device_id = device_id!("BOBDEVICE");
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(
+        JoinedRoomBuilder::new(room_id)
+            .add_state_event(StateTestEvent::Encryption)
+            .add_state_event(StateTestEvent::HistoryVisibility),
+    );
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    // We have a room key with a shared history.
+    client
+        .olm_machine_for_testing()
+        .await
+        .as_ref()
+        .unwrap()
+        .share_room_key(room_id, iter::empty(), EncryptionSettings::default())
+        .await
+        .unwrap();
+    let session_id = client_room_key_session_id(&client, room_id).await.unwrap();
+
+    let bob = OlmMachine::new(bob_id, bob_device_id).await;
+    let (bob_device_keys, bob_one_time_key) = device_keys_and_one_time_key(&bob).await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/keys/query"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "device_keys": {
+                bob_id.as_str(): { bob_device_id.as_str(): bob_device_keys },
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/keys/claim"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "one_time_keys": {
+                bob_id.as_str(): {
+                    bob_device_id.as_str(): BTreeMap::from([bob_one_time_key]),
+                },
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/invite$"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/sendToDevice/m.room.encrypted/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client.encryption().set_share_history_on_invite(true).await.unwrap();
+
+    let room = client.get_room(room_id).unwrap();
+    room.invite_user_by_id(bob_id).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let to_device_request =
+        requests.iter().find(|request| request.url.path().contains("/sendToDevice/")).unwrap();
+    let body: JsonValue = to_device_request.body_json().unwrap();
+    let content = &body["messages"][bob_id.as_str()][bob_device_id.as_str()];
+
+    // Bob receives the room key from our device,
+    let (device_keys, _) =
+        device_keys_and_one_time_key(client.olm_machine_for_testing().await.as_ref().unwrap())
+            .await;
+    receive_device_keys(&bob, &user_id, &device_id, device_keys).await;
+
+    let event = json!({
+        "sender": user_id,
+        "type": "m.room.encrypted",
+        "content": content,
+    });
+    bob.receive_sync_changes(EncryptionSyncChanges {
+        to_device_events: vec![serde_json::from_value(event).unwrap()],
+        changed_devices: &Default::default(),
+        one_time_keys_counts: &Default::default(),
+        unused_fallback_keys: None,
+        next_batch_token: None,
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(
+        bob.shared_history_room_key_senders().await.unwrap(),
+        BTreeMap::from([(room_id.to_owned(), BTreeSet::from([user_id.clone()]))])
+    );
+
+    // And imports it once they accept our invite.
+    bob.accept_shared_history_room_keys(room_id, &user_id).await.unwrap();
+    assert_eq!(room_key_session_id(&bob, room_id).await, Some(session_id));
+}
+
+#[async_test]
+async fn test_shared_history_room_keys_imported_on_join() {
+    let (client, server) = logged_in_client_with_server().await;
+    let user_id = client.user_id().unwrap().to_owned();
+    let device_id = client.device_id().unwrap().to_owned();
+    let room_id = room_id!("!test:localhost");
+    let alice_id = user_id!("@alice:example.org");
+    let alice_device_id = device_id!("ALICEDEVICE");
+
+    let alice = OlmMachine::new(alice_id, alice_device_id).await;
+
+    // We know about Alice's device,
+    let (alice_device_keys, _) = device_keys_and_one_time_key(&alice).await;
+    receive_device_keys(
+        client.olm_machine_for_testing().await.as_ref().unwrap(),
+        alice_id,
+        alice_device_id,
+        alice_device_keys,
+    )
+    .await;
+
+    // Alice creates an Olm session with our device,
+    let (device_keys, one_time_key) =
+        device_keys_and_one_time_key(client.olm_machine_for_testing().await.as_ref().unwrap())
+            .await;
+    receive_device_keys(&alice, &user_id, &device_id, device_keys).await;
+
+    let (request_id, _) = alice.get_missing_sessions(iter::once(&*user_id)).await.unwrap().unwrap();
+    let keys_claim_response = claim_keys::v3::Response::new(BTreeMap::from([(
+        user_id.clone(),
+        BTreeMap::from([(device_id.clone(), BTreeMap::from([one_time_key]))]),
+    )]));
+    alice.mark_request_as_sent(&request_id, &keys_claim_response).await.unwrap();
+
+    // And forwards us a room key with a shared history while inviting us.
+    alice.store().set_share_history_on_invite(true).await.unwrap();
+    alice.share_room_key(room_id, iter::empty(), EncryptionSettings::default()).await.unwrap();
+    let session_id = room_key_session_id(&alice, room_id).await.unwrap();
+
+    let requests =
+        alice.share_room_history_keys(room_id, &user_id, HistoryVisibility::Shared).await.unwrap();
+    let content = requests[0].messages.values().next().unwrap().values().next().unwrap();
+
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_invited_room(InvitedRoomBuilder::new(room_id).add_state_event(
+        StrippedStateTestEvent::Custom(json!({
+            "content": { "membership": "invite" },
+            "sender": alice_id,
+            "state_key": user_id,
+            "type": "m.room.member",
+        })),
+    ));
+    let mut response = sync_builder.build_json_sync_response();
+    response["to_device"] = json!({
+        "events": [{
+            "sender": alice_id,
+            "type": "m.room.encrypted",
+            "content": content,
+        }],
+    });
+
+    mock_sync(&server, response, None).await;
+    let sync_token = client.sync_once(SyncSettings::default()).await.unwrap().next_batch;
+
+    // The room key isn't imported before we join the room.
+    assert_eq!(
+        client
+            .olm_machine_for_testing()
+            .await
+            .as_ref()
+            .unwrap()
+            .shared_history_room_key_senders()
+            .await
+            .unwrap(),
+        BTreeMap::from([(room_id.to_owned(), BTreeSet::from([alice_id.to_owned()]))])
+    );
+    assert!(client_room_key_session_id(&client, room_id).await.is_none());
+
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+    mock_sync(&server, sync_builder.build_json_sync_response(), Some(sync_token.clone())).await;
+    client.sync_once(SyncSettings::default().token(sync_token)).await.unwrap();
+
+    assert_eq!(client_room_key_session_id(&client, room_id).await, Some(session_id));
+    assert!(client
+        .olm_machine_for_testing()
+        .await
+        .as_ref()
+        .unwrap()
+        .shared_history_room_key_senders()
+        .await
+        .unwrap()
+        .is_empty());
+}
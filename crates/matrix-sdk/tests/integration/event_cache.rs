@@ -1,7 +1,8 @@
-use std::time::Duration;
+use std::{io::Cursor, time::Duration};
 
 use assert_matches2::{assert_let, assert_matches};
 use matrix_sdk::{
+    crypto::decrypt_room_key_export,
     event_cache::{BackPaginationOutcome, EventCacheError, RoomEventCacheUpdate},
     test_utils::logged_in_client_with_server,
 };
@@ -168,6 +169,93 @@ async fn test_add_initial_events() {
     assert!(subscriber.is_empty());
 }
 
+#[async_test]
+async fn test_retry_decryption_replaces_events() {
+    const SESSION_KEY: &[u8] = b"\
+        -----BEGIN MEGOLM SESSION DATA-----\n\
+        ASKcWoiAVUM97482UAi83Avce62hSLce7i5JhsqoF6xeAAAACqt2Cg3nyJPRWTTMXxXH7TXnkfdlmBXbQtq5\
+        bpHo3LRijcq2Gc6TXilESCmJN14pIsfKRJrWjZ0squ/XsoTFytuVLWwkNaW3QF6obeg2IoVtJXLMPdw3b2vO\
+        vgwGY3OMP0XafH13j1vcb6YLzvgLkZQLnYvd47hv3yK/9GmKS9tokuaQ7dCVYckYcIOS09EDTs70YdxUd5WG\
+        rQynATCLFP1p/NAGv70r9MK7Cy/mNpjD0r4qC7UEDIoi1kOWzHgnLo19wtvwsb8Fg8ATxcs3Wmtj8hIUYpDx\
+        ia4sM10zbytUuaPUAfCDf42IyxdmOnGe1CueXhgI71y+RW0s0argNqUt7jB70JT0o9CyX6UBGRaqLk2MPY9T\
+        hUu5J8X3UgIa6rcbWigzohzWm9rdbEHFrSWqjpfQYMaAKQQgETrjSy4XTrp2RhC2oNqG/hylI4ab+F4X6fpH\
+        DYP1NqNMP5g36xNu7LhDnrUB5qsPjYOmWORxGLfudpF3oLYCSlr3DgHqEIB6HjQblLZ3KQuPBse3zxyROTnS\
+        AhdPH4a/z1wioFtKNVph3hecsiKEdqnz4Y2coSIdhz58mJ9JWNQoFAENE5CSsoEZAGvafYZVpW4C75YY2zq1\
+        wIeiFi1dT43/jLAUGkslsi1VvnyfUu8qO404RxYO3XHoGLMFoFLOO+lZ+VGci2Vz10AhxJhEBHxRKxw4k2uB\
+        HztoSJUr/2Y\n\
+        -----END MEGOLM SESSION DATA-----";
+
+    let (client, server) = logged_in_client_with_server().await;
+
+    client.event_cache().subscribe().unwrap();
+
+    let room_id = room_id!("!DovneieKSTkdHKpIXy:morpheus.localhost");
+
+    // Receive an event that can't be decrypted yet.
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id).add_timeline_event(
+        sync_timeline_event!({
+            "sender": "@bob:example.com",
+            "type": "m.room.encrypted",
+            "event_id": "$utd",
+            "origin_server_ts": 12344446,
+            "content": {
+                "algorithm": "m.megolm.v1.aes-sha2",
+                "ciphertext": "AwgAEtABPRMavuZMDJrPo6pGQP4qVmpcuapuXtzKXJyi3YpEsjSWdzuRKIgJzD4P\
+                    cSqJM1A8kzxecTQNJsC5q22+KSFEPxPnI4ltpm7GFowSoPSW9+bFdnlfUzEP1jPq\
+                    YevHAsMJp2fRKkzQQbPordrUk1gNqEpGl4BYFeRqKl9GPdKFwy45huvQCLNNueql\
+                    CFZVoYMuhxrfyMiJJAVNTofkr2um2mKjDTlajHtr39pTG8k0eOjSXkLOSdZvNOMz\
+                    hGhSaFNeERSA2G2YbeknOvU7MvjiO0AKuxaAe1CaVhAI14FCgzrJ8g0y5nly+n7x\
+                    QzL2G2Dn8EoXM5Iqj8W99iokQoVsSrUEnaQ1WnSIfewvDDt4LCaD/w7PGETMCQ",
+                "sender_key": "DeHIg4gwhClxzFYcmNntPNF9YtsdZbmMy8+3kzCMXHA",
+                "device_id": "NLAZCWIOCO",
+                "session_id": "gM8i47Xhu0q52xLfgUXzanCMpLinoyVyH7R58cBuVBU",
+            },
+        }),
+    ));
+
+    mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(Default::default()).await.unwrap();
+    server.reset().await;
+
+    let room = client.get_room(room_id).unwrap();
+    let (room_event_cache, _drop_handles) = room.event_cache().await.unwrap();
+    let (events, mut subscriber) = room_event_cache.subscribe().await.unwrap();
+
+    assert_eq!(events.len(), 1);
+    assert!(events[0].encryption_info.is_none());
+    assert!(subscriber.is_empty());
+
+    // When the room key is received,
+    let exported_keys = decrypt_room_key_export(Cursor::new(SESSION_KEY), "1234").unwrap();
+    client
+        .olm_machine_for_testing()
+        .await
+        .as_ref()
+        .unwrap()
+        .store()
+        .import_exported_room_keys(exported_keys, |_, _| {})
+        .await
+        .unwrap();
+
+    // Then the event is decrypted and replaced in the event cache.
+    let update = timeout(Duration::from_secs(2), subscriber.recv())
+        .await
+        .expect("timeout after receiving the room key")
+        .expect("should've received a room event cache update");
+
+    assert_let!(RoomEventCacheUpdate::ReplacedEvents { events } = update);
+    assert_eq!(events.len(), 1);
+    assert!(events[0].encryption_info.is_some());
+    assert_event_matches_msg(&events[0], "It's a secret to everybody");
+
+    let (events, _) = room_event_cache.subscribe().await.unwrap();
+    assert_eq!(events.len(), 1);
+    assert_event_matches_msg(&events[0], "It's a secret to everybody");
+
+    assert!(subscriber.is_empty());
+}
+
 macro_rules! non_sync_events {
     ( @_ $builder:expr, [ ( $room_id:expr , $event_id:literal : $msg:literal ) $(, $( $rest:tt )* )? ] [ $( $accumulator:tt )* ] ) => {
         non_sync_events!(
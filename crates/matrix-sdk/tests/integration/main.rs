@@ -17,6 +17,8 @@ mod matrix_auth;
 mod notification;
 mod refresh_token;
 mod room;
+mod search;
+mod send_queue;
 #[cfg(feature = "experimental-widgets")]
 mod widget;
 
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use matrix_sdk::{config::SyncSettings, BeaconError, Client};
+use matrix_sdk_test::{
+    async_test, test_json, JoinedRoomBuilder, StateTestEvent, SyncResponseBuilder,
+    DEFAULT_TEST_ROOM_ID,
+};
+use ruma::{event_id, user_id, MilliSecondsSinceUnixEpoch};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, header, method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use crate::{logged_in_client_with_server, mock_encryption_state, mock_sync};
+
+/// Sync the default test room with a `beacon_info` state event of the current
+/// user.
+async fn sync_beacon_info(client: &Client, server: &MockServer, live: bool) {
+    let mut sync_builder = SyncResponseBuilder::new();
+    sync_builder.add_joined_room(JoinedRoomBuilder::new(&DEFAULT_TEST_ROOM_ID).add_state_event(
+        StateTestEvent::Custom(json!({
+            "content": {
+                "description": "Going home",
+                "live": live,
+                "org.matrix.msc3488.ts": MilliSecondsSinceUnixEpoch::now(),
+                "timeout": 3_600_000,
+                "org.matrix.msc3488.asset": { "type": "m.self" },
+            },
+            "event_id": "$beacon_info",
+            "origin_server_ts": 1_636_829_458,
+            "sender": "@example:localhost",
+            "state_key": "@example:localhost",
+            "type": "org.matrix.msc3672.beacon_info",
+        })),
+    ));
+
+    mock_sync(server, sync_builder.build_json_sync_response(), None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+}
+
+#[async_test]
+async fn test_start_live_location_share() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    mock_sync(&server, &*test_json::SYNC, None).await;
+    client.sync_once(SyncSettings::default()).await.unwrap();
+    server.reset().await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/state/org.matrix.msc3672.beacon_info/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "description": "Going home", "live": true })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let response = room
+        .start_live_location_share(Duration::from_secs(3600), Some("Going home".to_owned()))
+        .await
+        .unwrap();
+    assert_eq!(response.event_id, event_id!("$h29iv0s8:example.com"));
+}
+
+#[async_test]
+async fn test_get_user_beacon_info() {
+    let (client, server) = logged_in_client_with_server().await;
+    sync_beacon_info(&client, &server, true).await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+
+    let beacon_info = room.get_user_beacon_info(user_id!("@example:localhost")).await.unwrap();
+    assert_eq!(beacon_info.event_id, event_id!("$beacon_info"));
+    assert!(beacon_info.content.is_live());
+    assert_eq!(beacon_info.content.description.as_deref(), Some("Going home"));
+
+    // Another user doesn't share their location.
+    assert_let!(
+        Err(BeaconError::NotFound) = room.get_user_beacon_info(user_id!("@bob:localhost")).await
+    );
+}
+
+#[async_test]
+async fn test_send_location_beacon() {
+    let (client, server) = logged_in_client_with_server().await;
+    sync_beacon_info(&client, &server, true).await;
+
+    mock_encryption_state(&server, false).await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/org.matrix.msc3672.beacon/"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({
+            "m.relates_to": { "rel_type": "m.reference", "event_id": "$beacon_info" },
+            "org.matrix.msc3488.location": { "uri": "geo:51.5008,0.1247" },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let response = room.send_location_beacon("geo:51.5008,0.1247".to_owned()).await.unwrap();
+    assert_eq!(response.event_id, event_id!("$h29iv0s8:example.com"));
+}
+
+#[async_test]
+async fn test_send_location_beacon_fails_without_live_share() {
+    let (client, server) = logged_in_client_with_server().await;
+    sync_beacon_info(&client, &server, false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/org.matrix.msc3672.beacon/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    assert_let!(
+        Err(BeaconError::NotLive) =
+            room.send_location_beacon("geo:51.5008,0.1247".to_owned()).await
+    );
+}
+
+#[async_test]
+async fn test_stop_live_location_share() {
+    let (client, server) = logged_in_client_with_server().await;
+    sync_beacon_info(&client, &server, true).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/state/org.matrix.msc3672.beacon_info/.*"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(body_partial_json(json!({ "description": "Going home", "live": false })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(&*test_json::EVENT_ID))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let response = room.stop_live_location_share().await.unwrap();
+    assert_eq!(response.event_id, event_id!("$h29iv0s8:example.com"));
+}
@@ -0,0 +1,196 @@
+use assert_matches2::assert_let;
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk::room::media_gallery::MediaGalleryItemInfo;
+use matrix_sdk_test::{async_test, DEFAULT_TEST_ROOM_ID};
+use ruma::{event_id, events::room::MediaSource, mxc_uri, user_id};
+use serde_json::{json, Value as JsonValue};
+use wiremock::{
+    matchers::{header, method, path_regex, query_param, query_param_is_missing},
+    Mock, Request, ResponseTemplate,
+};
+
+use crate::{mock_encryption_state, synced_client};
+
+fn message_event(event_id: &str, ts: u64, content: JsonValue) -> JsonValue {
+    json!({
+        "content": content,
+        "event_id": event_id,
+        "origin_server_ts": ts,
+        "room_id": *DEFAULT_TEST_ROOM_ID,
+        "sender": "@alice:example.org",
+        "type": "m.room.message",
+    })
+}
+
+/// Whether the `filter` of the given `/messages` request is the expected one.
+fn has_filter(request: &Request, expected: &JsonValue) -> bool {
+    request
+        .url
+        .query_pairs()
+        .find(|(key, _)| key == "filter")
+        .and_then(|(_, filter)| serde_json::from_str::<JsonValue>(&filter).ok())
+        .is_some_and(|filter| filter == *expected)
+}
+
+#[async_test]
+async fn test_media_gallery_groups_media_by_month() {
+    let (client, server) = synced_client().await;
+    mock_encryption_state(&server, false).await;
+
+    // 2024-03-01 and 2024-02-29.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param_is_missing("from"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "start": "page1",
+            "end": "page2",
+            "chunk": [
+                message_event("$image", 1_709_251_300_000, json!({
+                    "body": "cat.png",
+                    "msgtype": "m.image",
+                    "url": "mxc://example.org/cat",
+                    "info": { "mimetype": "image/png", "thumbnail_url": "mxc://example.org/cat_thumb" },
+                })),
+                message_event("$text", 1_709_251_250_000, json!({
+                    "body": "Look at my cat!",
+                    "msgtype": "m.text",
+                })),
+                message_event("$video", 1_709_251_000_000, json!({
+                    "body": "cat.mp4",
+                    "msgtype": "m.video",
+                    "url": "mxc://example.org/cat_video",
+                })),
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    // 2024-02-03 and 2024-01-11.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "page2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "start": "page2",
+            "chunk": [
+                message_event("$file", 1_707_000_000_000, json!({
+                    "body": "cats.pdf",
+                    "msgtype": "m.file",
+                    "url": "mxc://example.org/cats_pdf",
+                })),
+                message_event("$audio", 1_705_000_000_000, json!({
+                    "body": "meow.ogg",
+                    "msgtype": "m.audio",
+                    "url": "mxc://example.org/meow",
+                })),
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let months = room.media_gallery().into_stream();
+    pin_mut!(months);
+
+    let march = months.next().await.unwrap().unwrap();
+    assert_eq!((march.year, march.month), (2024, 3));
+    assert_eq!(march.items.len(), 1);
+
+    let image = &march.items[0];
+    assert_eq!(image.event_id, event_id!("$image"));
+    assert_eq!(image.sender, user_id!("@alice:example.org"));
+    assert_eq!(image.body, "cat.png");
+    assert_eq!(image.source, MediaSource::Plain(mxc_uri!("mxc://example.org/cat").to_owned()));
+    assert_eq!(
+        image.thumbnail_source,
+        Some(MediaSource::Plain(mxc_uri!("mxc://example.org/cat_thumb").to_owned()))
+    );
+    assert_let!(MediaGalleryItemInfo::Image(Some(info)) = &image.info);
+    assert_eq!(info.mimetype.as_deref(), Some("image/png"));
+
+    let february = months.next().await.unwrap().unwrap();
+    assert_eq!((february.year, february.month), (2024, 2));
+    assert_eq!(february.items.len(), 2);
+    assert_eq!(february.items[0].event_id, event_id!("$video"));
+    assert_let!(MediaGalleryItemInfo::Video(_) = &february.items[0].info);
+    assert_eq!(february.items[1].event_id, event_id!("$file"));
+    assert_let!(MediaGalleryItemInfo::File(_) = &february.items[1].info);
+
+    let january = months.next().await.unwrap().unwrap();
+    assert_eq!((january.year, january.month), (2024, 1));
+    assert_eq!(january.items.len(), 1);
+    assert_eq!(january.items[0].event_id, event_id!("$audio"));
+    assert_let!(MediaGalleryItemInfo::Audio(_) = &january.items[0].info);
+
+    assert!(months.next().await.is_none());
+}
+
+#[async_test]
+async fn test_media_gallery_yields_month_at_page_boundary() {
+    let (client, server) = synced_client().await;
+    mock_encryption_state(&server, false).await;
+
+    // The server only returns the events with a URL in an unencrypted room.
+    let filter = json!({ "types": ["m.room.message"], "contains_url": true });
+
+    // 2024-03-01 and 2024-02-29.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param_is_missing("from"))
+        .and(move |request: &Request| has_filter(request, &filter))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "start": "page1",
+            "end": "page2",
+            "chunk": [
+                message_event("$image", 1_709_251_300_000, json!({
+                    "body": "cat.png",
+                    "msgtype": "m.image",
+                    "url": "mxc://example.org/cat",
+                })),
+                message_event("$notice", 1_709_251_000_000, json!({
+                    "body": "This isn't a media of the gallery",
+                    "msgtype": "m.notice",
+                    "url": "mxc://example.org/sticker",
+                })),
+            ],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let room = client.get_room(&DEFAULT_TEST_ROOM_ID).unwrap();
+    let months = room.media_gallery().into_stream();
+    pin_mut!(months);
+
+    // The page contains an event of February, so March is complete without
+    // fetching the next page.
+    let march = months.next().await.unwrap().unwrap();
+    assert_eq!((march.year, march.month), (2024, 3));
+    assert_eq!(march.items.len(), 1);
+    assert_eq!(march.items[0].event_id, event_id!("$image"));
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(
+        requests.iter().filter(|request| request.url.path().ends_with("/messages")).count(),
+        1
+    );
+
+    // The start of the room is reached without any other media.
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/messages$"))
+        .and(header("authorization", "Bearer 1234"))
+        .and(query_param("from", "page2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "start": "page2",
+            "chunk": [],
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(months.next().await.is_none());
+}
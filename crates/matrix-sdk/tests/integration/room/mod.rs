@@ -1,6 +1,8 @@
+mod beacon;
 mod common;
 mod joined;
 mod left;
+mod media_gallery;
 mod notification_mode;
 mod spaces;
 mod tags;
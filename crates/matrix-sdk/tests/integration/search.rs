@@ -0,0 +1,157 @@
+use futures_util::{pin_mut, StreamExt};
+use matrix_sdk::{search::OrderBy, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::async_test;
+use ruma::{event_id, room_id, uint, user_id};
+use serde_json::json;
+use wiremock::{
+    matchers::{body_partial_json, method, path, query_param, query_param_is_missing},
+    Mock, ResponseTemplate,
+};
+
+fn search_result(event_id: &str, body: &str, rank: f64) -> serde_json::Value {
+    json!({
+        "rank": rank,
+        "result": {
+            "content": { "body": body, "msgtype": "m.text" },
+            "event_id": event_id,
+            "origin_server_ts": 1444812213737u64,
+            "room_id": "!galette:saucisse.bzh",
+            "sender": "@alice:example.org",
+            "type": "m.room.message",
+        },
+        "context": {
+            "events_before": [{
+                "content": { "body": "What should we eat?", "msgtype": "m.text" },
+                "event_id": "$before",
+                "origin_server_ts": 1444812213736u64,
+                "room_id": "!galette:saucisse.bzh",
+                "sender": "@bob:example.org",
+                "type": "m.room.message",
+            }],
+            "events_after": [],
+            "profile_info": {
+                "@bob:example.org": { "displayname": "Bob" },
+            },
+        },
+    })
+}
+
+#[async_test]
+async fn test_search_pages() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/search"))
+        .and(query_param_is_missing("next_batch"))
+        .and(body_partial_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "search_term": "pancakes",
+                    "order_by": "recent",
+                    "filter": { "rooms": [room_id] },
+                    "event_context": { "before_limit": 1, "after_limit": 0, "include_profile": true },
+                    "groupings": { "group_by": [{ "key": "room_id" }] },
+                },
+            },
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "count": 2,
+                    "highlights": ["pancakes", "pancake"],
+                    "next_batch": "page2",
+                    "results": [search_result("$first", "I love pancakes", 2.0)],
+                    "groups": {
+                        "room_id": {
+                            "!galette:saucisse.bzh": {
+                                "order": 1,
+                                "results": ["$first", "$second"],
+                            },
+                        },
+                    },
+                },
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/search"))
+        .and(query_param("next_batch", "page2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "search_categories": {
+                "room_events": {
+                    "count": 2,
+                    "highlights": ["pancakes"],
+                    "results": [search_result("$second", "Pancakes are great", 1.0)],
+                },
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let pages = client
+        .search("pancakes")
+        .rooms(vec![room_id.to_owned()])
+        .order_by(OrderBy::Recent)
+        .event_context(uint!(1), uint!(0), true)
+        .group_by_room()
+        .into_stream();
+    pin_mut!(pages);
+
+    // First page.
+    let page = pages.next().await.unwrap().unwrap();
+    assert_eq!(page.count, Some(uint!(2)));
+    assert_eq!(page.highlights, ["pancakes", "pancake"]);
+    assert_eq!(page.next_batch.as_deref(), Some("page2"));
+
+    assert_eq!(page.results.len(), 1);
+    let result = &page.results[0];
+    assert_eq!(result.event.event_id().as_deref(), Some(event_id!("$first")));
+    assert_eq!(result.rank, Some(2.0));
+    assert_eq!(result.events_before.len(), 1);
+    assert_eq!(result.events_before[0].event_id().as_deref(), Some(event_id!("$before")));
+    assert!(result.events_after.is_empty());
+    assert_eq!(
+        result.profile_info.get(user_id!("@bob:example.org")).unwrap().displayname.as_deref(),
+        Some("Bob")
+    );
+
+    let group = page.groups.get(room_id).unwrap();
+    assert_eq!(group.event_ids, [event_id!("$first"), event_id!("$second")]);
+    assert_eq!(group.order, Some(uint!(1)));
+
+    // Second and last page.
+    let page = pages.next().await.unwrap().unwrap();
+    assert_eq!(page.results.len(), 1);
+    assert_eq!(page.results[0].event.event_id().as_deref(), Some(event_id!("$second")));
+    assert!(page.groups.is_empty());
+    assert!(page.next_batch.is_none());
+
+    // The stream ends after the last page.
+    assert!(pages.next().await.is_none());
+}
+
+#[async_test]
+async fn test_search_error_ends_the_stream() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/search"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({
+            "errcode": "M_UNKNOWN",
+            "error": "Unknown error",
+        })))
+        .mount(&server)
+        .await;
+
+    let pages = client.search("pancakes").into_stream();
+    pin_mut!(pages);
+
+    assert!(pages.next().await.unwrap().is_err());
+    assert!(pages.next().await.is_none());
+}
@@ -0,0 +1,321 @@
+use std::time::Duration;
+
+use assert_matches2::assert_let;
+use matrix_sdk::{send_queue::RoomSendQueueUpdate, test_utils::logged_in_client_with_server};
+use matrix_sdk_test::{async_test, JoinedRoomBuilder, SyncResponseBuilder};
+use ruma::{event_id, events::room::message::RoomMessageEventContent, room_id};
+use serde_json::json;
+use tokio::time::timeout;
+use wiremock::{
+    matchers::{body_string_contains, method, path_regex},
+    Mock, ResponseTemplate,
+};
+
+use crate::{mock_encryption_state, mock_sync};
+
+#[async_test]
+async fn test_requests_are_persisted_and_sent_in_order() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+
+    // Make sure the client is aware of the room.
+    {
+        let mut sync_builder = SyncResponseBuilder::new();
+        sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+        mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+        client.sync_once(Default::default()).await.unwrap();
+        server.reset().await;
+    }
+
+    mock_encryption_state(&server, false).await;
+
+    // Disable the queues, so that the requests stay in the store for now.
+    let send_queue = client.send_queue();
+    send_queue.set_enabled(false);
+
+    let room = client.get_room(room_id).unwrap();
+    let queue = room.send_queue();
+    let (initial, mut updates) = queue.subscribe().await.unwrap();
+    assert!(initial.is_empty());
+
+    let txn1 = queue.send(RoomMessageEventContent::text_plain("First!").into()).await.unwrap();
+    let txn2 = queue.send(RoomMessageEventContent::text_plain("Second.").into()).await.unwrap();
+
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(request)) = updates.recv().await);
+    assert_eq!(request.transaction_id, txn1);
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(request)) = updates.recv().await);
+    assert_eq!(request.transaction_id, txn2);
+
+    // The requests have been persisted, in order.
+    let stored = client.store().load_send_queue_requests(room_id).await.unwrap();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].transaction_id, txn1);
+    assert_eq!(stored[1].transaction_id, txn2);
+    assert_eq!(
+        client.store().load_rooms_with_unsent_requests().await.unwrap(),
+        vec![room_id.to_owned()]
+    );
+
+    // The response for the first message takes longer, so it'd come back last if
+    // the requests weren't serialized.
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(body_string_contains("First!"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "event_id": "$first" }))
+                .set_delay(Duration::from_millis(200)),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(body_string_contains("Second."))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$second" })))
+        .mount(&server)
+        .await;
+
+    send_queue.set_enabled(true);
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, event_id }) = update);
+    assert_eq!(transaction_id, txn1);
+    assert_eq!(event_id, event_id!("$first"));
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, event_id }) = update);
+    assert_eq!(transaction_id, txn2);
+    assert_eq!(event_id, event_id!("$second"));
+
+    // Sent requests are removed from the store.
+    assert!(client.store().load_send_queue_requests(room_id).await.unwrap().is_empty());
+    assert!(client.store().load_rooms_with_unsent_requests().await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn test_unrecoverable_error_blocks_queue_until_retried() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+
+    {
+        let mut sync_builder = SyncResponseBuilder::new();
+        sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+        mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+        client.sync_once(Default::default()).await.unwrap();
+        server.reset().await;
+    }
+
+    mock_encryption_state(&server, false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You shall not pass.",
+        })))
+        .up_to_n_times(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$event" })))
+        .mount(&server)
+        .await;
+
+    let send_queue = client.send_queue();
+    send_queue.set_enabled(false);
+
+    let room = client.get_room(room_id).unwrap();
+    let queue = room.send_queue();
+    let (_, mut updates) = queue.subscribe().await.unwrap();
+
+    let txn1 = queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+    let txn2 = queue.send(RoomMessageEventContent::text_plain("World").into()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(_)) = updates.recv().await);
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(_)) = updates.recv().await);
+
+    send_queue.set_enabled(true);
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SendError { transaction_id, is_recoverable, .. }) = update);
+    assert_eq!(transaction_id, txn1);
+    assert!(!is_recoverable);
+
+    // The failed request is kept, and the next one isn't sent before it.
+    assert!(queue.is_blocked());
+    assert!(timeout(Duration::from_millis(300), updates.recv()).await.is_err());
+
+    let stored = client.store().load_send_queue_requests(room_id).await.unwrap();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].transaction_id, txn1);
+    assert_eq!(stored[1].transaction_id, txn2);
+
+    // Retrying the failed request unblocks the queue, and the request keeps its
+    // position in it.
+    queue.push(stored[0].clone()).await.unwrap();
+    assert!(!queue.is_blocked());
+
+    let stored = client.store().load_send_queue_requests(room_id).await.unwrap();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[0].transaction_id, txn1);
+    assert_eq!(stored[1].transaction_id, txn2);
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, .. }) = update);
+    assert_eq!(transaction_id, txn1);
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, .. }) = update);
+    assert_eq!(transaction_id, txn2);
+
+    assert!(client.store().load_send_queue_requests(room_id).await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn test_cancelling_failed_request_unblocks_queue() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+
+    {
+        let mut sync_builder = SyncResponseBuilder::new();
+        sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+        mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+        client.sync_once(Default::default()).await.unwrap();
+        server.reset().await;
+    }
+
+    mock_encryption_state(&server, false).await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(body_string_contains("Hello"))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "You shall not pass.",
+        })))
+        .mount(&server)
+        .await;
+
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.*/send/.*"))
+        .and(body_string_contains("World"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "$event" })))
+        .mount(&server)
+        .await;
+
+    let send_queue = client.send_queue();
+    send_queue.set_enabled(false);
+
+    let room = client.get_room(room_id).unwrap();
+    let queue = room.send_queue();
+    let (_, mut updates) = queue.subscribe().await.unwrap();
+
+    let txn1 = queue.send(RoomMessageEventContent::text_plain("Hello").into()).await.unwrap();
+    let txn2 = queue.send(RoomMessageEventContent::text_plain("World").into()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(_)) = updates.recv().await);
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(_)) = updates.recv().await);
+
+    send_queue.set_enabled(true);
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SendError { transaction_id, is_recoverable, .. }) = update);
+    assert_eq!(transaction_id, txn1);
+    assert!(!is_recoverable);
+    assert!(queue.is_blocked());
+
+    // Cancelling the failed request unblocks the queue.
+    assert!(queue.cancel(&txn1).await.unwrap());
+    assert!(!queue.is_blocked());
+    assert_let!(
+        Ok(RoomSendQueueUpdate::CancelledLocalEvent { transaction_id }) = updates.recv().await
+    );
+    assert_eq!(transaction_id, txn1);
+
+    let update = timeout(Duration::from_secs(2), updates.recv()).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::SentEvent { transaction_id, .. }) = update);
+    assert_eq!(transaction_id, txn2);
+}
+
+#[async_test]
+async fn test_cancel_queued_request() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+
+    {
+        let mut sync_builder = SyncResponseBuilder::new();
+        sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+        mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+        client.sync_once(Default::default()).await.unwrap();
+        server.reset().await;
+    }
+
+    let send_queue = client.send_queue();
+    send_queue.set_enabled(false);
+
+    let room = client.get_room(room_id).unwrap();
+    let queue = room.send_queue();
+    let (_, mut updates) = queue.subscribe().await.unwrap();
+
+    let txn = queue.redact(event_id!("$event").to_owned(), None).await.unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(_)) = updates.recv().await);
+
+    assert!(queue.cancel(&txn).await.unwrap());
+    assert_let!(
+        Ok(RoomSendQueueUpdate::CancelledLocalEvent { transaction_id }) = updates.recv().await
+    );
+    assert_eq!(transaction_id, txn);
+
+    // The request isn't in the queue anymore.
+    assert!(!queue.cancel(&txn).await.unwrap());
+    assert!(client.store().load_send_queue_requests(room_id).await.unwrap().is_empty());
+}
+
+#[async_test]
+async fn test_cancel_queued_attachment_removes_its_file() {
+    let (client, server) = logged_in_client_with_server().await;
+
+    let room_id = room_id!("!galette:saucisse.bzh");
+
+    {
+        let mut sync_builder = SyncResponseBuilder::new();
+        sync_builder.add_joined_room(JoinedRoomBuilder::new(room_id));
+        mock_sync(&server, sync_builder.build_json_sync_response(), None).await;
+        client.sync_once(Default::default()).await.unwrap();
+        server.reset().await;
+    }
+
+    let send_queue = client.send_queue();
+    send_queue.set_enabled(false);
+
+    let room = client.get_room(room_id).unwrap();
+    let queue = room.send_queue();
+    let (_, mut updates) = queue.subscribe().await.unwrap();
+
+    let txn = queue
+        .send_attachment("image.jpg".to_owned(), &mime::IMAGE_JPEG, b"hello".to_vec(), None)
+        .await
+        .unwrap();
+    assert_let!(Ok(RoomSendQueueUpdate::NewLocalEvent(_)) = updates.recv().await);
+
+    // The file of the attachment is kept in the media store, not in the queue.
+    let request = client.store().load_send_queue_request(room_id, &txn).await.unwrap().unwrap();
+    let media_request = request.attachment_media_request();
+    assert_eq!(
+        client.store().get_media_content(&media_request).await.unwrap().as_deref(),
+        Some(&b"hello"[..])
+    );
+
+    assert!(queue.cancel(&txn).await.unwrap());
+    assert_let!(
+        Ok(RoomSendQueueUpdate::CancelledLocalEvent { transaction_id }) = updates.recv().await
+    );
+    assert_eq!(transaction_id, txn);
+
+    // The file is removed along with the request.
+    assert!(client.store().get_media_content(&media_request).await.unwrap().is_none());
+}
@@ -505,6 +505,11 @@ impl OidcCli {
                                     }
                                 }
 
+                                matrix_sdk_ui::sync_service::State::Offline => {
+                                    // The sync service restarts by itself.
+                                    num_running = 0;
+                                }
+
                                 matrix_sdk_ui::sync_service::State::Error => {
                                     num_errors += 1;
                                     num_running = 0;
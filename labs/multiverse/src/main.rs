@@ -669,7 +669,9 @@ impl App {
                         | TimelineItemContent::FailedToParseMessageLike { .. }
                         | TimelineItemContent::FailedToParseState { .. }
                         | TimelineItemContent::Poll(_)
-                        | TimelineItemContent::CallInvite => {
+                        | TimelineItemContent::CallInvite
+                        | TimelineItemContent::Location(_)
+                        | TimelineItemContent::LiveLocation(_) => {
                             continue;
                         }
                     }
@@ -682,6 +684,9 @@ impl App {
                     VirtualTimelineItem::ReadMarker => {
                         content.push("Read marker".to_owned());
                     }
+                    VirtualTimelineItem::CollapsedStateEvents(summary) => {
+                        content.push(format!("{} state events", summary.num_events()));
+                    }
                 },
             }
         }